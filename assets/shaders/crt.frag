@@ -0,0 +1,34 @@
+#version 100
+precision mediump float;
+
+varying lowp vec2 uv;
+varying lowp vec4 color;
+
+uniform sampler2D Texture;
+// size of the simulated screen in pixels
+uniform vec2 resolution;
+
+const float CURVATURE = 0.08;
+const float SCANLINE_STRENGTH = 0.2;
+const float VIGNETTE_STRENGTH = 0.35;
+
+void main() {
+    // bulge the picture out from the center, like the glass of an old monitor
+    vec2 centered = uv * 2.0 - 1.0;
+    centered *= 1.0 + dot(centered.yx, centered.yx) * CURVATURE;
+    vec2 curved = centered * 0.5 + 0.5;
+    if (curved.x < 0.0 || curved.x > 1.0 || curved.y < 0.0 || curved.y > 1.0) {
+        gl_FragColor = vec4(0.0, 0.0, 0.0, 1.0);
+        return;
+    }
+
+    vec4 texel = texture2D(Texture, curved);
+
+    // darken the gap between each row of simulated pixels
+    float scanline = 1.0 - SCANLINE_STRENGTH * (0.5 + 0.5 * cos(curved.y * resolution.y * 6.2832));
+
+    // fade out toward the corners
+    float vignette = 1.0 - VIGNETTE_STRENGTH * dot(centered, centered) * 0.5;
+
+    gl_FragColor = vec4(texel.rgb * scanline * vignette, texel.a) * color;
+}
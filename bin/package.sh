@@ -12,4 +12,5 @@ while read f; do
   cp "$f" "${PACKAGE_DIR}/$f"
 done < manifest.txt
 
-zip -r "${PACKAGE_DIR}.zip" "${PACKAGE_DIR}"
\ No newline at end of file
+# index.html sits at the root of the zip, where itch.io looks for it
+(cd "${PACKAGE_DIR}" && zip -r "../stonehold.zip" .)
\ No newline at end of file
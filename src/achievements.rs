@@ -0,0 +1,219 @@
+use anyhow::Result;
+use nanoserde::{DeJson, SerJson};
+
+use crate::{
+    clock,
+    constants::{ACHIEVEMENTS_PATH, QUICK_TRAP_COUNT, QUICK_TRAP_WINDOW, SPEEDRUN_ESCAPE_TIME},
+    event::{EventHandler, GameEvent},
+    storage,
+};
+
+/// Something worth doing in a round, unlocked once and kept between runs
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Achievement {
+    /// escape without shoving a guard or hitting one with a rock
+    Pacifist,
+    /// trap several guards in quick succession
+    QuickTrapper,
+    /// escape fast
+    Speedrunner,
+    /// escape without taking a hit
+    Untouchable,
+    /// escape without a guard ever spotting you
+    Unseen,
+}
+
+impl Achievement {
+    pub const ALL: &'static [Self] = &[
+        Self::Pacifist,
+        Self::QuickTrapper,
+        Self::Speedrunner,
+        Self::Untouchable,
+        Self::Unseen,
+    ];
+
+    /// Name it is stored under, which never changes
+    pub fn id(self) -> &'static str {
+        match self {
+            Self::Pacifist => "pacifist",
+            Self::QuickTrapper => "quick-trapper",
+            Self::Speedrunner => "speedrunner",
+            Self::Untouchable => "untouchable",
+            Self::Unseen => "unseen",
+        }
+    }
+
+    /// Locale key for its name
+    pub fn name_key(self) -> String {
+        format!("achievement-{}", self.id())
+    }
+
+    fn from_id(id: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|a| a.id() == id)
+    }
+}
+
+/// How the stored achievements are written out
+#[derive(Debug, Default, DeJson, SerJson)]
+struct SavedAchievements {
+    unlocked: Vec<String>,
+}
+
+/// Unlocked achievements, and what has happened so far this round toward the rest
+#[derive(Debug, Default)]
+pub struct Achievements {
+    unlocked: Vec<Achievement>,
+    /// unlocked since the game last collected them, to be announced
+    newly_unlocked: Vec<Achievement>,
+    round_start: f64,
+    guards_hit: bool,
+    damaged: bool,
+    spotted: bool,
+    /// when recent guards were trapped, oldest first
+    trap_times: Vec<f64>,
+}
+
+impl Achievements {
+    /// Read the unlocked achievements from storage. Nothing stored yet means none are unlocked.
+    pub fn read() -> Result<Self> {
+        let saved: SavedAchievements = match storage::read(ACHIEVEMENTS_PATH)? {
+            Some(json) => DeJson::deserialize_json(&json)?,
+            None => SavedAchievements::default(),
+        };
+        Ok(Self {
+            unlocked: saved
+                .unlocked
+                .iter()
+                .filter_map(|id| Achievement::from_id(id))
+                .collect(),
+            ..Default::default()
+        })
+    }
+
+    pub fn write(&self) -> Result<()> {
+        let saved = SavedAchievements {
+            unlocked: self.unlocked.iter().map(|a| a.id().to_string()).collect(),
+        };
+        storage::write(ACHIEVEMENTS_PATH, &saved.serialize_json())
+    }
+
+    pub fn is_unlocked(&self, achievement: Achievement) -> bool {
+        self.unlocked.contains(&achievement)
+    }
+
+    /// Forget the last round's progress. A continued round passes in when it really started.
+    pub fn start_round(&mut self, start_time: f64) {
+        self.round_start = start_time;
+        self.guards_hit = false;
+        self.damaged = false;
+        self.spotted = false;
+        self.trap_times.clear();
+    }
+
+    /// Achievements unlocked since the last call
+    pub fn take_unlocked(&mut self) -> Vec<Achievement> {
+        std::mem::take(&mut self.newly_unlocked)
+    }
+
+    fn unlock(&mut self, achievement: Achievement) {
+        if !self.is_unlocked(achievement) {
+            self.unlocked.push(achievement);
+            self.newly_unlocked.push(achievement);
+        }
+    }
+
+    /// Count an event toward the achievements, as of `now`
+    fn record(&mut self, event: &GameEvent, now: f64) {
+        match event {
+            GameEvent::AttackHit { .. } | GameEvent::ProjectileImpact { guard: Some(_), .. } => {
+                self.guards_hit = true
+            }
+            GameEvent::PlayerDamaged { .. } => self.damaged = true,
+            GameEvent::GuardAlerted { .. } => self.spotted = true,
+            GameEvent::GuardTrapped { .. } => {
+                self.trap_times.push(now);
+                self.trap_times
+                    .retain(|time| now - time <= QUICK_TRAP_WINDOW);
+                if self.trap_times.len() >= QUICK_TRAP_COUNT {
+                    self.unlock(Achievement::QuickTrapper);
+                }
+            }
+            GameEvent::PlayerEscaped => {
+                if !self.guards_hit {
+                    self.unlock(Achievement::Pacifist);
+                }
+                if now - self.round_start < SPEEDRUN_ESCAPE_TIME {
+                    self.unlock(Achievement::Speedrunner);
+                }
+                if !self.damaged {
+                    self.unlock(Achievement::Untouchable);
+                }
+                if !self.spotted {
+                    self.unlock(Achievement::Unseen);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl EventHandler for Achievements {
+    fn handle_event(&mut self, event: &GameEvent) {
+        self.record(event, clock::now());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::{EntityKind, EntityRegistry};
+    use macroquad::math::Vec2;
+
+    #[test]
+    fn test_achievements_unlock_from_events() {
+        let mut entities = EntityRegistry::default();
+        let player = entities.spawn(EntityKind::Player);
+        let guard = entities.spawn(EntityKind::Guard);
+        let door = entities.spawn(EntityKind::GuardDoor);
+        let trapped = GameEvent::GuardTrapped {
+            door,
+            guard,
+            position: Vec2::ZERO,
+        };
+        let mut achievements = Achievements::default();
+        achievements.start_round(0.);
+
+        // too far apart to count as quick
+        achievements.record(&trapped, 1.);
+        achievements.record(&trapped, 2.);
+        achievements.record(&trapped, 1. + QUICK_TRAP_WINDOW * 2.);
+        assert!(achievements.take_unlocked().is_empty());
+        achievements.record(&trapped, 2. + QUICK_TRAP_WINDOW * 2.);
+        achievements.record(&trapped, 3. + QUICK_TRAP_WINDOW * 2.);
+        assert_eq!(
+            achievements.take_unlocked(),
+            vec![Achievement::QuickTrapper]
+        );
+
+        achievements.record(&GameEvent::AttackHit { player, guard }, 40.);
+        achievements.record(&GameEvent::PlayerDamaged { amount: 1 }, 41.);
+        achievements.record(&GameEvent::PlayerEscaped, SPEEDRUN_ESCAPE_TIME + 1.);
+        assert_eq!(achievements.take_unlocked(), vec![Achievement::Unseen]);
+
+        // a clean, quick round picks up the rest, and nothing is announced twice
+        achievements.start_round(100.);
+        achievements.record(&trapped, 101.);
+        achievements.record(&GameEvent::PlayerEscaped, 110.);
+        assert_eq!(
+            achievements.take_unlocked(),
+            vec![
+                Achievement::Pacifist,
+                Achievement::Speedrunner,
+                Achievement::Untouchable
+            ]
+        );
+        assert!(Achievement::ALL
+            .iter()
+            .all(|a| achievements.is_unlocked(*a)));
+    }
+}
@@ -1,43 +1,298 @@
-use anyhow::Result;
-use futures::try_join;
-use macroquad::audio::{load_sound, Sound};
+use std::{collections::HashMap, sync::RwLock};
 
-use crate::constants::{
-    ALERT_SOUND_PATH, ATTACK_SOUND_PATH, CLICK_SOUND_PATH, DEFEAT_SOUND_PATH,
-    DOOR_CLOSE_SOUND_PATH, KNOCKBACK_SOUND_PATH, VICTORY_SOUND_PATH,
+use anyhow::{anyhow, Result};
+use futures::future::try_join_all;
+use macroquad::{
+    audio::{load_sound, play_sound, stop_sound, PlaySoundParams, Sound},
+    file::load_string,
+    math::Vec2,
+    time::get_time,
 };
+use nanoserde::DeJson;
 
-// container class for different sounds
+use crate::{
+    constants::{
+        DUCK_FADE_TIME, DUCK_HOLD_TIME, DUCK_VOLUME, FOOTSTEP_SNEAK_VOLUME, FOOTSTEP_VOLUME,
+        SOUND_FULL_VOLUME_DISTANCE, SOUND_MANIFEST_PATH, SOUND_MAX_DISTANCE,
+    },
+    event::{EventHandler, GameEvent, Surface},
+    loading::LoadProgress,
+};
+
+/// where positional sounds are heard from, in tiles. follows the player.
+static LISTENER: RwLock<Vec2> = RwLock::new(Vec2::ZERO);
+
+/// when the last cue started playing
+static DUCK_START: RwLock<Option<f64>> = RwLock::new(None);
+
+pub fn set_listener(position: Vec2) {
+    *LISTENER.write().unwrap() = position;
+}
+
+/// Play a sound that fades out the further it is from the listener. Sounds out of earshot are
+/// skipped. macroquad's audio has no panning, so only the volume changes.
+pub fn play_sound_at(sound: &Sound, position: Vec2, volume: f32) {
+    let distance = LISTENER.read().unwrap().distance(position);
+    let volume = volume * attenuation(distance);
+    if volume > 0. {
+        play_sound(
+            sound,
+            PlaySoundParams {
+                looped: false,
+                volume,
+            },
+        );
+    }
+}
+
+/// Volume for a sound some distance away: full up close, falling off linearly to silence
+fn attenuation(distance: f32) -> f32 {
+    let t =
+        (distance - SOUND_FULL_VOLUME_DISTANCE) / (SOUND_MAX_DISTANCE - SOUND_FULL_VOLUME_DISTANCE);
+    1. - t.clamp(0., 1.)
+}
+
+/// Handle to a sound in the [`SoundBank`], by its name in the sound manifest.
+///
+/// The sounds the game itself relies on have constants here. Anything else can make its own
+/// handle for a sound it adds to the manifest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SoundId(pub &'static str);
+
+impl SoundId {
+    pub const CLICK: Self = Self("click");
+    pub const ATTACK: Self = Self("attack");
+    pub const KNOCKBACK: Self = Self("knockback");
+    pub const ALERT: Self = Self("alert");
+    pub const CLOSE_DOOR: Self = Self("close_door");
+    pub const VICTORY: Self = Self("victory");
+    pub const DEFEAT: Self = Self("defeat");
+    pub const ROCK_IMPACT: Self = Self("rock_impact");
+    pub const PICKUP: Self = Self("pickup");
+    pub const UNLOCK_DOOR: Self = Self("unlock_door");
+    pub const FOOTSTEP: Self = Self("footstep");
+    /// footstep on the pool and stairs by the exit
+    pub const FOOTSTEP_STONE: Self = Self("footstep_stone");
+    /// a monster pipe about to let out a guard
+    pub const PIPE_RUMBLE: Self = Self("pipe_rumble");
+    /// looped while the alarm counts down to the guards sealing the exit
+    pub const ESCAPE_MUSIC: Self = Self("escape_music");
+    /// coins spilling out of an opened chest
+    pub const COINS: Self = Self("coins");
+
+    /// important cues that duck everything else while they play, so they can be heard
+    pub const CUES: &'static [Self] = &[Self::ALERT, Self::VICTORY, Self::DEFEAT];
+
+    /// sounds the manifest has to have for the game to work
+    pub const REQUIRED: &'static [Self] = &[
+        Self::CLICK,
+        Self::ATTACK,
+        Self::KNOCKBACK,
+        Self::ALERT,
+        Self::CLOSE_DOOR,
+        Self::VICTORY,
+        Self::DEFEAT,
+        Self::ROCK_IMPACT,
+        Self::PICKUP,
+        Self::UNLOCK_DOOR,
+        Self::FOOTSTEP,
+        Self::FOOTSTEP_STONE,
+        Self::PIPE_RUMBLE,
+        Self::ESCAPE_MUSIC,
+        Self::COINS,
+    ];
+}
+
+/// Names and paths of the sound effects to load, read from a JSON object of `"name": "path"`
 #[derive(Clone, Debug)]
-pub struct Sounds {
-    pub click: Sound,
-    pub attack: Sound,
-    pub knockback: Sound,
-    pub alert: Sound,
-    pub close_door: Sound,
-    pub victory: Sound,
-    pub defeat: Sound,
+pub struct SoundManifest {
+    pub entries: HashMap<String, String>,
 }
 
-impl Sounds {
+impl SoundManifest {
     pub async fn load() -> Result<Self> {
-        let (click, attack, knockback, alert, close_door, victory, defeat) = try_join!(
-            load_sound(CLICK_SOUND_PATH),
-            load_sound(ATTACK_SOUND_PATH),
-            load_sound(KNOCKBACK_SOUND_PATH),
-            load_sound(ALERT_SOUND_PATH),
-            load_sound(DOOR_CLOSE_SOUND_PATH),
-            load_sound(VICTORY_SOUND_PATH),
-            load_sound(DEFEAT_SOUND_PATH),
-        )?;
+        let json = load_string(SOUND_MANIFEST_PATH).await?;
+        Self::from_json(&json)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        let entries: HashMap<String, String> = DeJson::deserialize_json(json)?;
+        if let Some(missing) = SoundId::REQUIRED
+            .iter()
+            .find(|id| !entries.contains_key(id.0))
+        {
+            return Err(anyhow!("sound manifest is missing {:?}", missing.0));
+        }
+        Ok(Self { entries })
+    }
+}
+
+/// Every loaded sound effect, by name
+#[derive(Clone, Debug, Default)]
+pub struct SoundBank {
+    sounds: HashMap<String, Sound>,
+}
+
+impl SoundBank {
+    pub async fn load(manifest: &SoundManifest, progress: &LoadProgress) -> Result<Self> {
+        let sounds = try_join_all(manifest.entries.iter().map(|(name, path)| async move {
+            let sound = progress.track(load_sound(path)).await?;
+            Ok::<_, anyhow::Error>((name.clone(), sound))
+        }))
+        .await?;
         Ok(Self {
-            click,
-            attack,
-            knockback,
-            alert,
-            close_door,
-            victory,
-            defeat,
+            sounds: sounds.into_iter().collect(),
         })
     }
+
+    pub fn get(&self, id: SoundId) -> Option<&Sound> {
+        self.sounds.get(id.0)
+    }
+
+    pub fn play(&self, id: SoundId) {
+        match self.get(id) {
+            Some(sound) => play_sound(
+                sound,
+                PlaySoundParams {
+                    looped: false,
+                    volume: duck(id),
+                },
+            ),
+            None => warn!("no sound named {:?}", id.0),
+        }
+    }
+
+    /// Play a sound over and over until it is stopped
+    pub fn play_looped(&self, id: SoundId) {
+        match self.get(id) {
+            Some(sound) => play_sound(
+                sound,
+                PlaySoundParams {
+                    looped: true,
+                    volume: 1.,
+                },
+            ),
+            None => warn!("no sound named {:?}", id.0),
+        }
+    }
+
+    pub fn stop(&self, id: SoundId) {
+        if let Some(sound) = self.get(id) {
+            stop_sound(sound);
+        }
+    }
+
+    /// Play a sound that fades with distance from the listener, like [`play_sound_at`]
+    pub fn play_at(&self, id: SoundId, position: Vec2, volume: f32) {
+        match self.get(id) {
+            Some(sound) => play_sound_at(sound, position, volume * duck(id)),
+            None => warn!("no sound named {:?}", id.0),
+        }
+    }
+}
+
+/// Volume to play a sound at under ducking. Cues start a new duck and play at full volume.
+fn duck(id: SoundId) -> f32 {
+    if SoundId::CUES.contains(&id) {
+        *DUCK_START.write().unwrap() = Some(get_time());
+        return 1.;
+    }
+    duck_volume(DUCK_START.read().unwrap().map(|start| get_time() - start))
+}
+
+/// How loud other sounds are some time after a cue started: held down while it plays, then
+/// faded back up to full
+fn duck_volume(since_cue: Option<f64>) -> f32 {
+    let Some(since_cue) = since_cue else {
+        return 1.;
+    };
+    let t = ((since_cue - DUCK_HOLD_TIME) / DUCK_FADE_TIME).clamp(0., 1.) as f32;
+    DUCK_VOLUME + (1. - DUCK_VOLUME) * t
+}
+
+/// Plays sound effects in response to game events
+pub struct SoundEventHandler {
+    sounds: SoundBank,
+}
+
+impl SoundEventHandler {
+    pub fn new(sounds: &SoundBank) -> Self {
+        Self {
+            sounds: sounds.clone(),
+        }
+    }
+}
+
+impl EventHandler for SoundEventHandler {
+    fn handle_event(&mut self, event: &GameEvent) {
+        match event {
+            GameEvent::GuardTrapped { position, .. }
+            | GameEvent::PlatePressed { position, .. }
+            | GameEvent::LeverPulled { position, .. } => {
+                self.sounds.play_at(SoundId::CLOSE_DOOR, *position, 1.)
+            }
+            GameEvent::ChestOpened { position, .. } => {
+                self.sounds.play_at(SoundId::COINS, *position, 1.)
+            }
+            GameEvent::PipeRumbling { position } => {
+                self.sounds.play_at(SoundId::PIPE_RUMBLE, *position, 1.)
+            }
+            GameEvent::GuardAlerted { position, .. } => {
+                self.sounds.play_at(SoundId::ALERT, *position, 1.)
+            }
+            GameEvent::Footstep {
+                position,
+                surface,
+                sneaking,
+            } => {
+                let sound = match surface {
+                    Surface::Ground => SoundId::FOOTSTEP,
+                    Surface::Stone | Surface::Ice => SoundId::FOOTSTEP_STONE,
+                };
+                let volume = if *sneaking {
+                    FOOTSTEP_SNEAK_VOLUME
+                } else {
+                    FOOTSTEP_VOLUME
+                };
+                self.sounds.play_at(sound, *position, volume)
+            }
+            GameEvent::PlayerEscaped => self.sounds.play(SoundId::VICTORY),
+            GameEvent::PlayerDied => self.sounds.play(SoundId::DEFEAT),
+            GameEvent::ProjectileImpact { .. } => self.sounds.play(SoundId::ROCK_IMPACT),
+            GameEvent::ItemPickedUp { .. } => self.sounds.play(SoundId::PICKUP),
+            GameEvent::DoorUnlocked { .. } => self.sounds.play(SoundId::UNLOCK_DOOR),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sound_manifest_has_required_sounds() {
+        let manifest = SoundManifest::from_json(include_str!("../assets/sounds.json")).unwrap();
+        assert!(manifest.entries.len() >= SoundId::REQUIRED.len());
+        assert!(SoundManifest::from_json(r#"{"click": "click.ogg"}"#).is_err());
+    }
+
+    #[test]
+    fn test_duck_volume_holds_then_fades_back() {
+        assert_eq!(duck_volume(None), 1.);
+        assert_eq!(duck_volume(Some(0.)), DUCK_VOLUME);
+        assert_eq!(duck_volume(Some(DUCK_HOLD_TIME)), DUCK_VOLUME);
+        let halfway = duck_volume(Some(DUCK_HOLD_TIME + DUCK_FADE_TIME / 2.));
+        assert!(halfway > DUCK_VOLUME && halfway < 1.);
+        assert_eq!(duck_volume(Some(DUCK_HOLD_TIME + DUCK_FADE_TIME)), 1.);
+    }
+
+    #[test]
+    fn test_attenuation_fades_with_distance() {
+        assert_eq!(attenuation(0.), 1.);
+        assert_eq!(attenuation(SOUND_FULL_VOLUME_DISTANCE), 1.);
+        let halfway = (SOUND_FULL_VOLUME_DISTANCE + SOUND_MAX_DISTANCE) / 2.;
+        assert!((attenuation(halfway) - 0.5).abs() < 1e-6);
+        assert_eq!(attenuation(SOUND_MAX_DISTANCE * 2.), 0.);
+    }
 }
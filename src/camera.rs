@@ -1,12 +1,22 @@
 use macroquad::{
     camera::Camera2D,
     color::WHITE,
-    math::{vec2, Vec2},
+    material::{gl_use_default_material, gl_use_material, load_material, Material, MaterialParams},
+    math::{vec2, Rect, UVec2, Vec2},
+    miniquad::{ShaderSource, UniformType},
+    rand::gen_range,
     texture::{draw_texture_ex, render_target, DrawTextureParams, FilterMode},
+    time::{get_frame_time, get_time},
     window::{screen_height, screen_width},
 };
 
-use crate::constants::{SIMULATED_RESOLUTION, SIMULATED_TILE_PX};
+use crate::{
+    constants::{
+        CAMERA_FRAME_MARGIN, CAMERA_MAX_ZOOM_OUT, SHAKE_MAX_OFFSET, SHAKE_TRAUMA_DECAY,
+        SIMULATED_RESOLUTION, SIMULATED_TILE_PX, SPECTATE_TIME, SPECTATOR_PAN_SPEED,
+    },
+    settings::Settings,
+};
 
 pub struct Cameras {
     /// Worldspace camera (tile units, render_target)
@@ -17,27 +27,87 @@ pub struct Cameras {
 
     /// Screenspace camera (screen pixel units)
     pub screen_camera: Camera2D,
+
+    /// how hard the world camera is shaking, from 0 to 1
+    trauma: f32,
+    /// reduced motion turns shaking off
+    shake: bool,
+
+    /// scale the simulated screen up by whole multiples only
+    integer_scaling: bool,
+
+    /// post-process for the world render, if the CRT effect is turned on
+    crt_material: Option<Material>,
 }
 
 impl Cameras {
-    pub fn new() -> Self {
+    pub fn new(settings: &Settings) -> Self {
         Self {
             world_camera: create_world_camera(),
             ui_camera: create_ui_camera(),
-            screen_camera: create_screen_camera(),
+            screen_camera: create_screen_camera(settings.integer_scaling),
+            trauma: 0.,
+            shake: !settings.reduced_motion,
+            integer_scaling: settings.integer_scaling,
+            crt_material: settings.crt.then(load_crt_material).flatten(),
         }
     }
 
-    pub fn update(&mut self, player_pos: Vec2) {
-        // update world camera to follow player
-        self.world_camera.target = player_pos;
+    /// Rebuild the cameras and their render targets for changed settings
+    pub fn apply_settings(&mut self, settings: &Settings) {
+        let target = self.world_camera.target;
+        *self = Self {
+            trauma: self.trauma,
+            ..Self::new(settings)
+        };
+        self.world_camera.target = target;
+    }
+
+    /// Follow the players, zooming out as far as needed to keep them all on screen
+    pub fn update(&mut self, player_positions: &[Vec2]) {
+        let min = player_positions.iter().copied().reduce(Vec2::min);
+        let max = player_positions.iter().copied().reduce(Vec2::max);
+        let (Some(min), Some(max)) = (min, max) else {
+            return;
+        };
+        let view = SIMULATED_RESOLUTION.as_vec2() / SIMULATED_TILE_PX;
+        let spread = (max - min + CAMERA_FRAME_MARGIN * 2.) / view;
+        let zoom_out = spread.max_element().clamp(1., CAMERA_MAX_ZOOM_OUT);
+        self.world_camera.zoom = 2. / (view * zoom_out);
+
+        // update world camera to follow player, jittered by any shake
+        self.trauma = (self.trauma - SHAKE_TRAUMA_DECAY * get_frame_time()).max(0.);
+        let shake = self.trauma * self.trauma * SHAKE_MAX_OFFSET;
+        self.world_camera.target =
+            (min + max) / 2. + vec2(gen_range(-1., 1.), gen_range(-1., 1.)) * shake;
 
         // update screen camera to compensate for resolution changes.
         // creating a new one is cheap so we just do that
-        self.screen_camera = create_screen_camera();
+        self.screen_camera = create_screen_camera(self.integer_scaling);
+    }
+
+    /// Shake the world camera. Trauma from several hits adds up, and wears off over time.
+    pub fn add_trauma(&mut self, amount: f32) {
+        if !self.shake {
+            return;
+        }
+        self.trauma = (self.trauma + amount).min(1.);
+    }
+
+    /// The world position under a point on the real screen, given in screen pixels
+    pub fn screen_to_world(&self, point: Vec2) -> Vec2 {
+        unproject(
+            point,
+            vec2(screen_width(), screen_height()),
+            self.screen_camera.zoom,
+            visible_rect(&self.world_camera),
+        )
     }
 
     pub fn draw_world_render_to_screen(&self) {
+        if let Some(material) = &self.crt_material {
+            gl_use_material(material);
+        }
         draw_texture_ex(
             &self
                 .world_camera
@@ -52,7 +122,8 @@ impl Cameras {
                 dest_size: Some(vec2(1., 1.)),
                 ..Default::default()
             },
-        )
+        );
+        gl_use_default_material();
     }
 
     pub fn draw_ui_render_to_screen(&self) {
@@ -74,6 +145,80 @@ impl Cameras {
     }
 }
 
+/// Free camera for looking around once everyone is down, before moving on to the game over screen
+#[derive(Debug)]
+pub struct Spectator {
+    pub position: Vec2,
+    started: f64,
+}
+
+impl Spectator {
+    pub fn new(position: Vec2) -> Self {
+        Self {
+            position,
+            started: get_time(),
+        }
+    }
+
+    /// Pan the camera, keeping it inside `bounds`
+    pub fn pan(&mut self, direction: Vec2, bounds: Vec2) {
+        let step = direction.normalize_or_zero() * SPECTATOR_PAN_SPEED * get_frame_time();
+        self.position = (self.position + step).clamp(Vec2::ZERO, bounds);
+    }
+
+    /// Seconds until the game over screen comes up on its own
+    pub fn time_left(&self) -> f64 {
+        (self.started + SPECTATE_TIME - get_time()).max(0.)
+    }
+}
+
+/// Load the scanline, curvature and vignette shader. Returns None if it fails to compile, in
+/// which case the world is drawn without it.
+fn load_crt_material() -> Option<Material> {
+    let material = load_material(
+        ShaderSource::Glsl {
+            vertex: include_str!("../assets/shaders/crt.vert"),
+            fragment: include_str!("../assets/shaders/crt.frag"),
+        },
+        MaterialParams {
+            uniforms: vec![("resolution".into(), UniformType::Float2)],
+            ..Default::default()
+        },
+    );
+    match material {
+        Ok(material) => {
+            material.set_uniform("resolution", SIMULATED_RESOLUTION.as_vec2());
+            Some(material)
+        }
+        Err(err) => {
+            warn!("could not load the CRT shader: {}", err);
+            None
+        }
+    }
+}
+
+/// The part of the world a camera shows, in world units
+pub fn visible_rect(camera: &Camera2D) -> Rect {
+    let half_size = (1. / camera.zoom).abs();
+    let min = camera.target - half_size;
+    Rect::new(min.x, min.y, half_size.x * 2., half_size.y * 2.)
+}
+
+/// Map a point on a `screen` sized screen to the world `view` shown on it, where the screen camera
+/// has `screen_zoom`. The view is scaled up and centered, with a border around it.
+fn unproject(point: Vec2, screen: Vec2, screen_zoom: Vec2, view: Rect) -> Vec2 {
+    let size = screen_zoom * screen / 2.;
+    let uv = (point - (screen - size) / 2.) / size;
+    view.point() + uv * view.size()
+}
+
+/// Round a world position to the nearest simulated pixel, so sprites moving at sub-pixel speeds
+/// step a whole pixel at a time instead of shimmering. Only for drawing; physics keeps the exact
+/// position.
+pub fn snap_to_pixel(position: Vec2) -> Vec2 {
+    (position * SIMULATED_TILE_PX).round() / SIMULATED_TILE_PX
+}
+
 /// Create a world camera, zoomed to a world space where 1 unit = 1 tile.
 pub fn create_world_camera() -> Camera2D {
     let render_target = render_target(SIMULATED_RESOLUTION.x, SIMULATED_RESOLUTION.y);
@@ -88,6 +233,24 @@ pub fn create_world_camera() -> Camera2D {
     }
 }
 
+/// Create a camera that fits a whole map of `size` tiles into its render target, at one texel
+/// per tileset pixel
+pub fn create_map_camera(size: UVec2) -> Camera2D {
+    let render_target = render_target(
+        size.x * SIMULATED_TILE_PX as u32,
+        size.y * SIMULATED_TILE_PX as u32,
+    );
+    render_target.texture.set_filter(FilterMode::Nearest);
+    let width = size.x as f32;
+    let height = size.y as f32;
+    Camera2D {
+        target: vec2(width / 2., height / 2.),
+        zoom: vec2(2. / width, 2. / height),
+        render_target: Some(render_target),
+        ..Default::default()
+    }
+}
+
 /// Create a UI camera, zoomed to simulated resolution
 pub fn create_ui_camera() -> Camera2D {
     let render_target = render_target(SIMULATED_RESOLUTION.x, SIMULATED_RESOLUTION.y);
@@ -102,25 +265,65 @@ pub fn create_ui_camera() -> Camera2D {
     }
 }
 
-/// Create a screen camera, which scales up and letterboxes the world camera.
-pub fn create_screen_camera() -> Camera2D {
-    let world_aspect = SIMULATED_RESOLUTION.x as f32 / SIMULATED_RESOLUTION.y as f32;
-    let screen_aspect = screen_width() / screen_height();
+/// Create a screen camera, which scales up and letterboxes the world camera. With integer
+/// scaling, the scale is rounded down to a whole number and the rest of the screen is border.
+pub fn create_screen_camera(integer_scaling: bool) -> Camera2D {
+    let resolution = SIMULATED_RESOLUTION.as_vec2();
+    let screen = vec2(screen_width(), screen_height());
 
-    // a [0.5 0.5] target with [2. 2.] zoom renders the rect [0. 0.][1. 1.]
-    let target = vec2(0.5, 0.5);
-    let mut zoom = vec2(2., 2.);
-    if screen_aspect > world_aspect {
-        // screen is wider than world aspect. decrease x zoom to compensate
-        zoom.x *= world_aspect / screen_aspect;
-    } else {
-        // screen is taller than world aspect. decrease y zoom to compensate
-        zoom.y *= screen_aspect / world_aspect;
+    // largest scale that fits the simulated screen in the real one
+    let mut scale = (screen / resolution).min_element();
+    if integer_scaling && scale >= 1. {
+        scale = scale.floor();
     }
 
+    // a [0.5 0.5] target with [2. 2.] zoom renders the rect [0. 0.][1. 1.] over the whole
+    // screen. shrink the zoom so it only covers the scaled simulated screen.
+    let target = vec2(0.5, 0.5);
+    let zoom = 2. * resolution * scale / screen;
+
     Camera2D {
         target,
         zoom,
         ..Default::default()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unproject_accounts_for_the_border() {
+        // a 100x50 view scaled up to 400x200, centered on a 600x200 screen
+        let screen = vec2(600., 200.);
+        let zoom = 2. * vec2(400., 200.) / screen;
+        let view = Rect::new(10., 20., 100., 50.);
+        assert_eq!(
+            unproject(vec2(100., 0.), screen, zoom, view),
+            vec2(10., 20.)
+        );
+        assert_eq!(
+            unproject(vec2(300., 100.), screen, zoom, view),
+            vec2(60., 45.)
+        );
+        assert_eq!(
+            unproject(vec2(500., 200.), screen, zoom, view),
+            vec2(110., 70.)
+        );
+    }
+
+    #[test]
+    fn test_snap_to_pixel_rounds_to_whole_simulated_pixels() {
+        let pixel = 1. / SIMULATED_TILE_PX;
+        assert_eq!(snap_to_pixel(vec2(2., -3.)), vec2(2., -3.));
+        assert_eq!(
+            snap_to_pixel(vec2(pixel * 0.4, pixel * 0.6)),
+            vec2(0., pixel)
+        );
+        assert_eq!(
+            snap_to_pixel(vec2(1. + pixel * 2.3, 0.)),
+            vec2(1. + pixel * 2., 0.)
+        );
+    }
+}
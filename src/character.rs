@@ -1,69 +1,185 @@
 use macroquad::{
-    audio::{play_sound_once},
-    color::WHITE,
-    input::{is_key_down, is_mouse_button_down, mouse_position_local, KeyCode, MouseButton},
-    logging::info,
+    color::{Color, BLACK, DARKGRAY, WHITE, YELLOW},
     math::{vec2, Rect, Vec2},
-    shapes::draw_circle,
-    time::{get_frame_time, get_time},
+    rand::gen_range,
+    shapes::{draw_circle, draw_circle_lines, draw_line, draw_rectangle, draw_triangle},
+    texture::{draw_texture_ex, DrawTextureParams},
+    time::get_frame_time,
 };
 use macroquad_tiled::Map as TiledMap;
 use nalgebra::{vector, Vector2};
 use rapier2d::{
+    control::KinematicCharacterController,
     dynamics::{RigidBodyBuilder, RigidBodyHandle, RigidBodySet},
-    geometry::{ColliderBuilder, ColliderHandle, ColliderSet},
-    math::Isometry,
-    pipeline::ActiveEvents,
+    geometry::{
+        ActiveCollisionTypes, Collider, ColliderBuilder, ColliderHandle, ColliderSet, SharedShape,
+    },
+    math::{Isometry, Real, Vector},
+    pipeline::{ActiveEvents, QueryFilter},
 };
 
 use crate::{
-    audio::Sounds,
+    audio::{SoundBank, SoundId},
+    camera::snap_to_pixel,
+    clock,
     constants::{
-        ALERTED_INDICATOR_COOLDOWN, ATTACK_COOLDOWN, ATTACK_DURATION, DAMAGE_COOLDOWN,
-        GRAVE_TILE_ID, GUARD_ACCELERATION, GUARD_ALERT_DISTANCE, GUARD_BRAKING, GUARD_FRICTION,
-        GUARD_FRICTION_COMBINE_RULE, GUARD_KNOCKBACK_COOLDOWN, GUARD_LINEAR_DAMPING, GUARD_MASS,
-        GUARD_MAX_HEALTH, GUARD_RADIUS, GUARD_RESTITUTION, GUARD_SPRITE_ID, HEART_TILE_ID,
-        KNOCKBACK_COOLDOWN, PLAYER_ACCELERATION, PLAYER_ATTACK_KNOCKBACK, PLAYER_ATTACK_RADIUS,
-        PLAYER_BRAKING, PLAYER_FRICTION, PLAYER_FRICTION_COMBINE_RULE, PLAYER_GUARD_KNOCKBACK,
-        PLAYER_KNOCKBACK_COOLDOWN, PLAYER_LINEAR_DAMPING, PLAYER_MASS, PLAYER_MAX_HEALTH,
-        PLAYER_RADIUS, PLAYER_RESTITUTION, PLAYER_SPRITE_ID, QUESTION_MARK_TILE_ID,
-        SIMULATED_TILE_PX, TILESET_MAP_ID,
+        ALERTED_INDICATOR_COOLDOWN, ATTACK_CHARGE_KNOCKBACK_FACTOR, ATTACK_CHARGE_RADIUS_FACTOR,
+        ATTACK_CHARGE_TIME, ATTACK_DURATION, ATTACK_NOISE_RADIUS, BOSS_CHARGE_DISTANCE,
+        BOSS_CHARGE_SPEED, BOSS_CHARGE_WINDUP, BOSS_CONTACT_DAMAGE, BOSS_DRAW_SIZE, BOSS_MASS,
+        BOSS_MAX_HEALTH, BOSS_RADIUS, BOSS_SPRITE_ID, CHARGED_ATTACK_DAMAGE, DAMAGE_FLASH_COLOR,
+        DAMAGE_FLASH_TIME, FOOTSTEP_NOISE_INTERVAL, FOOTSTEP_NOISE_RADIUS,
+        FOOTSTEP_SPEED_THRESHOLD, GRAVE_TILE_ID, GUARD_ALERT_DISTANCE, GUARD_BLOCKED_SPEED,
+        GUARD_BLOCKED_TIME, GUARD_CONTACT_DAMAGE, GUARD_DETOUR_TIME, GUARD_FRICTION,
+        GUARD_FRICTION_COMBINE_RULE, GUARD_HEALTH_BAR_FADE_TIME, GUARD_HEALTH_BAR_GAP,
+        GUARD_HEALTH_BAR_HEIGHT, GUARD_HEALTH_BAR_TIME, GUARD_HIDDEN_NOTICE_DISTANCE,
+        GUARD_INVESTIGATE_DISTANCE, GUARD_INVESTIGATE_SUSPICION, GUARD_LINEAR_DAMPING, GUARD_MASS,
+        GUARD_MAX_HEALTH, GUARD_RADIUS, GUARD_RESTITUTION, GUARD_SEPARATION_RADIUS,
+        GUARD_SEPARATION_WEIGHT, GUARD_SPRITE_ID, GUARD_SURROUND_RADIUS, GUARD_SUSPICION_DECAY,
+        GUARD_SUSPICION_RATE, GUARD_SUSPICION_SPRINT_FACTOR, HEART_TILE_ID,
+        ICE_ACCELERATION_FACTOR, ICE_BRAKING_FACTOR, ICE_DAMPING_FACTOR, JAILED_PACE_SPEED,
+        JAILED_PACE_WIDTH, PLAYER_ATTACK_RADIUS, PLAYER_FRICTION, PLAYER_FRICTION_COMBINE_RULE,
+        PLAYER_LINEAR_DAMPING, PLAYER_MASS, PLAYER_MAX_HEALTH, PLAYER_MAX_STAMINA, PLAYER_RADIUS,
+        PLAYER_RESTITUTION, PLAYER_SPRITE_ID, QUESTION_MARK_TILE_ID, REGEN_HEART_ALPHA,
+        RETICLE_TILE_ID, SIMULATED_TILE_PX, SNEAK_ACCELERATION_FACTOR, SNEAK_NOISE_FACTOR,
+        SPRINT_ACCELERATION_FACTOR, STAMINA_BAR_WIDTH, STAMINA_REGEN_RATE, STATUS_ICON_SIZE,
+        SUSPICION_METER_ALPHA, TILESET_MAP_ID,
     },
-    physics::Physics,
+    corpse::Corpse,
+    damage::{Damage, DamageKind, InvulnerabilityFrames},
+    debug,
+    difficulty::Regen,
+    entity::{EntityId, EntityKind, EntityRegistry},
+    event::Surface,
+    input::{BufferedPress, PlayerInput},
+    map::sprite_source,
+    net::CharacterState,
+    palette::CueStyle,
+    passage::PassageDirection,
+    physics::{CollisionLayer, Physics},
+    status::{StatusEffects, StatusKind},
+    steering::separation,
+    tuning::tuning,
+    upgrades::PlayerUpgrades,
 };
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug)]
 pub enum FacingDirection {
     Left,
     Right,
 }
 
+impl FacingDirection {
+    /// The way to face for a direction, or None if it is straight up or down
+    fn from_x(x: f32) -> Option<Self> {
+        if x > 0. {
+            Some(FacingDirection::Left)
+        } else if x < 0. {
+            Some(FacingDirection::Right)
+        } else {
+            None
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Character {
+    pub id: EntityId,
     pub position: Vec2,
     attack_position: Vec2,
     input_direction: Vec2,
-    facing_direction: FacingDirection,
+    /// the way the character last moved
+    move_facing: FacingDirection,
     sprite_id: u32,
     acceleration: f32,
     braking: f32,
+    /// linear damping of the body off the ice
+    linear_damping: f32,
+    /// what the character is standing on, set by the game before each update
+    pub surface: Surface,
     pub collider_handle: Option<ColliderHandle>,
     pub attack_collider_handle: Option<ColliderHandle>,
     body_handle: Option<RigidBodyHandle>,
+    /// moves the body, if it is kinematic. dynamic bodies are moved by the physics step.
+    controller: Option<KinematicCharacterController>,
     health: u32,
-    _max_health: u32,
-    accumulated_knockback: Vec2,
-    is_alerted: bool,
+    /// max health before upgrades
+    base_max_health: u32,
+    /// what the players bought in the shop. guards never have any.
+    upgrades: PlayerUpgrades,
+    /// impulse waiting to be put on the body at the next update, from knockback and charges
+    pending_impulse: Vec2,
+    /// how sure a guard is that someone is about, from 0 to 1. it goes to look past
+    /// [`GUARD_INVESTIGATE_SUSPICION`] and gives chase at 1, after which it only calms down if it
+    /// loses track of the player.
+    suspicion: f32,
     pub is_attacking: bool,
     attack_direction: Vec2,
     last_attack_start: f64,
+    /// when the held attack button went down
+    attack_charge_start: Option<f64>,
+    /// how charged the last released attack was, from 0 to 1
+    attack_charge: f32,
+    /// the attack button was down on the last input
+    attack_held: bool,
+    /// presses that came in while the attack or throw was still cooling down
+    attack_buffer: BufferedPress,
+    throw_buffer: BufferedPress,
+    /// who hit the character recently enough that they can't hit again yet
+    invulnerability: InvulnerabilityFrames,
+    /// when the character was last hurt, on the game clock
     last_damage_time: f64,
+    /// how far the next heart has grown back, from 0 to 1
+    regen_progress: f32,
     last_knockback_time: f64,
     last_alerted: f64,
     pub death_time: f64,
     pub draw_attack: bool,
-    pub sounds: Sounds,
+    pub sounds: SoundBank,
     pub knockback_cooldown: f64,
+    /// set when the player asks to throw a rock; consumed by the game
+    pub wants_throw: bool,
+    last_throw_time: f64,
+    /// set when the player presses the interact key; consumed by the game
+    pub wants_interact: bool,
+    /// the hiding spot a player is tucked away in, if any
+    hiding_spot: Option<EntityId>,
+    stunned_until: f64,
+    /// timed effects like haste and poison
+    status: StatusEffects,
+    /// poison damage taken since the game last collected it
+    pending_poison_damage: u32,
+    /// where an unalerted guard is headed to check out a noise
+    investigate_target: Option<Vec2>,
+    pub is_sneaking: bool,
+    pub is_sprinting: bool,
+    /// seconds of sprinting left
+    stamina: f32,
+    velocity: Vec2,
+    /// movement and braking acceleration applied on the last update, for the debug readout
+    acceleration_applied: Vec2,
+    footstep_noise_radius: f32,
+    last_footstep_time: f64,
+    /// loudest noise made since the game last collected it
+    pending_noise: Option<f32>,
+    /// a footstep landed since the game last collected it
+    pending_footstep: bool,
+    /// bosses can only be trapped in reinforced doors, and charge at the player
+    pub is_boss: bool,
+    /// width and height of the sprite in tiles
+    draw_size: f32,
+    /// when the current charge started winding up
+    charge_started: Option<f64>,
+    charge_direction: Vec2,
+    last_charge_time: f64,
+    /// top left of the cell door a trapped guard paces behind. None while the guard is free.
+    jail_cell: Option<Vec2>,
+    /// where an alerted guard runs to when the player is out of sight, like the exit once the
+    /// alarm goes off
+    rally_point: Option<Vec2>,
+    /// when a guard started pushing at something without getting anywhere
+    blocked_since: Option<f64>,
+    /// direction a blocked guard is steering around the obstacle in, and until when
+    detour: Option<(Vec2, f64)>,
 }
 
 impl Character {
@@ -71,35 +187,80 @@ impl Character {
         position: Vec2,
         collider_set: &mut ColliderSet,
         rigid_body_set: &mut RigidBodySet,
-        sounds: Sounds,
+        entities: &mut EntityRegistry,
+        sounds: SoundBank,
     ) -> Self {
         let (collider_handle, body_handle, attack_collider_handle) =
             T::init_physics(position, collider_set, rigid_body_set);
+        // the attack sensor is left unregistered so its overlaps aren't mistaken for body contact
+        let id = entities.spawn(T::entity_kind());
+        entities.register_collider(collider_handle, id);
+        entities.register_body(body_handle, id);
         Self {
+            id,
             position,
             attack_position: position,
             input_direction: Vec2::ZERO,
-            facing_direction: FacingDirection::Left,
+            move_facing: FacingDirection::Left,
             sprite_id: T::get_sprite_id(),
             acceleration: T::get_acceleration(),
             braking: T::get_braking(),
+            linear_damping: rigid_body_set[body_handle].linear_damping(),
+            surface: Surface::default(),
             collider_handle: Some(collider_handle),
             attack_collider_handle,
             body_handle: Some(body_handle),
+            controller: rigid_body_set[body_handle]
+                .is_kinematic()
+                .then(top_down_controller),
             health: T::get_max_health(),
-            _max_health: T::get_max_health(),
-            accumulated_knockback: Vec2::ZERO,
-            is_alerted: false,
+            base_max_health: T::get_max_health(),
+            upgrades: PlayerUpgrades::default(),
+            pending_impulse: Vec2::ZERO,
+            suspicion: 0.,
             is_attacking: false,
             attack_direction: Vec2::ZERO,
             last_attack_start: 0.,
-            last_damage_time: 0.,
+            attack_charge_start: None,
+            attack_charge: 0.,
+            attack_held: false,
+            attack_buffer: BufferedPress::default(),
+            throw_buffer: BufferedPress::default(),
+            invulnerability: InvulnerabilityFrames::default(),
+            last_damage_time: f64::NEG_INFINITY,
+            regen_progress: 0.,
             last_knockback_time: 0.,
             last_alerted: 0.,
             death_time: 0.,
             draw_attack: T::draw_attack(),
             sounds,
             knockback_cooldown: T::knockback_cooldown(),
+            wants_throw: false,
+            wants_interact: false,
+            hiding_spot: None,
+            last_throw_time: 0.,
+            stunned_until: 0.,
+            status: StatusEffects::default(),
+            pending_poison_damage: 0,
+            investigate_target: None,
+            rally_point: None,
+            blocked_since: None,
+            detour: None,
+            is_sneaking: false,
+            is_sprinting: false,
+            stamina: PLAYER_MAX_STAMINA,
+            velocity: Vec2::ZERO,
+            acceleration_applied: Vec2::ZERO,
+            footstep_noise_radius: T::footstep_noise_radius(),
+            last_footstep_time: 0.,
+            pending_noise: None,
+            pending_footstep: false,
+            is_boss: T::is_boss(),
+            draw_size: T::draw_size(),
+            charge_started: None,
+            charge_direction: Vec2::ZERO,
+            last_charge_time: 0.,
+            jail_cell: None,
         }
     }
 
@@ -107,56 +268,219 @@ impl Character {
         position: Vec2,
         collider_set: &mut ColliderSet,
         rigid_body_set: &mut RigidBodySet,
-        sounds: &Sounds,
+        entities: &mut EntityRegistry,
+        sounds: &SoundBank,
     ) -> Self {
-        Self::create::<PlayerConfigProvider>(position, collider_set, rigid_body_set, sounds.clone())
+        Self::create::<PlayerConfigProvider>(
+            position,
+            collider_set,
+            rigid_body_set,
+            entities,
+            sounds.clone(),
+        )
     }
 
     pub fn create_guard(
         position: Vec2,
         collider_set: &mut ColliderSet,
         rigid_body_set: &mut RigidBodySet,
-        sounds: &Sounds,
+        entities: &mut EntityRegistry,
+        sounds: &SoundBank,
     ) -> Self {
-        Self::create::<GuardConfigProvider>(position, collider_set, rigid_body_set, sounds.clone())
+        Self::create::<GuardConfigProvider>(
+            position,
+            collider_set,
+            rigid_body_set,
+            entities,
+            sounds.clone(),
+        )
     }
 
-    pub fn collect_player_inputs(&mut self) {
-        self.input_direction = Vec2::ZERO;
-        if is_key_down(KeyCode::W) {
-            self.input_direction += vec2(0., -1.);
+    pub fn create_boss(
+        position: Vec2,
+        collider_set: &mut ColliderSet,
+        rigid_body_set: &mut RigidBodySet,
+        entities: &mut EntityRegistry,
+        sounds: &SoundBank,
+    ) -> Self {
+        Self::create::<BossConfigProvider>(
+            position,
+            collider_set,
+            rigid_body_set,
+            entities,
+            sounds.clone(),
+        )
+    }
+
+    pub fn apply_input(&mut self, input: &PlayerInput) {
+        if self.hiding_spot.is_some() {
+            // keep still and quiet until climbing back out
+            self.input_direction = Vec2::ZERO;
+            self.is_sneaking = false;
+            self.is_sprinting = false;
+            self.attack_charge_start = None;
+            self.wants_interact |= input.interact;
+            return;
         }
-        if is_key_down(KeyCode::S) {
-            self.input_direction += vec2(0., 1.);
+        self.input_direction = input.move_direction.normalize_or_zero();
+        self.is_sneaking = input.sneak;
+        self.is_sprinting = input.sprint && !self.is_sneaking && self.stamina > 0.;
+
+        // hold to charge the attack, release to swing. a tap during the cooldown is buffered,
+        // and swings uncharged once the cooldown is up.
+        let now = clock::now();
+        if input.attack && !self.attack_held {
+            self.attack_buffer.press(now);
         }
-        if is_key_down(KeyCode::A) {
-            self.input_direction += vec2(-1., 0.);
+        self.attack_held = input.attack;
+        let can_attack = self.attack_charge_start.is_none()
+            && now
+                > self.last_attack_start + self.upgrades.attack_cooldown(tuning().attack_cooldown);
+        let buffered = can_attack && self.attack_buffer.take(now);
+        if can_attack && (input.attack || buffered) {
+            self.attack_charge_start = Some(now);
         }
-        if is_key_down(KeyCode::D) {
-            self.input_direction += vec2(1., 0.);
+        if !input.attack && self.attack_charge_start.is_some() {
+            self.attack_charge = self.current_attack_charge();
+            self.attack_charge_start = None;
+            self.sounds.play(SoundId::ATTACK);
+            self.make_noise(ATTACK_NOISE_RADIUS);
+            self.is_attacking = true;
+            self.last_attack_start = clock::now();
         }
+        self.attack_direction = input.aim.normalize_or_zero();
 
-        if is_mouse_button_down(MouseButton::Left)
-            && get_time() > self.last_attack_start + ATTACK_COOLDOWN
-        {
-            if !self.is_attacking {
-                play_sound_once(&self.sounds.attack);
-            }
-            self.is_attacking = true;
-            self.last_attack_start = get_time();
+        if input.throw {
+            self.throw_buffer.press(now);
+        }
+        if now > self.last_throw_time + tuning().throw_cooldown && self.throw_buffer.take(now) {
+            self.wants_throw = true;
+            self.last_throw_time = now;
         }
-        self.attack_direction = mouse_position_local().normalize_or_zero();
+        if input.interact {
+            self.wants_interact = true;
+        }
+    }
 
-        self.input_direction = self.input_direction.normalize_or_zero();
+    /// Steer a guard for this frame. `neighbors` are the centers of the guards around it, which an
+    /// alerted guard keeps its distance from. `slot` is where its squad wants it around the player,
+    /// which it makes for until it is close enough to close in.
+    pub fn collect_guard_inputs(
+        &mut self,
+        player: &Character,
+        neighbors: &[Vec2],
+        slot: Option<Vec2>,
+    ) {
+        self.input_direction = Vec2::ZERO;
+        if !player.is_alive() {
+            return;
+        }
+
+        if self.is_alerted() {
+            if self.is_boss && self.update_charge(player) {
+                return;
+            }
+            let target = match self.rally_point {
+                Some(rally_point)
+                    if self.position.distance(player.position) > GUARD_ALERT_DISTANCE =>
+                {
+                    rally_point
+                }
+                _ => match slot {
+                    Some(slot)
+                        if self.position.distance(player.position) > GUARD_SURROUND_RADIUS =>
+                    {
+                        slot
+                    }
+                    _ => player.position,
+                },
+            };
+            let push = separation(self.center(), neighbors, GUARD_SEPARATION_RADIUS);
+            self.input_direction = ((target - self.position).normalize_or_zero()
+                + push * GUARD_SEPARATION_WEIGHT)
+                .normalize_or_zero();
+        } else if let Some(target) = self.investigate_target {
+            if self.center().distance(target) < GUARD_INVESTIGATE_DISTANCE {
+                self.investigate_target = None;
+            } else {
+                self.input_direction = (target - self.center()).normalize_or_zero();
+            }
+        }
+        self.avoid_obstacles();
     }
 
-    pub fn collect_guard_inputs(&mut self, player: &Character) {
-        if !self.is_alerted || !player.is_alive() {
-            self.input_direction = Vec2::ZERO;
+    /// Steer around whatever a guard is stuck against, like a crate shoved into its way, instead
+    /// of pushing at it forever
+    fn avoid_obstacles(&mut self) {
+        let now = clock::now();
+        if let Some((direction, until)) = self.detour {
+            if now < until && self.input_direction != Vec2::ZERO {
+                self.input_direction = direction;
+                return;
+            }
+            self.detour = None;
+        }
+        if self.input_direction == Vec2::ZERO || self.velocity.length() > GUARD_BLOCKED_SPEED {
+            self.blocked_since = None;
             return;
         }
+        let blocked_since = *self.blocked_since.get_or_insert(now);
+        if now > blocked_since + GUARD_BLOCKED_TIME {
+            self.blocked_since = None;
+            // slide along the obstacle to one side or the other
+            let side = if gen_range(0, 2) == 0 { 1. } else { -1. };
+            let direction = self.input_direction.perp() * side;
+            self.detour = Some((direction, now + GUARD_DETOUR_TIME));
+            self.input_direction = direction;
+        }
+    }
+
+    /// Wind up a charge when the player gets close, then lunge at where they were standing.
+    /// Returns true while winding up, when the boss should hold still.
+    fn update_charge(&mut self, player: &Character) -> bool {
+        let now = clock::now();
+        if let Some(charge_started) = self.charge_started {
+            if now < charge_started + BOSS_CHARGE_WINDUP {
+                return true;
+            }
+            self.charge_started = None;
+            self.last_charge_time = now;
+            if !self.is_stunned() {
+                self.pending_impulse += self.charge_direction * BOSS_CHARGE_SPEED * BOSS_MASS;
+            }
+            return false;
+        }
+
+        if now > self.last_charge_time + tuning().boss_charge_cooldown
+            && !self.is_stunned()
+            && self.center().distance(player.center()) < BOSS_CHARGE_DISTANCE
+        {
+            self.charge_started = Some(now);
+            self.charge_direction = (player.center() - self.center()).normalize_or_zero();
+            return true;
+        }
+        false
+    }
 
-        self.input_direction = (player.position - self.position).normalize_or_zero();
+    pub fn is_winding_up_charge(&self) -> bool {
+        self.charge_started.is_some()
+    }
+
+    /// Short description of what a guard is up to, for the debug overlay
+    pub fn ai_state(&self) -> &'static str {
+        if !self.is_alive() {
+            "dead"
+        } else if self.is_stunned() {
+            "stunned"
+        } else if self.is_winding_up_charge() {
+            "winding up"
+        } else if self.is_alerted() {
+            "chasing"
+        } else if self.investigate_target.is_some() {
+            "investigating"
+        } else {
+            "idle"
+        }
     }
 
     pub fn update(&mut self, physics: &mut Physics) {
@@ -168,16 +492,28 @@ impl Character {
             return;
         }
 
+        let poison_damage = self.status.tick(clock::now()).min(self.health);
+        if poison_damage > 0 {
+            self.health -= poison_damage;
+            self.pending_poison_damage += poison_damage;
+            if !self.is_alive() {
+                self.death_time = clock::now();
+                return;
+            }
+        }
+
         // timeout attack
-        if self.is_attacking && get_time() > self.last_attack_start + ATTACK_DURATION {
+        if self.is_attacking && clock::now() > self.last_attack_start + ATTACK_DURATION {
             self.is_attacking = false;
         }
 
         // set the attack collider position. attack collider is always centered around the player radius in the attack direction.
         if let Some(attack_collider_handle) = self.attack_collider_handle {
             let attack_collider = &mut physics.colliders[attack_collider_handle];
+            let attack_radius = self.attack_radius();
+            attack_collider.set_shape(SharedShape::ball(attack_radius));
             let attack_direction = vector![self.attack_direction.x, self.attack_direction.y]
-                * (PLAYER_ATTACK_RADIUS - PLAYER_RADIUS);
+                * (attack_radius - PLAYER_RADIUS);
             attack_collider.set_position_wrt_parent(Isometry::translation(
                 attack_direction.x,
                 attack_direction.y,
@@ -186,33 +522,119 @@ impl Character {
 
         // move the player
         let body = &mut physics.bodies[self.body_handle.unwrap()];
+        let (grip, braking, damping) = if self.surface == Surface::Ice {
+            (
+                ICE_ACCELERATION_FACTOR,
+                self.braking * ICE_BRAKING_FACTOR,
+                self.linear_damping * ICE_DAMPING_FACTOR,
+            )
+        } else {
+            (1., self.braking, self.linear_damping)
+        };
+        body.set_linear_damping(damping);
 
-        let (move_acc, braking_acc) = if self.is_knockback_stunned() {
+        let (move_acc, braking_acc) = if self.is_stunned() {
             (Vector2::zeros(), Vector2::zeros())
         } else {
-            let move_acc = self.input_direction * self.acceleration;
+            let mut acceleration = if self.is_sneaking {
+                self.acceleration * SNEAK_ACCELERATION_FACTOR
+            } else if self.is_sprinting {
+                self.acceleration * SPRINT_ACCELERATION_FACTOR
+            } else {
+                self.acceleration
+            };
+            acceleration *= self.status.acceleration_factor(clock::now()) * grip;
+            let move_acc = self.input_direction * acceleration;
             let move_acc = vector![move_acc.x, move_acc.y];
 
             let vel_dir = vec2(body.linvel().x, body.linvel().y).normalize_or_zero();
             let braking_acc =
-                (self.input_direction - vel_dir) * body.linvel().magnitude() * self.braking;
+                (self.input_direction - vel_dir) * body.linvel().magnitude() * braking;
             let braking_acc = vector![braking_acc.x, braking_acc.y];
 
             (move_acc, braking_acc)
         };
 
-        let knockback = vector![self.accumulated_knockback.x, self.accumulated_knockback.y];
-        self.accumulated_knockback = Vec2::ZERO;
+        let impulse = std::mem::take(&mut self.pending_impulse);
+        let impulse = vector![impulse.x, impulse.y];
+        let linvel = if self.controller.is_some() {
+            // impulses don't move kinematic bodies, so turn it into a change in speed by hand
+            let mass = physics.colliders[self.collider_handle.unwrap()].mass();
+            body.linvel() + impulse / mass
+        } else {
+            body.apply_impulse(impulse, true);
+            *body.linvel()
+        };
+
+        let applied = move_acc + braking_acc;
+        self.acceleration_applied = vec2(applied.x, applied.y);
 
         let dt = get_frame_time();
-        let new_linvel = body.linvel() + move_acc * dt + braking_acc * dt + knockback;
-        body.set_linvel(new_linvel, true);
+        self.update_stamina(dt);
+        let new_linvel = linvel + move_acc * dt + braking_acc * dt;
+        if self.controller.is_some() {
+            // nothing damps a kinematic body, so slow it the same way the physics step would
+            let new_linvel = new_linvel / (1. + dt * damping);
+            self.move_kinematic(new_linvel * dt, dt, physics);
+        } else {
+            body.set_linvel(new_linvel, true);
+        }
+
+        // footsteps
+        if self.velocity.length() > FOOTSTEP_SPEED_THRESHOLD
+            && clock::now() > self.last_footstep_time + FOOTSTEP_NOISE_INTERVAL
+        {
+            self.last_footstep_time = clock::now();
+            self.pending_footstep = true;
+            self.make_noise(self.footstep_noise_radius);
+        }
 
         // latch facing direction on nonzero input direction
-        if self.input_direction.x > 0. {
-            self.facing_direction = FacingDirection::Left;
-        } else if self.input_direction.x < 0. {
-            self.facing_direction = FacingDirection::Right;
+        if let Some(facing) = FacingDirection::from_x(self.input_direction.x) {
+            self.move_facing = facing;
+        }
+    }
+
+    /// Move a kinematic body as far towards `desired` as it can go, sliding along whatever it runs
+    /// into. Guards and crates are left for the body to push out of the way.
+    fn move_kinematic(&mut self, desired: Vector<Real>, dt: f32, physics: &mut Physics) {
+        let (Some(controller), Some(body_handle), Some(collider_handle)) =
+            (&self.controller, self.body_handle, self.collider_handle)
+        else {
+            return;
+        };
+        // one-way passages only stand in the way of going against them
+        let blocks = |_, collider: &Collider| {
+            PassageDirection::from_user_data(collider.user_data)
+                .is_none_or(|direction| direction.vector().dot(vec2(desired.x, desired.y)) < 0.)
+        };
+        let filter = QueryFilter::exclude_dynamic()
+            .groups(CollisionLayer::Player.groups())
+            .exclude_sensors()
+            .exclude_rigid_body(body_handle)
+            .predicate(&blocks);
+        let collider = &physics.colliders[collider_handle];
+        let movement = controller.move_shape(
+            dt,
+            &physics.bodies,
+            &physics.colliders,
+            &physics.query_pipeline,
+            collider.shape(),
+            collider.position(),
+            desired,
+            filter,
+            |_| {},
+        );
+        let body = &mut physics.bodies[body_handle];
+        body.set_next_kinematic_translation(body.translation() + movement.translation);
+    }
+
+    /// Sprinting drains stamina, anything else lets it recover
+    fn update_stamina(&mut self, dt: f32) {
+        if self.is_sprinting && self.input_direction != Vec2::ZERO {
+            self.stamina = (self.stamina - dt).max(0.);
+        } else {
+            self.stamina = (self.stamina + dt * STAMINA_REGEN_RATE).min(PLAYER_MAX_STAMINA);
         }
     }
 
@@ -222,10 +644,10 @@ impl Character {
         }
 
         let body = &physics.bodies[self.body_handle.unwrap()];
-        // TODO(axelmagn): snap to simulated pixel
         // mq -> nalgebra conversion
         self.position.x = body.translation().x - 0.5;
         self.position.y = body.translation().y - 0.5;
+        self.velocity = vec2(body.linvel().x, body.linvel().y);
 
         if let Some(attack_collider_handle) = self.attack_collider_handle {
             let attack_collider = &physics.colliders[attack_collider_handle];
@@ -234,29 +656,75 @@ impl Character {
         }
     }
 
-    pub fn draw(&self, tile_map: &TiledMap) {
+    pub fn draw(&self, tile_map: &TiledMap, style: &CueStyle) {
+        // whatever they are hiding in is drawn instead
+        if self.hiding_spot.is_some() {
+            return;
+        }
+
         // draw attack
+        let attack_position = snap_to_pixel(self.attack_position);
         if self.draw_attack && self.is_alive() {
             if self.is_attacking {
                 draw_circle(
-                    self.attack_position.x,
-                    self.attack_position.y,
-                    PLAYER_ATTACK_RADIUS,
+                    attack_position.x,
+                    attack_position.y,
+                    self.attack_radius(),
                     WHITE,
                 )
-            } else {
-                let draw_rect = Rect::new(
-                    self.attack_position.x - 0.5,
-                    self.attack_position.y - 0.5,
-                    1.,
-                    1.,
-                );
-                tile_map.spr(
-                    TILESET_MAP_ID,
-                    60, /* todo: move to constant */
-                    draw_rect,
+            } else if self.attack_charge_start.is_some() {
+                // ring grows with the charge, and goes solid once it is full
+                let thickness = if self.current_attack_charge() < 1. {
+                    0.05
+                } else {
+                    0.15
+                };
+                draw_circle_lines(
+                    attack_position.x,
+                    attack_position.y,
+                    self.attack_radius(),
+                    thickness,
+                    WHITE,
                 );
             }
+            // the reticle always shows where the next shove will land
+            let draw_rect = Rect::new(attack_position.x - 0.5, attack_position.y - 0.5, 1., 1.);
+            tile_map.spr(TILESET_MAP_ID, RETICLE_TILE_ID, draw_rect);
+        }
+
+        // telegraph a charge with the lane it is about to take
+        if self.is_winding_up_charge() && self.is_alive() {
+            let center = self.center();
+            let end = center + self.charge_direction * BOSS_CHARGE_DISTANCE;
+            let alert = style.palette.alert;
+            draw_line(
+                center.x,
+                center.y,
+                end.x,
+                end.y,
+                self.draw_size,
+                Color { a: 0.3, ..alert },
+            );
+            if style.shape_cues {
+                // chevrons down the lane, pointing the way it will go
+                for i in 1..=3 {
+                    let tip = center + self.charge_direction * BOSS_CHARGE_DISTANCE * i as f32 / 4.;
+                    draw_chevron(tip, self.charge_direction, 0.5, alert);
+                }
+            }
+        }
+
+        if style.high_contrast && self.is_alive() {
+            // only the player draws its attack
+            let outline = if self.draw_attack {
+                style.palette.player_outline
+            } else {
+                style.palette.guard_outline
+            };
+            let center = snap_to_pixel(self.center());
+            let radius = self.draw_size / 2.;
+            draw_circle_lines(center.x, center.y, radius, 0.2, BLACK);
+            draw_circle_lines(center.x, center.y, radius, 0.1, outline);
         }
 
         // draw player
@@ -267,14 +735,107 @@ impl Character {
             GRAVE_TILE_ID
         };
         tile_map.spr(TILESET_MAP_ID, sprite_id, draw_rect);
-        if self.is_alerted && get_time() < self.last_alerted + ALERTED_INDICATOR_COOLDOWN {
+        // a hurt player flashes red for a moment
+        if self.draw_attack
+            && self.is_alive()
+            && clock::now() < self.last_damage_time + DAMAGE_FLASH_TIME
+        {
+            let tileset = &tile_map.tilesets[TILESET_MAP_ID];
+            draw_texture_ex(
+                &tileset.texture,
+                draw_rect.x,
+                draw_rect.y,
+                DAMAGE_FLASH_COLOR,
+                DrawTextureParams {
+                    dest_size: Some(draw_rect.size()),
+                    source: Some(sprite_source(tileset, sprite_id)),
+                    ..Default::default()
+                },
+            );
+        }
+        if !self.draw_attack && self.is_alive() {
+            self.draw_health_bar(draw_rect, style);
+        }
+        if self.is_alerted() && clock::now() < self.last_alerted + ALERTED_INDICATOR_COOLDOWN {
             draw_rect.y -= 1.;
             tile_map.spr(TILESET_MAP_ID, QUESTION_MARK_TILE_ID, draw_rect);
+        } else if self.suspicion > 0. && !self.is_alerted() && self.is_alive() {
+            draw_rect.y -= 1.;
+            self.draw_suspicion(tile_map, draw_rect);
+        } else if style.shape_cues && self.is_alerted() && self.is_alive() {
+            // a warning triangle for as long as the guard gives chase
+            let top = vec2(self.center().x, draw_rect.y - 0.6);
+            draw_chevron(top, vec2(0., -1.), 0.6, style.palette.alert);
+        }
+    }
+
+    /// Draw a guard's health in segments just over its head, for a few seconds after it is hurt
+    fn draw_health_bar(&self, draw_rect: Rect, style: &CueStyle) {
+        let shown_for = clock::now() - self.last_damage_time;
+        let alpha =
+            ((GUARD_HEALTH_BAR_TIME - shown_for) / GUARD_HEALTH_BAR_FADE_TIME).clamp(0., 1.) as f32;
+        if alpha <= 0. {
+            return;
+        }
+        let max_health = self.max_health().max(1);
+        let y = draw_rect.y - GUARD_HEALTH_BAR_HEIGHT - GUARD_HEALTH_BAR_GAP;
+        draw_rectangle(
+            draw_rect.x - GUARD_HEALTH_BAR_GAP,
+            y - GUARD_HEALTH_BAR_GAP,
+            draw_rect.w + GUARD_HEALTH_BAR_GAP * 2.,
+            GUARD_HEALTH_BAR_HEIGHT + GUARD_HEALTH_BAR_GAP * 2.,
+            Color { a: alpha, ..BLACK },
+        );
+        let segment_width =
+            (draw_rect.w - GUARD_HEALTH_BAR_GAP * (max_health - 1) as f32) / max_health as f32;
+        for i in 0..max_health {
+            let color = if i < self.health {
+                style.palette.alert
+            } else {
+                DARKGRAY
+            };
+            draw_rectangle(
+                draw_rect.x + (segment_width + GUARD_HEALTH_BAR_GAP) * i as f32,
+                y,
+                segment_width,
+                GUARD_HEALTH_BAR_HEIGHT,
+                Color { a: alpha, ..color },
+            );
+        }
+    }
+
+    /// Draw the question mark filling up from the bottom as the guard grows suspicious
+    fn draw_suspicion(&self, tile_map: &TiledMap, draw_rect: Rect) {
+        let tileset = &tile_map.tilesets[TILESET_MAP_ID];
+        let full_source = sprite_source(tileset, QUESTION_MARK_TILE_ID);
+        let empty = 1. - self.suspicion;
+        let mut filled_source = full_source;
+        filled_source.y += full_source.h * empty;
+        filled_source.h *= self.suspicion;
+        for (source, y, color) in [
+            (
+                full_source,
+                draw_rect.y,
+                Color::new(1., 1., 1., SUSPICION_METER_ALPHA),
+            ),
+            (filled_source, draw_rect.y + draw_rect.h * empty, WHITE),
+        ] {
+            draw_texture_ex(
+                &tileset.texture,
+                draw_rect.x,
+                y,
+                color,
+                DrawTextureParams {
+                    dest_size: Some(vec2(draw_rect.w, draw_rect.h * source.h / full_source.h)),
+                    source: Some(source),
+                    ..Default::default()
+                },
+            );
         }
     }
 
-    pub fn draw_ui(&self, tile_map: &TiledMap) {
-        let origin = vec2(16., 16.);
+    /// Draw hearts and the stamina bar with their top left corner at `origin`
+    pub fn draw_ui(&self, tile_map: &TiledMap, origin: Vec2) {
         for i in 0..self.health {
             let padding = -1.;
             let offset_x = (SIMULATED_TILE_PX * 2. + padding) * i as f32;
@@ -286,87 +847,486 @@ impl Character {
             );
             tile_map.spr(TILESET_MAP_ID, HEART_TILE_ID, draw_rect);
         }
+
+        // the heart growing back, filled in from the left
+        if self.regen_progress > 0. {
+            let tileset = &tile_map.tilesets[TILESET_MAP_ID];
+            let mut source = sprite_source(tileset, HEART_TILE_ID);
+            source.w *= self.regen_progress;
+            let offset_x = (SIMULATED_TILE_PX * 2. - 1.) * self.health as f32;
+            draw_texture_ex(
+                &tileset.texture,
+                origin.x + offset_x,
+                origin.y,
+                Color::new(1., 1., 1., REGEN_HEART_ALPHA),
+                DrawTextureParams {
+                    dest_size: Some(vec2(
+                        SIMULATED_TILE_PX * 2. * self.regen_progress,
+                        SIMULATED_TILE_PX * 2.,
+                    )),
+                    source: Some(source),
+                    ..Default::default()
+                },
+            );
+        }
+
+        // stamina bar, just under the hearts
+        let bar = Rect::new(
+            origin.x,
+            origin.y + SIMULATED_TILE_PX * 2. + 4.,
+            STAMINA_BAR_WIDTH,
+            8.,
+        );
+        draw_rectangle(bar.x, bar.y, bar.w, bar.h, DARKGRAY);
+        draw_rectangle(
+            bar.x,
+            bar.y,
+            bar.w * self.stamina / PLAYER_MAX_STAMINA,
+            bar.h,
+            YELLOW,
+        );
+
+        // status effect icons, under the stamina bar
+        for (i, kind) in self.status.active(clock::now()).enumerate() {
+            let draw_rect = Rect::new(
+                bar.x + (STATUS_ICON_SIZE + 4.) * i as f32,
+                bar.y + bar.h + 4.,
+                STATUS_ICON_SIZE,
+                STATUS_ICON_SIZE,
+            );
+            tile_map.spr(TILESET_MAP_ID, kind.sprite_id(), draw_rect);
+        }
+    }
+
+    /// Draw position, velocity and applied acceleration readouts below the character
+    pub fn draw_debug(&self) {
+        let rect = self.get_draw_rect();
+        let readouts = [
+            ("pos", self.position),
+            ("vel", self.velocity),
+            ("acc", self.acceleration_applied),
+        ];
+        for (i, (name, value)) in readouts.iter().enumerate() {
+            debug::draw_label(
+                &format!("{} {:.1}, {:.1}", name, value.x, value.y),
+                vec2(
+                    rect.x,
+                    rect.bottom() + (i + 1) as f32 * debug::LABEL_LINE_HEIGHT,
+                ),
+                WHITE,
+            );
+        }
+    }
+
+    /// Which way the sprite faces. Players face where they aim, so they can shove backwards
+    /// while walking away. Everyone else faces the way they last moved.
+    fn facing(&self) -> FacingDirection {
+        if !self.draw_attack {
+            return self.move_facing;
+        }
+        FacingDirection::from_x(self.attack_direction.x).unwrap_or(self.move_facing)
     }
 
     pub fn get_draw_rect(&self) -> Rect {
-        match self.facing_direction {
+        // sprites grow out from the center of the body
+        let offset = (self.draw_size - 1.) / 2.;
+        let position = snap_to_pixel(self.position);
+        let x = position.x - offset;
+        let y = position.y - offset;
+        match self.facing() {
             FacingDirection::Left => Rect {
-                x: self.position.x,
-                y: self.position.y,
-                w: 1.,
-                h: 1.,
+                x,
+                y,
+                w: self.draw_size,
+                h: self.draw_size,
             },
             FacingDirection::Right => Rect {
-                x: self.position.x + 1.,
-                y: self.position.y,
-                w: -1.,
-                h: 1.,
+                x: x + self.draw_size,
+                y,
+                w: -self.draw_size,
+                h: self.draw_size,
             },
         }
     }
 
     pub fn is_knockback_stunned(&self) -> bool {
-        get_time() < self.last_knockback_time + self.knockback_cooldown
+        clock::now() < self.last_knockback_time + self.knockback_cooldown
+    }
+
+    /// Stunned characters can't steer or brake
+    pub fn is_stunned(&self) -> bool {
+        self.is_knockback_stunned() || clock::now() < self.stunned_until
+    }
+
+    pub fn stun(&mut self, duration: f64) {
+        self.stunned_until = self.stunned_until.max(clock::now() + duration);
+    }
+
+    /// Make a noise centered on this character. Sneaking muffles it.
+    pub fn make_noise(&mut self, radius: f32) {
+        let radius = if self.is_sneaking {
+            radius * SNEAK_NOISE_FACTOR
+        } else {
+            radius
+        };
+        if radius <= 0. {
+            return;
+        }
+        self.pending_noise = Some(self.pending_noise.unwrap_or(0.).max(radius));
+    }
+
+    /// Collect the radius of the loudest noise made since the last call
+    pub fn take_noise(&mut self) -> Option<f32> {
+        self.pending_noise.take()
+    }
+
+    /// Check whether a footstep landed since the last call
+    pub fn take_footstep(&mut self) -> bool {
+        std::mem::take(&mut self.pending_footstep)
+    }
+
+    /// Send an unalerted guard to check out a position
+    pub fn investigate(&mut self, target: Vec2) {
+        if self.is_alerted() {
+            return;
+        }
+        self.investigate_target = Some(target);
+    }
+
+    /// React to a startling noise, like a door slamming. A guard already checking out a noise
+    /// takes it as proof and gives chase, and anyone else comes to look.
+    pub fn hear_alarm(&mut self, target: Vec2) {
+        if self.investigate_target.is_some() {
+            self.investigate_target = None;
+            self.alert_guard();
+        } else {
+            self.investigate(target);
+        }
     }
 
     pub fn is_alive(&self) -> bool {
         self.health > 0
     }
 
-    pub fn can_damage(&self) -> bool {
-        get_time() > self.last_damage_time + DAMAGE_COOLDOWN
+    pub fn health(&self) -> u32 {
+        self.health
     }
 
-    pub fn can_knockback(&self) -> bool {
-        get_time() > self.last_knockback_time + KNOCKBACK_COOLDOWN
+    pub fn is_alerted(&self) -> bool {
+        self.suspicion >= 1.
+    }
+
+    /// Overwrite health outright, like when restoring a saved run
+    pub fn set_health(&mut self, health: u32) {
+        self.health = health.min(self.max_health());
     }
 
-    pub fn handle_player_guard_collision(&mut self, guard: &Character) {
-        info!("PLAYER HIT");
-        self.deal_damage(1);
+    pub fn max_health(&self) -> u32 {
+        self.upgrades.max_health(self.base_max_health)
+    }
+
+    /// Take on upgrades from the shop. Any hearts they add come already filled.
+    pub fn set_upgrades(&mut self, upgrades: PlayerUpgrades) {
+        let old_max_health = self.max_health();
+        self.upgrades = upgrades;
+        self.heal(self.max_health().saturating_sub(old_max_health));
+        self.health = self.health.min(self.max_health());
+    }
 
-        let knockback_dir = (self.position - guard.position).normalize_or_zero();
-        let knockback = knockback_dir * PLAYER_GUARD_KNOCKBACK;
-        self.apply_knockback(knockback);
+    /// What a co-op partner needs to draw this character
+    pub fn net_state(&self) -> CharacterState {
+        CharacterState {
+            id: self.id.to_bits(),
+            position: self.position.to_array(),
+            health: self.health,
+            facing_left: matches!(self.facing(), FacingDirection::Left),
+            suspicion: self.suspicion,
+            is_attacking: self.is_attacking,
+            hiding_spot: self.hiding_spot.map(EntityId::to_bits),
+        }
     }
 
-    pub fn deal_damage(&mut self, amount: u32) {
-        if !self.can_damage() || !self.is_alive() {
+    /// Take on the state the host sent, without simulating anything
+    pub fn apply_net_state(&mut self, state: &CharacterState) {
+        self.position = Vec2::from_array(state.position);
+        self.attack_position = self.position;
+        if self.is_alive() && state.health == 0 {
+            self.death_time = clock::now();
+        }
+        self.set_health(state.health);
+        self.move_facing = if state.facing_left {
+            FacingDirection::Left
+        } else {
+            FacingDirection::Right
+        };
+        if state.suspicion >= 1. && !self.is_alerted() {
+            self.last_alerted = clock::now();
+        }
+        self.suspicion = state.suspicion;
+        self.is_attacking = state.is_attacking;
+        self.hiding_spot = state.hiding_spot.map(EntityId::from_bits);
+    }
+
+    /// Take all the character's health at once, however recently it was last hurt
+    pub fn kill(&mut self) {
+        if !self.is_alive() {
             return;
         }
-        self.health -= amount.min(self.health);
-        self.last_damage_time = get_time();
+        self.health = 0;
+        self.death_time = clock::now();
+    }
 
+    pub fn heal(&mut self, amount: u32) {
         if !self.is_alive() {
-            self.death_time = get_time();
+            return;
+        }
+        self.health = (self.health + amount).min(self.max_health());
+        if self.health == self.max_health() {
+            self.regen_progress = 0.;
         }
     }
 
-    pub fn apply_knockback(&mut self, delta_velocity: Vec2) {
-        if !self.can_knockback() {
+    /// Put a timed effect on the character, or keep one it already has going for longer
+    pub fn add_status(&mut self, kind: StatusKind, duration: f64) {
+        if !self.is_alive() {
             return;
         }
+        self.status.add(kind, clock::now(), duration);
+    }
+
+    /// Poison damage taken since this was last called
+    pub fn take_poison_damage(&mut self) -> u32 {
+        std::mem::take(&mut self.pending_poison_damage)
+    }
+
+    /// Knockback can't land again until the stun from the last one wears off
+    pub fn can_knockback(&self) -> bool {
+        !self.is_knockback_stunned()
+    }
+
+    /// The blow this guard deals a player at `position` that bumps into it
+    pub fn contact_damage(&self, position: Vec2) -> Damage {
+        let knockback_dir = (position - self.position).normalize_or_zero();
+        let (amount, kind) = if self.is_boss {
+            (BOSS_CONTACT_DAMAGE, DamageKind::Boss)
+        } else {
+            (GUARD_CONTACT_DAMAGE, DamageKind::Guard)
+        };
+        Damage {
+            source: Some(self.id),
+            amount,
+            kind,
+            knockback: knockback_dir * tuning().player_guard_knockback,
+        }
+    }
 
-        self.accumulated_knockback += delta_velocity;
-        self.last_knockback_time = get_time();
-        play_sound_once(&self.sounds.knockback);
+    /// Knock the character back and hurt it, unless the source hit it too recently. Returns the
+    /// health it lost.
+    pub fn take_damage(&mut self, damage: &Damage) -> u32 {
+        if !self.is_alive() {
+            return 0;
+        }
+        if damage.knockback != Vec2::ZERO {
+            self.apply_knockback(damage.knockback);
+        }
+
+        let now = clock::now();
+        let amount = self
+            .status
+            .damage_taken(damage.amount, now)
+            .min(self.health);
+        if amount == 0 {
+            return 0;
+        }
+        if let Some(source) = damage.source {
+            if !self
+                .invulnerability
+                .try_hit(source, now, tuning().damage_cooldown)
+            {
+                return 0;
+            }
+        }
+        self.health -= amount;
+        self.last_damage_time = now;
+        self.regen_progress = 0.;
+
+        if !self.is_alive() {
+            self.death_time = now;
+        }
+        amount
     }
 
-    pub fn check_guard_distance(&mut self, player: &Character) {
-        if self.position.distance_squared(player.position)
-            < GUARD_ALERT_DISTANCE * GUARD_ALERT_DISTANCE
+    /// Grow health back a little at a time, once the character hasn't been hurt for a while and
+    /// `calm_since` is long enough ago
+    pub fn regenerate(&mut self, regen: Regen, calm_since: f64, dt: f32) {
+        let now = clock::now();
+        if !self.is_alive()
+            || self.health >= self.max_health()
+            || now < self.last_damage_time.max(calm_since) + regen.delay
         {
-            self.alert_guard();
+            return;
+        }
+        self.regen_progress += regen.rate * dt;
+        if self.regen_progress >= 1. {
+            self.regen_progress -= 1.;
+            self.heal(1);
+        }
+    }
+
+    /// Push the character with an impulse, so heavier characters are moved less by the same blow.
+    /// Returns false if the character was knocked back too recently to be knocked again.
+    pub fn apply_knockback(&mut self, impulse: Vec2) -> bool {
+        let factor = self.status.knockback_factor(clock::now());
+        if factor == 0. || !self.can_knockback() {
+            return false;
+        }
+
+        self.pending_impulse += impulse * factor;
+        self.last_knockback_time = clock::now();
+        self.sounds.play(SoundId::KNOCKBACK);
+        true
+    }
+
+    /// Grow suspicious of a player the guard can see, faster the closer they are and faster
+    /// again if they are sprinting. A suspicious guard goes to look where the player is, and a
+    /// full meter sets it chasing. Returns true if the guard was newly alerted.
+    pub fn notice(&mut self, player: &Character, dt: f32) -> bool {
+        let distance = self.position.distance(player.position);
+        let range = if player.is_hidden() {
+            GUARD_HIDDEN_NOTICE_DISTANCE
+        } else {
+            GUARD_ALERT_DISTANCE
+        };
+        if self.is_alerted() || distance >= range {
+            return false;
+        }
+        let mut rate = GUARD_SUSPICION_RATE * (2. - distance / GUARD_ALERT_DISTANCE);
+        if player.is_sprinting {
+            rate *= GUARD_SUSPICION_SPRINT_FACTOR;
+        }
+        // outpace calming down, which happens whether or not anyone is in sight
+        let suspicion = self.suspicion + (rate + GUARD_SUSPICION_DECAY) * dt;
+        if suspicion >= 1. {
+            return self.alert_guard();
+        }
+        self.suspicion = suspicion;
+        if self.suspicion >= GUARD_INVESTIGATE_SUSPICION {
+            self.investigate_target = Some(player.center());
+        }
+        false
+    }
+
+    /// Give up the chase after the player slipped out of sight, and go look where they were last
+    /// seen instead
+    pub fn lose_track(&mut self, last_seen: Vec2) {
+        if !self.is_alerted() || self.rally_point.is_some() {
+            return;
+        }
+        self.suspicion = GUARD_INVESTIGATE_SUSPICION;
+        self.investigate_target = Some(last_seen);
+        self.charge_started = None;
+    }
+
+    /// Let a guard that isn't chasing anyone yet grow less suspicious
+    pub fn calm_down(&mut self, dt: f32) {
+        if !self.is_alerted() {
+            self.suspicion = (self.suspicion - GUARD_SUSPICION_DECAY * dt).max(0.);
+        }
+    }
+
+    /// Returns true if the guard was not already alerted
+    pub fn alert_guard(&mut self) -> bool {
+        if self.is_alerted() {
+            return false;
         }
+        self.suspicion = 1.;
+        self.last_alerted = clock::now();
+        true
+    }
+
+    /// Alert the guard and send it to hold a position, going after the player if they come near
+    pub fn rally_to(&mut self, target: Vec2) {
+        self.alert_guard();
+        self.rally_point = Some(target);
+        self.investigate_target = None;
     }
 
-    pub fn alert_guard(&mut self) {
-        if self.is_alerted {
+    /// Climb into a hiding spot. The body is moved there and taken out of the physics world, so
+    /// nothing can bump into it until the player comes back out.
+    pub fn hide(&mut self, spot: EntityId, position: Vec2, physics: &mut Physics) {
+        self.hiding_spot = Some(spot);
+        self.input_direction = Vec2::ZERO;
+        self.is_attacking = false;
+        self.teleport(position, physics);
+        if let Some(body_handle) = self.body_handle {
+            physics.bodies[body_handle].set_enabled(false);
+        }
+    }
+
+    /// Climb back out of the hiding spot, onto `position`
+    pub fn leave_hiding(&mut self, position: Vec2, physics: &mut Physics) {
+        if self.hiding_spot.take().is_none() {
             return;
         }
-        self.is_alerted = true;
-        self.last_alerted = get_time();
-        play_sound_once(&self.sounds.alert);
+        if let Some(body_handle) = self.body_handle {
+            physics.bodies[body_handle].set_enabled(true);
+        }
+        self.teleport(position, physics);
+    }
+
+    pub fn hiding_spot(&self) -> Option<EntityId> {
+        self.hiding_spot
+    }
+
+    pub fn is_hidden(&self) -> bool {
+        self.hiding_spot.is_some()
+    }
+
+    /// Put the character's center somewhere outright, and stop it there
+    fn teleport(&mut self, center: Vec2, physics: &mut Physics) {
+        self.position = center - vec2(0.5, 0.5);
+        self.attack_position = center;
+        let Some(body_handle) = self.body_handle else {
+            return;
+        };
+        let body = &mut physics.bodies[body_handle];
+        body.set_translation(vector![center.x, center.y], true);
+        body.set_linvel(vector![0., 0.], true);
+    }
+
+    /// Lock a trapped guard up behind a cell door, where it paces back and forth harmlessly.
+    /// Its physics should be destroyed first, so that nothing can bump into it.
+    pub fn jail(&mut self, cell: Vec2) {
+        self.jail_cell = Some(cell);
+        self.position = cell;
+        self.input_direction = Vec2::ZERO;
+        self.suspicion = 0.;
+        self.is_attacking = false;
+        self.investigate_target = None;
+        self.rally_point = None;
+        self.detour = None;
+        self.charge_started = None;
+    }
+
+    /// Where the guard is locked up, if it has been trapped
+    pub fn jail_cell(&self) -> Option<Vec2> {
+        self.jail_cell
+    }
+
+    /// Walk the jailed guard across its cell door
+    pub fn pace(&mut self) {
+        let Some(cell) = self.jail_cell else {
+            return;
+        };
+        // guards are out of step with each other, so the cells don't all move together
+        let phase = clock::now() * JAILED_PACE_SPEED + self.id.to_bits() as f64;
+        let offset = (phase.sin() as f32 + 1.) / 2. * JAILED_PACE_WIDTH;
+        self.position = cell + vec2(offset, 0.);
+        self.move_facing = if phase.cos() > 0. {
+            FacingDirection::Right
+        } else {
+            FacingDirection::Left
+        };
     }
 
     pub fn destroy_physics(&mut self, physics: &mut Physics) {
@@ -378,13 +1338,57 @@ impl Character {
         self.collider_handle = None;
     }
 
-    pub fn handle_attack_collision(&mut self, guard: &mut Character) {
+    /// Knock a guard away from the attack. Returns the impulse it was hit with, if the knockback
+    /// landed.
+    pub fn handle_attack_collision(&mut self, guard: &mut Character) -> Option<f32> {
         if !self.is_attacking {
-            return;
+            return None;
         }
-        info!("ATTACK COLLISION");
+        debug!("attack hit guard {:?}", guard.id);
         let knockback_dir = self.attack_direction;
-        guard.apply_knockback(knockback_dir * PLAYER_ATTACK_KNOCKBACK);
+        let knockback = tuning().player_attack_knockback
+            * (1. + (ATTACK_CHARGE_KNOCKBACK_FACTOR - 1.) * self.attack_charge);
+        guard
+            .apply_knockback(knockback_dir * knockback)
+            .then_some(knockback)
+    }
+
+    /// The blow a fully charged attack deals on top of its knockback, if the last attack was one
+    pub fn charged_attack_damage(&self) -> Option<Damage> {
+        (self.attack_charge >= 1.).then_some(Damage {
+            source: Some(self.id),
+            amount: CHARGED_ATTACK_DAMAGE,
+            kind: DamageKind::Attack,
+            knockback: Vec2::ZERO,
+        })
+    }
+
+    /// Where the character is drawn and with which sprite, to leave a corpse behind
+    pub fn corpse(&self) -> Corpse {
+        Corpse::new(self.get_draw_rect(), self.sprite_id, self.death_time)
+    }
+
+    /// How far the held attack has charged, from 0 to 1
+    fn current_attack_charge(&self) -> f32 {
+        match self.attack_charge_start {
+            Some(start) => ((clock::now() - start) / ATTACK_CHARGE_TIME).min(1.) as f32,
+            None => 0.,
+        }
+    }
+
+    /// Reach of the attack being charged, or of the last one swung
+    fn attack_radius(&self) -> f32 {
+        let charge = if self.attack_charge_start.is_some() {
+            self.current_attack_charge()
+        } else {
+            self.attack_charge
+        };
+        self.upgrades.attack_radius(PLAYER_ATTACK_RADIUS)
+            * (1. + (ATTACK_CHARGE_RADIUS_FACTOR - 1.) * charge)
+    }
+
+    pub fn attack_direction(&self) -> Vec2 {
+        self.attack_direction
     }
 
     pub fn center(&self) -> Vec2 {
@@ -393,13 +1397,18 @@ impl Character {
 }
 
 pub trait CharacterConfigProvider {
+    fn entity_kind() -> EntityKind;
     fn get_sprite_id() -> u32;
     fn get_acceleration() -> f32;
     fn get_braking() -> f32;
     fn get_max_health() -> u32;
+    #[allow(dead_code)]
     fn destroy_on_death() -> bool;
     fn draw_attack() -> bool;
     fn knockback_cooldown() -> f64;
+    fn footstep_noise_radius() -> f32;
+    fn is_boss() -> bool;
+    fn draw_size() -> f32;
 
     fn init_physics(
         position: Vec2,
@@ -410,6 +1419,10 @@ pub trait CharacterConfigProvider {
 
 struct PlayerConfigProvider;
 impl CharacterConfigProvider for PlayerConfigProvider {
+    fn entity_kind() -> EntityKind {
+        EntityKind::Player
+    }
+
     fn get_sprite_id() -> u32 {
         PLAYER_SPRITE_ID
     }
@@ -420,7 +1433,13 @@ impl CharacterConfigProvider for PlayerConfigProvider {
         rigid_body_set: &mut RigidBodySet,
     ) -> (ColliderHandle, RigidBodyHandle, Option<ColliderHandle>) {
         // character body
-        let body = RigidBodyBuilder::dynamic()
+        let kinematic = tuning().kinematic_player;
+        let body = if kinematic {
+            RigidBodyBuilder::kinematic_position_based()
+        } else {
+            RigidBodyBuilder::dynamic()
+        };
+        let body = body
             .translation(vector![position.x + 0.5, position.y + 0.5])
             .lock_rotations()
             .linear_damping(PLAYER_LINEAR_DAMPING) // TODO: make const
@@ -432,10 +1451,21 @@ impl CharacterConfigProvider for PlayerConfigProvider {
             .friction(PLAYER_FRICTION)
             .friction_combine_rule(PLAYER_FRICTION_COMBINE_RULE)
             .restitution(PLAYER_RESTITUTION)
-            .active_events(ActiveEvents::COLLISION_EVENTS)
-            .build();
+            .collision_groups(CollisionLayer::Player.groups())
+            .active_events(ActiveEvents::COLLISION_EVENTS);
+        // kinematic bodies don't touch fixed ones by default, which would leave traps and items
+        // unable to tell the player is on them
+        let collider = if kinematic {
+            collider.active_collision_types(
+                ActiveCollisionTypes::default() | ActiveCollisionTypes::KINEMATIC_FIXED,
+            )
+        } else {
+            collider
+        }
+        .build();
 
         let attack_collider = ColliderBuilder::ball(PLAYER_ATTACK_RADIUS)
+            .collision_groups(CollisionLayer::AttackSensor.groups())
             .active_events(ActiveEvents::COLLISION_EVENTS)
             .sensor(true)
             .build();
@@ -449,11 +1479,11 @@ impl CharacterConfigProvider for PlayerConfigProvider {
     }
 
     fn get_acceleration() -> f32 {
-        PLAYER_ACCELERATION
+        tuning().player_acceleration
     }
 
     fn get_braking() -> f32 {
-        PLAYER_BRAKING
+        tuning().player_braking
     }
 
     fn get_max_health() -> u32 {
@@ -469,22 +1499,38 @@ impl CharacterConfigProvider for PlayerConfigProvider {
     }
 
     fn knockback_cooldown() -> f64 {
-        PLAYER_KNOCKBACK_COOLDOWN
+        tuning().player_knockback_cooldown
+    }
+
+    fn footstep_noise_radius() -> f32 {
+        FOOTSTEP_NOISE_RADIUS
+    }
+
+    fn is_boss() -> bool {
+        false
+    }
+
+    fn draw_size() -> f32 {
+        1.
     }
 }
 
 struct GuardConfigProvider;
 impl CharacterConfigProvider for GuardConfigProvider {
+    fn entity_kind() -> EntityKind {
+        EntityKind::Guard
+    }
+
     fn get_sprite_id() -> u32 {
         GUARD_SPRITE_ID
     }
 
     fn get_acceleration() -> f32 {
-        GUARD_ACCELERATION
+        tuning().guard_acceleration
     }
 
     fn get_braking() -> f32 {
-        GUARD_BRAKING
+        tuning().guard_braking
     }
 
     fn init_physics(
@@ -503,6 +1549,7 @@ impl CharacterConfigProvider for GuardConfigProvider {
             .friction(GUARD_FRICTION)
             .friction_combine_rule(GUARD_FRICTION_COMBINE_RULE)
             .restitution(GUARD_RESTITUTION)
+            .collision_groups(CollisionLayer::Guard.groups())
             .active_events(ActiveEvents::COLLISION_EVENTS)
             .build();
 
@@ -525,6 +1572,112 @@ impl CharacterConfigProvider for GuardConfigProvider {
     }
 
     fn knockback_cooldown() -> f64 {
-        GUARD_KNOCKBACK_COOLDOWN
+        tuning().guard_knockback_cooldown
+    }
+
+    /// guards know what guard footsteps sound like
+    fn footstep_noise_radius() -> f32 {
+        0.
+    }
+
+    fn is_boss() -> bool {
+        false
+    }
+
+    fn draw_size() -> f32 {
+        1.
+    }
+}
+
+/// A big, slow guard that charges at the player. It only fits in a reinforced cell.
+struct BossConfigProvider;
+impl CharacterConfigProvider for BossConfigProvider {
+    fn entity_kind() -> EntityKind {
+        EntityKind::Guard
+    }
+
+    fn get_sprite_id() -> u32 {
+        BOSS_SPRITE_ID
+    }
+
+    fn get_acceleration() -> f32 {
+        tuning().boss_acceleration
+    }
+
+    fn get_braking() -> f32 {
+        tuning().guard_braking
+    }
+
+    fn init_physics(
+        position: Vec2,
+        collider_set: &mut ColliderSet,
+        rigid_body_set: &mut RigidBodySet,
+    ) -> (ColliderHandle, RigidBodyHandle, Option<ColliderHandle>) {
+        let body = RigidBodyBuilder::dynamic()
+            .translation(vector![position.x + 0.5, position.y + 0.5])
+            .lock_rotations()
+            .linear_damping(GUARD_LINEAR_DAMPING)
+            .ccd_enabled(true)
+            .build();
+        let collider = ColliderBuilder::ball(BOSS_RADIUS)
+            .mass(BOSS_MASS)
+            .friction(GUARD_FRICTION)
+            .friction_combine_rule(GUARD_FRICTION_COMBINE_RULE)
+            .restitution(GUARD_RESTITUTION)
+            .collision_groups(CollisionLayer::Guard.groups())
+            .active_events(ActiveEvents::COLLISION_EVENTS)
+            .build();
+
+        let body_handle = rigid_body_set.insert(body);
+        let collider_handle =
+            collider_set.insert_with_parent(collider, body_handle, rigid_body_set);
+        (collider_handle, body_handle, None)
+    }
+
+    fn get_max_health() -> u32 {
+        BOSS_MAX_HEALTH
+    }
+
+    fn destroy_on_death() -> bool {
+        true
+    }
+
+    fn draw_attack() -> bool {
+        false
+    }
+
+    fn knockback_cooldown() -> f64 {
+        tuning().guard_knockback_cooldown
+    }
+
+    fn footstep_noise_radius() -> f32 {
+        0.
+    }
+
+    fn is_boss() -> bool {
+        true
+    }
+
+    fn draw_size() -> f32 {
+        BOSS_DRAW_SIZE
+    }
+}
+
+/// Small filled triangle with its tip at `tip`, pointing along `direction`
+fn draw_chevron(tip: Vec2, direction: Vec2, size: f32, color: Color) {
+    let back = tip - direction * size;
+    let side = direction.perp() * size / 2.;
+    draw_triangle(tip, back + side, back - side, color);
+}
+
+/// A character controller for a top-down world. With no floor to stand on, every surface is a
+/// wall to slide along, and there are no steps to climb or ground to snap to.
+fn top_down_controller() -> KinematicCharacterController {
+    KinematicCharacterController {
+        autostep: None,
+        snap_to_ground: None,
+        max_slope_climb_angle: std::f32::consts::TAU,
+        min_slope_slide_angle: 0.,
+        ..Default::default()
     }
 }
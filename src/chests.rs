@@ -0,0 +1,62 @@
+use macroquad::math::{vec2, Rect, UVec2, Vec2};
+use macroquad_tiled::Map as TiledMap;
+
+use crate::{
+    constants::{CHEST_CLOSED_SPRITE_ID, CHEST_OPEN_SPRITE_ID, TILESET_MAP_ID},
+    entity::{EntityId, EntityKind, EntityRegistry},
+    event::{EventQueue, GameEvent},
+    interact::Interactable,
+};
+
+/// A treasure chest tucked away in a dead end, holding coins for the shop between floors
+pub struct Chest {
+    pub id: EntityId,
+    pub position: UVec2,
+    /// coins handed over when it is opened
+    pub coins: u32,
+    pub is_open: bool,
+}
+
+impl Chest {
+    pub fn create(position: UVec2, coins: u32, entities: &mut EntityRegistry) -> Self {
+        // like levers, chests are only ever used, never bumped into
+        let id = entities.spawn(EntityKind::Chest);
+        Self {
+            id,
+            position,
+            coins,
+            is_open: false,
+        }
+    }
+
+    pub fn center(&self) -> Vec2 {
+        self.position.as_vec2() + vec2(0.5, 0.5)
+    }
+
+    pub fn draw(&self, tile_map: &TiledMap) {
+        let sprite_id = if self.is_open {
+            CHEST_OPEN_SPRITE_ID
+        } else {
+            CHEST_CLOSED_SPRITE_ID
+        };
+        let draw_rect = Rect::new(self.position.x as f32, self.position.y as f32, 1., 1.);
+        tile_map.spr(TILESET_MAP_ID, sprite_id, draw_rect);
+    }
+}
+
+impl Interactable for Chest {
+    fn interact_position(&self) -> Vec2 {
+        self.center()
+    }
+
+    fn prompt(&self) -> &'static str {
+        "prompt-open-chest"
+    }
+
+    fn interact(&mut self, _player: EntityId, events: &mut EventQueue) {
+        events.emit(GameEvent::ChestOpened {
+            chest: self.id,
+            position: self.center(),
+        });
+    }
+}
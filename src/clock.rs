@@ -0,0 +1,91 @@
+use std::sync::RwLock;
+
+use macroquad::time::get_time;
+
+/// The clock gameplay runs on. It keeps pace with the real one, except during a hit-stop, when it
+/// stands still for a few frames and then carries on from where it stopped. Cooldowns and timers
+/// read it instead of the real clock, so a hit-stop doesn't eat into them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Clock {
+    /// seconds the game clock has fallen behind the real one, over every hit-stop so far
+    behind: f64,
+    /// real time the current hit-stop started, and frames of it left
+    stopped: Option<(f64, u32)>,
+}
+
+static CLOCK: RwLock<Clock> = RwLock::new(Clock::new());
+
+impl Clock {
+    const fn new() -> Self {
+        Self {
+            behind: 0.,
+            stopped: None,
+        }
+    }
+
+    fn now(&self, real: f64) -> f64 {
+        match self.stopped {
+            Some((since, _)) => since - self.behind,
+            None => real - self.behind,
+        }
+    }
+
+    fn stop(&mut self, real: f64, frames: u32) {
+        match &mut self.stopped {
+            Some((_, left)) => *left = (*left).max(frames),
+            None => self.stopped = Some((real, frames)),
+        }
+    }
+
+    fn tick(&mut self, real: f64) -> bool {
+        match &mut self.stopped {
+            Some((_, left)) if *left > 0 => {
+                *left -= 1;
+                true
+            }
+            Some((since, _)) => {
+                self.behind += real - *since;
+                self.stopped = None;
+                false
+            }
+            None => false,
+        }
+    }
+}
+
+/// Seconds on the game clock
+pub fn now() -> f64 {
+    CLOCK.read().unwrap().now(get_time())
+}
+
+/// Hold the game still for a few frames, to give a hit some weight. A hit-stop already going is
+/// only ever lengthened.
+pub fn hit_stop(frames: u32) {
+    CLOCK.write().unwrap().stop(get_time(), frames);
+}
+
+/// Count off a frame of any hit-stop. Call once a frame before updating the game. Returns whether
+/// the game should hold still this frame.
+pub fn tick() -> bool {
+    CLOCK.write().unwrap().tick(get_time())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hit_stop_holds_the_clock_then_carries_on() {
+        let mut clock = Clock::new();
+        assert_eq!(clock.now(10.), 10.);
+        clock.stop(10., 2);
+        clock.stop(10.01, 1);
+        assert_eq!(clock.now(10.02), 10.);
+        assert!(clock.tick(10.02));
+        assert!(clock.tick(10.04));
+        assert!(!clock.tick(10.06));
+        assert!((clock.now(10.06) - 10.).abs() < 1e-9);
+        assert!((clock.now(11.06) - 11.).abs() < 1e-9);
+        assert!(!clock.tick(11.06));
+    }
+}
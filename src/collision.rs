@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+
+use crate::{
+    entity::{EntityId, EntityKind},
+    event::GameEvent,
+};
+
+/// Turns a collision between two entities into a game event. Arguments are passed in the order
+/// the rule was registered with, regardless of the order rapier reported the colliders in.
+pub type CollisionRule = fn(EntityId, EntityId) -> Option<GameEvent>;
+
+/// Dispatch table from pairs of entity kinds to collision rules
+pub struct CollisionRouter {
+    rules: HashMap<(EntityKind, EntityKind), CollisionRule>,
+}
+
+impl CollisionRouter {
+    pub fn new() -> Self {
+        let mut router = Self {
+            rules: HashMap::new(),
+        };
+        router.register(EntityKind::Player, EntityKind::Guard, |player, guard| {
+            Some(GameEvent::PlayerGuardCollision { player, guard })
+        });
+        router.register(
+            EntityKind::Projectile,
+            EntityKind::Wall,
+            |projectile, _wall| {
+                Some(GameEvent::ProjectileImpact {
+                    projectile,
+                    guard: None,
+                })
+            },
+        );
+        router.register(
+            EntityKind::Projectile,
+            EntityKind::Guard,
+            |projectile, guard| {
+                Some(GameEvent::ProjectileImpact {
+                    projectile,
+                    guard: Some(guard),
+                })
+            },
+        );
+        router.register(EntityKind::Player, EntityKind::Item, |player, item| {
+            Some(GameEvent::ItemPickedUp { player, item })
+        });
+        router.register(
+            EntityKind::Player,
+            EntityKind::LockedDoor,
+            |_player, door| Some(GameEvent::LockedDoorTouched { door }),
+        );
+        router
+    }
+
+    pub fn register(&mut self, a: EntityKind, b: EntityKind, rule: CollisionRule) {
+        self.rules.insert((a, b), rule);
+    }
+
+    /// Find the rule for a pair of entities in either order and apply it
+    pub fn route(
+        &self,
+        (a_id, a_kind): (EntityId, EntityKind),
+        (b_id, b_kind): (EntityId, EntityKind),
+    ) -> Option<GameEvent> {
+        if let Some(rule) = self.rules.get(&(a_kind, b_kind)) {
+            return rule(a_id, b_id);
+        }
+        if let Some(rule) = self.rules.get(&(b_kind, a_kind)) {
+            return rule(b_id, a_id);
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::EntityRegistry;
+
+    #[test]
+    fn test_collision_router_is_symmetric() {
+        let mut entities = EntityRegistry::default();
+        let player = (entities.spawn(EntityKind::Player), EntityKind::Player);
+        let guard = (entities.spawn(EntityKind::Guard), EntityKind::Guard);
+        let wall = (entities.spawn(EntityKind::Wall), EntityKind::Wall);
+        let router = CollisionRouter::new();
+
+        let expected = Some(GameEvent::PlayerGuardCollision {
+            player: player.0,
+            guard: guard.0,
+        });
+        assert_eq!(router.route(player, guard), expected);
+        assert_eq!(router.route(guard, player), expected);
+        assert_eq!(router.route(player, wall), None);
+        assert_eq!(router.route(wall, guard), None);
+    }
+}
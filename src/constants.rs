@@ -1,6 +1,7 @@
-use std::ops::Range;
-
-use macroquad::math::{uvec2, UVec2};
+use macroquad::{
+    color::Color,
+    math::{uvec2, vec2, UVec2, Vec2},
+};
 use rapier2d::dynamics::CoefficientCombineRule;
 
 /// Resolution of the simulated screen
@@ -20,38 +21,248 @@ pub const TILESET_MAP_ID: &str = "tiny_dungeon";
 
 /// ID that the map uses to refer to its tileset
 pub const TERRAIN_MAP_ID: &str = "terrain";
+
+/// layer for decorations drawn over the terrain
+pub const PROPS_MAP_ID: &str = "props";
+/// layer drawn over the characters, so they can step behind the walls in front of them
+pub const OVERHEAD_MAP_ID: &str = "overhead";
+
+/// maps at least this many tiles across load their wall colliders in chunks, as characters come near
+pub const CHUNKED_MAP_SIZE: u32 = 200;
+/// width and height of a map chunk, in tiles
+pub const CHUNK_SIZE: u32 = 32;
+/// tiles around each player that keep chunks loaded. reaches past the edge of the widest view.
+pub const CHUNK_PLAYER_MARGIN: f32 = 48.;
+/// tiles around each guard that keep chunks loaded, so guards out of view still bump into walls
+pub const CHUNK_GUARD_MARGIN: f32 = 4.;
 // pub const TERRAIN_MAP_ID: &str = "generated";
 
 /// load path for the tile map data
 // pub const TILE_MAP_JSON_PATH: &str = "assets/tiled/export/sandbox01.tmj";
 pub const TILE_MAP_JSON_PATH: &str = "assets/tiled/export/sandbox03.tmj";
 
-pub const CLICK_SOUND_PATH: &str = "assets/kenney_interface-sounds/Audio/click_004.ogg";
-pub const ATTACK_SOUND_PATH: &str = "assets/kenney_impact-sounds/Audio/impactPunch_heavy_001.ogg";
-pub const KNOCKBACK_SOUND_PATH: &str = "assets/kenney_impact-sounds/Audio/impactBell_heavy_002.ogg";
-pub const ALERT_SOUND_PATH: &str =
-    "assets/kenney_music-jingles/Audio/Pizzicato jingles/jingles_PIZZI00.ogg";
-pub const DOOR_CLOSE_SOUND_PATH: &str = "assets/kenney_rpg-audio/Audio/doorClose_1.ogg";
-pub const VICTORY_SOUND_PATH: &str =
-    "assets/kenney_music-jingles/Audio/Pizzicato jingles/jingles_PIZZI10.ogg";
-pub const DEFEAT_SOUND_PATH: &str =
-    "assets/kenney_music-jingles/Audio/Pizzicato jingles/jingles_PIZZI07.ogg";
-
-// TODO(axelmagn): fill this out
-/// Tile ID ranges which should be treated as solid
-pub const SOLID_TILES: &[Range<u32>] = &[
-    Range { start: 0, end: 6 },
-    Range { start: 12, end: 14 },
-    Range { start: 15, end: 18 },
-    Range { start: 19, end: 21 },
-    Range { start: 24, end: 28 },
+/// hand-made rooms that map generation can stamp into generated ones
+pub const PREFAB_PATHS: &[&str] = &[
+    "assets/tiled/prefabs/barracks.tmj",
+    "assets/tiled/prefabs/shrine.tmj",
+    "assets/tiled/prefabs/vault.tmj",
 ];
+pub const PREFAB_TERRAIN_LAYER_ID: &str = "terrain";
+pub const PREFAB_PROPS_LAYER_ID: &str = "props";
+pub const PREFAB_ANCHOR_PROPERTY: &str = "anchor";
+pub const PREFAB_ROTATABLE_PROPERTY: &str = "rotatable";
+
+/// tile themes that maps get painted with. the first one is the default.
+pub const THEME_PATHS: &[&str] = &["assets/themes/dungeon.json", "assets/themes/crypt.json"];
+
+/// translations of the menus and UI. english is built in, and fills in anything these leave out.
+pub const LOCALE_PATHS: &[&str] = &["assets/locales/es.txt"];
+pub const DEFAULT_LANGUAGE: &str = "en";
+
+/// where the log is written natively. logs from earlier runs are kept beside it, numbered.
+#[cfg(not(target_arch = "wasm32"))]
+pub const LOG_PATH: &str = "stonehold.log";
+#[cfg(not(target_arch = "wasm32"))]
+pub const LOG_BACKUPS: usize = 3;
+/// bytes a log can grow to before it is moved along and a new one started
+#[cfg(not(target_arch = "wasm32"))]
+pub const LOG_MAX_BYTES: u64 = 1 << 20;
+/// environment variable with the log filter, like `info,stonehold::map=debug`
+pub const LOG_FILTER_VAR: &str = "STONEHOLD_LOG";
+
+/// where a run in progress is saved when the player quits
+pub const SAVE_PATH: &str = "stonehold_save.json";
+
+/// gameplay tuning that overrides the values in this file. reloaded with F5.
+pub const TUNING_PATH: &str = "assets/tuning.ron";
+
+/// seconds between checks for changed map and tileset files
+pub const ASSET_POLL_INTERVAL: f64 = 0.5;
+
+/// where player preferences are kept
+pub const SETTINGS_PATH: &str = "stonehold_settings.json";
+
+/// window sizes the settings menu cycles through, all multiples of the simulated resolution
+pub const WINDOW_SIZE_PRESETS: &[(u32, u32)] = &[(640, 480), (960, 720), (1280, 960), (1920, 1440)];
+
+/// text sizes the settings menu cycles through, relative to the usual size
+pub const TEXT_SCALES: &[f32] = &[1., 1.25, 1.5];
+
+/// where the ghost of the fastest escape from the last raced map is kept
+pub const GHOST_PATH: &str = "stonehold_ghost.json";
+/// seconds between recorded ghost positions
+pub const GHOST_SAMPLE_INTERVAL: f64 = 0.1;
+pub const GHOST_ALPHA: f32 = 0.4;
+
+/// where the best speedrun splits are kept
+pub const SPLITS_PATH: &str = "stonehold_splits.json";
+
+/// seconds without hearing from a co-op partner before they count as gone
+pub const NET_TIMEOUT: f64 = 5.;
+/// the snapshot that ends a round is sent this many times, since no later one makes up for it
+pub const NET_RESULT_REPEATS: usize = 5;
+/// where a joining partner appears, in tiles from the host's player
+pub const PARTNER_SPAWN_OFFSET: Vec2 = vec2(1., 0.);
+
+/// where unlocked achievements are kept
+pub const ACHIEVEMENTS_PATH: &str = "stonehold_achievements.json";
+/// guards to trap within the window for the quick trapper achievement
+pub const QUICK_TRAP_COUNT: usize = 3;
+pub const QUICK_TRAP_WINDOW: f64 = 10.;
+/// seconds to escape within for the speedrunner achievement
+pub const SPEEDRUN_ESCAPE_TIME: f64 = 120.;
+/// seconds a toast stays up, including fading in and out
+pub const TOAST_TIME: f64 = 3.;
+pub const TOAST_FADE_TIME: f64 = 0.3;
+/// seconds a line stays in the message ticker, including fading out, and how many fit at once
+pub const TICKER_TIME: f64 = 5.;
+pub const TICKER_FADE_TIME: f64 = 1.;
+pub const TICKER_MAX_ENTRIES: usize = 5;
+/// tiles from the player that footsteps are reported from. any closer and the guard is in plain
+/// sight.
+pub const TICKER_FOOTSTEP_MIN_DISTANCE: f32 = 8.;
+pub const TICKER_FOOTSTEP_MAX_DISTANCE: f32 = 14.;
+/// gap kept between menu panels and the edge of the window, in screen pixels
+pub const MENU_MARGIN: f32 = 16.;
+/// height of a row of menu text, and width of menu buttons, at the normal text size
+pub const MENU_ROW_HEIGHT: f32 = 64.;
+pub const MENU_BUTTON_WIDTH: f32 = 320.;
+/// width of the game over screen's results panel at the normal text size
+pub const RESULTS_WIDTH: f32 = 560.;
+/// tiles of the main menu's background floor shown from top to bottom of the window
+pub const DIORAMA_VIEW_HEIGHT: f32 = 14.;
+/// how fast the background floor pans, in radians of its sway a second
+pub const DIORAMA_PAN_SPEED: f64 = 0.04;
+/// seconds before each demo of the controls in the instructions starts over
+pub const DEMO_LOOP_TIME: f64 = 3.;
+/// how dark the background floor is kept, so the menu over it can be read
+pub const DIORAMA_SHADE: f32 = 0.55;
+
+/// seconds from the escape alarm going off until the guards seal the exit
+pub const ESCAPE_ALARM_TIME: f64 = 30.;
+/// seconds left on the alarm when its timer turns to the alert color
+pub const ESCAPE_ALARM_WARNING_TIME: f64 = 10.;
+
+/// seconds into a floor before guards start coming out of the monster pipes
+pub const REINFORCEMENT_START_TIME: f64 = 90.;
+/// seconds between guards coming out of the pipes, and while the alarm is going off
+pub const REINFORCEMENT_INTERVAL: f64 = 20.;
+pub const REINFORCEMENT_ALARM_INTERVAL: f64 = 8.;
+/// seconds a pipe rumbles before a guard comes out of it
+pub const REINFORCEMENT_WARNING_TIME: f64 = 1.5;
+/// most guards the pipes send out on one floor
+pub const REINFORCEMENT_CAP: u32 = 4;
+
+/// where the fastest escapes are kept
+pub const LEADERBOARD_PATH: &str = "stonehold_leaderboard.json";
+pub const LEADERBOARD_SIZE: usize = 10;
+pub const INITIALS_LENGTH: usize = 3;
+
+/// number of frames the performance overlay averages over
+pub const PERF_WINDOW_FRAMES: usize = 120;
+
+/// size of the progress bar on the loading screen, in screen pixels
+pub const LOADING_BAR_WIDTH: f32 = 480.;
+pub const LOADING_BAR_HEIGHT: f32 = 32.;
+
+/// on-screen controls, in screen pixels
+pub const TOUCH_STICK_RADIUS: f32 = 96.;
+pub const TOUCH_BUTTON_RADIUS: f32 = 64.;
+/// fraction of the stick's reach that is ignored, and below which the player sneaks
+pub const TOUCH_STICK_DEAD_ZONE: f32 = 0.2;
+pub const TOUCH_STICK_SNEAK: f32 = 0.5;
+/// seconds an attack or throw pressed too early is held on to, waiting for its cooldown
+pub const INPUT_BUFFER_TIME: f64 = 0.15;
+
+/// where F12 screenshots and map captures are written
+pub const SCREENSHOT_DIR: &str = "screenshots";
+
+/// name and path of every sound effect
+pub const SOUND_MANIFEST_PATH: &str = "assets/sounds.json";
+pub const FOOTSTEP_VOLUME: f32 = 0.5;
+/// footsteps are quieter while sneaking
+pub const FOOTSTEP_SNEAK_VOLUME: f32 = 0.15;
+
+/// other sounds drop to this volume while an alert or victory cue plays
+pub const DUCK_VOLUME: f32 = 0.3;
+/// seconds other sounds stay ducked after a cue starts, about as long as the jingles
+pub const DUCK_HOLD_TIME: f64 = 1.5;
+/// seconds to fade back up to full volume afterwards
+pub const DUCK_FADE_TIME: f64 = 0.5;
+
+/// positional sounds play at full volume within this many tiles of the player
+pub const SOUND_FULL_VOLUME_DISTANCE: f32 = 4.;
+/// and fade out to nothing by this many tiles away
+pub const SOUND_MAX_DISTANCE: f32 = 32.;
 
 pub const MIN_ROOM_SIZE: UVec2 = uvec2(10, 10);
 pub const MAX_ROOM_SIZE: UVec2 = uvec2(20, 20);
 pub const MAX_ROOM_COUNT: u32 = 50;
 pub const CORRIDOR_PADDING: Option<u32> = Some(2);
 pub const DOOR_CLEARANCE: u32 = 8;
+/// extra corridors joining rooms that aren't next to each other, so maps have loops
+pub const CORRIDOR_LOOP_COUNT: u32 = 3;
+/// fill in corridor stubs and nooks that lead nowhere
+pub const TRIM_DEAD_ENDS: bool = true;
+/// share of guard doors that swing open and shut on a timer instead of staying open
+pub const CYCLING_DOOR_RATIO: f32 = 0.3;
+pub const SPIKE_TRAP_COUNT: u32 = 8;
+/// each plate works a cell door that starts out shut
+pub const PRESSURE_PLATE_COUNT: u32 = 2;
+/// each lever works a cell door in another room, which starts out shut
+pub const LEVER_COUNT: u32 = 2;
+pub const TORCH_COUNT: u32 = 16;
+pub const CRATE_COUNT: u32 = 6;
+/// share of the rooms left without guards, items or traps, smallest first
+pub const EMPTY_ROOM_SHARE: f32 = 0.2;
+/// guard posts at least this many tiles in area hold two guards
+pub const GUARD_POST_LARGE_AREA: f32 = 256.;
+/// tiles between guards spawned in the same room
+pub const GUARD_SPAWN_SPACING: f32 = 2.;
+/// chests only go in dead-end rooms, so a floor may have fewer
+pub const CHEST_COUNT: u32 = 2;
+/// coins in a chest, from min up to and including max
+pub const CHEST_COINS_MIN: u32 = 5;
+pub const CHEST_COINS_MAX: u32 = 15;
+/// barrels to hide in, set against the back walls of the rooms
+pub const BARREL_COUNT: u32 = 4;
+
+/// tiles from a player that things can be used with the interact key
+pub const INTERACT_DISTANCE: f32 = 1.5;
+/// things set in a wall, like a locked door, can be used from this far in front of the wall
+pub const INTERACT_SIGHT_SLACK: f32 = 0.75;
+/// shown in front of interact prompts
+pub const INTERACT_KEY_LABEL: &str = "[E]";
+/// torches are kept at least this many tiles apart
+pub const TORCH_SPACING: f32 = 6.;
+/// BSP layouts stop splitting once a piece of the map is smaller than this
+pub const BSP_MIN_LEAF_SIZE: UVec2 = uvec2(24, 20);
+/// minimum space between a BSP room and the edge of its piece of the map
+pub const BSP_ROOM_MARGIN: u32 = 2;
+/// chance that a cave tile starts out as wall before smoothing
+pub const CAVE_FILL_PROB: f32 = 0.45;
+pub const CAVE_SMOOTHING_STEPS: u32 = 5;
+/// caves have no real rooms, so open squares of this size stand in for them
+pub const CAVE_ROOM_SIZE: u32 = 7;
+/// minimum gap between cave rooms, in tiles
+pub const CAVE_ROOM_SPACING: u32 = 14;
+pub const LOCKED_DOOR_COUNT: u32 = 2;
+/// one-way passages only go where the map can be got around without them
+pub const ONE_WAY_PASSAGE_COUNT: u32 = 2;
+/// half the thickness of a one-way passage's slab, in tiles
+pub const ONE_WAY_HALF_THICKNESS: f32 = 0.25;
+/// radians off a passage's direction that it still pushes back from
+pub const ONE_WAY_ALLOWED_ANGLE: f32 = std::f32::consts::FRAC_PI_4;
+/// how many times to regenerate a map that fails validation before falling back to a grid
+pub const MAPGEN_MAX_ATTEMPTS: u32 = 10;
+/// fewer rooms than this and a generated map is thrown out
+pub const MIN_ROOM_COUNT: u32 = 4;
+/// tiles of wall between the rooms of the fallback grid layout
+pub const GRID_ROOM_SPACING: u32 = 6;
+/// chance that a guard room gets a prefab stamped into it, if one fits
+pub const PREFAB_ROOM_PROB: f32 = 0.3;
+/// chance that a guard room has its floor iced over
+pub const ICE_ROOM_PROB: f32 = 0.15;
 pub const TILE_FILLER_PROB: f32 = 0.003;
 
 pub const WALL_01_TILE_ID: u32 = 0;
@@ -70,23 +281,6 @@ pub const WALL_OUTER_UL_ID: u32 = 4;
 pub const WALL_OUTER_UR_ID: u32 = 5;
 pub const WALL_OUTER_DL_ID: u32 = 16;
 pub const WALL_OUTER_DR_ID: u32 = 17;
-pub const WALL_TILE_IDS: &[u32] = &[
-    WALL_01_TILE_ID,
-    WALL_02_TILE_ID,
-    WALL_03_TILE_ID,
-    WALL_UP_TILE_ID,
-    WALL_DOWN_TILE_ID,
-    WALL_LEFT_TILE_ID,
-    WALL_RIGHT_TILE_ID,
-    WALL_INNER_UL_ID,
-    WALL_INNER_UR_ID,
-    WALL_INNER_DL_ID,
-    WALL_INNER_DR_ID,
-    WALL_OUTER_UL_ID,
-    WALL_OUTER_UR_ID,
-    WALL_OUTER_DL_ID,
-    WALL_OUTER_DR_ID,
-];
 
 pub const FACADE_CENTER_TILE_ID: u32 = 40;
 pub const FACADE_LEFT_TILE_ID: u32 = 57;
@@ -112,6 +306,40 @@ pub const _MONSTER_PIPE_OPEN_TILE_ID: u32 = 20;
 pub const POOL_EMPTY_TILE_ID: u32 = 31;
 pub const _POOL_FULL_TILE_ID: u32 = 32;
 
+pub const SPIKES_UP_TILE_ID: u32 = 41;
+pub const SPIKES_DOWN_TILE_ID: u32 = 56;
+pub const PRESSURE_PLATE_UP_TILE_ID: u32 = 54;
+pub const PRESSURE_PLATE_DOWN_TILE_ID: u32 = 55;
+/// pale stone slab, standing in for ice
+pub const ICE_TILE_ID: u32 = 39;
+/// wall face with a burning sconce
+pub const TORCH_TILE_ID: u32 = 29;
+
+/// seconds each frame of an animated tile is shown for
+pub const TORCH_FRAME_TIME: f32 = 0.18;
+pub const POOL_FRAME_TIME: f32 = 0.5;
+pub const PIPE_FRAME_TIME: f32 = 0.25;
+
+/// seconds a cycling guard door spends open, then shut
+pub const DOOR_CYCLE_OPEN_TIME: f64 = 2.;
+pub const DOOR_CYCLE_CLOSED_TIME: f64 = 1.5;
+/// seconds a guard door's bars take to drop shut
+pub const DOOR_CLOSE_TIME: f64 = 0.3;
+/// seconds after a door starts closing that a guard walking in still gets trapped
+pub const DOOR_TRAP_GRACE_TIME: f64 = 0.2;
+/// jailed guards pace across their cell door, this many tiles from side to side
+pub const JAILED_PACE_WIDTH: f32 = 1.;
+/// how quickly jailed guards pace, in radians of their back and forth per second
+pub const JAILED_PACE_SPEED: f64 = 1.5;
+
+pub const LOCKED_DOOR_LEFT_TILE_ID: u32 = 76;
+pub const LOCKED_DOOR_CENTER_TILE_ID: u32 = 77;
+pub const LOCKED_DOOR_RIGHT_TILE_ID: u32 = 78;
+
+/// iron bars across an open cell door, to tell a boss cell apart
+pub const REINFORCED_DOOR_LEFT_TILE_ID: u32 = 76;
+pub const REINFORCED_DOOR_RIGHT_TILE_ID: u32 = 78;
+
 pub const PLAYER_ACCELERATION: f32 = 55.;
 pub const PLAYER_BRAKING: f32 = 10.;
 pub const PLAYER_FRICTION: f32 = 0.;
@@ -120,6 +348,8 @@ pub const PLAYER_LINEAR_DAMPING: f32 = 2.2;
 pub const PLAYER_MASS: f32 = 100.;
 pub const PLAYER_RADIUS: f32 = 0.5;
 pub const PLAYER_RESTITUTION: f32 = 0.5;
+/// move the player with a kinematic character controller, sliding along walls instead of bouncing
+pub const KINEMATIC_PLAYER: bool = false;
 pub const PLAYER_SPRITE_ID: u32 = 112;
 pub const PLAYER_MAX_HEALTH: u32 = 5;
 
@@ -128,28 +358,233 @@ pub const GUARD_BRAKING: f32 = 10.;
 pub const GUARD_FRICTION: f32 = 0.;
 pub const GUARD_FRICTION_COMBINE_RULE: CoefficientCombineRule = CoefficientCombineRule::Min;
 pub const GUARD_LINEAR_DAMPING: f32 = 2.;
+/// on ice, characters get a grip slowly, can barely stop, and slide on and on
+pub const ICE_ACCELERATION_FACTOR: f32 = 0.4;
+pub const ICE_BRAKING_FACTOR: f32 = 0.05;
+pub const ICE_DAMPING_FACTOR: f32 = 0.1;
 pub const GUARD_MASS: f32 = 200.;
 pub const GUARD_RADIUS: f32 = 0.5;
 pub const GUARD_RESTITUTION: f32 = 0.5;
 pub const GUARD_SPRITE_ID: u32 = 96;
 pub const GUARD_MAX_HEALTH: u32 = 3;
+/// a guard pushing at something this slowly, in tiles per second, is blocked by it
+pub const GUARD_BLOCKED_SPEED: f32 = 0.3;
+/// seconds a guard stays blocked before it steers around, and how long it steers around for
+pub const GUARD_BLOCKED_TIME: f64 = 0.5;
+pub const GUARD_DETOUR_TIME: f64 = 0.8;
+/// alerted guards closer together than this steer apart, so a group spreads out instead of piling up
+pub const GUARD_SEPARATION_RADIUS: f32 = 2.5;
+/// how hard that pushes, against a pull of 1 toward their target
+pub const GUARD_SEPARATION_WEIGHT: f32 = 1.5;
+/// radius of the ring alerted guards spread around a player on, before closing in
+pub const GUARD_SURROUND_RADIUS: f32 = 3.;
+
+/// crates are heavy enough that shoving one is slow going, and stop soon after
+pub const CRATE_MASS: f32 = 300.;
+pub const CRATE_LINEAR_DAMPING: f32 = 8.;
+pub const CRATE_HALF_SIZE: f32 = 0.45;
+pub const CRATE_SPRITE_ID: u32 = 61;
+
+/// a boss guard shows up on every floor that is a multiple of this
+pub const BOSS_FLOOR_INTERVAL: u32 = 3;
+pub const BOSS_ACCELERATION: f32 = 24.;
+pub const BOSS_MASS: f32 = 600.;
+pub const BOSS_RADIUS: f32 = 0.9;
+pub const BOSS_DRAW_SIZE: f32 = 2.;
+pub const BOSS_SPRITE_ID: u32 = 109;
+pub const BOSS_MAX_HEALTH: u32 = 8;
+/// an alerted boss this close to the player winds up a charge
+pub const BOSS_CHARGE_DISTANCE: f32 = 7.;
+/// how long the boss stands still, telegraphing its charge
+pub const BOSS_CHARGE_WINDUP: f64 = 0.8;
+pub const BOSS_CHARGE_COOLDOWN: f64 = 3.;
+/// speed the charge launches the boss at
+pub const BOSS_CHARGE_SPEED: f32 = 20.;
 
 pub const QUESTION_MARK_TILE_ID: u32 = 127;
+pub const RETICLE_TILE_ID: u32 = 60;
 pub const HEART_TILE_ID: u32 = 128;
+/// how solid the heart that is growing back is drawn on the HUD
+pub const REGEN_HEART_ALPHA: f32 = 0.6;
+/// how solid the empty part of a guard's suspicion meter is drawn
+pub const SUSPICION_METER_ALPHA: f32 = 0.3;
+/// seconds a guard's health bar stays up after it is hurt, the last of them fading out
+pub const GUARD_HEALTH_BAR_TIME: f64 = 3.;
+pub const GUARD_HEALTH_BAR_FADE_TIME: f64 = 0.5;
+/// height of the health bar in tiles, and the gap between its segments
+pub const GUARD_HEALTH_BAR_HEIGHT: f32 = 0.12;
+pub const GUARD_HEALTH_BAR_GAP: f32 = 0.04;
 pub const GRAVE_TILE_ID: u32 = 64;
+pub const ROCK_TILE_ID: u32 = 102;
+// the tileset has no key, so keys borrow the little gray vial
+pub const KEY_TILE_ID: u32 = 125;
+pub const SPEED_POTION_TILE_ID: u32 = 116;
 
 pub const DAMAGE_COOLDOWN: f64 = 1.;
-pub const KNOCKBACK_COOLDOWN: f64 = 0.2;
 pub const ALERTED_INDICATOR_COOLDOWN: f64 = 3.;
 pub const ATTACK_COOLDOWN: f64 = 0.4;
 pub const ATTACK_DURATION: f64 = 0.1;
 
-pub const PLAYER_GUARD_KNOCKBACK: f32 = 32.;
-pub const PLAYER_ATTACK_KNOCKBACK: f32 = 45.;
+/// knockback impulses, in mass times tiles per second. the guard's hit on the player and the
+/// player's attack on a guard.
+pub const PLAYER_GUARD_KNOCKBACK: f32 = 3200.;
+pub const PLAYER_ATTACK_KNOCKBACK: f32 = 9000.;
 pub const GUARD_ALERT_DISTANCE: f32 = 10.;
+/// suspicion a guard builds per second with a player in sight at the edge of its range. it builds
+/// twice as fast up close, and faster again while the player sprints.
+pub const GUARD_SUSPICION_RATE: f32 = 1.2;
+pub const GUARD_SUSPICION_SPRINT_FACTOR: f32 = 2.;
+/// suspicion a guard that isn't chasing yet loses per second
+pub const GUARD_SUSPICION_DECAY: f32 = 0.3;
+/// suspicion past which a guard goes to look. a full meter sets it chasing.
+pub const GUARD_INVESTIGATE_SUSPICION: f32 = 0.4;
+/// a guard only sees a hidden player from closer than this, just short of where it stops when
+/// it comes to look, so it has to stumble right into the hiding spot
+pub const GUARD_HIDDEN_NOTICE_DISTANCE: f32 = 0.8;
+/// cell size of the spatial hashes that guards and doors are looked up in
+pub const SPATIAL_HASH_CELL_SIZE: f32 = 8.;
+/// how far from a guard's center a door's center can be, for the door to be touching it
+pub const GUARD_DOOR_REACH: f32 = 2.;
+/// open cell doors pointed out during a chase, when the assist is on, and how far away they can be
+pub const DOOR_MARKER_COUNT: usize = 3;
+pub const DOOR_MARKER_RANGE: f32 = 16.;
+/// tiles from the player's center the markers sit at, and how faint they are
+pub const DOOR_MARKER_DISTANCE: f32 = 2.;
+pub const DOOR_MARKER_ALPHA: f32 = 0.35;
 pub const PLAYER_ATTACK_RADIUS: f32 = 1.6;
+/// seconds of holding the attack for a full charge
+pub const ATTACK_CHARGE_TIME: f64 = 1.;
+/// knockback and reach of a fully charged attack, relative to a quick one
+pub const ATTACK_CHARGE_KNOCKBACK_FACTOR: f32 = 2.;
+pub const ATTACK_CHARGE_RADIUS_FACTOR: f32 = 1.5;
+/// health a fully charged attack takes off a guard, on difficulties where guards can fall
+pub const CHARGED_ATTACK_DAMAGE: u32 = 1;
+/// seconds a guard that falls for good takes to sink into its grave
+pub const GUARD_DEATH_ANIMATION_TIME: f64 = 0.6;
+
+/// coins the first level of a shop upgrade costs. each level after costs that much more.
+pub const UPGRADE_BASE_COST: u32 = 10;
+pub const UPGRADE_MAX_LEVEL: u32 = 3;
+/// each level of quick attack multiplies the attack cooldown by this
+pub const QUICK_ATTACK_FACTOR: f64 = 0.8;
+/// each level of long reach adds this share of the base attack radius
+pub const LONG_REACH_BONUS: f32 = 0.2;
+
+/// screen shake. trauma runs from 0 to 1, and the shake grows with its square.
+pub const SHAKE_MAX_OFFSET: f32 = 0.4;
+pub const SHAKE_TRAUMA_DECAY: f32 = 1.5;
+pub const DAMAGE_TRAUMA: f32 = 0.6;
+pub const HEAVY_KNOCKBACK_TRAUMA: f32 = 0.4;
+/// frames the game holds still when a player is hurt, and when they land a heavy shove
+pub const DAMAGE_HIT_STOP_FRAMES: u32 = 3;
+pub const HEAVY_KNOCKBACK_HIT_STOP_FRAMES: u32 = 2;
+/// seconds a hurt player flashes red
+pub const DAMAGE_FLASH_TIME: f64 = 0.15;
+pub const DAMAGE_FLASH_COLOR: Color = Color::new(1., 0.15, 0.15, 0.8);
+
+/// tiles kept between the players and the edge of the screen when the camera frames them both
+pub const CAMERA_FRAME_MARGIN: f32 = 4.;
+/// how many times more of the map the camera shows when zoomed out as far as it goes
+pub const CAMERA_MAX_ZOOM_OUT: f32 = 2.;
+/// where a second player's hearts go on the HUD, in simulated pixels
+pub const PARTNER_HUD_ORIGIN: Vec2 = vec2(16., SIMULATED_RESOLUTION.y as f32 - 64.);
+/// a knockback impulse at least this strong counts as a heavy hit
+pub const HEAVY_KNOCKBACK_THRESHOLD: f32 = 14000.;
+
+/// light level of the world away from any light
+pub const AMBIENT_LIGHT: Color = Color::new(0.25, 0.25, 0.35, 1.);
+/// radius of lights, in tiles
+pub const PLAYER_LIGHT_RADIUS: f32 = 7.;
+pub const PLAYER_LIGHT_COLOR: Color = Color::new(0.8, 0.8, 0.75, 1.);
+pub const TORCH_LIGHT_RADIUS: f32 = 4.;
+pub const TORCH_LIGHT_COLOR: Color = Color::new(0.9, 0.55, 0.25, 1.);
+/// how much a torch's radius wavers as it flickers
+pub const TORCH_FLICKER: f32 = 0.06;
+/// width and height of the texture lights are drawn with
+pub const LIGHT_TEXTURE_SIZE: u16 = 64;
 
 pub const DEATH_LINGER_TIME: f64 = 1.;
+/// seconds to look around after everyone is down, before the game over screen comes up anyway
+pub const SPECTATE_TIME: f64 = 15.;
+/// tiles per second the spectator camera pans
+pub const SPECTATOR_PAN_SPEED: f32 = 12.;
 
 pub const PLAYER_KNOCKBACK_COOLDOWN: f64 = 0.1;
 pub const GUARD_KNOCKBACK_COOLDOWN: f64 = 0.4;
+
+pub const THROW_COOLDOWN: f64 = 1.;
+pub const PROJECTILE_SPEED: f32 = 18.;
+pub const PROJECTILE_RADIUS: f32 = 0.2;
+pub const PROJECTILE_DRAW_SIZE: f32 = 0.5;
+/// how long a rock flies before landing, which caps its range
+pub const PROJECTILE_LIFETIME: f64 = 0.6;
+pub const PROJECTILE_STUN_DURATION: f64 = 1.5;
+/// health a guard loses to a thrown rock
+pub const PROJECTILE_DAMAGE: u32 = 1;
+pub const PROJECTILE_NOISE_RADIUS: f32 = 12.;
+
+/// how close a guard needs to get to a noise before giving up the search
+pub const GUARD_INVESTIGATE_DISTANCE: f32 = 1.;
+
+/// noise radii in tiles. guards inside the radius go to investigate, walls or not
+pub const FOOTSTEP_NOISE_RADIUS: f32 = 5.;
+pub const FOOTSTEP_NOISE_INTERVAL: f64 = 0.4;
+/// characters moving slower than this (tiles/s) are considered standing still
+pub const FOOTSTEP_SPEED_THRESHOLD: f32 = 1.;
+pub const ATTACK_NOISE_RADIUS: f32 = 8.;
+pub const DOOR_SLAM_NOISE_RADIUS: f32 = 16.;
+/// the exit grinding open carries across most of the floor
+pub const EXIT_OPEN_NOISE_RADIUS: f32 = 32.;
+
+pub const SNEAK_ACCELERATION_FACTOR: f32 = 0.5;
+pub const SNEAK_NOISE_FACTOR: f32 = 0.3;
+
+pub const SPRINT_ACCELERATION_FACTOR: f32 = 1.6;
+/// seconds of sprinting on a full bar. sprinting drains one per second.
+pub const PLAYER_MAX_STAMINA: f32 = 3.;
+/// stamina recovered per second when not sprinting
+pub const STAMINA_REGEN_RATE: f32 = 0.5;
+pub const STAMINA_BAR_WIDTH: f32 = 128.;
+
+/// seconds spikes spend up, then down
+pub const SPIKES_UP_TIME: f64 = 1.;
+pub const SPIKES_DOWN_TIME: f64 = 2.;
+pub const SPIKE_DAMAGE: u32 = 1;
+/// health a player loses bumping into a guard, and into a boss
+pub const GUARD_CONTACT_DAMAGE: u32 = 1;
+pub const BOSS_CONTACT_DAMAGE: u32 = 2;
+pub const TRAP_SENSOR_HALF_SIZE: f32 = 0.3;
+/// a staff, standing in for a lever's handle
+pub const LEVER_SPRITE_ID: u32 = 130;
+/// radians a lever's handle leans to either side
+pub const LEVER_LEAN: f32 = 0.5;
+pub const CHEST_CLOSED_SPRITE_ID: u32 = 89;
+pub const CHEST_OPEN_SPRITE_ID: u32 = 90;
+pub const BARREL_SPRITE_ID: u32 = 82;
+
+pub const ITEM_RADIUS: f32 = 0.4;
+pub const ITEM_DRAW_SIZE: f32 = 0.75;
+/// chance that a guard room gets an item on its floor
+pub const ITEM_SPAWN_PROB: f32 = 0.5;
+pub const SPEED_POTION_DURATION: f64 = 8.;
+pub const SPEED_POTION_ACCELERATION_FACTOR: f32 = 1.5;
+
+/// a snail, a framed rune and a slime, for the HUD's status effect icons
+pub const SLOW_TILE_ID: u32 = 123;
+pub const INVULNERABLE_TILE_ID: u32 = 101;
+pub const POISONED_TILE_ID: u32 = 108;
+pub const SLOW_ACCELERATION_FACTOR: f32 = 0.5;
+/// slowed characters are knocked this much further
+pub const SLOW_KNOCKBACK_FACTOR: f32 = 1.5;
+/// seconds a player is slowed after a guard runs into them
+pub const GUARD_HIT_SLOW_DURATION: f64 = 1.;
+/// seconds players can't be hurt at the start of a floor
+pub const SPAWN_INVULNERABLE_DURATION: f64 = 2.;
+/// seconds counted down before a round starts, with everyone held still
+pub const ROUND_COUNTDOWN_TIME: f64 = 3.;
+/// spikes are rusty, and poison whoever they catch for a while
+pub const SPIKE_POISON_DURATION: f64 = 4.5;
+pub const POISON_TICK_INTERVAL: f64 = 2.;
+pub const POISON_DAMAGE: u32 = 1;
+/// width and height of a status effect icon on the HUD, in screen pixels
+pub const STATUS_ICON_SIZE: f32 = 24.;
@@ -0,0 +1,56 @@
+use macroquad::{
+    color::Color,
+    math::{vec2, Rect},
+    texture::{draw_texture_ex, DrawTextureParams},
+};
+use macroquad_tiled::Map as TiledMap;
+
+use crate::{
+    constants::{GRAVE_TILE_ID, GUARD_DEATH_ANIMATION_TIME, TILESET_MAP_ID},
+    map::sprite_source,
+};
+
+/// What's left of a guard that was put down for good. It plays out the guard falling, then stays
+/// behind as a grave for the rest of the floor. Nothing can bump into it.
+pub struct Corpse {
+    /// where the guard was drawn when it died
+    draw_rect: Rect,
+    sprite_id: u32,
+    died_at: f64,
+}
+
+impl Corpse {
+    pub fn new(draw_rect: Rect, sprite_id: u32, died_at: f64) -> Self {
+        Self {
+            draw_rect,
+            sprite_id,
+            died_at,
+        }
+    }
+
+    pub fn draw(&self, tile_map: &TiledMap, now: f64) {
+        let progress = ((now - self.died_at) / GUARD_DEATH_ANIMATION_TIME) as f32;
+        if progress >= 1. {
+            tile_map.spr(TILESET_MAP_ID, GRAVE_TILE_ID, self.draw_rect);
+            return;
+        }
+
+        // the guard flushes red and sinks into the floor, fading as it goes
+        let tileset = &tile_map.tilesets[TILESET_MAP_ID];
+        let mut source = sprite_source(tileset, self.sprite_id);
+        source.h *= 1. - progress;
+        let rect = self.draw_rect;
+        let height = rect.h * (1. - progress);
+        draw_texture_ex(
+            &tileset.texture,
+            rect.x,
+            rect.y + rect.h - height,
+            Color::new(1., 1. - progress, 1. - progress, 1. - progress / 2.),
+            DrawTextureParams {
+                dest_size: Some(vec2(rect.w, height)),
+                source: Some(source),
+                ..Default::default()
+            },
+        );
+    }
+}
@@ -0,0 +1,73 @@
+use macroquad::math::{vec2, Rect, Vec2};
+use macroquad_tiled::Map as TiledMap;
+use nalgebra::vector;
+use rapier2d::{
+    dynamics::{RigidBodyBuilder, RigidBodyHandle, RigidBodySet},
+    geometry::{ColliderBuilder, ColliderSet},
+};
+
+use crate::{
+    camera::snap_to_pixel,
+    constants::{
+        CRATE_HALF_SIZE, CRATE_LINEAR_DAMPING, CRATE_MASS, CRATE_SPRITE_ID, TILESET_MAP_ID,
+    },
+    entity::{EntityKind, EntityRegistry},
+    physics::{CollisionLayer, Physics},
+};
+
+/// A heavy box that can be shoved around, to block a corridor or wedge a door
+pub struct Crate {
+    /// top left of the tile-sized box
+    pub position: Vec2,
+    body_handle: RigidBodyHandle,
+}
+
+impl Crate {
+    pub fn create(
+        position: Vec2,
+        collider_set: &mut ColliderSet,
+        rigid_body_set: &mut RigidBodySet,
+        entities: &mut EntityRegistry,
+    ) -> Self {
+        let body = RigidBodyBuilder::dynamic()
+            .translation(vector![position.x + 0.5, position.y + 0.5])
+            .lock_rotations()
+            .linear_damping(CRATE_LINEAR_DAMPING)
+            .build();
+        let collider = ColliderBuilder::cuboid(CRATE_HALF_SIZE, CRATE_HALF_SIZE)
+            .mass(CRATE_MASS)
+            .friction(0.)
+            .collision_groups(CollisionLayer::Wall.groups())
+            .build();
+        let body_handle = rigid_body_set.insert(body);
+        let collider_handle =
+            collider_set.insert_with_parent(collider, body_handle, rigid_body_set);
+        let id = entities.spawn(EntityKind::Crate);
+        entities.register_collider(collider_handle, id);
+        entities.register_body(body_handle, id);
+
+        Self {
+            position,
+            body_handle,
+        }
+    }
+
+    /// Follow the box wherever it was shoved
+    pub fn post_physics(&mut self, physics: &Physics) {
+        let translation = physics.bodies[self.body_handle].translation();
+        self.position = vec2(translation.x - 0.5, translation.y - 0.5);
+    }
+
+    /// Put the box somewhere outright, like where the host says it is
+    pub fn set_position(&mut self, position: Vec2, physics: &mut Physics) {
+        self.position = position;
+        physics.bodies[self.body_handle]
+            .set_translation(vector![position.x + 0.5, position.y + 0.5], true);
+    }
+
+    pub fn draw(&self, tile_map: &TiledMap) {
+        let position = snap_to_pixel(self.position);
+        let draw_rect = Rect::new(position.x, position.y, 1., 1.);
+        tile_map.spr(TILESET_MAP_ID, CRATE_SPRITE_ID, draw_rect);
+    }
+}
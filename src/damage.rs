@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+
+use macroquad::math::Vec2;
+use nanoserde::{DeJson, SerJson};
+
+use crate::entity::EntityId;
+
+/// What dealt a blow, which decides its side effects and how a death is reported
+#[derive(Clone, Copy, Debug, PartialEq, Eq, DeJson, SerJson)]
+pub enum DamageKind {
+    Guard,
+    Boss,
+    Spikes,
+    Poison,
+    Rock,
+    /// a player's fully charged shove
+    Attack,
+}
+
+impl DamageKind {
+    /// Locale key for what dealt the damage, as the game over screen names it
+    pub fn name_key(&self) -> &'static str {
+        match self {
+            DamageKind::Guard => "damage-guard",
+            DamageKind::Boss => "damage-boss",
+            DamageKind::Spikes => "damage-spikes",
+            DamageKind::Poison => "damage-poison",
+            DamageKind::Rock => "damage-rock",
+            DamageKind::Attack => "damage-attack",
+        }
+    }
+}
+
+/// A blow about to land on a character
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Damage {
+    /// whatever dealt it. blows with no source, like poison ticks, don't wait out invulnerability.
+    pub source: Option<EntityId>,
+    pub amount: u32,
+    pub kind: DamageKind,
+    /// impulse to knock the character back with. it lands even when the damage doesn't.
+    pub knockback: Vec2,
+}
+
+/// When each source last hurt a character. After a hit the character is invulnerable to that
+/// source for a while, but not to anything else, so two guards can both land a blow.
+#[derive(Clone, Debug, Default)]
+pub struct InvulnerabilityFrames {
+    last_hit: HashMap<EntityId, f64>,
+}
+
+impl InvulnerabilityFrames {
+    /// Record a hit from `source` at `now`, unless it hit too recently. Returns whether it can hit.
+    pub fn try_hit(&mut self, source: EntityId, now: f64, cooldown: f64) -> bool {
+        if self
+            .last_hit
+            .get(&source)
+            .is_some_and(|last_hit| now < last_hit + cooldown)
+        {
+            return false;
+        }
+        self.last_hit.insert(source, now);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::{EntityKind, EntityRegistry};
+
+    #[test]
+    fn test_invulnerability_is_tracked_per_source() {
+        let mut entities = EntityRegistry::default();
+        let guard = entities.spawn(EntityKind::Guard);
+        let trap = entities.spawn(EntityKind::SpikeTrap);
+        let mut frames = InvulnerabilityFrames::default();
+
+        assert!(frames.try_hit(guard, 0., 1.));
+        assert!(!frames.try_hit(guard, 0.5, 1.));
+        assert!(frames.try_hit(trap, 0.5, 1.));
+        assert!(frames.try_hit(guard, 1., 1.));
+        assert!(!frames.try_hit(trap, 1., 1.));
+    }
+}
@@ -0,0 +1,73 @@
+use macroquad::{
+    color::{Color, GREEN, WHITE, YELLOW},
+    math::{vec2, Vec2},
+    shapes::{draw_circle_lines, draw_rectangle_lines},
+    text::{draw_text_ex, TextParams},
+};
+
+use crate::{constants::SIMULATED_TILE_PX, physics::Physics};
+
+/// width of overlay lines, one simulated pixel
+const LINE_THICKNESS: f32 = 1. / SIMULATED_TILE_PX;
+const LABEL_FONT_SIZE: u16 = 16;
+/// distance between stacked labels, in tiles
+pub const LABEL_LINE_HEIGHT: f32 = LABEL_FONT_SIZE as f32 / SIMULATED_TILE_PX;
+
+/// Draw every collider as a wireframe in world space. Solid colliders are green and sensors
+/// are yellow.
+pub fn draw_colliders(physics: &Physics) {
+    for (_, collider) in physics.colliders.iter() {
+        let color = if collider.is_sensor() { YELLOW } else { GREEN };
+        let center = collider.translation();
+        let shape = collider.shape();
+        if let Some(ball) = shape.as_ball() {
+            draw_circle_lines(center.x, center.y, ball.radius, LINE_THICKNESS, color);
+        } else if let Some(cuboid) = shape.as_cuboid() {
+            let half = cuboid.half_extents;
+            draw_rectangle_lines(
+                center.x - half.x,
+                center.y - half.y,
+                half.x * 2.,
+                half.y * 2.,
+                LINE_THICKNESS,
+                color,
+            );
+        } else {
+            let aabb = collider.compute_aabb();
+            draw_rectangle_lines(
+                aabb.mins.x,
+                aabb.mins.y,
+                aabb.maxs.x - aabb.mins.x,
+                aabb.maxs.y - aabb.mins.y,
+                LINE_THICKNESS,
+                color,
+            );
+        }
+    }
+}
+
+/// Label every rigid body with the kind and ID of the entity that owns it
+pub fn draw_body_labels(physics: &Physics) {
+    for (handle, body) in physics.bodies.iter() {
+        let Some((id, kind)) = physics.entities.body_entity(handle) else {
+            continue;
+        };
+        let position = vec2(body.translation().x, body.translation().y + 1.);
+        draw_label(&format!("{:?} {}", kind, id.to_bits()), position, WHITE);
+    }
+}
+
+/// Draw a small label in world space, with its baseline at `position`
+pub fn draw_label(text: &str, position: Vec2, color: Color) {
+    draw_text_ex(
+        text,
+        position.x,
+        position.y,
+        TextParams {
+            font_size: LABEL_FONT_SIZE,
+            font_scale: 1. / SIMULATED_TILE_PX,
+            color,
+            ..Default::default()
+        },
+    );
+}
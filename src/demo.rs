@@ -0,0 +1,106 @@
+use macroquad::{
+    color::WHITE,
+    math::{vec2, Rect},
+    texture::{draw_texture_ex, DrawTextureParams},
+};
+use macroquad_tiled::TileSet;
+
+use crate::{
+    constants::{
+        DEMO_LOOP_TIME, DOOR_LEFT_CLOSED_TILE_ID, DOOR_LEFT_OPEN_TILE_ID,
+        DOOR_RIGHT_CLOSED_TILE_ID, DOOR_RIGHT_OPEN_TILE_ID, GUARD_SPRITE_ID, PLAYER_SPRITE_ID,
+    },
+    map::sprite_source,
+};
+
+/// A little looping scene next to a line of the instructions, acting out what it describes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Demo {
+    /// the player pacing back and forth
+    Walk,
+    /// the player shoving a guard back
+    Shove,
+    /// a cell door swinging open and shut beside the player
+    Interact,
+    /// the player shoving a guard into a cell, which shuts behind it
+    Trap,
+}
+
+impl Demo {
+    /// Tiles wide the demo is, for one tile of height
+    pub const WIDTH: f32 = 4.;
+
+    /// Draw the demo `time` seconds in, filling `area` from the left. One tile is as tall as the
+    /// area.
+    pub fn draw(self, tileset: &TileSet, area: Rect, time: f64) {
+        let tile = area.h;
+        let sprite = |id: u32, x: f32, y: f32, flip_x: bool| {
+            draw_texture_ex(
+                &tileset.texture,
+                area.x + x * tile,
+                area.y + y * tile,
+                WHITE,
+                DrawTextureParams {
+                    dest_size: Some(vec2(tile, tile)),
+                    source: Some(sprite_source(tileset, id)),
+                    flip_x,
+                    ..Default::default()
+                },
+            );
+        };
+        // how far through the loop, from 0 to 1
+        let t = (time / DEMO_LOOP_TIME).fract() as f32;
+        // a little hop with each step
+        let step = |t: f32| -((t * 24.).sin().abs() * 0.08);
+        let door = |x: f32, open: bool| {
+            if open {
+                sprite(DOOR_LEFT_OPEN_TILE_ID, x, 0., false);
+                sprite(DOOR_RIGHT_OPEN_TILE_ID, x + 1., 0., false);
+            } else {
+                sprite(DOOR_LEFT_CLOSED_TILE_ID, x, 0., false);
+                sprite(DOOR_RIGHT_CLOSED_TILE_ID, x + 1., 0., false);
+            }
+        };
+
+        // sprites face left, so they are flipped to face right
+        match self {
+            Demo::Walk => {
+                let sway = (t * std::f32::consts::TAU).sin();
+                let heading_right = (t * std::f32::consts::TAU).cos() > 0.;
+                let x = (sway + 1.) / 2. * (Self::WIDTH - 1.);
+                sprite(PLAYER_SPRITE_ID, x, step(t), heading_right);
+            }
+            Demo::Shove => {
+                // walk up to the guard, then knock it back
+                let player_x = ease(t / 0.4) * 1.2;
+                let guard_x = 2. + ease((t - 0.4) / 0.2) * 1.;
+                let player_y = if t < 0.4 { step(t) } else { 0. };
+                sprite(GUARD_SPRITE_ID, guard_x, 0., false);
+                sprite(PLAYER_SPRITE_ID, player_x, player_y, true);
+            }
+            Demo::Interact => {
+                sprite(PLAYER_SPRITE_ID, 0.5, 0., true);
+                door(2., t % 0.5 < 0.25);
+            }
+            Demo::Trap => {
+                // shove the guard into the open cell, where it stays once the door shuts
+                let player_x = ease(t / 0.3) * 0.5;
+                let guard_x = 1.5 + ease((t - 0.3) / 0.3) * 1.;
+                let shut = t > 0.7;
+                if !shut {
+                    door(2., true);
+                    sprite(GUARD_SPRITE_ID, guard_x, 0., false);
+                } else {
+                    door(2., false);
+                }
+                sprite(PLAYER_SPRITE_ID, player_x, 0., true);
+            }
+        }
+    }
+}
+
+/// Smoothly from 0 to 1 as `t` goes from 0 to 1, and held at either end outside that
+fn ease(t: f32) -> f32 {
+    let t = t.clamp(0., 1.);
+    t * t * (3. - 2. * t)
+}
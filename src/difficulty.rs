@@ -0,0 +1,62 @@
+/// How forgiving a run is, picked in the settings menu
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Difficulty {
+    /// locale key for the difficulty's name in the settings menu
+    pub name: &'static str,
+    /// health won back out of combat. None leaves healing to heart pickups.
+    pub regen: Option<Regen>,
+    /// hearts a heart pickup restores
+    pub heart_heal: u32,
+    /// fully charged shoves wear guards down, until they fall for good
+    pub lethal_attacks: bool,
+}
+
+/// Slow healing for a player that is out of danger
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Regen {
+    /// seconds since the player was last hurt, and since any guard last gave chase, before it starts
+    pub delay: f64,
+    /// hearts per second
+    pub rate: f32,
+}
+
+impl Difficulty {
+    pub const EASY: Self = Self {
+        name: "difficulty-easy",
+        regen: Some(Regen {
+            delay: 5.,
+            rate: 0.25,
+        }),
+        heart_heal: 2,
+        lethal_attacks: true,
+    };
+
+    pub const NORMAL: Self = Self {
+        name: "difficulty-normal",
+        regen: None,
+        heart_heal: 1,
+        lethal_attacks: false,
+    };
+
+    /// in the order the settings menu cycles through them, starting from the default
+    pub const ALL: &'static [Self] = &[Self::NORMAL, Self::EASY];
+
+    pub fn get(index: usize) -> Self {
+        Self::ALL[index % Self::ALL.len()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::locale::Localizer;
+
+    #[test]
+    fn test_every_difficulty_has_a_name() {
+        let localizer = Localizer::default();
+        for difficulty in Difficulty::ALL {
+            assert_ne!(localizer.get(difficulty.name), difficulty.name);
+        }
+        assert_eq!(Difficulty::get(0), Difficulty::NORMAL);
+    }
+}
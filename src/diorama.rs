@@ -0,0 +1,110 @@
+use macroquad::{
+    camera::{set_camera, set_default_camera, Camera2D},
+    color::{Color, WHITE},
+    math::{vec2, Rect, Vec2},
+    shapes::draw_rectangle,
+    texture::{draw_texture_ex, DrawTextureParams},
+    time::get_time,
+    window::{screen_height, screen_width},
+};
+use macroquad_tiled::{Layer, TileSet};
+
+use crate::{
+    constants::{DIORAMA_PAN_SPEED, DIORAMA_SHADE, DIORAMA_VIEW_HEIGHT},
+    map::{animation::TileAnimator, clip_to_tiles, copy_tileset, sprite_source},
+    world::World,
+};
+
+/// A freshly generated floor with nobody in it, slowly panned across behind the main menu.
+/// Nothing in it is simulated; its layers are only drawn.
+pub struct Diorama {
+    /// terrain, props and wall tops, in drawing order
+    layers: [Layer; 3],
+    animator: TileAnimator,
+    tileset: TileSet,
+    /// when the pan started
+    start_time: f64,
+}
+
+impl Diorama {
+    pub fn new(world: World, tileset: &TileSet) -> Self {
+        Self {
+            animator: TileAnimator::new(&world.theme.animations),
+            layers: [world.terrain, world.props, world.overhead],
+            tileset: copy_tileset(tileset),
+            start_time: get_time(),
+        }
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        self.animator.update(dt);
+    }
+
+    /// Draw the floor over the whole screen, shaded so text over it stands out
+    pub fn draw(&self) {
+        let view = self.view();
+        set_camera(&Camera2D::from_display_rect(view));
+        for layer in &self.layers {
+            self.draw_layer(layer, view);
+        }
+        set_default_camera();
+        draw_rectangle(
+            0.,
+            0.,
+            screen_width(),
+            screen_height(),
+            Color::new(0., 0., 0., DIORAMA_SHADE),
+        );
+    }
+
+    /// The part of the floor on screen, drifting back and forth across it on a slow figure of
+    /// eight so it never settles into a straight line
+    fn view(&self) -> Rect {
+        let size = vec2(
+            DIORAMA_VIEW_HEIGHT * screen_width() / screen_height(),
+            DIORAMA_VIEW_HEIGHT,
+        );
+        let map_size = vec2(self.layers[0].width as f32, self.layers[0].height as f32);
+        let t = (get_time() - self.start_time) * DIORAMA_PAN_SPEED;
+        let sway = vec2(t.sin() as f32, (t * 2.).sin() as f32);
+        // on a floor smaller than the window there is nowhere to pan to
+        let reach = ((map_size - size) / 2.).max(Vec2::ZERO);
+        let center = map_size / 2. + reach * sway;
+        Rect::new(
+            center.x - size.x / 2.,
+            center.y - size.y / 2.,
+            size.x,
+            size.y,
+        )
+    }
+
+    fn draw_layer(&self, layer: &Layer, view: Rect) {
+        let Some(tiles) = clip_to_tiles(view, layer.width, layer.height) else {
+            return;
+        };
+        let (left, top) = (tiles.x as u32, tiles.y as u32);
+        for y in top..top + tiles.h as u32 {
+            for x in left..left + tiles.w as u32 {
+                let Some(tile) = &layer.data[(y * layer.width + x) as usize] else {
+                    continue;
+                };
+                let (id, flip_x) = match self.animator.frame(tile.id, x, y) {
+                    Some(frame) => (frame.id, frame.flip_x),
+                    None => (tile.id, false),
+                };
+                draw_texture_ex(
+                    &self.tileset.texture,
+                    x as f32,
+                    y as f32,
+                    WHITE,
+                    DrawTextureParams {
+                        dest_size: Some(vec2(1., 1.)),
+                        source: Some(sprite_source(&self.tileset, id)),
+                        flip_x,
+                        ..Default::default()
+                    },
+                );
+            }
+        }
+    }
+}
@@ -1,55 +1,179 @@
-use macroquad::{
-    logging::info,
-    math::{vec2, UVec2, Vec2},
-};
-use macroquad_tiled::{Layer, Tile};
+use macroquad::math::{vec2, Rect, UVec2, Vec2};
+use macroquad_tiled::{Layer, Map as TiledMap, Tile};
 use nalgebra::vector;
-use rapier2d::geometry::{ColliderBuilder, ColliderHandle, ColliderSet};
+use rapier2d::{
+    geometry::{ColliderBuilder, ColliderHandle, ColliderSet},
+    pipeline::ActiveEvents,
+};
 
 use crate::{
+    clock,
     constants::{
-        DOOR_LEFT_CLOSED_TILE_ID, DOOR_LEFT_OPEN_TILE_ID, DOOR_RIGHT_CLOSED_TILE_ID,
-        DOOR_RIGHT_OPEN_TILE_ID, TILESET_MAP_ID, _MONSTER_PIPE_OPEN_TILE_ID, _POOL_FULL_TILE_ID,
+        DOOR_CLOSE_TIME, DOOR_CYCLE_CLOSED_TIME, DOOR_CYCLE_OPEN_TIME, DOOR_TRAP_GRACE_TIME,
     },
-    map::mapgen::xytoi,
+    entity::{EntityId, EntityKind, EntityRegistry},
+    event::{EventQueue, GameEvent},
+    interact::Interactable,
+    map::{mapgen::xytoi, sprite_source, theme::TileTheme},
+    physics::{CollisionLayer, Physics},
 };
 
 pub struct GuardDoor {
+    pub id: EntityId,
     position: UVec2,
     pub is_open: bool,
+    /// reinforced doors only hold bosses, and regular doors can't hold them
+    pub reinforced: bool,
+    /// cycling doors swing open and shut on a timer, offset by this many seconds so they don't
+    /// all move together. None for doors that stay open.
+    pub cycle_offset: Option<f64>,
+    /// shut for good, with a guard inside
+    pub is_trapped: bool,
+    /// when the bars started dropping. the tiles only change once they are all the way down.
+    closing_since: Option<f64>,
     pub collider_handle: ColliderHandle,
 }
 
 impl GuardDoor {
-    pub fn create(position: UVec2, collider_set: &mut ColliderSet) -> Self {
+    pub fn create(
+        position: UVec2,
+        reinforced: bool,
+        collider_set: &mut ColliderSet,
+        entities: &mut EntityRegistry,
+    ) -> Self {
         // set up collider
         let collider = ColliderBuilder::cuboid(1.0, 0.5)
             .translation(vector![position.x as f32 + 2.0, position.y as f32 + 0.5])
             .sensor(true)
+            .collision_groups(CollisionLayer::DoorSensor.groups())
             .build();
         let collider_handle = collider_set.insert(collider);
+        let id = entities.spawn(EntityKind::GuardDoor);
+        entities.register_collider(collider_handle, id);
 
         Self {
+            id,
             position,
             is_open: true,
+            reinforced,
+            cycle_offset: None,
+            is_trapped: false,
+            closing_since: None,
             collider_handle,
         }
     }
 
-    pub fn close_door(&mut self, layer: &mut Layer) {
+    /// Finish closing, and swing a cycling door open or shut when its timer says so
+    pub fn update(&mut self, layer: &mut Layer, theme: &TileTheme) {
+        self.finish_closing(layer, theme);
+        let Some(offset) = self.cycle_offset else {
+            return;
+        };
+        if self.is_trapped {
+            return;
+        }
+        let phase = (clock::now() + offset) % (DOOR_CYCLE_OPEN_TIME + DOOR_CYCLE_CLOSED_TIME);
+        self.swing(phase < DOOR_CYCLE_OPEN_TIME, layer, theme);
+    }
+
+    /// Open or shut the door at once, unless a guard is already locked inside
+    pub fn set_open(&mut self, is_open: bool, layer: &mut Layer, theme: &TileTheme) {
+        if self.is_trapped || is_open == self.is_open {
+            return;
+        }
+        self.is_open = is_open;
+        self.closing_since = None;
+        self.write_tiles(layer, theme);
+    }
+
+    /// Swing the door open or shut, unless a guard is already locked inside. Doors spring open
+    /// at once, but take a moment to close.
+    pub fn swing(&mut self, is_open: bool, layer: &mut Layer, theme: &TileTheme) {
+        if is_open {
+            self.set_open(true, layer, theme);
+        } else if !self.is_trapped && self.is_open {
+            self.is_open = false;
+            self.closing_since = Some(clock::now());
+        }
+    }
+
+    /// Shut the door for good on a trapped guard
+    pub fn close_door(&mut self) {
         self.is_open = false;
+        self.is_trapped = true;
+        self.closing_since.get_or_insert_with(clock::now);
+    }
+
+    /// Put the closed tiles in once the bars are all the way down
+    pub fn finish_closing(&mut self, layer: &mut Layer, theme: &TileTheme) {
+        if self
+            .closing_since
+            .is_some_and(|since| clock::now() > since + DOOR_CLOSE_TIME)
+        {
+            self.closing_since = None;
+            self.write_tiles(layer, theme);
+        }
+    }
+
+    /// Guards walking in still get trapped for a moment after the door starts to close
+    pub fn can_trap(&self) -> bool {
+        !self.is_trapped
+            && (self.is_open
+                || self
+                    .closing_since
+                    .is_some_and(|since| clock::now() < since + DOOR_TRAP_GRACE_TIME))
+    }
+
+    /// Draw the closed door's bars dropping down over the open doorway
+    pub fn draw(&self, tile_map: &TiledMap, theme: &TileTheme) {
+        let Some(since) = self.closing_since else {
+            return;
+        };
+        let Some(tileset) = tile_map.tilesets.get(&theme.tileset) else {
+            return;
+        };
+        let progress = ((clock::now() - since) / DOOR_CLOSE_TIME).min(1.) as f32;
+        for (x, sprite) in [(1, theme.doors.left_closed), (2, theme.doors.right_closed)] {
+            let mut source = sprite_source(tileset, sprite);
+            source.h *= progress;
+            let dest = Rect::new(
+                (self.position.x + x) as f32,
+                self.position.y as f32,
+                1.,
+                progress,
+            );
+            tile_map.spr_ex(&theme.tileset, source, dest);
+        }
+    }
+
+    fn write_tiles(&self, layer: &mut Layer, theme: &TileTheme) {
+        let (left, right) = if self.is_open && self.reinforced {
+            (theme.doors.reinforced_left, theme.doors.reinforced_right)
+        } else if self.is_open {
+            (theme.doors.left_open, theme.doors.right_open)
+        } else {
+            (theme.doors.left_closed, theme.doors.right_closed)
+        };
         let i = xytoi(self.position.x, self.position.y, layer);
         layer.data[i + 1] = Some(Tile {
-            id: DOOR_LEFT_CLOSED_TILE_ID,
-            tileset: TILESET_MAP_ID.into(),
+            id: left,
+            tileset: theme.tileset.clone(),
             attrs: "".into(),
         });
         layer.data[i + 2] = Some(Tile {
-            id: DOOR_RIGHT_CLOSED_TILE_ID,
-            tileset: TILESET_MAP_ID.into(),
+            id: right,
+            tileset: theme.tileset.clone(),
             attrs: "".into(),
         });
     }
+
+    pub fn position(&self) -> UVec2 {
+        self.position
+    }
+
+    pub fn center(&self) -> Vec2 {
+        self.position.as_vec2() + vec2(2.0, 0.5)
+    }
 }
 
 pub struct ExitDoor {
@@ -59,13 +183,20 @@ pub struct ExitDoor {
 }
 
 impl ExitDoor {
-    pub fn create(position: UVec2, collider_set: &mut ColliderSet) -> Self {
+    pub fn create(
+        position: UVec2,
+        collider_set: &mut ColliderSet,
+        entities: &mut EntityRegistry,
+    ) -> Self {
         // set up collider
         let collider = ColliderBuilder::cuboid(1.0, 0.5)
             .translation(vector![position.x as f32 + 2.0, position.y as f32 + 0.5])
             .sensor(true)
+            .collision_groups(CollisionLayer::DoorSensor.groups())
             .build();
         let collider_handle = collider_set.insert(collider);
+        let id = entities.spawn(EntityKind::ExitDoor);
+        entities.register_collider(collider_handle, id);
 
         Self {
             position,
@@ -74,39 +205,39 @@ impl ExitDoor {
         }
     }
 
-    pub fn open_door(&mut self, layer: &mut Layer) {
-        info!("EXIT OPEN");
+    pub fn open_door(&mut self, layer: &mut Layer, theme: &TileTheme) {
+        debug!("exit door opened");
         self.is_open = true;
         let i = xytoi(self.position.x, self.position.y, layer);
         layer.data[i] = Some(Tile {
-            id: _MONSTER_PIPE_OPEN_TILE_ID,
-            tileset: TILESET_MAP_ID.into(),
+            id: theme.doors.pipe_open,
+            tileset: theme.tileset.clone(),
             attrs: "".into(),
         });
         layer.data[i + 1] = Some(Tile {
-            id: DOOR_LEFT_OPEN_TILE_ID,
-            tileset: TILESET_MAP_ID.into(),
+            id: theme.doors.left_open,
+            tileset: theme.tileset.clone(),
             attrs: "".into(),
         });
         layer.data[i + 2] = Some(Tile {
-            id: DOOR_RIGHT_OPEN_TILE_ID,
-            tileset: TILESET_MAP_ID.into(),
+            id: theme.doors.right_open,
+            tileset: theme.tileset.clone(),
             attrs: "".into(),
         });
         layer.data[i + 3] = Some(Tile {
-            id: _MONSTER_PIPE_OPEN_TILE_ID,
-            tileset: TILESET_MAP_ID.into(),
+            id: theme.doors.pipe_open,
+            tileset: theme.tileset.clone(),
             attrs: "".into(),
         });
         let i = xytoi(self.position.x, self.position.y + 1, layer);
         layer.data[i] = Some(Tile {
-            id: _POOL_FULL_TILE_ID,
-            tileset: TILESET_MAP_ID.into(),
+            id: theme.doors.pool_full,
+            tileset: theme.tileset.clone(),
             attrs: "".into(),
         });
         layer.data[i + 3] = Some(Tile {
-            id: _POOL_FULL_TILE_ID,
-            tileset: TILESET_MAP_ID.into(),
+            id: theme.doors.pool_full,
+            tileset: theme.tileset.clone(),
             attrs: "".into(),
         });
     }
@@ -115,3 +246,74 @@ impl ExitDoor {
         self.position.as_vec2() + vec2(1.0, 0.5)
     }
 }
+
+/// A gate across a corridor. Solid until the player spends a key on it.
+pub struct LockedDoor {
+    pub id: EntityId,
+    /// leftmost tile of the gate
+    pub position: UVec2,
+    pub width: u32,
+    collider_handle: ColliderHandle,
+}
+
+impl LockedDoor {
+    pub fn create(
+        position: UVec2,
+        width: u32,
+        collider_set: &mut ColliderSet,
+        entities: &mut EntityRegistry,
+    ) -> Self {
+        let half_width = width as f32 / 2.;
+        let collider = ColliderBuilder::cuboid(half_width, 0.5)
+            .translation(vector![
+                position.x as f32 + half_width,
+                position.y as f32 + 0.5
+            ])
+            .collision_groups(CollisionLayer::Wall.groups())
+            .active_events(ActiveEvents::COLLISION_EVENTS)
+            .build();
+        let collider_handle = collider_set.insert(collider);
+        let id = entities.spawn(EntityKind::LockedDoor);
+        entities.register_collider(collider_handle, id);
+
+        Self {
+            id,
+            position,
+            width,
+            collider_handle,
+        }
+    }
+
+    pub fn center(&self) -> Vec2 {
+        self.position.as_vec2() + vec2(self.width as f32 / 2., 0.5)
+    }
+
+    /// Remove the gate, leaving open floor behind
+    pub fn unlock(&self, physics: &mut Physics, layer: &mut Layer, theme: &TileTheme) {
+        physics.remove_collider(self.collider_handle);
+        physics.entities.despawn(self.id);
+        for x in self.position.x..(self.position.x + self.width) {
+            let i = xytoi(x, self.position.y, layer);
+            layer.data[i] = Some(Tile {
+                id: theme.ground.base,
+                tileset: theme.tileset.clone(),
+                attrs: "".into(),
+            });
+        }
+    }
+}
+
+impl Interactable for LockedDoor {
+    fn interact_position(&self) -> Vec2 {
+        self.center()
+    }
+
+    fn prompt(&self) -> &'static str {
+        "prompt-unlock"
+    }
+
+    /// Try a key in the gate, the same as walking into it
+    fn interact(&mut self, _player: EntityId, events: &mut EventQueue) {
+        events.emit(GameEvent::LockedDoorTouched { door: self.id });
+    }
+}
@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+
+use rapier2d::{dynamics::RigidBodyHandle, geometry::ColliderHandle};
+
+/// Unique ID for anything in the world that owns colliders
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct EntityId(u32);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EntityKind {
+    Player,
+    Guard,
+    GuardDoor,
+    LockedDoor,
+    ExitDoor,
+    Wall,
+    Projectile,
+    Item,
+    SpikeTrap,
+    PressurePlate,
+    Crate,
+    Lever,
+    Chest,
+    OneWayPassage,
+    HidingSpot,
+}
+
+/// Maps collider and rigid body handles back to the entities that own them, so physics events and
+/// queries can be resolved into gameplay objects without comparing handles field by field.
+///
+/// This is deliberately not a full ECS. Entity state still lives in the concrete structs `Game`
+/// owns (`Character`, `GuardDoor`, ...), and IDs are only the shared handle between them and the
+/// physics world. New entity types get an `EntityKind` and their own collection on `Game`.
+#[derive(Debug, Default)]
+pub struct EntityRegistry {
+    next_id: u32,
+    kinds: HashMap<EntityId, EntityKind>,
+    colliders: HashMap<ColliderHandle, EntityId>,
+    bodies: HashMap<RigidBodyHandle, EntityId>,
+}
+
+impl EntityId {
+    /// Plain number for the ID, to send over the network
+    pub fn to_bits(self) -> u32 {
+        self.0
+    }
+
+    /// The ID a number from [`EntityId::to_bits`] stands for
+    pub fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+}
+
+impl EntityRegistry {
+    /// Allocate a new entity ID of the given kind
+    pub fn spawn(&mut self, kind: EntityKind) -> EntityId {
+        let id = EntityId(self.next_id);
+        self.next_id += 1;
+        self.kinds.insert(id, kind);
+        id
+    }
+
+    /// Forget an entity and any colliders or bodies still registered to it
+    pub fn despawn(&mut self, id: EntityId) {
+        self.kinds.remove(&id);
+        self.colliders.retain(|_, owner| *owner != id);
+        self.bodies.retain(|_, owner| *owner != id);
+    }
+
+    pub fn register_collider(&mut self, collider_handle: ColliderHandle, id: EntityId) {
+        self.colliders.insert(collider_handle, id);
+    }
+
+    pub fn unregister_collider(&mut self, collider_handle: ColliderHandle) {
+        self.colliders.remove(&collider_handle);
+    }
+
+    pub fn register_body(&mut self, body_handle: RigidBodyHandle, id: EntityId) {
+        self.bodies.insert(body_handle, id);
+    }
+
+    pub fn unregister_body(&mut self, body_handle: RigidBodyHandle) {
+        self.bodies.remove(&body_handle);
+    }
+
+    pub fn kind(&self, id: EntityId) -> Option<EntityKind> {
+        self.kinds.get(&id).copied()
+    }
+
+    /// Look up the entity owning a collider, along with its kind
+    pub fn collider_entity(
+        &self,
+        collider_handle: ColliderHandle,
+    ) -> Option<(EntityId, EntityKind)> {
+        let id = *self.colliders.get(&collider_handle)?;
+        Some((id, self.kind(id)?))
+    }
+
+    /// Look up the entity owning a rigid body, along with its kind
+    pub fn body_entity(&self, body_handle: RigidBodyHandle) -> Option<(EntityId, EntityKind)> {
+        let id = *self.bodies.get(&body_handle)?;
+        Some((id, self.kind(id)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_despawn_forgets_colliders_and_bodies() {
+        let mut entities = EntityRegistry::default();
+        let crate_ = entities.spawn(EntityKind::Crate);
+        let wall = entities.spawn(EntityKind::Wall);
+        let (collider, body) = (
+            ColliderHandle::from_raw_parts(0, 0),
+            RigidBodyHandle::from_raw_parts(0, 0),
+        );
+        let wall_collider = ColliderHandle::from_raw_parts(1, 0);
+        entities.register_collider(collider, crate_);
+        entities.register_body(body, crate_);
+        entities.register_collider(wall_collider, wall);
+        assert_eq!(
+            entities.collider_entity(collider),
+            Some((crate_, EntityKind::Crate))
+        );
+        assert_eq!(
+            entities.body_entity(body),
+            Some((crate_, EntityKind::Crate))
+        );
+
+        entities.despawn(crate_);
+        assert_eq!(entities.collider_entity(collider), None);
+        assert_eq!(entities.body_entity(body), None);
+        assert_eq!(entities.kind(crate_), None);
+        assert_eq!(
+            entities.collider_entity(wall_collider),
+            Some((wall, EntityKind::Wall))
+        );
+    }
+}
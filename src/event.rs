@@ -0,0 +1,112 @@
+use std::collections::VecDeque;
+
+use macroquad::math::Vec2;
+
+use crate::{damage::Damage, entity::EntityId};
+
+/// Things that happen during a round that more than one system may care about.
+///
+/// Gameplay code emits these into an [`EventQueue`], and [`crate::game::Game`] drains the queue
+/// once per phase of its update, applying the core game rules and then forwarding each event to
+/// every registered [`EventHandler`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum GameEvent {
+    /// a player's body touched a guard's body
+    PlayerGuardCollision { player: EntityId, guard: EntityId },
+    /// a player's attack sensor overlaps a guard while attacking
+    AttackHit { player: EntityId, guard: EntityId },
+    /// a guard wandered into an open cell door
+    GuardTrapped {
+        door: EntityId,
+        guard: EntityId,
+        position: Vec2,
+    },
+    /// a guard spotted the player
+    GuardAlerted { guard: EntityId, position: Vec2 },
+    /// a thrown projectile hit a wall or guard, or landed at the end of its flight
+    ProjectileImpact {
+        projectile: EntityId,
+        guard: Option<EntityId>,
+    },
+    /// something loud happened. unalerted guards within the radius will investigate. alarming
+    /// noises, like a door slamming, send guards that were already investigating into a chase.
+    Noise {
+        position: Vec2,
+        radius: f32,
+        alarming: bool,
+    },
+    /// a player walked over a floor item
+    ItemPickedUp { player: EntityId, item: EntityId },
+    /// the player bumped into a locked door
+    LockedDoorTouched { door: EntityId },
+    /// the player spent a key to open a locked door
+    DoorUnlocked { door: EntityId },
+    /// raised spikes caught a character standing on them
+    SpikesHit { trap: EntityId, character: EntityId },
+    /// someone stepped onto a pressure plate
+    PlatePressed { plate: EntityId, position: Vec2 },
+    /// a player pulled a lever
+    LeverPulled { lever: EntityId, position: Vec2 },
+    /// a player opened a treasure chest, and everyone shares its coins
+    ChestOpened { chest: EntityId, position: Vec2 },
+    /// a player climbed into a hiding spot, or back out of the one they were in
+    HidingToggled { spot: EntityId, player: EntityId },
+    /// a character put a foot down while walking
+    Footstep {
+        position: Vec2,
+        surface: Surface,
+        sneaking: bool,
+    },
+    /// a blow is about to land on a character, if it isn't invulnerable to it
+    DamageDealt { target: EntityId, damage: Damage },
+    /// a player's shoves wore a guard down until it fell for good
+    GuardDefeated { guard: EntityId, position: Vec2 },
+    /// a player lost health
+    PlayerDamaged { amount: u32 },
+    /// a monster pipe is about to let out another guard
+    PipeRumbling { position: Vec2 },
+    /// a guard came out of a monster pipe
+    ReinforcementArrived { position: Vec2 },
+    /// enough guards were trapped and the exit door opened
+    ExitOpened,
+    /// every player still standing made it out of the exit door
+    PlayerEscaped,
+    /// every player died and the death linger time has passed
+    PlayerDied,
+}
+
+/// What a footstep landed on, which decides how it sounds
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Surface {
+    #[default]
+    Ground,
+    /// the pool and stairs in front of the exit
+    Stone,
+    /// iced-over floor, which characters slide around on
+    Ice,
+}
+
+/// Something that reacts to game events without needing access to the rest of the game.
+pub trait EventHandler {
+    fn handle_event(&mut self, event: &GameEvent);
+}
+
+/// FIFO of events waiting to be dispatched
+#[derive(Debug, Default)]
+pub struct EventQueue {
+    events: VecDeque<GameEvent>,
+}
+
+impl EventQueue {
+    pub fn emit(&mut self, event: GameEvent) {
+        self.events.push_back(event);
+    }
+
+    pub fn pop(&mut self) -> Option<GameEvent> {
+        self.events.pop_front()
+    }
+
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+}
@@ -1,459 +1,3013 @@
+use std::{fmt::Display, iter, ptr};
+
 use crate::{
-    audio::Sounds,
-    camera::Cameras,
+    achievements::Achievements,
+    audio::{self, SoundBank, SoundEventHandler, SoundId, SoundManifest},
+    camera::{create_map_camera, visible_rect, Cameras, Spectator},
     character::Character,
+    chests::Chest,
+    clock,
+    collision::CollisionRouter,
     constants::{
-        DEATH_LINGER_TIME, GUARD_SPRITE_ID, SIMULATED_RESOLUTION, TERRAIN_MAP_ID, TILESET_MAP_ID,
+        CHEST_CLOSED_SPRITE_ID, CHUNK_GUARD_MARGIN, CHUNK_PLAYER_MARGIN, DAMAGE_HIT_STOP_FRAMES,
+        DAMAGE_TRAUMA, DEATH_LINGER_TIME, DOOR_MARKER_ALPHA, DOOR_MARKER_COUNT,
+        DOOR_MARKER_DISTANCE, DOOR_MARKER_RANGE, DOOR_SLAM_NOISE_RADIUS, ESCAPE_ALARM_TIME,
+        ESCAPE_ALARM_WARNING_TIME, EXIT_OPEN_NOISE_RADIUS, GRAVE_TILE_ID, GUARD_ALERT_DISTANCE,
+        GUARD_DOOR_REACH, GUARD_HIDDEN_NOTICE_DISTANCE, GUARD_HIT_SLOW_DURATION,
+        GUARD_SEPARATION_RADIUS, GUARD_SPRITE_ID, GUARD_SURROUND_RADIUS,
+        HEAVY_KNOCKBACK_HIT_STOP_FRAMES, HEAVY_KNOCKBACK_THRESHOLD, HEAVY_KNOCKBACK_TRAUMA,
+        INTERACT_SIGHT_SLACK, KEY_TILE_ID, LOCALE_PATHS, NET_RESULT_REPEATS, OVERHEAD_MAP_ID,
+        PARTNER_HUD_ORIGIN, PARTNER_SPAWN_OFFSET, PLAYER_RADIUS, PREFAB_PATHS, PROJECTILE_DAMAGE,
+        PROJECTILE_NOISE_RADIUS, PROJECTILE_RADIUS, PROJECTILE_STUN_DURATION, PROPS_MAP_ID,
+        ROUND_COUNTDOWN_TIME, SIMULATED_RESOLUTION, SPATIAL_HASH_CELL_SIZE,
+        SPAWN_INVULNERABLE_DURATION, SPEED_POTION_DURATION, SPIKE_DAMAGE, SPIKE_POISON_DURATION,
+        TERRAIN_MAP_ID, THEME_PATHS, TICKER_FOOTSTEP_MAX_DISTANCE, TICKER_FOOTSTEP_MIN_DISTANCE,
+        TILESET_MAP_ID, TILESET_TEXTURE_PATH, TILE_MAP_JSON_PATH, TUNING_PATH,
     },
-    door::{ExitDoor, GuardDoor},
+    corpse::Corpse,
+    crates::Crate,
+    damage::{Damage, DamageKind},
+    debug,
+    diorama::Diorama,
+    door::{ExitDoor, GuardDoor, LockedDoor},
+    entity::{EntityId, EntityKind},
+    event::{EventHandler, EventQueue, GameEvent, Surface},
+    ghost::{Ghost, GhostRecorder},
+    hiding::{HidingSpot, HidingSpotKind},
+    input::{ArrowKeys, PlayerInput, TouchControls},
+    interact::{self, Interactable},
+    items::{Item, ItemKind},
+    lighting::Lighting,
+    loading::LoadProgress,
+    locale::{self, tr, tr_with, Locale},
     map::{
-        mapgen::{MapGenResult, MapGenerator},
+        animation::TileAnimator,
+        copy_tileset,
+        mapgen::{overhead_layer, xytoi},
+        prefab::Prefab,
+        theme::TileTheme,
         Map,
     },
-    menus::{GameOverMenu, InstructionsMenu, MainMenu},
-    physics::Physics,
+    menus::{
+        GameOverContext, GameOverMenu, InstructionsMenu, LeaderboardMenu, MainMenu, PauseMenu,
+        SettingsMenu, ShopMenu,
+    },
+    net::{CharacterState, DoorState, NetMessage, NetRole, NetSession, RoundOutcome, Snapshot},
+    palette::CueStyle,
+    passage::OneWayPassage,
+    perf::PerfStats,
+    physics::{CollisionLayer, Physics},
+    pipes::Reinforcements,
+    projectiles::Projectile,
+    save::{SaveGame, SavedCharacter, SavedGuardDoor},
+    scene::{Scene, SceneStack, Transition},
+    screenshot,
+    settings::Settings,
+    spatial::SpatialHash,
+    splits::{self, SplitKind, SplitTracker, Splits},
+    stats::RunStats,
+    status::StatusKind,
+    steering,
+    ticker::Ticker,
+    toast::Toasts,
+    traps::{Lever, PressurePlate, SpikeTrap},
+    tuning::{set_tuning, Tuning},
+    upgrades::PlayerUpgrades,
+    watcher::AssetWatcher,
+    world::{World, WorldBuilder},
 };
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use futures::{future::try_join_all, join, try_join};
 use macroquad::{
-    audio::play_sound_once,
-    camera::set_camera,
-    color::{Color, DARKGRAY, WHITE},
-    logging::info,
-    math::{uvec2, vec2, Rect},
-    rand::srand,
-    text::draw_text,
+    camera::{set_camera, Camera2D},
+    color::{Color, DARKGRAY, GOLD, GREEN, WHITE, YELLOW},
+    input::{
+        is_key_down, is_key_pressed, is_mouse_button_pressed, mouse_position, KeyCode, MouseButton,
+    },
+    math::{uvec2, vec2, Rect, UVec2, Vec2},
+    shapes::draw_triangle,
+    text::{draw_text, measure_text},
     texture::{draw_texture_ex, load_texture, DrawTextureParams, Texture2D},
-    time::get_time,
-    window::{clear_background, next_frame},
+    time::{get_frame_time, get_time},
+    window::clear_background,
 };
+use macroquad_tiled::{Layer, Tile};
 use rapier2d::geometry::CollisionEvent;
 
-#[derive(Clone, Copy, Debug, PartialEq)]
-pub enum GameState {
-    MainMenu,
-    Instructions,
-    InGame,
-    GameOver,
+/// The round being played, under whatever menus are opened over it
+pub struct Round {
+    /// a saved run that has already been restored, rather than a fresh floor
+    continued: bool,
+}
+
+impl Round {
+    pub fn new() -> Self {
+        Self { continued: false }
+    }
+
+    pub fn continued() -> Self {
+        Self { continued: true }
+    }
+}
+
+impl Scene<Game> for Round {
+    fn open(&mut self, game: &mut Game) {
+        if !self.continued {
+            game.start_time = clock::now();
+            game.stats = RunStats::default();
+            game.achievements.start_round(game.start_time);
+            game.splits.start_floor();
+            game.ticker.clear();
+        }
+        game.outcome = RoundOutcome::Playing;
+        game.start_ghost();
+        game.start_countdown();
+        game.setup();
+    }
+
+    /// Carry on with the same round after the pause menu
+    fn resume(&mut self, game: &mut Game) {
+        game.resume();
+        // a countdown cut short by the pause menu starts over
+        if game.countdown_until.is_some() {
+            game.start_countdown();
+        }
+    }
+
+    /// Tidy up after the round, ready for the next one
+    fn close(&mut self, game: &mut Game) -> Result<()> {
+        game.reset()
+    }
+
+    fn update(&mut self, game: &mut Game) -> Option<Transition<Game>> {
+        // whatever the round opened last frame takes over now
+        if let Some(next_scene) = game.next_scene.take() {
+            return Some(next_scene);
+        }
+        game.run_frame();
+        None
+    }
+
+    fn draw(&mut self, game: &mut Game) {
+        game.draw();
+    }
 }
 
 pub struct Game {
-    pub state: GameState,
     pub map: Map,
-    pub sounds: Sounds,
+    pub prefabs: Vec<Prefab>,
+    pub themes: Vec<TileTheme>,
+    /// theme the current map was painted with
+    pub theme: TileTheme,
+    pub sounds: SoundBank,
     pub player: Character,
+    /// second player in a co-op game, who the host simulates and a client plays
+    pub partner: Option<Character>,
+    /// what the client last asked the partner to do
+    pub partner_input: PlayerInput,
+    /// connection to the other player in a co-op game
+    pub net: Option<NetSession>,
+    /// the partner plays on this screen, with the arrow keys
+    pub local_coop: bool,
+    pub arrow_keys: ArrowKeys,
     pub guards: Vec<Character>,
+    /// trapped guards, pacing behind the cell doors that closed on them
+    pub jailed: Vec<Character>,
+    /// graves of the guards that fell for good
+    pub corpses: Vec<Corpse>,
     pub guard_doors: Vec<GuardDoor>,
+    /// guards by position, as indexes into `guards`. rebuilt every frame once they have moved.
+    guard_grid: SpatialHash<usize>,
+    /// guard doors by position, as indexes into `guard_doors`. rebuilt when the floor is set up.
+    door_grid: SpatialHash<usize>,
     pub exit_door: ExitDoor,
+    /// more guards coming out of the pipes by the exit, if the floor goes on too long
+    pub reinforcements: Reinforcements,
+    pub locked_doors: Vec<LockedDoor>,
+    pub one_way_passages: Vec<OneWayPassage>,
+    pub spike_traps: Vec<SpikeTrap>,
+    pub pressure_plates: Vec<PressurePlate>,
+    pub levers: Vec<Lever>,
+    pub projectiles: Vec<Projectile>,
+    pub items: Vec<Item>,
+    pub crates: Vec<Crate>,
+    pub chests: Vec<Chest>,
+    pub hiding_spots: Vec<HidingSpot>,
     pub physics: Physics,
     pub cameras: Cameras,
+    pub settings: Settings,
     pub score: u32,
     pub score_target: u32,
-    pub game_over_message: String,
+    pub keys: u32,
+    /// coins from chests, carried from floor to floor until the run ends
+    pub coins: u32,
+    /// bought with coins in the shop between floors, and kept until the run ends
+    pub upgrades: PlayerUpgrades,
+    /// how many maps deep the current run is. escaping goes down a floor, dying starts over.
+    pub floor: u32,
+    /// how the last round went, for the game over screen
+    pub results: GameOverContext,
     pub arrow_texture: Texture2D,
     pub start_time: f64,
-    pub run_time: Option<f64>,
+    /// tallies for the current round, kept around for the game over screen
+    pub stats: RunStats,
+    pub achievements: Achievements,
+    /// popups over the HUD, like achievements being unlocked
+    pub toasts: Toasts,
+    /// what has been happening in the round, for the player to keep up with
+    pub ticker: Ticker,
     pub best_time: Option<f64>,
+    /// escape time to offer to the leaderboard next time it is shown
+    pub leaderboard_time: Option<f64>,
+    /// draw colliders, guard AI states and player movement readouts over the world
+    pub debug_overlay: bool,
+    /// show frame rate, physics time and entity counts
+    pub perf_overlay: bool,
+    pub perf: PerfStats,
+    /// full map render waiting to be written out, drawn the frame before
+    pub map_capture: Option<Camera2D>,
+    /// darkness and light over the world. None if its shaders couldn't be loaded.
+    pub lighting: Option<Lighting>,
+    /// reloads the map when its files change on disk
+    pub asset_watcher: AssetWatcher,
     pub won_last_round: bool,
+    pub events: EventQueue,
+    pub collision_router: CollisionRouter,
+    pub event_handlers: Vec<Box<dyn EventHandler>>,
+    /// on-screen controls, shown once the screen is touched
+    pub touch: TouchControls,
+    /// seed the current map was generated from. None for a continued run, whose map was loaded.
+    pub seed: Option<u64>,
+    /// fastest escape from this same map, to race against
+    pub ghost: Option<Ghost>,
+    pub ghost_recorder: GhostRecorder,
+    /// free camera once everyone is down. None while anyone is still standing.
+    pub spectator: Option<Spectator>,
+    /// when the escape alarm went off. None until the exit opens, or for good if it's turned off.
+    pub alarm_started: Option<f64>,
+    /// when the pause menu was opened over the round, if it is paused
+    pub paused_at: Option<f64>,
+    /// the round is held still, counting down, until then
+    pub countdown_until: Option<f64>,
+    /// where the round goes once this frame is done, like the pause menu or the game over screen
+    pub next_scene: Option<Transition<Game>>,
+    /// how the round ended, for a client to follow along
+    pub outcome: RoundOutcome,
+    /// F5 was pressed, so the tuning is loaded again before the next frame
+    pub tuning_stale: bool,
+    /// the map's files changed, so it is loaded again before the next frame
+    pub map_stale: bool,
+    /// split times through the run, against the best ones
+    pub splits: SplitTracker,
+    /// last time any guard was giving chase, which holds off health regen
+    pub last_chase_time: f64,
 }
 
 impl Game {
-    pub fn new(map: Map, sounds: Sounds, arrow_texture: Texture2D) -> Self {
-        let mut physics = Physics::default();
-        let seed = (get_time() % 1. * (u64::MAX as f64)) as u64;
-        info!("Random Seed: {}", seed);
-        srand(seed);
-
-        let mapgen = MapGenerator::new(uvec2(
-            map.tile_map.raw_tiled_map.width,
-            map.tile_map.raw_tiled_map.height,
-        ));
-
-        let MapGenResult {
-            rooms,
-            layer,
-            guard_doors,
-            exit_door,
-        } = mapgen.generate_layer();
+    pub fn new(
+        map: Map,
+        prefabs: Vec<Prefab>,
+        themes: Vec<TileTheme>,
+        sounds: SoundBank,
+        arrow_texture: Texture2D,
+        settings: Settings,
+        achievements: Achievements,
+    ) -> Result<Self> {
+        let floor = 1;
         let mut map = map;
-        map.tile_map.layers.insert(TERRAIN_MAP_ID.into(), layer);
-        info!("rooms: {:?}", rooms);
-
-        let player = Character::create_player(
-            rooms[0].center(),
-            &mut physics.colliders,
-            &mut physics.bodies,
-            &sounds,
-        );
-
-        let guards = rooms[1..]
-            .iter()
-            .map(|room| {
-                Character::create_guard(
-                    room.center(),
-                    &mut physics.colliders,
-                    &mut physics.bodies,
-                    &sounds,
-                )
-            })
-            .collect();
-
-        let guard_doors: Vec<GuardDoor> = guard_doors
-            .iter()
-            .map(|position| GuardDoor::create(*position, &mut physics.colliders))
-            .collect();
-
-        // DEBUG
-        // let score_target = 1;
-        let score_target = guard_doors.len() as u32 / 2;
+        let seed = Self::new_seed();
+        let mut world = Self::build_world(&map, &prefabs, &themes, floor, seed)?;
+        let (player, guards) = world.create_characters(&sounds);
+        map.animator = TileAnimator::new(&world.theme.animations);
+        map.tile_map
+            .layers
+            .insert(TERRAIN_MAP_ID.into(), world.terrain);
+        map.tile_map.layers.insert(PROPS_MAP_ID.into(), world.props);
+        map.tile_map
+            .layers
+            .insert(OVERHEAD_MAP_ID.into(), world.overhead);
 
-        let exit_door = ExitDoor::create(exit_door, &mut physics.colliders);
+        let event_handlers: Vec<Box<dyn EventHandler>> =
+            vec![Box::new(SoundEventHandler::new(&sounds))];
 
-        Self {
-            state: GameState::MainMenu,
+        Ok(Self {
             map,
+            prefabs,
+            themes,
+            theme: world.theme,
             sounds,
             player,
+            partner: None,
+            partner_input: PlayerInput::default(),
+            net: None,
+            local_coop: false,
+            arrow_keys: ArrowKeys::default(),
             guards,
-            guard_doors,
-            exit_door,
-            physics,
-            cameras: Cameras::new(),
+            jailed: Vec::new(),
+            corpses: Vec::new(),
+            guard_doors: world.guard_doors,
+            guard_grid: SpatialHash::new(SPATIAL_HASH_CELL_SIZE),
+            door_grid: SpatialHash::new(SPATIAL_HASH_CELL_SIZE),
+            reinforcements: Reinforcements::new(&world.exit_door),
+            exit_door: world.exit_door,
+            locked_doors: world.locked_doors,
+            one_way_passages: world.one_way_passages,
+            spike_traps: world.spike_traps,
+            pressure_plates: world.pressure_plates,
+            levers: world.levers,
+            projectiles: Vec::new(),
+            items: world.items,
+            crates: world.crates,
+            chests: world.chests,
+            hiding_spots: world.hiding_spots,
+            physics: world.physics,
+            cameras: Cameras::new(&settings),
+            settings,
             score: 0,
-            score_target,
-            game_over_message: String::new(),
+            score_target: world.score_target,
+            keys: 0,
+            coins: 0,
+            upgrades: PlayerUpgrades::default(),
+            floor,
+            results: GameOverContext::default(),
             arrow_texture,
-            start_time: get_time(),
-            run_time: None,
+            start_time: clock::now(),
+            stats: RunStats::default(),
+            achievements,
+            toasts: Toasts::default(),
+            ticker: Ticker::default(),
             best_time: None,
+            leaderboard_time: None,
+            debug_overlay: false,
+            perf_overlay: false,
+            perf: PerfStats::default(),
+            map_capture: None,
+            lighting: Lighting::new(),
+            asset_watcher: AssetWatcher::new(&[TILE_MAP_JSON_PATH, TILESET_TEXTURE_PATH]),
             won_last_round: false,
-        }
+            events: EventQueue::default(),
+            collision_router: CollisionRouter::new(),
+            event_handlers,
+            touch: TouchControls::default(),
+            seed: Some(seed),
+            ghost: None,
+            ghost_recorder: GhostRecorder::default(),
+            spectator: None,
+            alarm_started: None,
+            paused_at: None,
+            countdown_until: None,
+            next_scene: None,
+            outcome: RoundOutcome::Playing,
+            tuning_stale: false,
+            map_stale: false,
+            splits: SplitTracker::default(),
+            last_chase_time: 0.,
+        })
     }
 
     pub async fn load() -> Result<Self> {
-        let map = Map::load().await?;
-        let sounds = Sounds::load().await?;
-        let arrow =
-            load_texture("assets/kenney_ui-pack-rpg-expansion/PNG/arrowBlue_right.png").await?;
-        info!("LOADED ALL ASSETS");
+        // tuning goes first, since building the first map reads it
+        load_tuning().await;
+        // the sound manifest says how many sounds there are to count toward progress
+        let sound_manifest = SoundManifest::load().await?;
+        // assets are counted off as they finish, while the loading screen draws in between
+        let progress = LoadProgress::new(
+            1 + sound_manifest.entries.len()
+                + 1
+                + PREFAB_PATHS.len()
+                + THEME_PATHS.len()
+                + LOCALE_PATHS.len(),
+        );
+        let assets = async {
+            let map = progress.track(Map::load()).await?;
+            let tileset_texture = map.tile_map.tilesets[TILESET_MAP_ID].texture.clone();
+            let (sounds, arrow, prefabs, themes, locales) = try_join!(
+                SoundBank::load(&sound_manifest, &progress),
+                progress.track(load_texture(
+                    "assets/kenney_ui-pack-rpg-expansion/PNG/arrowBlue_right.png"
+                )),
+                try_join_all(
+                    PREFAB_PATHS
+                        .iter()
+                        .map(|path| progress.track(Prefab::load(path, &tileset_texture))),
+                ),
+                try_join_all(
+                    THEME_PATHS
+                        .iter()
+                        .map(|path| progress.track(TileTheme::load(path))),
+                ),
+                try_join_all(
+                    LOCALE_PATHS
+                        .iter()
+                        .map(|path| progress.track(Locale::load(path))),
+                ),
+            )?;
+            Ok::<_, anyhow::Error>((map, sounds, arrow, prefabs, themes, locales))
+        };
+        let (assets, ()) = join!(assets, progress.draw_until_done());
+        let (mut map, sounds, arrow, prefabs, themes, locales) = assets?;
+        map.load_theme_tilesets(&themes).await?;
+        locale::add_locales(locales);
+        info!("loaded all assets");
+
+        let settings = Settings::read().unwrap_or_else(|err| {
+            warn!("could not read settings: {}", err);
+            Settings::default()
+        });
+        settings.apply_window();
+        locale::set_language(&settings.language);
 
-        Ok(Self::new(map, sounds, arrow))
+        let achievements = Achievements::read().unwrap_or_else(|err| {
+            warn!("could not read achievements: {}", err);
+            Achievements::default()
+        });
+
+        let mut game = Self::new(map, prefabs, themes, sounds, arrow, settings, achievements)?;
+        game.net = NetSession::from_args(std::env::args().skip(1))?;
+        game.splits = SplitTracker::new(Splits::read().unwrap_or_else(|err| {
+            warn!("could not read splits: {}", err);
+            Splits::default()
+        }));
+        Ok(game)
     }
 
-    pub fn reset(&mut self) {
-        let mut physics = Physics::default();
-        let seed = (get_time() % 1. * (u64::MAX as f64)) as u64;
-        info!("Random Seed: {}", seed);
-        srand(seed);
+    pub fn reset(&mut self) -> Result<()> {
+        if self.won_last_round {
+            self.floor += 1;
+        } else {
+            self.floor = 1;
+            self.coins = 0;
+            self.upgrades = PlayerUpgrades::default();
+            self.splits.start_run();
+        }
+        self.load_world(Self::new_seed())
+    }
 
-        let mapgen = MapGenerator::new(uvec2(
-            self.map.tile_map.raw_tiled_map.width,
-            self.map.tile_map.raw_tiled_map.height,
-        ));
-
-        let MapGenResult {
-            rooms,
-            layer,
-            guard_doors,
-            exit_door,
-        } = mapgen.generate_layer();
+    /// Generate the map for the current floor and put everyone in it
+    fn load_world(&mut self, seed: u64) -> Result<()> {
+        let mut world =
+            Self::build_world(&self.map, &self.prefabs, &self.themes, self.floor, seed)?;
+        self.seed = Some(seed);
+        let (player, guards) = world.create_characters(&self.sounds);
         self.map
             .tile_map
             .layers
-            .insert(TERRAIN_MAP_ID.into(), layer);
-        info!("rooms: {:?}", rooms);
-
-        let player = Character::create_player(
-            rooms[0].center(),
-            &mut physics.colliders,
-            &mut physics.bodies,
-            &self.sounds,
-        );
-
-        let guards: Vec<Character> = rooms[1..]
-            .iter()
-            .map(|room| {
-                Character::create_guard(
-                    room.center(),
-                    &mut physics.colliders,
-                    &mut physics.bodies,
-                    &self.sounds,
-                )
-            })
-            .collect();
-
-        let guard_doors: Vec<GuardDoor> = guard_doors
-            .iter()
-            .map(|position| GuardDoor::create(*position, &mut physics.colliders))
-            .collect();
-
-        let exit_door = ExitDoor::create(exit_door, &mut physics.colliders);
+            .insert(TERRAIN_MAP_ID.into(), world.terrain);
+        self.map
+            .tile_map
+            .layers
+            .insert(PROPS_MAP_ID.into(), world.props);
+        self.map
+            .tile_map
+            .layers
+            .insert(OVERHEAD_MAP_ID.into(), world.overhead);
 
-        self.physics = physics;
+        self.map.remove_colliders(&mut self.physics);
+        self.physics = world.physics;
         self.player = player;
+        // a partner who is still around comes back in beside the player
+        self.partner = None;
+        self.spectator = None;
+        self.stop_alarm();
         self.guards = guards;
-        self.guard_doors = guard_doors;
-        self.exit_door = exit_door;
+        self.jailed.clear();
+        self.corpses.clear();
+        self.guard_doors = world.guard_doors;
+        self.reinforcements = Reinforcements::new(&world.exit_door);
+        self.exit_door = world.exit_door;
+        self.locked_doors = world.locked_doors;
+        self.one_way_passages = world.one_way_passages;
+        self.spike_traps = world.spike_traps;
+        self.pressure_plates = world.pressure_plates;
+        self.levers = world.levers;
+        self.projectiles.clear();
+        self.items = world.items;
+        self.crates = world.crates;
+        self.chests = world.chests;
+        self.hiding_spots = world.hiding_spots;
         self.score = 0;
+        self.score_target = world.score_target;
+        self.keys = 0;
+        self.map.animator = TileAnimator::new(&world.theme.animations);
+        self.theme = world.theme;
+        self.events.clear();
         self.setup();
+        Ok(())
     }
 
-    pub fn setup(&mut self) {
-        self.map.init_colliders(&mut self.physics.colliders);
+    fn new_seed() -> u64 {
+        let seed = (get_time() % 1. * (u64::MAX as f64)) as u64;
+        info!("random seed: {}", seed);
+        seed
     }
 
-    pub async fn run_state(&mut self) -> Result<()> {
-        loop {
-            self.state = match &mut self.state {
-                GameState::MainMenu => MainMenu::new(&self.sounds).run().await?,
-                GameState::Instructions => InstructionsMenu::new(&self.sounds).run().await?,
-                GameState::InGame => {
-                    self.start_time = get_time();
-                    let result = self.run().await?;
-                    self.reset();
-                    result
-                }
-                GameState::GameOver => {
-                    GameOverMenu::new(
-                        &self.game_over_message,
-                        &self.sounds,
-                        self.won_last_round,
-                        self.run_time,
-                        self.best_time,
-                    )
-                    .run()
-                    .await?
-                }
+    /// Generate a fresh map the size of the loaded one, and the world that goes with it
+    fn build_world(
+        map: &Map,
+        prefabs: &[Prefab],
+        themes: &[TileTheme],
+        floor: u32,
+        seed: u64,
+    ) -> Result<World> {
+        WorldBuilder {
+            size: uvec2(
+                map.tile_map.raw_tiled_map.width,
+                map.tile_map.raw_tiled_map.height,
+            ),
+            prefabs,
+            themes,
+            floor,
+        }
+        .build(seed)
+    }
+
+    /// Generate a floor for the main menu to show in the background. A new one is made for every
+    /// visit, which also keeps the generator exercised on plenty of seeds.
+    fn build_diorama(&self) -> Option<Diorama> {
+        match Self::build_world(&self.map, &self.prefabs, &self.themes, 1, Self::new_seed()) {
+            Ok(world) => {
+                let tileset = self.map.tile_map.tilesets.get(&world.theme.tileset)?;
+                Some(Diorama::new(world, tileset))
+            }
+            Err(err) => {
+                warn!("could not generate the menu background: {}", err);
+                None
             }
         }
     }
 
-    pub async fn run(&mut self) -> Result<GameState> {
-        self.setup();
+    pub fn setup(&mut self) {
+        self.map.init_colliders(&self.theme, &mut self.physics);
+        // pick up anything bought in the shop since the floor was built
+        for player in iter::once(&mut self.player).chain(self.partner.as_mut()) {
+            player.set_upgrades(self.upgrades);
+        }
+        if self.local_coop {
+            self.spawn_partner();
+        }
+        self.update_chunks();
+        self.physics.update_queries();
+        self.door_grid.clear();
+        for (i, door) in self.guard_doors.iter().enumerate() {
+            self.door_grid.insert(door.center(), i);
+        }
+        if let Some(lighting) = &mut self.lighting {
+            lighting.set_torches(
+                &self.map.tile_map.layers[TERRAIN_MAP_ID],
+                self.theme.facades.torch,
+            );
+        }
+    }
+
+    /// Keep wall colliders loaded around the players, and close around each guard
+    fn update_chunks(&mut self) {
+        let areas: Vec<(Vec2, f32)> = iter::once(&self.player)
+            .chain(self.partner.as_ref())
+            .map(|player| (player.center(), CHUNK_PLAYER_MARGIN))
+            .chain(
+                self.guards
+                    .iter()
+                    .map(|guard| (guard.center(), CHUNK_GUARD_MARGIN)),
+            )
+            .collect();
+        self.map
+            .update_chunks(&areas, &self.theme, &mut self.physics);
+    }
+
+    pub async fn run_state(&mut self) -> Result<()> {
+        let main_menu = self.main_menu();
+        let mut scenes = SceneStack::new(main_menu, self);
         loop {
-            if self.state != GameState::InGame {
-                return Ok(self.state);
+            scenes.run_frame(self).await?;
+            // scenes can't wait on loading, so whatever the round asked for is loaded between frames
+            if std::mem::take(&mut self.tuning_stale) {
+                load_tuning().await;
+            }
+            if std::mem::take(&mut self.map_stale) {
+                if let Err(err) = self.reload_map().await {
+                    warn!("could not reload the map: {}", err);
+                }
             }
-            self.collect_inputs();
-            self.update();
-            self.draw();
-            next_frame().await
         }
     }
 
-    fn collect_inputs(&mut self) {
-        self.player.collect_player_inputs();
+    pub fn main_menu(&mut self) -> Box<dyn Scene<Game>> {
+        self.local_coop = false;
+        let can_continue = SaveGame::exists();
+        let diorama = self.build_diorama();
+        Box::new(MainMenu::new(
+            &self.sounds,
+            can_continue,
+            self.settings.text_scale(),
+            diorama,
+        ))
+    }
 
-        for guard in &mut self.guards {
-            guard.collect_guard_inputs(&self.player);
-        }
+    pub fn instructions_menu(&self) -> Box<dyn Scene<Game>> {
+        let tileset = self.map.tile_map.tilesets.get(TILESET_MAP_ID);
+        Box::new(InstructionsMenu::new(
+            &self.sounds,
+            self.settings.text_scale(),
+            self.local_coop,
+            tileset.map(copy_tileset),
+        ))
     }
 
-    fn update(&mut self) {
-        // update player
-        self.player.update(&mut self.physics);
+    pub fn pause_menu(&self) -> Box<dyn Scene<Game>> {
+        Box::new(PauseMenu::new(&self.sounds, self.settings.text_scale()))
+    }
 
-        // update guards
-        for guard in &mut self.guards {
-            guard.update(&mut self.physics);
-        }
+    pub fn game_over_menu(&self) -> Box<dyn Scene<Game>> {
+        Box::new(GameOverMenu::new(
+            self.results.clone(),
+            &self.sounds,
+            self.settings.text_scale(),
+        ))
+    }
 
-        // tick physics
-        let (collision_recv, contact_force_recv) = self.physics.step();
+    pub fn settings_menu(&self) -> Box<dyn Scene<Game>> {
+        Box::new(SettingsMenu::new(&self.sounds, &self.settings))
+    }
 
-        self.player.post_physics(&mut self.physics);
+    /// The board of best times. After an escape it leads on to the round's stats.
+    pub fn leaderboard_menu(&mut self) -> Box<dyn Scene<Game>> {
+        Box::new(LeaderboardMenu::new(
+            &self.sounds,
+            self.leaderboard_time.take(),
+            self.settings.text_scale(),
+        ))
+    }
 
-        while let Ok(collision_event) = collision_recv.try_recv() {
-            self.handle_collision(&collision_event);
+    /// Head down to the next floor, or start over. The way down is through the shop. a client's
+    /// upgrades are the host's to buy.
+    pub fn next_round(&self) -> Box<dyn Scene<Game>> {
+        if self.won_last_round && !self.is_client() {
+            Box::new(ShopMenu::new(
+                &self.sounds,
+                self.coins,
+                self.upgrades,
+                self.settings.text_scale(),
+            ))
+        } else {
+            Box::new(Round::new())
         }
+    }
 
-        // handle player attack
-        if self.player.is_attacking && self.player.attack_collider_handle.is_some() {
-            for guard in &mut self.guards {
-                if guard.collider_handle.is_some()
-                    && self.physics.narrow_phase.intersection_pair(
-                        self.player.attack_collider_handle.unwrap(),
-                        guard.collider_handle.unwrap(),
-                    ) == Some(true)
-                {
-                    self.player.handle_attack_collision(guard);
-                }
+    /// Pick the saved run back up, or go back to the main menu if it can't be
+    pub fn continue_run(&mut self) -> Transition<Game> {
+        match SaveGame::read().and_then(|save| self.restore(save)) {
+            Ok(()) => Transition::Switch(Box::new(Round::continued())),
+            Err(err) => {
+                warn!("could not continue the saved run: {}", err);
+                Transition::Switch(self.main_menu())
             }
         }
+    }
 
-        // handle guard door collisions
-        let mut removed_guards = Vec::new();
-        for door in self.guard_doors.iter_mut() {
-            if !door.is_open {
-                continue;
-            }
-            for (j, guard) in &mut self.guards.iter_mut().enumerate() {
-                if guard.collider_handle.is_none() {
-                    continue;
-                }
+    /// Leave the round from the pause menu, saving it to pick up later
+    pub fn quit_paused_run(&mut self) -> Transition<Game> {
+        // the time spent paused isn't part of the saved run
+        self.resume();
+        self.quit_run()
+    }
 
-                if self
-                    .physics
-                    .narrow_phase
-                    .intersection_pair(door.collider_handle, guard.collider_handle.unwrap())
-                    == Some(true)
-                {
-                    door.close_door(self.map.tile_map.layers.get_mut(TERRAIN_MAP_ID).unwrap());
-                    removed_guards.push(j);
-                    play_sound_once(&self.sounds.close_door);
-                }
-            }
+    /// Play a frame of the round
+    fn run_frame(&mut self) {
+        if is_key_pressed(KeyCode::Escape) {
+            self.pause();
+        }
+        if is_key_pressed(KeyCode::F3) {
+            self.debug_overlay = !self.debug_overlay;
+        }
+        if is_key_pressed(KeyCode::F4) {
+            self.perf_overlay = !self.perf_overlay;
+        }
+        if is_key_pressed(KeyCode::F5) {
+            self.tuning_stale = true;
+        }
+        if self.asset_watcher.poll() {
+            self.map_stale = true;
         }
-        // clean up removed guards
-        removed_guards.sort();
-        for i in removed_guards.iter().rev() {
-            self.guards[*i].destroy_physics(&mut self.physics);
-            self.guards.remove(*i);
+        self.capture_screenshots();
+        self.poll_net();
+        self.update_countdown();
+        let hit_stopped = clock::tick();
+        if self.countdown_until.is_none() && !hit_stopped {
+            self.collect_inputs();
+            self.update();
+        } else {
+            self.update_cameras();
         }
-        self.score += removed_guards.len() as u32;
+        self.send_snapshot();
+    }
 
-        // open exit if needed
-        if !self.exit_door.is_open && self.score >= self.score_target {
-            self.exit_door
-                .open_door(self.map.tile_map.layers.get_mut(TERRAIN_MAP_ID).unwrap());
+    /// Open the pause menu over the round. An online round can't be held for the other player,
+    /// so it is saved and quit instead.
+    fn pause(&mut self) {
+        if self.net.is_some() {
+            self.next_scene = Some(self.quit_run());
+            return;
         }
+        // a countdown is already off the clock from when it started
+        self.paused_at.get_or_insert(clock::now());
+        self.next_scene = Some(Transition::Push(self.pause_menu()));
+    }
 
-        // handle player exit
-        if self.exit_door.is_open
-            && self.player.collider_handle.is_some()
-            && self.physics.narrow_phase.intersection_pair(
-                self.player.collider_handle.unwrap(),
-                self.exit_door.collider_handle,
-            ) == Some(true)
+    /// Hold everyone still for a moment to get their bearings before the round gets going. The
+    /// countdown is left off the run clock, like time in the pause menu.
+    fn start_countdown(&mut self) {
+        let now = clock::now();
+        self.countdown_until = Some(now + ROUND_COUNTDOWN_TIME);
+        self.paused_at = Some(now);
+    }
+
+    /// Start the round once the countdown runs out
+    fn update_countdown(&mut self) {
+        if !self
+            .countdown_until
+            .is_some_and(|countdown_until| clock::now() >= countdown_until)
         {
-            self.game_over_message = String::from("You Escaped!");
-            let time_elapsed = get_time() - self.start_time;
-            self.run_time = Some(time_elapsed);
-            match self.best_time {
-                Some(prev_time) if prev_time > time_elapsed => self.best_time = Some(time_elapsed),
-                None => self.best_time = Some(time_elapsed),
-                _ => {}
-            }
-            self.won_last_round = true;
-            self.state = GameState::GameOver;
-            play_sound_once(&self.sounds.victory);
             return;
         }
+        self.countdown_until = None;
+        self.resume();
+        for player in iter::once(&mut self.player).chain(self.partner.as_mut()) {
+            player.add_status(StatusKind::Invulnerable, SPAWN_INVULNERABLE_DURATION);
+        }
+    }
 
-        // handle player death
-        if !self.player.is_alive() && get_time() > self.player.death_time + DEATH_LINGER_TIME {
-            info!("YOU LOSE!");
-            self.game_over_message = String::from("You Got Clobbered!");
-            self.state = GameState::GameOver;
-            self.won_last_round = false;
-            play_sound_once(&self.sounds.defeat);
+    /// Leave the time spent in the pause menu off the run clock and the round's timers
+    fn resume(&mut self) {
+        let Some(paused_at) = self.paused_at.take() else {
             return;
+        };
+        let paused_for = clock::now() - paused_at;
+        self.start_time += paused_for;
+        self.achievements.start_round(self.start_time);
+        self.ghost_recorder.skip(paused_for);
+        if let Some(alarm_started) = &mut self.alarm_started {
+            *alarm_started += paused_for;
         }
+    }
 
-        while let Ok(_contact_force_event) = contact_force_recv.try_recv() {
-            // Handle the contact force event.
-            // info!("Received contact force event: {:?}", contact_force_event);
+    /// F12 saves the last rendered frame of the world. Shift+F12 also renders the whole map,
+    /// which is written out the frame after it is drawn.
+    fn capture_screenshots(&mut self) {
+        if let Some(camera) = self.map_capture.take() {
+            let render_target = camera.render_target.as_ref().unwrap();
+            match screenshot::save_render_target(render_target, "map") {
+                Ok(path) => info!("saved map to {}", path),
+                Err(err) => warn!("could not save map: {}", err),
+            }
+        }
+        if !is_key_pressed(KeyCode::F12) {
+            return;
+        }
+        let render_target = self.cameras.world_camera.render_target.as_ref().unwrap();
+        match screenshot::save_render_target(render_target, "screenshot") {
+            Ok(path) => info!("saved screenshot to {}", path),
+            Err(err) => warn!("could not save screenshot: {}", err),
         }
+        if is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift) {
+            let terrain = &self.map.tile_map.layers[TERRAIN_MAP_ID];
+            self.map_capture = Some(create_map_camera(uvec2(terrain.width, terrain.height)));
+        }
+    }
 
-        for guard in &mut self.guards {
-            guard.post_physics(&mut self.physics);
+    /// Load the tile map and tileset again. The current layout is kept if the map is still the
+    /// same size, and a new one is generated if not.
+    async fn reload_map(&mut self) -> Result<()> {
+        let mut map = Map::load().await?;
+        map.load_theme_tilesets(&self.themes).await?;
+        info!("reloaded map from {}", TILE_MAP_JSON_PATH);
+        let raw_map = &map.tile_map.raw_tiled_map;
+        let old_raw_map = &self.map.tile_map.raw_tiled_map;
+        if (raw_map.width, raw_map.height) != (old_raw_map.width, old_raw_map.height) {
+            self.map = map;
+            return self.reset();
         }
 
-        // check guard distance to player
-        for guard in &mut self.guards {
-            guard.check_guard_distance(&self.player);
+        for id in [TERRAIN_MAP_ID, PROPS_MAP_ID, OVERHEAD_MAP_ID] {
+            if let Some(layer) = self.map.tile_map.layers.remove(id) {
+                map.tile_map.layers.insert(id.into(), layer);
+            }
         }
+        // the layout hasn't changed, so neither have its colliders
+        map.colliders = std::mem::take(&mut self.map.colliders);
+        map.chunks = self.map.chunks.take();
+        self.map = map;
+        Ok(())
+    }
 
-        // update cameras (position on player, etc)
-        self.cameras.update(self.player.position);
+    /// Save the run and head back to the main menu
+    fn quit_run(&mut self) -> Transition<Game> {
+        // a client's run is the host's to save
+        if !self.is_client() {
+            if let Err(err) = self.save().write() {
+                warn!("could not save the run: {}", err);
+            }
+        }
+        self.stop_alarm();
+        // the next new game starts from the top
+        self.won_last_round = false;
+        Transition::Switch(self.main_menu())
     }
 
-    fn draw(&self) {
-        clear_background(DARKGRAY);
-        self.draw_world();
-        self.draw_ui();
-        self.draw_screen();
+    /// Time the round and note how it went for the game over screen, before the next floor is
+    /// generated over it
+    fn finish_round(&mut self, won: bool) {
+        let time_elapsed = clock::now() - self.start_time;
+        self.stats.time = time_elapsed;
+        if won && self.best_time.is_none_or(|best| time_elapsed < best) {
+            self.best_time = Some(time_elapsed);
+        }
+        if won {
+            self.splits.floor(time_elapsed);
+        }
+        // a client's splits are raced on the host
+        if !self.is_client() {
+            if let Err(err) = self.splits.best.write() {
+                warn!("could not save splits: {}", err);
+            }
+        }
+        self.won_last_round = won;
+        self.results = GameOverContext {
+            message: tr(if won {
+                "game-over-escaped"
+            } else {
+                "game-over-died"
+            }),
+            won,
+            run_time: time_elapsed,
+            best_time: self.best_time,
+            stats: self.stats.clone(),
+            seed: self.seed,
+        };
     }
 
-    fn draw_world(&self) {
-        // setup drawing for worldspace
-        set_camera(&self.cameras.world_camera);
+    fn delete_save(&self) {
+        if let Err(err) = SaveGame::delete() {
+            warn!("could not delete the saved run: {}", err);
+        }
+    }
 
-        // draw map
-        self.map.draw();
+    fn save(&self) -> SaveGame {
+        let tile_ids = |layer: Option<&Layer>| {
+            layer
+                .map(|layer| {
+                    layer
+                        .data
+                        .iter()
+                        .map(|tile| tile.as_ref().map(|tile| tile.id))
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+        let layers = &self.map.tile_map.layers;
+        let save_character = |character: &Character| SavedCharacter {
+            position: character.position.to_array(),
+            health: character.health(),
+            is_boss: character.is_boss,
+        };
 
-        // draw player
-        self.player.draw(&self.map.tile_map);
+        SaveGame {
+            floor: self.floor,
+            score: self.score,
+            score_target: self.score_target,
+            keys: self.keys,
+            coins: self.coins,
+            upgrades: self.upgrades,
+            elapsed: clock::now() - self.start_time,
+            stats: self.stats.clone(),
+            splits: self.splits.current.clone(),
+            theme: self.theme.name.clone(),
+            terrain: tile_ids(layers.get(TERRAIN_MAP_ID)),
+            props: tile_ids(layers.get(PROPS_MAP_ID)),
+            player: save_character(&self.player),
+            guards: self.guards.iter().map(save_character).collect(),
+            alarm_time_left: self.alarm_time_left(),
+            jailed: self
+                .jailed
+                .iter()
+                .map(|guard| SavedCharacter {
+                    position: guard.jail_cell().unwrap_or(guard.position).to_array(),
+                    ..save_character(guard)
+                })
+                .collect(),
+            guard_doors: self
+                .guard_doors
+                .iter()
+                .map(|door| SavedGuardDoor {
+                    position: door.position().to_array(),
+                    is_open: door.is_open,
+                    is_trapped: door.is_trapped,
+                    reinforced: door.reinforced,
+                    cycle_offset: door.cycle_offset,
+                })
+                .collect(),
+            exit_door: self.exit_door.position.to_array(),
+            locked_doors: self
+                .locked_doors
+                .iter()
+                .map(|door| (door.position.to_array(), door.width))
+                .collect(),
+            one_way_passages: self
+                .one_way_passages
+                .iter()
+                .map(|passage| {
+                    (
+                        passage.position.to_array(),
+                        passage.width,
+                        passage.direction,
+                    )
+                })
+                .collect(),
+            items: self
+                .items
+                .iter()
+                .map(|item| (item.position.to_array(), item.kind))
+                .collect(),
+            spike_traps: self
+                .spike_traps
+                .iter()
+                .map(|trap| (trap.position.to_array(), trap.offset))
+                .collect(),
+            pressure_plates: self
+                .pressure_plates
+                .iter()
+                .map(|plate| (plate.position.to_array(), plate.door_position.to_array()))
+                .collect(),
+            levers: self
+                .levers
+                .iter()
+                .map(|lever| {
+                    (
+                        lever.position.to_array(),
+                        lever.door_position.to_array(),
+                        lever.is_pulled,
+                    )
+                })
+                .collect(),
+            crates: self
+                .crates
+                .iter()
+                .map(|crate_| crate_.position.to_array())
+                .collect(),
+            chests: self
+                .chests
+                .iter()
+                .map(|chest| (chest.position.to_array(), chest.coins, chest.is_open))
+                .collect(),
+            barrels: self
+                .hiding_spots
+                .iter()
+                .filter(|spot| spot.kind == HidingSpotKind::Barrel)
+                .map(|spot| spot.position.to_array())
+                .collect(),
+        }
+    }
 
-        // draw guards
-        self.guards
-            .iter()
-            .for_each(|guard| guard.draw(&self.map.tile_map));
+    /// Start recording the player's path, and bring out the ghost if this map has one
+    fn start_ghost(&mut self) {
+        self.ghost_recorder.start();
+        self.ghost = self.seed.and_then(|seed| {
+            Ghost::read(seed, self.floor).unwrap_or_else(|err| {
+                warn!("could not read the ghost: {}", err);
+                None
+            })
+        });
+    }
 
-        // draw guidance arrow
-        if self.exit_door.is_open {
-            let door_dir = (self.exit_door.center() - self.player.center()).normalize();
-            let pos = self.player.position + door_dir * 3.;
-            let rotation = door_dir.y.atan2(door_dir.x);
-            draw_texture_ex(
-                &self.arrow_texture,
-                pos.x,
-                pos.y,
-                WHITE,
-                DrawTextureParams {
-                    dest_size: Some(vec2(1., 1.)),
-                    rotation,
-                    ..Default::default()
-                },
-            );
+    /// Keep the path just taken if it beat the ghost
+    fn save_ghost(&self) {
+        let Some(seed) = self.seed else {
+            return;
+        };
+        let ghost = self.ghost_recorder.finish(seed, self.floor);
+        if self
+            .ghost
+            .as_ref()
+            .is_some_and(|best| best.time <= ghost.time)
+        {
+            return;
+        }
+        if let Err(err) = ghost.write() {
+            warn!("could not save the ghost: {}", err);
         }
     }
 
-    fn draw_ui(&self) {
-        // setup drawing for UI space
-        set_camera(&self.cameras.ui_camera);
-        clear_background(Color::new(0., 0., 0., 0.));
-        self.player.draw_ui(&self.map.tile_map);
-
-        // draw score
-        let score_rect = Rect::new(SIMULATED_RESOLUTION.x as f32 - 128., 16., 32., 32.);
-        self.map
-            .tile_map
-            .spr(TILESET_MAP_ID, GUARD_SPRITE_ID, score_rect);
-        draw_text(
-            &format!("{}/{}", self.score, self.score_target),
-            score_rect.x + 48.,
-            score_rect.y + 32.,
-            48.,
-            WHITE,
+    /// Rebuild a saved run in place of the current one
+    fn restore(&mut self, save: SaveGame) -> Result<()> {
+        let (width, height) = (
+            self.map.tile_map.raw_tiled_map.width,
+            self.map.tile_map.raw_tiled_map.height,
         );
+        let size = (width * height) as usize;
+        if save.terrain.len() != size || save.props.len() != size {
+            return Err(anyhow!(
+                "saved map does not match the {}x{} map",
+                width,
+                height
+            ));
+        }
 
-        // draw timer
-        draw_text(&self.elapsed_time_str(), 16., 96., 48., WHITE)
-    }
-
-    fn elapsed_time_str(&self) -> String {
-        let t = (get_time() - self.start_time) as u64;
-        format!("{:02}:{:02}", t / 60, t % 60)
-    }
+        let theme = self
+            .themes
+            .iter()
+            .find(|theme| theme.name == save.theme)
+            .cloned()
+            .unwrap_or_default();
+        let layer = |tile_ids: &[Option<u32>]| Layer {
+            width,
+            height,
+            data: tile_ids
+                .iter()
+                .map(|id| {
+                    id.map(|id| Tile {
+                        id,
+                        tileset: theme.tileset.clone(),
+                        attrs: String::new(),
+                    })
+                })
+                .collect(),
+            ..Default::default()
+        };
+        let terrain = layer(&save.terrain);
+        // the wall tops are always the same for the same terrain, so they aren't saved
+        let overhead = overhead_layer(&terrain, &theme);
+        let layers = &mut self.map.tile_map.layers;
+        layers.insert(TERRAIN_MAP_ID.into(), terrain);
+        layers.insert(PROPS_MAP_ID.into(), layer(&save.props));
+        layers.insert(OVERHEAD_MAP_ID.into(), overhead);
 
-    fn draw_screen(&self) {
-        // draw full screen quad with previously rendered screen
-        set_camera(&self.cameras.screen_camera);
-        self.cameras.draw_world_render_to_screen();
-        self.cameras.draw_ui_render_to_screen();
-    }
+        let mut physics = Physics::default();
+        let mut player = Character::create_player(
+            Vec2::from_array(save.player.position),
+            &mut physics.colliders,
+            &mut physics.bodies,
+            &mut physics.entities,
+            &self.sounds,
+        );
+        // upgrades first, so extra hearts aren't cut off
+        player.set_upgrades(save.upgrades);
+        player.set_health(save.player.health);
 
-    fn handle_collision(&mut self, collision_event: &CollisionEvent) {
-        let c1_is_player = Some(collision_event.collider1()) == self.player.collider_handle;
-        let guard = self
+        let restore_guard = |saved: &SavedCharacter, physics: &mut Physics| {
+            let create = if saved.is_boss {
+                Character::create_boss
+            } else {
+                Character::create_guard
+            };
+            let mut guard = create(
+                Vec2::from_array(saved.position),
+                &mut physics.colliders,
+                &mut physics.bodies,
+                &mut physics.entities,
+                &self.sounds,
+            );
+            guard.set_health(saved.health);
+            guard
+        };
+        let guards = save
             .guards
-            .iter_mut()
-            .find(|guard| guard.collider_handle == Some(collision_event.collider2()));
+            .iter()
+            .map(|saved| restore_guard(saved, &mut physics))
+            .collect();
+        let jailed = save
+            .jailed
+            .iter()
+            .map(|saved| {
+                let mut guard = restore_guard(saved, &mut physics);
+                guard.destroy_physics(&mut physics);
+                guard.jail(Vec2::from_array(saved.position));
+                guard
+            })
+            .collect();
 
-        if c1_is_player && guard.is_some() {
-            if let Some(guard) = guard {
-                self.player.handle_player_guard_collision(guard);
-            }
+        let guard_doors: Vec<GuardDoor> = save
+            .guard_doors
+            .iter()
+            .map(|saved| {
+                let mut door = GuardDoor::create(
+                    UVec2::from_array(saved.position),
+                    saved.reinforced,
+                    &mut physics.colliders,
+                    &mut physics.entities,
+                );
+                door.is_open = saved.is_open;
+                door.is_trapped = saved.is_trapped;
+                door.cycle_offset = saved.cycle_offset;
+                door
+            })
+            .collect();
+
+        let exit_door = ExitDoor::create(
+            UVec2::from_array(save.exit_door),
+            &mut physics.colliders,
+            &mut physics.entities,
+        );
+
+        let locked_doors = save
+            .locked_doors
+            .iter()
+            .map(|(position, width)| {
+                LockedDoor::create(
+                    UVec2::from_array(*position),
+                    *width,
+                    &mut physics.colliders,
+                    &mut physics.entities,
+                )
+            })
+            .collect();
+
+        let one_way_passages = save
+            .one_way_passages
+            .iter()
+            .map(|(position, width, direction)| {
+                OneWayPassage::create(
+                    UVec2::from_array(*position),
+                    *width,
+                    *direction,
+                    &mut physics.colliders,
+                    &mut physics.entities,
+                )
+            })
+            .collect();
+
+        let items = save
+            .items
+            .iter()
+            .map(|(position, kind)| {
+                Item::create(
+                    *kind,
+                    UVec2::from_array(*position),
+                    &mut physics.colliders,
+                    &mut physics.entities,
+                )
+            })
+            .collect();
+
+        let crates = save
+            .crates
+            .iter()
+            .map(|position| {
+                Crate::create(
+                    Vec2::from_array(*position),
+                    &mut physics.colliders,
+                    &mut physics.bodies,
+                    &mut physics.entities,
+                )
+            })
+            .collect();
+
+        let spike_traps = save
+            .spike_traps
+            .iter()
+            .map(|(position, offset)| {
+                SpikeTrap::create(
+                    UVec2::from_array(*position),
+                    *offset,
+                    &mut physics.colliders,
+                    &mut physics.entities,
+                )
+            })
+            .collect();
+
+        let pressure_plates = save
+            .pressure_plates
+            .iter()
+            .map(|(position, door)| {
+                PressurePlate::create(
+                    UVec2::from_array(*position),
+                    UVec2::from_array(*door),
+                    &mut physics.colliders,
+                    &mut physics.entities,
+                )
+            })
+            .collect();
+
+        let levers = save
+            .levers
+            .iter()
+            .map(|(position, door, is_pulled)| {
+                let mut lever = Lever::create(
+                    UVec2::from_array(*position),
+                    UVec2::from_array(*door),
+                    &mut physics.entities,
+                );
+                lever.is_pulled = *is_pulled;
+                lever
+            })
+            .collect();
+
+        let chests = save
+            .chests
+            .iter()
+            .map(|(position, coins, is_open)| {
+                let mut chest =
+                    Chest::create(UVec2::from_array(*position), *coins, &mut physics.entities);
+                chest.is_open = *is_open;
+                chest
+            })
+            .collect();
+
+        let mut hiding_spots: Vec<HidingSpot> = save
+            .barrels
+            .iter()
+            .map(|position| {
+                HidingSpot::create(
+                    HidingSpotKind::Barrel,
+                    UVec2::from_array(*position),
+                    &mut physics.entities,
+                )
+            })
+            .collect();
+        hiding_spots.extend(
+            guard_doors
+                .iter()
+                .map(|door| HidingSpot::cell(door.position(), &mut physics.entities)),
+        );
+
+        self.map.remove_colliders(&mut self.physics);
+        self.physics = physics;
+        self.player = player;
+        self.partner = None;
+        self.spectator = None;
+        self.guards = guards;
+        self.jailed = jailed;
+        self.corpses.clear();
+        self.guard_doors = guard_doors;
+        self.reinforcements = Reinforcements::new(&exit_door);
+        self.exit_door = exit_door;
+        // the countdown picks up where it was when the run was saved, with the guards rallied again
+        self.stop_alarm();
+        if let Some(time_left) = save.alarm_time_left {
+            self.start_alarm();
+            self.alarm_started = Some(clock::now() - (ESCAPE_ALARM_TIME - time_left));
+        }
+        self.locked_doors = locked_doors;
+        self.one_way_passages = one_way_passages;
+        self.spike_traps = spike_traps;
+        self.pressure_plates = pressure_plates;
+        self.levers = levers;
+        self.projectiles.clear();
+        self.items = items;
+        self.crates = crates;
+        self.chests = chests;
+        self.hiding_spots = hiding_spots;
+        self.map.animator = TileAnimator::new(&theme.animations);
+        self.theme = theme;
+        // the map came from the save rather than a seed, so it can't have a ghost
+        self.seed = None;
+        self.floor = save.floor;
+        self.score = save.score;
+        self.score_target = save.score_target;
+        self.keys = save.keys;
+        self.coins = save.coins;
+        self.upgrades = save.upgrades;
+        self.start_time = clock::now() - save.elapsed;
+        self.stats = save.stats;
+        self.splits.start_run();
+        self.splits.current = save.splits;
+        self.achievements.start_round(self.start_time);
+        self.won_last_round = false;
+        self.events.clear();
+        Ok(())
+    }
+
+    fn collect_inputs(&mut self) {
+        let touch = self.touch.read();
+        // touches also click the mouse, which menus rely on, so ignore it once there are touches
+        let input = if self.touch.enabled {
+            PlayerInput::keyboard().merge(touch)
+        } else {
+            let mouse = self.cameras.screen_to_world(mouse_position().into());
+            PlayerInput::keyboard_mouse(mouse, self.local_player().center())
+        };
+        if self.is_client() {
+            // the host moves everyone, so a client only says what its player wants to do
+            if let Some(net) = &self.net {
+                net.send(&NetMessage::Input(input.into()));
+            }
+            return;
+        }
+        self.player.apply_input(&input);
+        if self.local_coop {
+            self.partner_input = self.arrow_keys.read();
+        }
+        if let Some(partner) = &mut self.partner {
+            partner.apply_input(&self.partner_input);
+        }
+
+        // guards may have come and gone since the grid was last built
+        self.rebuild_guard_grid();
+        let centers: Vec<Vec2> = self.guards.iter().map(Character::center).collect();
+        let slots = self.surround_slots(&centers);
+        for (i, guard) in self.guards.iter_mut().enumerate() {
+            let target = nearest_player(&self.player, self.partner.as_ref(), guard.center());
+            if target.is_hidden()
+                && guard.position.distance(target.position) >= GUARD_HIDDEN_NOTICE_DISTANCE
+            {
+                guard.lose_track(target.center());
+            }
+            let neighbors: Vec<Vec2> = self
+                .guard_grid
+                .query(guard.position, GUARD_SEPARATION_RADIUS)
+                .into_iter()
+                .filter(|&j| j != i)
+                .map(|j| centers[j])
+                .collect();
+            guard.collect_guard_inputs(target, &neighbors, slots[i]);
+        }
+    }
+
+    /// Where each alerted guard should be around the player it's chasing. Every player's chasers
+    /// form a squad that spreads out around them.
+    fn surround_slots(&self, centers: &[Vec2]) -> Vec<Option<Vec2>> {
+        let mut slots = vec![None; self.guards.len()];
+        for player in iter::once(&self.player).chain(self.partner.as_ref()) {
+            let squad: Vec<usize> = (0..self.guards.len())
+                .filter(|&i| {
+                    self.guards[i].is_alerted()
+                        && ptr::eq(
+                            nearest_player(&self.player, self.partner.as_ref(), centers[i]),
+                            player,
+                        )
+                })
+                .collect();
+            let positions: Vec<Vec2> = squad.iter().map(|&i| self.guards[i].position).collect();
+            let squad_slots =
+                steering::surround_slots(player.position, &positions, GUARD_SURROUND_RADIUS);
+            for (i, slot) in squad.into_iter().zip(squad_slots) {
+                slots[i] = Some(slot);
+            }
+        }
+        slots
+    }
+
+    /// Swing a guard door the other way, for a lever or pressure plate. Slamming it shut is loud
+    /// enough to carry through walls.
+    fn toggle_guard_door(&mut self, position: UVec2) {
+        let Some(door) = self
+            .guard_doors
+            .iter_mut()
+            .find(|door| door.position() == position)
+        else {
+            return;
+        };
+        let was_open = door.is_open;
+        door.swing(
+            !door.is_open,
+            self.map.tile_map.layers.get_mut(TERRAIN_MAP_ID).unwrap(),
+            &self.theme,
+        );
+        if was_open && !door.is_open {
+            self.events.emit(GameEvent::Noise {
+                position: door.center(),
+                radius: DOOR_SLAM_NOISE_RADIUS,
+                alarming: true,
+            });
+        }
+    }
+
+    fn rebuild_guard_grid(&mut self) {
+        self.guard_grid.clear();
+        for (i, guard) in self.guards.iter().enumerate() {
+            self.guard_grid.insert(guard.position, i);
+        }
+    }
+
+    fn is_client(&self) -> bool {
+        self.net
+            .as_ref()
+            .is_some_and(|net| net.role == NetRole::Client)
+    }
+
+    /// The character played on this screen. A client plays the partner.
+    fn local_player(&self) -> &Character {
+        match &self.partner {
+            Some(partner) if self.is_client() => partner,
+            _ => &self.player,
+        }
+    }
+
+    fn players(&self) -> impl Iterator<Item = &Character> {
+        iter::once(&self.player).chain(self.partner.as_ref())
+    }
+
+    fn player_mut(&mut self, id: EntityId) -> Option<&mut Character> {
+        iter::once(&mut self.player)
+            .chain(self.partner.as_mut())
+            .find(|player| player.id == id)
+    }
+
+    /// Bring the partner in beside the player
+    fn spawn_partner(&mut self) {
+        if self.partner.is_some() {
+            return;
+        }
+        let physics = &mut self.physics;
+        let mut partner = Character::create_player(
+            self.player.position + PARTNER_SPAWN_OFFSET,
+            &mut physics.colliders,
+            &mut physics.bodies,
+            &mut physics.entities,
+            &self.sounds,
+        );
+        partner.set_upgrades(self.upgrades);
+        self.partner = Some(partner);
+    }
+
+    /// Take in whatever the other player sent since the last frame
+    fn poll_net(&mut self) {
+        let Some(net) = &mut self.net else {
+            return;
+        };
+        let role = net.role;
+        let mut snapshot = None;
+        for message in net.receive() {
+            match message {
+                NetMessage::Input(input) if role == NetRole::Host => {
+                    self.partner_input = input.into()
+                }
+                NetMessage::Snapshot(latest) if role == NetRole::Client => snapshot = Some(latest),
+                _ => {}
+            }
+        }
+        let is_connected = net.is_connected();
+
+        match role {
+            NetRole::Host if is_connected => self.spawn_partner(),
+            NetRole::Host => {}
+            NetRole::Client => {
+                if let Some(snapshot) = snapshot {
+                    self.apply_snapshot(*snapshot);
+                } else if self.partner.is_some() && !is_connected {
+                    warn!("lost the connection to the host");
+                    self.next_scene = Some(Transition::Switch(self.main_menu()));
+                }
+            }
+        }
+    }
+
+    /// Send the client this frame's dungeon
+    fn send_snapshot(&self) {
+        let Some(net) = &self.net else {
+            return;
+        };
+        // a continued run was loaded rather than generated, so a client couldn't build its map
+        let Some(seed) = self.seed else {
+            return;
+        };
+        if net.role != NetRole::Host || !net.is_connected() {
+            return;
+        }
+        let snapshot = self.snapshot(seed);
+        let repeats = if snapshot.outcome == RoundOutcome::Playing {
+            1
+        } else {
+            NET_RESULT_REPEATS
+        };
+        let message = NetMessage::Snapshot(Box::new(snapshot));
+        for _ in 0..repeats {
+            net.send(&message);
+        }
+    }
+
+    fn snapshot(&self, seed: u64) -> Snapshot {
+        Snapshot {
+            seed,
+            floor: self.floor,
+            players: self.players().map(Character::net_state).collect(),
+            guards: self.guards.iter().map(Character::net_state).collect(),
+            jailed: self.jailed.iter().map(Character::net_state).collect(),
+            guard_doors: self
+                .guard_doors
+                .iter()
+                .map(|door| DoorState {
+                    is_open: door.is_open,
+                    is_trapped: door.is_trapped,
+                })
+                .collect(),
+            spikes: self.spike_traps.iter().map(|trap| trap.is_up).collect(),
+            levers: self.levers.iter().map(|lever| lever.is_pulled).collect(),
+            chests: self.chests.iter().map(|chest| chest.is_open).collect(),
+            exit_open: self.exit_door.is_open,
+            alarm_time_left: self.alarm_time_left(),
+            locked_doors: self
+                .locked_doors
+                .iter()
+                .map(|door| door.id.to_bits())
+                .collect(),
+            items: self.items.iter().map(|item| item.id.to_bits()).collect(),
+            crates: self
+                .crates
+                .iter()
+                .map(|crate_| crate_.position.to_array())
+                .collect(),
+            score: self.score,
+            score_target: self.score_target,
+            keys: self.keys,
+            coins: self.coins,
+            upgrades: self.upgrades,
+            outcome: self.outcome,
+        }
+    }
+
+    /// Show the host's dungeon as of a snapshot, generating its map first if it's a new one
+    fn apply_snapshot(&mut self, snapshot: Snapshot) {
+        if self.seed != Some(snapshot.seed) || self.floor != snapshot.floor {
+            if snapshot.outcome != RoundOutcome::Playing {
+                // left over from a round that has already ended here
+                return;
+            }
+            self.floor = snapshot.floor;
+            if let Err(err) = self.load_world(snapshot.seed) {
+                warn!("could not generate the host's map: {}", err);
+                return;
+            }
+        }
+        self.upgrades = snapshot.upgrades;
+        self.spawn_partner();
+
+        let players = iter::once(&mut self.player).chain(self.partner.as_mut());
+        for (player, state) in players.zip(&snapshot.players) {
+            player.set_upgrades(snapshot.upgrades);
+            player.apply_net_state(state);
+        }
+
+        // guards missing from the snapshot were jailed or killed
+        let find = |states: &[CharacterState], guard: &Character| {
+            states
+                .iter()
+                .find(|state| state.id == guard.id.to_bits())
+                .copied()
+        };
+        let (guards, gone): (Vec<_>, Vec<_>) = std::mem::take(&mut self.guards)
+            .into_iter()
+            .partition(|guard| find(&snapshot.guards, guard).is_some());
+        self.guards = guards;
+        for mut guard in gone {
+            guard.destroy_physics(&mut self.physics);
+            self.physics.entities.despawn(guard.id);
+            if find(&snapshot.jailed, &guard).is_some() {
+                guard.jail(guard.position);
+                self.jailed.push(guard);
+            }
+        }
+        // guards the host sent out since the map was built. the client never simulates, so only
+        // the ID has to match the host's.
+        for state in &snapshot.guards {
+            if self
+                .guards
+                .iter()
+                .all(|guard| guard.id.to_bits() != state.id)
+            {
+                let mut guard = Character::create_guard(
+                    Vec2::from_array(state.position),
+                    &mut self.physics.colliders,
+                    &mut self.physics.bodies,
+                    &mut self.physics.entities,
+                    &self.sounds,
+                );
+                guard.id = EntityId::from_bits(state.id);
+                self.guards.push(guard);
+            }
+        }
+        for guard in &mut self.guards {
+            if let Some(state) = find(&snapshot.guards, guard) {
+                guard.apply_net_state(&state);
+            }
+        }
+        // the host does the pacing, so jailed guards just go where they're told
+        for guard in &mut self.jailed {
+            if let Some(state) = find(&snapshot.jailed, guard) {
+                guard.apply_net_state(&state);
+            }
+        }
+
+        let physics = &mut self.physics;
+        let layer = self.map.tile_map.layers.get_mut(TERRAIN_MAP_ID).unwrap();
+        for (door, state) in self.guard_doors.iter_mut().zip(&snapshot.guard_doors) {
+            if state.is_trapped && !door.is_trapped {
+                door.close_door();
+            } else {
+                door.swing(state.is_open, layer, &self.theme);
+            }
+        }
+        for (trap, is_up) in self.spike_traps.iter_mut().zip(&snapshot.spikes) {
+            trap.set_up(*is_up, layer, &self.theme);
+        }
+        if snapshot.exit_open && !self.exit_door.is_open {
+            self.exit_door.open_door(layer, &self.theme);
+        }
+        self.locked_doors.retain(|door| {
+            let is_locked = snapshot.locked_doors.contains(&door.id.to_bits());
+            if !is_locked {
+                door.unlock(physics, layer, &self.theme);
+            }
+            is_locked
+        });
+        self.items.retain(|item| {
+            let is_there = snapshot.items.contains(&item.id.to_bits());
+            if !is_there {
+                item.destroy_physics(physics);
+            }
+            is_there
+        });
+        self.score = snapshot.score;
+        self.score_target = snapshot.score_target;
+        self.keys = snapshot.keys;
+        self.coins = snapshot.coins;
+        for (lever, is_pulled) in self.levers.iter_mut().zip(&snapshot.levers) {
+            lever.is_pulled = *is_pulled;
+        }
+        for (chest, is_open) in self.chests.iter_mut().zip(&snapshot.chests) {
+            chest.is_open = *is_open;
+        }
+        for spot in &mut self.hiding_spots {
+            spot.occupant = iter::once(&self.player)
+                .chain(self.partner.as_ref())
+                .find(|player| player.hiding_spot() == Some(spot.id))
+                .map(|player| player.id);
+        }
+        for (crate_, position) in self.crates.iter_mut().zip(&snapshot.crates) {
+            crate_.set_position(Vec2::from_array(*position), &mut self.physics);
+        }
+        match snapshot.alarm_time_left {
+            Some(time_left) => {
+                if self.alarm_started.is_none() {
+                    self.sounds.play_looped(SoundId::ESCAPE_MUSIC);
+                }
+                self.alarm_started = Some(clock::now() - (ESCAPE_ALARM_TIME - time_left));
+            }
+            None => self.stop_alarm(),
+        }
+
+        if snapshot.outcome == RoundOutcome::Playing {
+            return;
+        }
+        self.finish_round(snapshot.outcome == RoundOutcome::Escaped);
+        self.next_scene = Some(Transition::Switch(self.game_over_menu()));
+    }
+
+    fn update(&mut self) {
+        self.map.animator.update(get_frame_time());
+
+        if self.is_client() {
+            // everything else comes from the host's snapshots
+            let layer = self.map.tile_map.layers.get_mut(TERRAIN_MAP_ID).unwrap();
+            for door in &mut self.guard_doors {
+                door.finish_closing(layer, &self.theme);
+            }
+            let player = self.local_player();
+            let (center, position) = (player.center(), player.position);
+            audio::set_listener(center);
+            self.cameras.update(&[position]);
+            return;
+        }
+
+        let last_position = self.player.position;
+
+        let layer = &self.map.tile_map.layers[TERRAIN_MAP_ID];
+        for character in iter::once(&mut self.player)
+            .chain(self.partner.as_mut())
+            .chain(self.guards.iter_mut())
+        {
+            character.surface = surface_at(layer, &self.theme, character.center());
+        }
+
+        // update players
+        self.player.update(&mut self.physics);
+        if let Some(partner) = &mut self.partner {
+            partner.update(&mut self.physics);
+        }
+
+        // update guards
+        for guard in &mut self.guards {
+            guard.update(&mut self.physics);
+        }
+        for guard in &mut self.jailed {
+            guard.pace();
+        }
+
+        if self.guards.iter().any(Character::is_alerted) {
+            self.last_chase_time = clock::now();
+        }
+        if let Some(regen) = self.settings.difficulty().regen {
+            for player in iter::once(&mut self.player).chain(self.partner.as_mut()) {
+                player.regenerate(regen, self.last_chase_time, get_frame_time());
+            }
+        }
+
+        self.throw_projectile();
+        self.interact();
+
+        let layer = self.map.tile_map.layers.get_mut(TERRAIN_MAP_ID).unwrap();
+        for door in &mut self.guard_doors {
+            door.update(layer, &self.theme);
+        }
+        for trap in &mut self.spike_traps {
+            trap.update(layer, &self.theme);
+        }
+        self.update_hiding_spots();
+        self.reinforcements.update(
+            clock::now(),
+            clock::now() - self.start_time,
+            self.alarm_started.is_some(),
+            &mut self.events,
+        );
+
+        for player in iter::once(&mut self.player).chain(self.partner.as_mut()) {
+            if let Some(radius) = player.take_noise() {
+                self.events.emit(GameEvent::Noise {
+                    position: player.center(),
+                    radius,
+                    alarming: false,
+                });
+            }
+            let amount = player.take_poison_damage();
+            if amount > 0 {
+                if !player.is_alive() {
+                    self.stats.death_cause = Some(DamageKind::Poison);
+                }
+                self.events.emit(GameEvent::PlayerDamaged { amount });
+            }
+        }
+
+        let layer = &self.map.tile_map.layers[TERRAIN_MAP_ID];
+        for character in iter::once(&mut self.player)
+            .chain(self.partner.as_mut())
+            .chain(self.guards.iter_mut())
+        {
+            if character.take_footstep() {
+                let position = character.center();
+                self.events.emit(GameEvent::Footstep {
+                    position,
+                    surface: surface_at(layer, &self.theme, position),
+                    sneaking: character.is_sneaking,
+                });
+            }
+        }
+
+        // fast rocks could skip clean over a corner in one step, so look ahead along their path
+        let dt = get_frame_time();
+        for projectile in &self.projectiles {
+            if let Some(impact) = projectile.sweep(&self.physics, dt) {
+                self.events.emit(impact);
+            }
+        }
+
+        // tick physics
+        self.update_chunks();
+        let physics_start = get_time();
+        self.physics.step();
+        self.perf
+            .record(get_frame_time() as f64, get_time() - physics_start);
+
+        self.player.post_physics(&mut self.physics);
+        if let Some(partner) = &mut self.partner {
+            partner.post_physics(&mut self.physics);
+        }
+        for crate_ in &mut self.crates {
+            crate_.post_physics(&self.physics);
+        }
+        self.stats.distance_traveled += self.player.position.distance(last_position);
+        audio::set_listener(self.player.center());
+        self.ghost_recorder.record(self.player.position);
+        self.toasts.update();
+        self.ticker.update();
+
+        for projectile in &mut self.projectiles {
+            projectile.post_physics(&self.physics);
+            if projectile.is_expired() {
+                self.events.emit(GameEvent::ProjectileImpact {
+                    projectile: projectile.id,
+                    guard: None,
+                });
+            }
+        }
+
+        while let Some(collision_event) = self.physics.next_collision_event() {
+            self.handle_collision(&collision_event);
+        }
+        self.check_attack_intersections();
+        self.check_guard_door_intersections();
+        self.check_trap_intersections();
+        self.dispatch_events();
+        if self.round_over() {
+            return;
+        }
+
+        // open exit if needed
+        if !self.exit_door.is_open
+            && self.score >= self.score_target
+            && !self.guards.iter().any(|guard| guard.is_boss)
+        {
+            self.events.emit(GameEvent::ExitOpened);
+        }
+
+        // handle player exit. everyone still standing has to make it out together.
+        let on_exit: Vec<EntityId> = self
+            .physics
+            .intersecting(self.exit_door.collider_handle, &[EntityKind::Player])
+            .collect();
+        let is_on_exit = |player: &Character| on_exit.contains(&player.id);
+        if self.exit_door.is_open
+            && self.players().any(Character::is_alive)
+            && self
+                .players()
+                .all(|player| !player.is_alive() || is_on_exit(player))
+        {
+            self.events.emit(GameEvent::PlayerEscaped);
+        } else if self.players().all(|player| {
+            !player.is_alive() && clock::now() > player.death_time + DEATH_LINGER_TIME
+        }) {
+            self.update_spectator();
+        }
+        // nothing else happens in the frame the round ends
+        self.dispatch_events();
+        if self.round_over() {
+            return;
+        }
+
+        // nobody got out before the guards sealed the exit
+        if self
+            .alarm_time_left()
+            .is_some_and(|time_left| time_left <= 0.)
+        {
+            self.stop_alarm();
+            self.player.kill();
+            if let Some(partner) = &mut self.partner {
+                partner.kill();
+            }
+        }
+
+        for guard in &mut self.guards {
+            guard.post_physics(&mut self.physics);
+        }
+        self.rebuild_guard_grid();
+
+        // guards grow suspicious of players they can see, and calm down otherwise
+        let dt = get_frame_time();
+        for guard in &mut self.guards {
+            guard.calm_down(dt);
+        }
+        for player in iter::once(&self.player).chain(self.partner.as_ref()) {
+            for i in self.guard_grid.query(player.position, GUARD_ALERT_DISTANCE) {
+                let guard = &mut self.guards[i];
+                // guards can't see through walls
+                if !self
+                    .physics
+                    .line_of_sight(guard.center(), player.center(), 0.)
+                {
+                    continue;
+                }
+                if guard.notice(player, dt) {
+                    self.events.emit(GameEvent::GuardAlerted {
+                        guard: guard.id,
+                        position: guard.center(),
+                    });
+                }
+            }
+        }
+
+        self.dispatch_events();
+        self.update_cameras();
+    }
+
+    /// Position the cameras on the players. Players on the same screen share the camera.
+    fn update_cameras(&mut self) {
+        let framed: Vec<Vec2> = if let Some(spectator) = &self.spectator {
+            vec![spectator.position]
+        } else if self.local_coop {
+            let living = self.players().filter(|player| player.is_alive());
+            let positions: Vec<Vec2> = living.map(|player| player.position).collect();
+            if positions.is_empty() {
+                self.players().map(|player| player.position).collect()
+            } else {
+                positions
+            }
+        } else {
+            vec![self.local_player().position]
+        };
+        self.cameras.update(&framed);
+    }
+
+    /// Sound the alarm, and send every guard still loose to hold the exit
+    fn start_alarm(&mut self) {
+        self.alarm_started = Some(clock::now());
+        self.sounds.play_looped(SoundId::ESCAPE_MUSIC);
+        let exit = self.exit_door.center();
+        for guard in &mut self.guards {
+            guard.rally_to(exit);
+        }
+    }
+
+    fn stop_alarm(&mut self) {
+        if self.alarm_started.take().is_some() {
+            self.sounds.stop(SoundId::ESCAPE_MUSIC);
+        }
+    }
+
+    /// Seconds until the guards seal the exit, if the alarm is going off
+    fn alarm_time_left(&self) -> Option<f64> {
+        self.alarm_started
+            .map(|started| started + ESCAPE_ALARM_TIME - clock::now())
+    }
+
+    /// Let the player look around once everyone is down, until they ask to move on or time runs out
+    fn update_spectator(&mut self) {
+        let position = self.local_player().position;
+        let spectator = self
+            .spectator
+            .get_or_insert_with(|| Spectator::new(position));
+        let terrain = &self.map.tile_map.layers[TERRAIN_MAP_ID];
+        spectator.pan(
+            PlayerInput::keyboard().move_direction,
+            vec2(terrain.width as f32, terrain.height as f32),
+        );
+        if is_key_pressed(KeyCode::Enter)
+            || is_key_pressed(KeyCode::Space)
+            || is_mouse_button_pressed(MouseButton::Left)
+            || spectator.time_left() <= 0.
+        {
+            self.events.emit(GameEvent::PlayerDied);
+        }
+    }
+
+    /// Spawn a rock just outside each player's body if they asked to throw one
+    fn throw_projectile(&mut self) {
+        for player in iter::once(&mut self.player).chain(self.partner.as_mut()) {
+            if !player.wants_throw {
+                continue;
+            }
+            player.wants_throw = false;
+
+            let direction = player.attack_direction();
+            if !player.is_alive() || direction == Vec2::ZERO {
+                continue;
+            }
+            let position = player.center() + direction * (PLAYER_RADIUS + PROJECTILE_RADIUS + 0.1);
+            // no room to throw with your face to the wall
+            if !self
+                .physics
+                .entities_at(position, CollisionLayer::Wall.only())
+                .is_empty()
+            {
+                continue;
+            }
+            self.projectiles.push(Projectile::create(
+                position,
+                direction,
+                &mut self.physics.colliders,
+                &mut self.physics.bodies,
+                &mut self.physics.entities,
+            ));
+        }
+    }
+
+    /// Everything in the world a player can use with the interact key
+    fn interactables(&self) -> Vec<&dyn Interactable> {
+        let doors = self
+            .locked_doors
+            .iter()
+            .map(|door| door as &dyn Interactable);
+        let levers = self.levers.iter().map(|lever| lever as &dyn Interactable);
+        let chests = self
+            .chests
+            .iter()
+            .filter(|chest| !chest.is_open)
+            .map(|chest| chest as &dyn Interactable);
+        let hiding_spots = self
+            .hiding_spots
+            .iter()
+            .filter(|spot| spot_is_free(spot, &self.guard_doors))
+            .map(|spot| spot as &dyn Interactable);
+        doors
+            .chain(levers)
+            .chain(chests)
+            .chain(hiding_spots)
+            .collect()
+    }
+
+    fn interactables_mut(&mut self) -> Vec<&mut dyn Interactable> {
+        let doors = self
+            .locked_doors
+            .iter_mut()
+            .map(|door| door as &mut dyn Interactable);
+        let levers = self
+            .levers
+            .iter_mut()
+            .map(|lever| lever as &mut dyn Interactable);
+        let chests = self
+            .chests
+            .iter_mut()
+            .filter(|chest| !chest.is_open)
+            .map(|chest| chest as &mut dyn Interactable);
+        let guard_doors = &self.guard_doors;
+        let hiding_spots = self
+            .hiding_spots
+            .iter_mut()
+            .filter(|spot| spot_is_free(spot, guard_doors))
+            .map(|spot| spot as &mut dyn Interactable);
+        doors
+            .chain(levers)
+            .chain(chests)
+            .chain(hiding_spots)
+            .collect()
+    }
+
+    /// Turn players out of cells whose door swung shut on them, and free the spots of anyone who
+    /// died in hiding
+    fn update_hiding_spots(&mut self) {
+        for spot in &mut self.hiding_spots {
+            let Some(occupant) = spot.occupant else {
+                continue;
+            };
+            let Some(player) = iter::once(&mut self.player)
+                .chain(self.partner.as_mut())
+                .find(|player| player.id == occupant)
+            else {
+                spot.occupant = None;
+                continue;
+            };
+            let door_shut = spot.door_position().is_some_and(|position| {
+                self.guard_doors
+                    .iter()
+                    .any(|door| door.position() == position && !door.is_open)
+            });
+            if door_shut || !player.is_alive() {
+                player.leave_hiding(spot.exit_position(), &mut self.physics);
+                spot.occupant = None;
+            }
+        }
+    }
+
+    /// Use whatever is closest to each player who pressed the interact key
+    fn interact(&mut self) {
+        let mut users = Vec::new();
+        for player in iter::once(&mut self.player).chain(self.partner.as_mut()) {
+            if std::mem::take(&mut player.wants_interact) && player.is_alive() {
+                users.push((player.id, player.center(), player.hiding_spot()));
+            }
+        }
+        // what gets used only sees the event queue, so it can be borrowed apart from the rest
+        let mut events = std::mem::take(&mut self.events);
+        for (player, position, hiding_spot) in users {
+            if let Some(spot) = hiding_spot {
+                events.emit(GameEvent::HidingToggled { spot, player });
+                continue;
+            }
+            let can_reach = |target| {
+                self.physics
+                    .line_of_sight(position, target, INTERACT_SIGHT_SLACK)
+            };
+            let Some(i) = interact::nearest(&self.interactables(), position, can_reach) else {
+                continue;
+            };
+            self.interactables_mut()[i].interact(player, &mut events);
+        }
+        self.events = events;
+    }
+
+    /// Emit an attack hit for every guard inside a player's attack sensor
+    fn check_attack_intersections(&mut self) {
+        let players = iter::once(&self.player).chain(self.partner.as_ref());
+        for player in players.filter(|player| player.is_attacking) {
+            let Some(attack_collider_handle) = player.attack_collider_handle else {
+                continue;
+            };
+            for guard in self
+                .physics
+                .intersecting(attack_collider_handle, &[EntityKind::Guard])
+            {
+                self.events.emit(GameEvent::AttackHit {
+                    player: player.id,
+                    guard,
+                });
+            }
+        }
+    }
+
+    /// Hurt characters standing on raised spikes, and press plates that someone is standing on
+    fn check_trap_intersections(&mut self) {
+        const CHARACTERS: &[EntityKind] = &[EntityKind::Player, EntityKind::Guard];
+        for trap in self.spike_traps.iter().filter(|trap| trap.is_up) {
+            for character in self.physics.intersecting(trap.collider_handle, CHARACTERS) {
+                self.events.emit(GameEvent::SpikesHit {
+                    trap: trap.id,
+                    character,
+                });
+            }
+        }
+
+        let layer = self.map.tile_map.layers.get_mut(TERRAIN_MAP_ID).unwrap();
+        for plate in &mut self.pressure_plates {
+            let is_pressed = self
+                .physics
+                .intersecting(plate.collider_handle, CHARACTERS)
+                .next()
+                .is_some();
+            if plate.set_pressed(is_pressed, layer, &self.theme) {
+                self.events.emit(GameEvent::PlatePressed {
+                    plate: plate.id,
+                    position: plate.position.as_vec2() + vec2(0.5, 0.5),
+                });
+            }
+        }
+    }
+
+    /// Emit a trap event for every guard inside an open, or only just closing, cell door
+    fn check_guard_door_intersections(&mut self) {
+        for guard in &self.guards {
+            let Some(guard_collider_handle) = guard.collider_handle else {
+                continue;
+            };
+            // a guard straddling two doors only gets trapped once
+            let nearby = self.door_grid.query(guard.center(), GUARD_DOOR_REACH);
+            let door = nearby
+                .into_iter()
+                .map(|i| &self.guard_doors[i])
+                .find(|door| {
+                    door.can_trap()
+                        && door.reinforced == guard.is_boss
+                        && self
+                            .physics
+                            .narrow_phase
+                            .intersection_pair(door.collider_handle, guard_collider_handle)
+                            == Some(true)
+                });
+            if let Some(door) = door {
+                self.events.emit(GameEvent::GuardTrapped {
+                    door: door.id,
+                    guard: guard.id,
+                    position: door.center(),
+                });
+            }
+        }
+    }
+
+    /// Whether the round has been escaped, lost or quit, and is waiting to be replaced by the next
+    /// scene
+    fn round_over(&self) -> bool {
+        matches!(self.next_scene, Some(Transition::Switch(_)))
+    }
+
+    /// Drain the event queue, applying game rules and then notifying registered handlers.
+    /// Events emitted while handling are dispatched in the same call.
+    fn dispatch_events(&mut self) {
+        while let Some(event) = self.events.pop() {
+            self.handle_event(&event);
+            self.stats.handle_event(&event);
+            self.report(&event);
+            self.achievements.handle_event(&event);
+            for handler in &mut self.event_handlers {
+                handler.handle_event(&event);
+            }
+            // whatever was still queued behind the outcome happened too late to count
+            if self.round_over() {
+                self.events.clear();
+                break;
+            }
+        }
+
+        let unlocked = self.achievements.take_unlocked();
+        if unlocked.is_empty() {
+            return;
+        }
+        for achievement in unlocked {
+            let name = tr(&achievement.name_key());
+            self.toasts
+                .push(tr_with("toast-achievement", &[("name", &name)]));
+        }
+        if let Err(err) = self.achievements.write() {
+            warn!("could not save achievements: {}", err);
+        }
+    }
+
+    fn handle_event(&mut self, event: &GameEvent) {
+        match event {
+            GameEvent::PlayerGuardCollision { player, guard } => {
+                let Some(guard) = self.guards.iter().find(|g| g.id == *guard) else {
+                    return;
+                };
+                let Some(position) = self.players().find(|p| p.id == *player).map(|p| p.position)
+                else {
+                    return;
+                };
+                self.events.emit(GameEvent::DamageDealt {
+                    target: *player,
+                    damage: guard.contact_damage(position),
+                });
+            }
+            GameEvent::DamageDealt { target, damage } => {
+                if let Some(player) = self.player_mut(*target) {
+                    let amount = player.take_damage(damage);
+                    if amount == 0 {
+                        return;
+                    }
+                    match damage.kind {
+                        DamageKind::Guard | DamageKind::Boss => {
+                            player.add_status(StatusKind::Slow, GUARD_HIT_SLOW_DURATION)
+                        }
+                        DamageKind::Spikes => {
+                            player.add_status(StatusKind::Poisoned, SPIKE_POISON_DURATION)
+                        }
+                        DamageKind::Poison | DamageKind::Rock | DamageKind::Attack => {}
+                    }
+                    if !player.is_alive() {
+                        self.stats.death_cause = Some(damage.kind);
+                    }
+                    self.events.emit(GameEvent::PlayerDamaged { amount });
+                    return;
+                }
+                let Some(i) = self.guards.iter().position(|g| g.id == *target) else {
+                    return;
+                };
+                self.guards[i].take_damage(damage);
+                // a guard worn down to nothing is out of the fight, same as a jailed one
+                if !self.guards[i].is_alive() {
+                    let mut guard = self.guards.remove(i);
+                    guard.destroy_physics(&mut self.physics);
+                    self.physics.entities.despawn(*target);
+                    self.corpses.push(guard.corpse());
+                    // knockouts are their own tally, kept in the stats. only guards trapped behind
+                    // doors count toward the exit.
+                    if damage.kind == DamageKind::Attack {
+                        self.events.emit(GameEvent::GuardDefeated {
+                            guard: *target,
+                            position: guard.center(),
+                        });
+                    }
+                }
+            }
+            GameEvent::AttackHit { player, guard } => {
+                let Some(player) = iter::once(&mut self.player)
+                    .chain(self.partner.as_mut())
+                    .find(|p| p.id == *player)
+                else {
+                    return;
+                };
+                if let Some(guard) = self.guards.iter_mut().find(|g| g.id == *guard) {
+                    let knockback = player.handle_attack_collision(guard);
+                    if knockback.is_some_and(|knockback| knockback >= HEAVY_KNOCKBACK_THRESHOLD) {
+                        self.cameras.add_trauma(HEAVY_KNOCKBACK_TRAUMA);
+                        clock::hit_stop(HEAVY_KNOCKBACK_HIT_STOP_FRAMES);
+                    }
+                    let damage = player.charged_attack_damage();
+                    if let (Some(_), Some(damage)) = (knockback, damage) {
+                        if self.settings.difficulty().lethal_attacks {
+                            self.events.emit(GameEvent::DamageDealt {
+                                target: guard.id,
+                                damage,
+                            });
+                        }
+                    }
+                }
+            }
+            GameEvent::GuardTrapped { door, guard, .. } => {
+                let Some(i) = self.guards.iter().position(|g| g.id == *guard) else {
+                    return;
+                };
+                let Some(door) = self.guard_doors.iter_mut().find(|d| d.id == *door) else {
+                    return;
+                };
+                door.close_door();
+                self.events.emit(GameEvent::Noise {
+                    position: door.center(),
+                    radius: DOOR_SLAM_NOISE_RADIUS,
+                    alarming: true,
+                });
+                let mut jailed = self.guards.remove(i);
+                jailed.destroy_physics(&mut self.physics);
+                self.physics.entities.despawn(*guard);
+                jailed.jail(door.position().as_vec2() + vec2(1., 0.));
+                self.jailed.push(jailed);
+                self.splits.trap(clock::now() - self.start_time);
+                self.score += 1;
+            }
+            GameEvent::ProjectileImpact { projectile, guard } => {
+                // a rock can hit a guard and a wall in the same step. only the first impact counts.
+                let Some(i) = self.projectiles.iter().position(|p| p.id == *projectile) else {
+                    return;
+                };
+                let projectile = self.projectiles.remove(i);
+                projectile.destroy_physics(&mut self.physics);
+
+                if let Some(guard) =
+                    guard.and_then(|id| self.guards.iter_mut().find(|g| g.id == id))
+                {
+                    guard.stun(PROJECTILE_STUN_DURATION);
+                    self.events.emit(GameEvent::DamageDealt {
+                        target: guard.id,
+                        damage: Damage {
+                            source: Some(projectile.id),
+                            amount: PROJECTILE_DAMAGE,
+                            kind: DamageKind::Rock,
+                            knockback: Vec2::ZERO,
+                        },
+                    });
+                }
+                self.events.emit(GameEvent::Noise {
+                    position: projectile.position,
+                    radius: PROJECTILE_NOISE_RADIUS,
+                    alarming: false,
+                });
+            }
+            GameEvent::ItemPickedUp { player, item } => {
+                let Some(i) = self.items.iter().position(|it| it.id == *item) else {
+                    return;
+                };
+                let item = self.items.remove(i);
+                item.destroy_physics(&mut self.physics);
+                if item.kind == ItemKind::Key {
+                    // keys are shared, so either player can open a door with them
+                    self.keys += 1;
+                    return;
+                }
+                let heart_heal = self.settings.difficulty().heart_heal;
+                let Some(player) = self.player_mut(*player) else {
+                    return;
+                };
+                match item.kind {
+                    ItemKind::Heart => player.heal(heart_heal),
+                    ItemKind::SpeedPotion => {
+                        player.add_status(StatusKind::Haste, SPEED_POTION_DURATION)
+                    }
+                    ItemKind::Key => {}
+                }
+            }
+            GameEvent::LockedDoorTouched { door } => {
+                if self.keys > 0 {
+                    self.keys -= 1;
+                    self.events.emit(GameEvent::DoorUnlocked { door: *door });
+                }
+            }
+            GameEvent::DoorUnlocked { door } => {
+                let Some(i) = self.locked_doors.iter().position(|d| d.id == *door) else {
+                    return;
+                };
+                let door = self.locked_doors.remove(i);
+                door.unlock(
+                    &mut self.physics,
+                    self.map.tile_map.layers.get_mut(TERRAIN_MAP_ID).unwrap(),
+                    &self.theme,
+                );
+            }
+            GameEvent::SpikesHit { trap, character } => {
+                self.events.emit(GameEvent::DamageDealt {
+                    target: *character,
+                    damage: Damage {
+                        source: Some(*trap),
+                        amount: SPIKE_DAMAGE,
+                        kind: DamageKind::Spikes,
+                        knockback: Vec2::ZERO,
+                    },
+                });
+            }
+            GameEvent::PlatePressed { plate, .. } => {
+                let Some(plate) = self.pressure_plates.iter().find(|p| p.id == *plate) else {
+                    return;
+                };
+                self.toggle_guard_door(plate.door_position);
+            }
+            GameEvent::LeverPulled { lever, .. } => {
+                let Some(lever) = self.levers.iter_mut().find(|l| l.id == *lever) else {
+                    return;
+                };
+                lever.is_pulled = !lever.is_pulled;
+                let door_position = lever.door_position;
+                self.toggle_guard_door(door_position);
+            }
+            GameEvent::ChestOpened { chest, .. } => {
+                let Some(chest) = self.chests.iter_mut().find(|c| c.id == *chest) else {
+                    return;
+                };
+                if chest.is_open {
+                    return;
+                }
+                chest.is_open = true;
+                // coins are shared, like keys
+                self.coins += chest.coins;
+            }
+            GameEvent::HidingToggled { spot, player } => {
+                let Some(spot) = self.hiding_spots.iter().position(|s| s.id == *spot) else {
+                    return;
+                };
+                let spot = &mut self.hiding_spots[spot];
+                let (id, center, exit) = (spot.id, spot.center(), spot.exit_position());
+                let occupant = spot.occupant;
+                let Some(character) = iter::once(&mut self.player)
+                    .chain(self.partner.as_mut())
+                    .find(|character| character.id == *player)
+                else {
+                    return;
+                };
+                if occupant == Some(*player) {
+                    character.leave_hiding(exit, &mut self.physics);
+                    spot.occupant = None;
+                } else if occupant.is_none() && !character.is_hidden() {
+                    character.hide(id, center, &mut self.physics);
+                    spot.occupant = Some(*player);
+                }
+            }
+            GameEvent::Noise {
+                position,
+                radius,
+                alarming,
+            } => {
+                for guard in &mut self.guards {
+                    if guard.center().distance(*position) >= *radius {
+                        continue;
+                    }
+                    if *alarming {
+                        guard.hear_alarm(*position);
+                    } else {
+                        guard.investigate(*position);
+                    }
+                }
+            }
+            GameEvent::ExitOpened => {
+                self.exit_door.open_door(
+                    self.map.tile_map.layers.get_mut(TERRAIN_MAP_ID).unwrap(),
+                    &self.theme,
+                );
+                self.events.emit(GameEvent::Noise {
+                    position: self.exit_door.center(),
+                    radius: EXIT_OPEN_NOISE_RADIUS,
+                    alarming: true,
+                });
+                if self.settings.escape_alarm {
+                    self.start_alarm();
+                }
+            }
+            GameEvent::PlayerEscaped => {
+                self.stop_alarm();
+                // a partner may have fallen on the way, but the run was won
+                self.stats.death_cause = None;
+                self.delete_save();
+                self.finish_round(true);
+                self.leaderboard_time = Some(self.stats.time);
+                self.save_ghost();
+                self.outcome = RoundOutcome::Escaped;
+                self.next_scene = Some(Transition::Switch(self.leaderboard_menu()));
+            }
+            GameEvent::PlayerDied => {
+                self.stop_alarm();
+                self.delete_save();
+                self.finish_round(false);
+                self.outcome = RoundOutcome::Died;
+                self.next_scene = Some(Transition::Switch(self.game_over_menu()));
+            }
+            GameEvent::PlayerDamaged { .. } => {
+                self.cameras.add_trauma(DAMAGE_TRAUMA);
+                clock::hit_stop(DAMAGE_HIT_STOP_FRAMES);
+            }
+            GameEvent::ReinforcementArrived { position } => {
+                let mut guard = Character::create_guard(
+                    *position,
+                    &mut self.physics.colliders,
+                    &mut self.physics.bodies,
+                    &mut self.physics.entities,
+                    &self.sounds,
+                );
+                // they know something is up, so they come out looking
+                if self.alarm_started.is_some() {
+                    guard.rally_to(self.exit_door.center());
+                } else {
+                    guard.investigate(self.player.center());
+                }
+                self.guards.push(guard);
+            }
+            GameEvent::GuardAlerted { .. }
+            | GameEvent::GuardDefeated { .. }
+            | GameEvent::Footstep { .. }
+            | GameEvent::PipeRumbling { .. } => {}
+        }
+    }
+
+    /// Tell the player about anything worth knowing in the ticker, once the game has dealt with it
+    fn report(&mut self, event: &GameEvent) {
+        let key = match event {
+            GameEvent::GuardTrapped { .. } => "ticker-trapped",
+            GameEvent::GuardDefeated { .. } => "ticker-defeated",
+            GameEvent::ExitOpened => "ticker-exit-open",
+            GameEvent::GuardAlerted { .. } => "ticker-spotted",
+            GameEvent::PlayerDamaged { .. } => "ticker-hit",
+            GameEvent::PipeRumbling { .. } => "ticker-pipe",
+            GameEvent::ReinforcementArrived { .. } => "ticker-reinforcement",
+            GameEvent::DoorUnlocked { .. } => "ticker-unlocked",
+            GameEvent::ChestOpened { .. } => "ticker-chest",
+            // a guard out of sight, but not out of earshot
+            GameEvent::Footstep {
+                position,
+                sneaking: false,
+                ..
+            } => {
+                let distance = |player: &Character| player.center().distance(*position);
+                let heard = (TICKER_FOOTSTEP_MIN_DISTANCE..TICKER_FOOTSTEP_MAX_DISTANCE)
+                    .contains(&distance(self.local_player()));
+                // a partner wandering off isn't news
+                if !heard || self.players().any(|player| distance(player) < 1.) {
+                    return;
+                }
+                "ticker-footsteps"
+            }
+            _ => return,
+        };
+        // how close trapping guards has brought the exit, and how many have been knocked out
+        let progress: [(&str, &dyn Display); 3] = [
+            ("score", &self.score),
+            ("target", &self.score_target),
+            ("defeated", &self.stats.guards_defeated),
+        ];
+        self.ticker.push(tr_with(key, &progress));
+    }
+
+    fn draw(&mut self) {
+        clear_background(DARKGRAY);
+        self.draw_world();
+        let center = self.local_player().center();
+        if let Some(lighting) = &mut self.lighting {
+            lighting.draw(&self.cameras.world_camera, center);
+        }
+        self.draw_ui();
+        self.draw_screen();
+        self.touch.draw();
+
+        if let Some(camera) = &self.map_capture {
+            set_camera(camera);
+            clear_background(DARKGRAY);
+            self.map.draw(visible_rect(camera));
+        }
+    }
+
+    fn draw_world(&self) {
+        // setup drawing for worldspace
+        set_camera(&self.cameras.world_camera);
+
+        // draw map
+        let view = visible_rect(&self.cameras.world_camera);
+        self.map.draw(view);
+        self.one_way_passages
+            .iter()
+            .for_each(|passage| passage.draw());
+        for door in &self.guard_doors {
+            door.draw(&self.map.tile_map, &self.theme);
+        }
+        self.reinforcements
+            .draw(clock::now(), &self.map.tile_map, &self.theme);
+
+        // draw items
+        self.items
+            .iter()
+            .for_each(|item| item.draw(&self.map.tile_map));
+        self.levers
+            .iter()
+            .for_each(|lever| lever.draw(&self.map.tile_map));
+        self.chests
+            .iter()
+            .for_each(|chest| chest.draw(&self.map.tile_map));
+        self.crates
+            .iter()
+            .for_each(|crate_| crate_.draw(&self.map.tile_map));
+        self.hiding_spots
+            .iter()
+            .for_each(|spot| spot.draw(&self.map.tile_map));
+
+        // graves lie on the floor, under everyone still standing
+        let now = clock::now();
+        self.corpses
+            .iter()
+            .for_each(|corpse| corpse.draw(&self.map.tile_map, now));
+
+        // draw jailed guards over their cell doors
+        let style = CueStyle::new(&self.settings);
+        self.jailed
+            .iter()
+            .for_each(|guard| guard.draw(&self.map.tile_map, &style));
+
+        // draw the ghost under the player, so it never hides them
+        if let Some(ghost) = &self.ghost {
+            // the ghost waits at the start with everyone else during the countdown
+            let time = if self.countdown_until.is_some() {
+                0.
+            } else {
+                self.ghost_recorder.elapsed()
+            };
+            ghost.draw(time, &self.map.tile_map);
+        }
+
+        // draw players
+        self.players()
+            .for_each(|player| player.draw(&self.map.tile_map, &style));
+
+        // draw guards
+        self.guards
+            .iter()
+            .for_each(|guard| guard.draw(&self.map.tile_map, &style));
+
+        // walls in front of the characters hide their feet
+        self.map.draw_overhead(view);
+
+        // prompt for whatever the player could use
+        let player = self.local_player();
+        let hiding_spot = player
+            .hiding_spot()
+            .and_then(|id| self.hiding_spots.iter().find(|spot| spot.id == id));
+        if let Some(spot) = hiding_spot {
+            interact::draw_prompt(spot);
+        } else if player.is_alive() {
+            let interactables = self.interactables();
+            let position = player.center();
+            let can_reach = |target| {
+                self.physics
+                    .line_of_sight(position, target, INTERACT_SIGHT_SLACK)
+            };
+            if let Some(i) = interact::nearest(&interactables, position, can_reach) {
+                interact::draw_prompt(interactables[i]);
+            }
+        }
+
+        // draw projectiles
+        self.projectiles
+            .iter()
+            .for_each(|projectile| projectile.draw(&self.map.tile_map));
+
+        // draw guidance arrow
+        if self.exit_door.is_open {
+            let player = self.local_player();
+            let door_dir = (self.exit_door.center() - player.center()).normalize();
+            let pos = player.position + door_dir * 3.;
+            let rotation = door_dir.y.atan2(door_dir.x);
+            match style.palette.arrow {
+                // the arrow texture is blue, which tinting can't turn into another color
+                Some(color) => {
+                    let center = pos + vec2(0.5, 0.5);
+                    let back = center - door_dir * 0.5;
+                    let side = door_dir.perp() * 0.4;
+                    draw_triangle(center + door_dir * 0.5, back + side, back - side, color);
+                }
+                None => draw_texture_ex(
+                    &self.arrow_texture,
+                    pos.x,
+                    pos.y,
+                    WHITE,
+                    DrawTextureParams {
+                        dest_size: Some(vec2(1., 1.)),
+                        rotation,
+                        ..Default::default()
+                    },
+                ),
+            }
+        }
+        if self.settings.door_markers {
+            self.draw_door_markers();
+        }
+
+        if self.debug_overlay {
+            debug::draw_colliders(&self.physics);
+            debug::draw_body_labels(&self.physics);
+            self.player.draw_debug();
+            for guard in &self.guards {
+                let rect = guard.get_draw_rect();
+                debug::draw_label(guard.ai_state(), vec2(rect.x, rect.y), YELLOW);
+            }
+        }
+    }
+
+    /// Faint markers toward the nearest cells a guard could be trapped in, while one is giving
+    /// chase, to help plan where to lead it
+    fn draw_door_markers(&self) {
+        if !self.guards.iter().any(Character::is_alerted) {
+            return;
+        }
+        let center = self.local_player().center();
+        let mut doors: Vec<(f32, Vec2)> = self
+            .door_grid
+            .query(center, DOOR_MARKER_RANGE)
+            .into_iter()
+            .map(|i| &self.guard_doors[i])
+            .filter(|door| door.is_open && !door.is_trapped)
+            .map(|door| (door.center().distance(center), door.center()))
+            .filter(|(distance, _)| *distance <= DOOR_MARKER_RANGE)
+            .collect();
+        doors.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let color = CueStyle::new(&self.settings).palette.arrow.unwrap_or(WHITE);
+        let color = Color::new(color.r, color.g, color.b, DOOR_MARKER_ALPHA);
+        for (distance, door) in doors.into_iter().take(DOOR_MARKER_COUNT) {
+            // standing in the doorway already
+            if distance < DOOR_MARKER_DISTANCE {
+                continue;
+            }
+            let dir = (door - center).normalize();
+            let tip = center + dir * (DOOR_MARKER_DISTANCE + 0.3);
+            let back = center + dir * DOOR_MARKER_DISTANCE;
+            let side = dir.perp() * 0.2;
+            draw_triangle(tip, back + side, back - side, color);
+        }
+    }
+
+    fn draw_ui(&self) {
+        // setup drawing for UI space
+        set_camera(&self.cameras.ui_camera);
+        clear_background(Color::new(0., 0., 0., 0.));
+        self.local_player()
+            .draw_ui(&self.map.tile_map, vec2(16., 16.));
+        let other_player = if self.is_client() {
+            Some(&self.player)
+        } else {
+            self.partner.as_ref()
+        };
+        if let Some(other_player) = other_player {
+            other_player.draw_ui(&self.map.tile_map, PARTNER_HUD_ORIGIN);
+        }
+        let font_size = 48. * self.settings.text_scale();
+
+        // draw score
+        let score_rect = Rect::new(SIMULATED_RESOLUTION.x as f32 - 128., 16., 32., 32.);
+        self.map
+            .tile_map
+            .spr(TILESET_MAP_ID, GUARD_SPRITE_ID, score_rect);
+        draw_text(
+            &format!("{}/{}", self.score, self.score_target),
+            score_rect.x + 48.,
+            score_rect.y + 32.,
+            font_size,
+            WHITE,
+        );
+
+        // guards put down for good, once there are any
+        if self.stats.guards_defeated > 0 {
+            let defeated_rect = Rect::new(SIMULATED_RESOLUTION.x as f32 - 128., 160., 32., 32.);
+            self.map
+                .tile_map
+                .spr(TILESET_MAP_ID, GRAVE_TILE_ID, defeated_rect);
+            draw_text(
+                &format!("{}", self.stats.guards_defeated),
+                defeated_rect.x + 48.,
+                defeated_rect.y + 32.,
+                font_size,
+                WHITE,
+            );
+        }
+
+        // draw keys
+        if self.keys > 0 {
+            let keys_rect = Rect::new(SIMULATED_RESOLUTION.x as f32 - 128., 64., 32., 32.);
+            self.map
+                .tile_map
+                .spr(TILESET_MAP_ID, KEY_TILE_ID, keys_rect);
+            draw_text(
+                &format!("{}", self.keys),
+                keys_rect.x + 48.,
+                keys_rect.y + 32.,
+                font_size,
+                WHITE,
+            );
+        }
+
+        // draw coins, under the keys
+        if self.coins > 0 {
+            let coins_rect = Rect::new(SIMULATED_RESOLUTION.x as f32 - 128., 112., 32., 32.);
+            self.map
+                .tile_map
+                .spr(TILESET_MAP_ID, CHEST_CLOSED_SPRITE_ID, coins_rect);
+            draw_text(
+                &format!("{}", self.coins),
+                coins_rect.x + 48.,
+                coins_rect.y + 32.,
+                font_size,
+                GOLD,
+            );
+        }
+
+        // draw timer, below the hearts and stamina bar
+        draw_text(
+            &self.elapsed_time_str(),
+            16.,
+            48. + font_size,
+            font_size,
+            WHITE,
+        );
+        draw_text(
+            &tr_with("hud-floor", &[("floor", &self.floor)]),
+            16.,
+            48. + font_size * 2.,
+            font_size,
+            WHITE,
+        );
+
+        if self.settings.speedrun_splits {
+            self.draw_split(16., 48. + font_size * 3., font_size);
+        }
+
+        self.toasts
+            .draw(SIMULATED_RESOLUTION.x as f32, font_size * 2. / 3.);
+        self.ticker.draw(
+            SIMULATED_RESOLUTION.x as f32 - 16.,
+            SIMULATED_RESOLUTION.y as f32 - 16.,
+            font_size / 2.,
+        );
+
+        // the alarm's countdown, big across the top
+        if let Some(time_left) = self.alarm_time_left() {
+            let countdown = tr_with(
+                "hud-alarm",
+                &[("seconds", &(time_left.max(0.).ceil() as u32))],
+            );
+            let countdown_size = font_size * 1.5;
+            let width = measure_text(&countdown, None, countdown_size as u16, 1.).width;
+            let color = if time_left <= ESCAPE_ALARM_WARNING_TIME {
+                CueStyle::new(&self.settings).palette.alert
+            } else {
+                WHITE
+            };
+            draw_text(
+                &countdown,
+                (SIMULATED_RESOLUTION.x as f32 - width) / 2.,
+                16. + countdown_size,
+                countdown_size,
+                color,
+            );
+        }
+
+        // the countdown before the round starts, big in the middle
+        if let Some(countdown_until) = self.countdown_until {
+            let seconds = ((countdown_until - clock::now()).max(0.).ceil() as u32).max(1);
+            let countdown = seconds.to_string();
+            let countdown_size = font_size * 4.;
+            let size = measure_text(&countdown, None, countdown_size as u16, 1.);
+            draw_text(
+                &countdown,
+                (SIMULATED_RESOLUTION.x as f32 - size.width) / 2.,
+                (SIMULATED_RESOLUTION.y as f32 + size.height) / 2.,
+                countdown_size,
+                WHITE,
+            );
+        }
+
+        if let Some(spectator) = &self.spectator {
+            let prompt = tr_with(
+                "hud-spectating",
+                &[("seconds", &(spectator.time_left().ceil() as u32))],
+            );
+            // half size, so the whole line fits across the screen
+            let prompt_size = font_size / 2.;
+            let width = measure_text(&prompt, None, prompt_size as u16, 1.).width;
+            draw_text(
+                &prompt,
+                (SIMULATED_RESOLUTION.x as f32 - width) / 2.,
+                SIMULATED_RESOLUTION.y as f32 - 32.,
+                prompt_size,
+                WHITE,
+            );
+        }
+
+        if self.perf_overlay {
+            let lines = [
+                format!("FPS: {:.0}", self.perf.fps()),
+                format!("physics: {:.2} ms", self.perf.physics_ms()),
+                format!("colliders: {}", self.physics.colliders.len()),
+                format!("guards: {}", self.guards.len()),
+            ];
+            for (i, line) in lines.iter().enumerate() {
+                let y = SIMULATED_RESOLUTION.y as f32 - 16. - 24. * (lines.len() - 1 - i) as f32;
+                draw_text(line, 16., y, 24., WHITE);
+            }
+        }
+    }
+
+    /// Time spent on the current floor, as shown on the HUD
+    fn elapsed_time_str(&self) -> String {
+        // the clock stands still during the countdown
+        let now = self.paused_at.unwrap_or_else(clock::now);
+        splits::clock_str(now - self.start_time)
+    }
+
+    /// The latest split, and how far ahead of or behind the best run it was
+    fn draw_split(&self, x: f32, y: f32, font_size: f32) {
+        let Some(split) = self.splits.last else {
+            return;
+        };
+        let name = match split.kind {
+            SplitKind::Trap => tr_with("hud-split-trap", &[("count", &(split.index + 1))]),
+            SplitKind::Floor => tr_with("hud-split-floor", &[("floor", &(split.index + 1))]),
+        };
+        let text = format!("{} {}", name, splits::clock_str(split.time));
+        // smaller than the timer, so the line fits beside the HUD
+        let split_size = font_size * 2. / 3.;
+        draw_text(&text, x, y, split_size, WHITE);
+        let Some(delta) = split.delta else {
+            return;
+        };
+        let width = measure_text(&text, None, split_size as u16, 1.).width;
+        let color = if delta <= 0. {
+            GREEN
+        } else {
+            CueStyle::new(&self.settings).palette.alert
+        };
+        draw_text(
+            &splits::delta_str(delta),
+            x + width + split_size / 2.,
+            y,
+            split_size,
+            color,
+        );
+    }
+
+    fn draw_screen(&self) {
+        // draw full screen quad with previously rendered screen
+        set_camera(&self.cameras.screen_camera);
+        self.cameras.draw_world_render_to_screen();
+        if let Some(lighting) = &self.lighting {
+            lighting.draw_to_screen();
+        }
+        self.cameras.draw_ui_render_to_screen();
+    }
+
+    fn handle_collision(&mut self, collision_event: &CollisionEvent) {
+        if !collision_event.started() {
+            return;
+        }
+        let entities = &self.physics.entities;
+        let (Some(a), Some(b)) = (
+            entities.collider_entity(collision_event.collider1()),
+            entities.collider_entity(collision_event.collider2()),
+        ) else {
+            return;
+        };
+        if let Some(event) = self.collision_router.route(a, b) {
+            self.events.emit(event);
+        }
+    }
+}
+
+/// The living player closest to a position, for guards to go after
+fn nearest_player<'a>(
+    player: &'a Character,
+    partner: Option<&'a Character>,
+    position: Vec2,
+) -> &'a Character {
+    let Some(partner) = partner.filter(|partner| partner.is_alive()) else {
+        return player;
+    };
+    if !player.is_alive()
+        || partner.center().distance(position) < player.center().distance(position)
+    {
+        partner
+    } else {
+        player
+    }
+}
+
+/// Whether a hiding spot can be climbed into. Nobody can be in it already, and a cell is only
+/// any good while its door is open.
+fn spot_is_free(spot: &HidingSpot, guard_doors: &[GuardDoor]) -> bool {
+    spot.occupant.is_none()
+        && spot.door_position().is_none_or(|position| {
+            guard_doors
+                .iter()
+                .any(|door| door.position() == position && door.is_open)
+        })
+}
+
+/// Read the tuning file, keeping the current values if it can't be read
+async fn load_tuning() {
+    match Tuning::load(TUNING_PATH).await {
+        Ok(tuning) => {
+            info!("loaded tuning from {}", TUNING_PATH);
+            set_tuning(tuning);
+        }
+        Err(err) => warn!("could not load tuning from {}: {}", TUNING_PATH, err),
+    }
+}
+
+/// What a footstep at a position lands on
+fn surface_at(layer: &Layer, theme: &TileTheme, position: Vec2) -> Surface {
+    if position.x < 0. || position.y < 0. {
+        return Surface::Ground;
+    }
+    let (x, y) = (position.x as u32, position.y as u32);
+    if x >= layer.width || y >= layer.height {
+        return Surface::Ground;
+    }
+    let doors = &theme.doors;
+    match &layer.data[xytoi(x, y, layer)] {
+        Some(tile)
+            if [
+                doors.pool_empty,
+                doors.pool_full,
+                doors.stairs_left,
+                doors.stairs_right,
+            ]
+            .contains(&tile.id) =>
+        {
+            Surface::Stone
         }
+        Some(tile) if theme.ground.ice == Some(tile.id) => Surface::Ice,
+        _ => Surface::Ground,
     }
 }
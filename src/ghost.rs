@@ -0,0 +1,140 @@
+use anyhow::Result;
+use macroquad::{
+    color::Color,
+    math::{vec2, Vec2},
+    texture::{draw_texture_ex, DrawTextureParams},
+};
+use macroquad_tiled::Map as TiledMap;
+use nanoserde::{DeJson, SerJson};
+
+use crate::{
+    camera::snap_to_pixel,
+    clock,
+    constants::{GHOST_ALPHA, GHOST_PATH, GHOST_SAMPLE_INTERVAL, PLAYER_SPRITE_ID, TILESET_MAP_ID},
+    map::sprite_source,
+    storage,
+};
+
+/// Where the player went on their fastest escape from one map, to race against next time.
+///
+/// Maps are told apart by the seed they were generated from and the floor they were on, so a
+/// ghost only shows up when exactly the same map comes around again.
+#[derive(Clone, Debug, Default, PartialEq, DeJson, SerJson)]
+pub struct Ghost {
+    pub seed: u64,
+    pub floor: u32,
+    /// seconds from the start of the floor to the escape
+    pub time: f64,
+    /// player positions as `[x, y]`, every `GHOST_SAMPLE_INTERVAL` seconds from the start
+    pub positions: Vec<[f32; 2]>,
+}
+
+impl Ghost {
+    /// The stored ghost for a map, if it's the one that was last raced
+    pub fn read(seed: u64, floor: u32) -> Result<Option<Self>> {
+        let Some(json) = storage::read(GHOST_PATH)? else {
+            return Ok(None);
+        };
+        let ghost: Self = DeJson::deserialize_json(&json)?;
+        Ok((ghost.seed == seed && ghost.floor == floor).then_some(ghost))
+    }
+
+    pub fn write(&self) -> Result<()> {
+        storage::write(GHOST_PATH, &self.serialize_json())
+    }
+
+    /// Where the ghost was some seconds into the floor, or None once it has escaped
+    pub fn position_at(&self, elapsed: f64) -> Option<Vec2> {
+        let sample = elapsed / GHOST_SAMPLE_INTERVAL;
+        let i = sample as usize;
+        let from = Vec2::from_array(*self.positions.get(i)?);
+        let to = self
+            .positions
+            .get(i + 1)
+            .map_or(from, |position| Vec2::from_array(*position));
+        Some(from.lerp(to, sample.fract() as f32))
+    }
+
+    /// Draw a faded player where the ghost was at this point in the floor
+    pub fn draw(&self, elapsed: f64, tile_map: &TiledMap) {
+        let Some(position) = self.position_at(elapsed).map(snap_to_pixel) else {
+            return;
+        };
+        let tileset = &tile_map.tilesets[TILESET_MAP_ID];
+        let source = sprite_source(tileset, PLAYER_SPRITE_ID);
+        draw_texture_ex(
+            &tileset.texture,
+            position.x,
+            position.y,
+            Color::new(1., 1., 1., GHOST_ALPHA),
+            DrawTextureParams {
+                dest_size: Some(vec2(1., 1.)),
+                source: Some(source),
+                ..Default::default()
+            },
+        );
+    }
+}
+
+/// Samples the player's path through a floor, so it can become the next ghost
+#[derive(Debug, Default)]
+pub struct GhostRecorder {
+    start_time: f64,
+    positions: Vec<[f32; 2]>,
+}
+
+impl GhostRecorder {
+    pub fn start(&mut self) {
+        self.start_time = clock::now();
+        self.positions.clear();
+    }
+
+    /// Leave time the round spent paused out of the path
+    pub fn skip(&mut self, seconds: f64) {
+        self.start_time += seconds;
+    }
+
+    /// Seconds since the floor started
+    pub fn elapsed(&self) -> f64 {
+        clock::now() - self.start_time
+    }
+
+    pub fn record(&mut self, position: Vec2) {
+        // catch up on every sample since the last one, in case a frame ran long
+        let samples = (self.elapsed() / GHOST_SAMPLE_INTERVAL) as usize + 1;
+        while self.positions.len() < samples {
+            self.positions.push(position.to_array());
+        }
+    }
+
+    pub fn finish(&self, seed: u64, floor: u32) -> Ghost {
+        Ghost {
+            seed,
+            floor,
+            time: self.elapsed(),
+            positions: self.positions.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ghost_interpolates_between_samples_and_ends() {
+        let ghost = Ghost {
+            seed: 1,
+            floor: 1,
+            time: GHOST_SAMPLE_INTERVAL,
+            positions: vec![[0., 0.], [2., 4.]],
+        };
+        assert_eq!(ghost.position_at(0.), Some(vec2(0., 0.)));
+        let halfway = ghost.position_at(GHOST_SAMPLE_INTERVAL / 2.).unwrap();
+        assert!(halfway.distance(vec2(1., 2.)) < 1e-4);
+        assert_eq!(ghost.position_at(GHOST_SAMPLE_INTERVAL * 3.), None);
+
+        let json = ghost.serialize_json();
+        assert_eq!(Ghost::deserialize_json(&json).unwrap(), ghost);
+    }
+}
@@ -0,0 +1,99 @@
+use macroquad::math::{uvec2, vec2, Rect, UVec2, Vec2};
+use macroquad_tiled::Map as TiledMap;
+
+use crate::{
+    constants::{BARREL_SPRITE_ID, TILESET_MAP_ID},
+    entity::{EntityId, EntityKind, EntityRegistry},
+    event::{EventQueue, GameEvent},
+    interact::Interactable,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HidingSpotKind {
+    Barrel,
+    /// the doorway of a cell, which is only safe for as long as its door stays open
+    Cell,
+}
+
+/// Somewhere a player can climb into with the interact key. Guards can't see whoever is inside
+/// unless they are right on top of them.
+pub struct HidingSpot {
+    pub id: EntityId,
+    pub kind: HidingSpotKind,
+    /// tile the spot is on. for a cell, the left of the two doorway tiles.
+    pub position: UVec2,
+    /// the player inside, if anyone is
+    pub occupant: Option<EntityId>,
+}
+
+impl HidingSpot {
+    pub fn create(kind: HidingSpotKind, position: UVec2, entities: &mut EntityRegistry) -> Self {
+        // like chests, hiding spots are only ever used, never bumped into
+        let id = entities.spawn(EntityKind::HidingSpot);
+        Self {
+            id,
+            kind,
+            position,
+            occupant: None,
+        }
+    }
+
+    /// The hiding spot in the doorway of a guard door at `door_position`
+    pub fn cell(door_position: UVec2, entities: &mut EntityRegistry) -> Self {
+        Self::create(HidingSpotKind::Cell, door_position + uvec2(1, 0), entities)
+    }
+
+    /// Top left of the guard door a cell spot is in. None for anything else.
+    pub fn door_position(&self) -> Option<UVec2> {
+        match self.kind {
+            HidingSpotKind::Barrel => None,
+            HidingSpotKind::Cell => Some(self.position - uvec2(1, 0)),
+        }
+    }
+
+    /// Where whoever hides here is kept
+    pub fn center(&self) -> Vec2 {
+        match self.kind {
+            HidingSpotKind::Barrel => self.position.as_vec2() + vec2(0.5, 0.5),
+            HidingSpotKind::Cell => self.position.as_vec2() + vec2(1., 0.5),
+        }
+    }
+
+    /// Where whoever hides here steps back out to. Cells are left onto the floor in front of them.
+    pub fn exit_position(&self) -> Vec2 {
+        match self.kind {
+            HidingSpotKind::Barrel => self.center(),
+            HidingSpotKind::Cell => self.center() + vec2(0., 1.),
+        }
+    }
+
+    /// Draw the barrel. Cells are already part of the map.
+    pub fn draw(&self, tile_map: &TiledMap) {
+        if self.kind != HidingSpotKind::Barrel {
+            return;
+        }
+        let draw_rect = Rect::new(self.position.x as f32, self.position.y as f32, 1., 1.);
+        tile_map.spr(TILESET_MAP_ID, BARREL_SPRITE_ID, draw_rect);
+    }
+}
+
+impl Interactable for HidingSpot {
+    fn interact_position(&self) -> Vec2 {
+        self.center()
+    }
+
+    fn prompt(&self) -> &'static str {
+        if self.occupant.is_some() {
+            "prompt-leave-hiding"
+        } else {
+            "prompt-hide"
+        }
+    }
+
+    fn interact(&mut self, player: EntityId, events: &mut EventQueue) {
+        events.emit(GameEvent::HidingToggled {
+            spot: self.id,
+            player,
+        });
+    }
+}
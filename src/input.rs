@@ -0,0 +1,412 @@
+use macroquad::{
+    camera::set_default_camera,
+    color::Color,
+    input::{
+        is_key_down, is_key_pressed, is_mouse_button_down, is_mouse_button_pressed, touches,
+        KeyCode, MouseButton, TouchPhase,
+    },
+    math::{vec2, Vec2},
+    shapes::{draw_circle, draw_circle_lines},
+    window::{screen_height, screen_width},
+};
+
+use crate::{
+    constants::{
+        INPUT_BUFFER_TIME, TOUCH_BUTTON_RADIUS, TOUCH_STICK_DEAD_ZONE, TOUCH_STICK_RADIUS,
+        TOUCH_STICK_SNEAK,
+    },
+    locale::tr,
+};
+
+/// What the player is asking their character to do this frame, from whichever device
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PlayerInput {
+    /// direction to walk in. not normalized, so devices can be added together.
+    pub move_direction: Vec2,
+    pub sneak: bool,
+    pub sprint: bool,
+    /// the attack is being held down to charge, and swings when let go
+    pub attack: bool,
+    /// direction to attack and throw in, relative to the player
+    pub aim: Vec2,
+    /// a throw was asked for this frame
+    pub throw: bool,
+    /// the interact key was pressed this frame
+    pub interact: bool,
+}
+
+impl PlayerInput {
+    /// Player one's keys to move, and the mouse to aim, attack and throw. The aim points from
+    /// `center` to the mouse, both in world space.
+    pub fn keyboard_mouse(mouse: Vec2, center: Vec2) -> Self {
+        let keys = KeyBindings::PLAYER_ONE;
+        Self {
+            attack: keys.attack.is_down(),
+            aim: mouse - center,
+            throw: keys.throw.is_some_and(Binding::is_pressed),
+            ..Self::keyboard()
+        }
+    }
+
+    /// Just player one's movement keys, for when the mouse is really a finger on a touchscreen
+    pub fn keyboard() -> Self {
+        let keys = KeyBindings::PLAYER_ONE;
+        Self {
+            move_direction: keys.move_direction(),
+            sneak: keys.sneak.is_some_and(Binding::is_down),
+            sprint: keys.sprint.is_down(),
+            interact: keys.interact.is_pressed(),
+            ..Default::default()
+        }
+    }
+
+    /// Combine with input from another device. The other device's aim wins if it has one.
+    pub fn merge(self, other: Self) -> Self {
+        Self {
+            move_direction: self.move_direction + other.move_direction,
+            sneak: self.sneak || other.sneak,
+            sprint: self.sprint || other.sprint,
+            attack: self.attack || other.attack,
+            aim: if other.aim != Vec2::ZERO {
+                other.aim
+            } else {
+                self.aim
+            },
+            throw: self.throw || other.throw,
+            interact: self.interact || other.interact,
+        }
+    }
+}
+
+/// A button press held on to for a moment, so one made just before the action is allowed again
+/// still goes off as soon as it is
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BufferedPress {
+    pressed_at: Option<f64>,
+}
+
+impl BufferedPress {
+    pub fn press(&mut self, now: f64) {
+        self.pressed_at = Some(now);
+    }
+
+    /// Use up the press, if there is one from no longer than the buffer time before `now`
+    pub fn take(&mut self, now: f64) -> bool {
+        self.pressed_at
+            .take()
+            .is_some_and(|pressed_at| now - pressed_at <= INPUT_BUFFER_TIME)
+    }
+}
+
+/// Reads [`KeyBindings::PLAYER_TWO`] for a second player sharing the keyboard, aiming the way
+/// they last moved
+#[derive(Debug)]
+pub struct ArrowKeys {
+    last_direction: Vec2,
+}
+
+impl Default for ArrowKeys {
+    fn default() -> Self {
+        Self {
+            last_direction: Vec2::X,
+        }
+    }
+}
+
+impl ArrowKeys {
+    pub fn read(&mut self) -> PlayerInput {
+        let keys = KeyBindings::PLAYER_TWO;
+        let move_direction = keys.move_direction();
+        if move_direction != Vec2::ZERO {
+            self.last_direction = move_direction.normalize();
+        }
+        PlayerInput {
+            move_direction,
+            sprint: keys.sprint.is_down(),
+            attack: keys.attack.is_down(),
+            interact: keys.interact.is_pressed(),
+            aim: self.last_direction,
+            ..Default::default()
+        }
+    }
+}
+
+/// A key or mouse button that does something
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+}
+
+impl Binding {
+    pub fn is_down(self) -> bool {
+        match self {
+            Binding::Key(key) => is_key_down(key),
+            Binding::Mouse(button) => is_mouse_button_down(button),
+        }
+    }
+
+    pub fn is_pressed(self) -> bool {
+        match self {
+            Binding::Key(key) => is_key_pressed(key),
+            Binding::Mouse(button) => is_mouse_button_pressed(button),
+        }
+    }
+
+    /// What to call it when telling the player which key to press
+    pub fn name(self) -> String {
+        match self {
+            Binding::Key(key) => key_name(key),
+            Binding::Mouse(MouseButton::Left) => tr("key-mouse-left"),
+            Binding::Mouse(MouseButton::Right) => tr("key-mouse-right"),
+            Binding::Mouse(_) => tr("key-mouse-middle"),
+        }
+    }
+}
+
+/// Which keys do what for one player at the keyboard. Input is read through these, and the
+/// instructions name keys from them, so the two can't disagree.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KeyBindings {
+    /// up, left, down and right
+    pub movement: [KeyCode; 4],
+    pub sprint: Binding,
+    pub sneak: Option<Binding>,
+    pub attack: Binding,
+    pub throw: Option<Binding>,
+    pub interact: Binding,
+}
+
+impl KeyBindings {
+    /// WASD, with the mouse to attack and throw
+    pub const PLAYER_ONE: Self = Self {
+        movement: [KeyCode::W, KeyCode::A, KeyCode::S, KeyCode::D],
+        sprint: Binding::Key(KeyCode::LeftShift),
+        sneak: Some(Binding::Key(KeyCode::LeftControl)),
+        attack: Binding::Mouse(MouseButton::Left),
+        throw: Some(Binding::Mouse(MouseButton::Right)),
+        interact: Binding::Key(KeyCode::E),
+    };
+
+    /// The arrow keys and the keys to the right of them, for a second player sharing the keyboard.
+    /// There's no mouse to aim with, so attacks go the way the player last moved.
+    pub const PLAYER_TWO: Self = Self {
+        movement: [KeyCode::Up, KeyCode::Left, KeyCode::Down, KeyCode::Right],
+        sprint: Binding::Key(KeyCode::RightShift),
+        sneak: None,
+        attack: Binding::Key(KeyCode::RightControl),
+        throw: None,
+        interact: Binding::Key(KeyCode::RightAlt),
+    };
+
+    /// The way the movement keys held down point. Not normalized.
+    pub fn move_direction(&self) -> Vec2 {
+        let [up, left, down, right] = self.movement;
+        let mut move_direction = Vec2::ZERO;
+        for (key, direction) in [
+            (up, vec2(0., -1.)),
+            (left, vec2(-1., 0.)),
+            (down, vec2(0., 1.)),
+            (right, vec2(1., 0.)),
+        ] {
+            if is_key_down(key) {
+                move_direction += direction;
+            }
+        }
+        move_direction
+    }
+
+    /// The movement keys together, like "WASD" or "Up/Left/Down/Right"
+    pub fn movement_name(&self) -> String {
+        let names = self.movement.map(key_name);
+        if names.iter().all(|name| name.chars().count() == 1) {
+            names.concat()
+        } else {
+            names.join("/")
+        }
+    }
+}
+
+/// Spell out a key, with a space between words: `LeftControl` becomes "Left Control"
+fn key_name(key: KeyCode) -> String {
+    let code = format!("{:?}", key);
+    let mut name = String::new();
+    for (i, c) in code.chars().enumerate() {
+        if i > 0 && c.is_ascii_uppercase() {
+            name.push(' ');
+        }
+        name.push(c);
+    }
+    name
+}
+
+/// On-screen joystick and buttons for touchscreens, hidden until the screen is first touched.
+///
+/// Touching the left half of the screen puts a joystick under the finger. Pushing it less than
+/// half way sneaks. Holding anywhere on the right half charges an attack, and the button in the
+/// corner throws. Both aim the way the player last moved.
+#[derive(Debug)]
+pub struct TouchControls {
+    pub enabled: bool,
+    /// touch steering the joystick, and where it went down
+    stick: Option<(u64, Vec2)>,
+    /// how far the joystick is pushed from where it went down, in screen pixels
+    stick_offset: Vec2,
+    attack_touch: Option<u64>,
+    last_direction: Vec2,
+}
+
+impl Default for TouchControls {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            stick: None,
+            stick_offset: Vec2::ZERO,
+            attack_touch: None,
+            last_direction: Vec2::X,
+        }
+    }
+}
+
+impl TouchControls {
+    pub fn read(&mut self) -> PlayerInput {
+        let touches = touches();
+        if touches.is_empty() && !self.enabled {
+            return PlayerInput::default();
+        }
+        self.enabled = true;
+
+        let mut input = PlayerInput::default();
+        for touch in touches {
+            match touch.phase {
+                TouchPhase::Started => {
+                    if touch.position.x < screen_width() / 2. {
+                        if self.stick.is_none() {
+                            self.stick = Some((touch.id, touch.position));
+                            self.stick_offset = Vec2::ZERO;
+                        }
+                    } else if touch.position.distance(throw_button_center()) < TOUCH_BUTTON_RADIUS {
+                        input.throw = true;
+                    } else if self.attack_touch.is_none() {
+                        self.attack_touch = Some(touch.id);
+                    }
+                }
+                TouchPhase::Moved | TouchPhase::Stationary => {
+                    if let Some((id, origin)) = self.stick {
+                        if id == touch.id {
+                            self.stick_offset =
+                                (touch.position - origin).clamp_length_max(TOUCH_STICK_RADIUS);
+                        }
+                    }
+                }
+                TouchPhase::Ended | TouchPhase::Cancelled => {
+                    if self.stick.is_some_and(|(id, _)| id == touch.id) {
+                        self.stick = None;
+                        self.stick_offset = Vec2::ZERO;
+                    }
+                    if self.attack_touch == Some(touch.id) {
+                        self.attack_touch = None;
+                    }
+                }
+            }
+        }
+
+        let (move_direction, sneak) = stick_input(self.stick_offset);
+        if move_direction != Vec2::ZERO {
+            self.last_direction = move_direction.normalize();
+        }
+        PlayerInput {
+            move_direction,
+            sneak,
+            attack: self.attack_touch.is_some(),
+            aim: self.last_direction,
+            ..input
+        }
+    }
+
+    /// Draw the controls over everything else, in screen pixels
+    pub fn draw(&self) {
+        if !self.enabled {
+            return;
+        }
+        set_default_camera();
+        let color = Color::new(1., 1., 1., 0.4);
+
+        let origin = match self.stick {
+            Some((_, origin)) => origin,
+            None => vec2(
+                TOUCH_STICK_RADIUS * 1.5,
+                screen_height() - TOUCH_STICK_RADIUS * 1.5,
+            ),
+        };
+        draw_circle_lines(origin.x, origin.y, TOUCH_STICK_RADIUS, 4., color);
+        let knob = origin + self.stick_offset;
+        draw_circle(knob.x, knob.y, TOUCH_STICK_RADIUS / 3., color);
+
+        let throw = throw_button_center();
+        draw_circle_lines(throw.x, throw.y, TOUCH_BUTTON_RADIUS, 4., color);
+        if self.attack_touch.is_some() {
+            draw_circle(throw.x, throw.y, TOUCH_BUTTON_RADIUS / 2., color);
+        }
+    }
+}
+
+/// Throw button, in the bottom right corner
+fn throw_button_center() -> Vec2 {
+    vec2(
+        screen_width() - TOUCH_BUTTON_RADIUS * 1.5,
+        screen_height() - TOUCH_BUTTON_RADIUS * 1.5,
+    )
+}
+
+/// Movement for a joystick pushed some way off its center: nothing inside the dead zone, and
+/// sneaking while it is pushed less than part of the way
+fn stick_input(offset: Vec2) -> (Vec2, bool) {
+    let push = offset / TOUCH_STICK_RADIUS;
+    if push.length() < TOUCH_STICK_DEAD_ZONE {
+        return (Vec2::ZERO, false);
+    }
+    (push, push.length() < TOUCH_STICK_SNEAK)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stick_input_dead_zone_and_sneak() {
+        assert_eq!(stick_input(vec2(1., 0.)), (Vec2::ZERO, false));
+
+        let (direction, sneak) = stick_input(vec2(0., TOUCH_STICK_RADIUS * 0.3));
+        assert!(sneak);
+        assert!(direction.y > 0.);
+
+        let (direction, sneak) = stick_input(vec2(-TOUCH_STICK_RADIUS, 0.));
+        assert!(!sneak);
+        assert_eq!(direction, vec2(-1., 0.));
+    }
+
+    #[test]
+    fn test_keys_are_named_for_the_instructions() {
+        assert_eq!(key_name(KeyCode::LeftControl), "Left Control");
+        assert_eq!(key_name(KeyCode::E), "E");
+        assert_eq!(KeyBindings::PLAYER_ONE.movement_name(), "WASD");
+        assert_eq!(
+            KeyBindings::PLAYER_TWO.movement_name(),
+            "Up/Left/Down/Right"
+        );
+    }
+
+    #[test]
+    fn test_buffered_press_expires_and_is_used_once() {
+        let mut press = BufferedPress::default();
+        assert!(!press.take(0.));
+
+        press.press(1.);
+        assert!(press.take(1. + INPUT_BUFFER_TIME / 2.));
+        assert!(!press.take(1. + INPUT_BUFFER_TIME / 2.));
+
+        press.press(2.);
+        assert!(!press.take(2. + INPUT_BUFFER_TIME * 2.));
+    }
+}
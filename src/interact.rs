@@ -0,0 +1,117 @@
+use macroquad::{
+    color::{Color, WHITE},
+    math::{vec2, Vec2},
+    shapes::{draw_rectangle, draw_rectangle_lines},
+    text::{draw_text_ex, measure_text, TextParams},
+};
+
+use crate::{
+    constants::{INTERACT_DISTANCE, INTERACT_KEY_LABEL, SIMULATED_TILE_PX},
+    entity::EntityId,
+    event::EventQueue,
+    locale::tr,
+};
+
+const PROMPT_FONT_SIZE: u16 = 16;
+/// width of the key cap's outline, one simulated pixel
+const LINE_THICKNESS: f32 = 1. / SIMULATED_TILE_PX;
+
+/// Something in the world a player can walk up to and use with the interact key.
+///
+/// New kinds of object hook in by implementing this and being listed in `Game::interactables`.
+/// Using one only emits events, so what it actually does stays with the event handlers.
+pub trait Interactable {
+    /// where reach is measured from, and the prompt is drawn over
+    fn interact_position(&self) -> Vec2;
+
+    /// locale key for what using it does, shown next to the key in the prompt
+    fn prompt(&self) -> &'static str;
+
+    /// Use it. `player` is whoever pressed the key.
+    fn interact(&mut self, player: EntityId, events: &mut EventQueue);
+}
+
+/// Index of the closest interactable within reach of a position. `can_reach` says whether there
+/// is a clear way to an interactable's position, so nothing gets used through a wall.
+pub fn nearest(
+    interactables: &[&dyn Interactable],
+    position: Vec2,
+    can_reach: impl Fn(Vec2) -> bool,
+) -> Option<usize> {
+    interactables
+        .iter()
+        .enumerate()
+        .filter(|(_, interactable)| can_reach(interactable.interact_position()))
+        .map(|(i, interactable)| (i, interactable.interact_position().distance(position)))
+        .filter(|(_, distance)| *distance < INTERACT_DISTANCE)
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(i, _)| i)
+}
+
+/// Draw the interact key and what it will do, just above an interactable
+pub fn draw_prompt(interactable: &dyn Interactable) {
+    let text = format!("{} {}", INTERACT_KEY_LABEL, tr(interactable.prompt()));
+    let scale = 1. / SIMULATED_TILE_PX;
+    let size = measure_text(&text, None, PROMPT_FONT_SIZE, scale);
+    let position = interactable.interact_position() - vec2(size.width / 2., 1.);
+    let padding = 2. * scale;
+    draw_rectangle(
+        position.x - padding,
+        position.y - size.offset_y - padding,
+        size.width + padding * 2.,
+        size.height + padding * 2.,
+        Color::new(0., 0., 0., 0.6),
+    );
+    draw_rectangle_lines(
+        position.x - padding,
+        position.y - size.offset_y - padding,
+        size.width + padding * 2.,
+        size.height + padding * 2.,
+        LINE_THICKNESS,
+        WHITE,
+    );
+    draw_text_ex(
+        &text,
+        position.x,
+        position.y,
+        TextParams {
+            font_size: PROMPT_FONT_SIZE,
+            font_scale: scale,
+            color: WHITE,
+            ..Default::default()
+        },
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Lever(Vec2);
+
+    impl Interactable for Lever {
+        fn interact_position(&self) -> Vec2 {
+            self.0
+        }
+
+        fn prompt(&self) -> &'static str {
+            "prompt-unlock"
+        }
+
+        fn interact(&mut self, _player: EntityId, _events: &mut EventQueue) {}
+    }
+
+    #[test]
+    fn test_nearest_interactable_in_reach() {
+        let near = Lever(vec2(1., 0.));
+        let nearer = Lever(vec2(0., 0.5));
+        let far = Lever(vec2(INTERACT_DISTANCE * 2., 0.));
+        let interactables: [&dyn Interactable; 3] = [&near, &far, &nearer];
+        let anywhere = |_| true;
+        assert_eq!(nearest(&interactables, Vec2::ZERO, anywhere), Some(2));
+        assert_eq!(nearest(&interactables[..2], Vec2::ZERO, anywhere), Some(0));
+        assert_eq!(nearest(&interactables[1..2], Vec2::ZERO, anywhere), None);
+        let behind_wall = |target: Vec2| target.y == 0.;
+        assert_eq!(nearest(&interactables, Vec2::ZERO, behind_wall), Some(0));
+    }
+}
@@ -0,0 +1,103 @@
+use macroquad::{
+    math::{vec2, Rect, UVec2, Vec2},
+    rand::gen_range,
+};
+use macroquad_tiled::Map as TiledMap;
+use nalgebra::vector;
+use nanoserde::{DeJson, SerJson};
+use rapier2d::{
+    geometry::{ColliderBuilder, ColliderHandle, ColliderSet},
+    pipeline::ActiveEvents,
+};
+
+use crate::{
+    constants::{
+        HEART_TILE_ID, ITEM_DRAW_SIZE, ITEM_RADIUS, KEY_TILE_ID, SPEED_POTION_TILE_ID,
+        TILESET_MAP_ID,
+    },
+    entity::{EntityId, EntityKind, EntityRegistry},
+    physics::{CollisionLayer, Physics},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, DeJson, SerJson)]
+pub enum ItemKind {
+    /// restores health, how much depending on the difficulty
+    Heart,
+    Key,
+    /// temporarily makes the player faster
+    SpeedPotion,
+}
+
+impl ItemKind {
+    pub fn sprite_id(&self) -> u32 {
+        match self {
+            ItemKind::Heart => HEART_TILE_ID,
+            ItemKind::Key => KEY_TILE_ID,
+            ItemKind::SpeedPotion => SPEED_POTION_TILE_ID,
+        }
+    }
+
+    /// Roll a random consumable. Keys are placed separately, one per locked door.
+    pub fn random_consumable() -> Self {
+        match gen_range(0, 2) {
+            0 => ItemKind::Heart,
+            _ => ItemKind::SpeedPotion,
+        }
+    }
+}
+
+/// A pickup lying on the floor, waiting for the player to walk over it
+pub struct Item {
+    pub id: EntityId,
+    pub kind: ItemKind,
+    /// tile the item sits on
+    pub position: UVec2,
+    collider_handle: ColliderHandle,
+}
+
+impl Item {
+    pub fn create(
+        kind: ItemKind,
+        position: UVec2,
+        collider_set: &mut ColliderSet,
+        entities: &mut EntityRegistry,
+    ) -> Self {
+        let center = position.as_vec2() + vec2(0.5, 0.5);
+        let collider = ColliderBuilder::ball(ITEM_RADIUS)
+            .translation(vector![center.x, center.y])
+            .sensor(true)
+            .collision_groups(CollisionLayer::TileSensor.groups())
+            .active_events(ActiveEvents::COLLISION_EVENTS)
+            .build();
+        let collider_handle = collider_set.insert(collider);
+        let id = entities.spawn(EntityKind::Item);
+        entities.register_collider(collider_handle, id);
+
+        Self {
+            id,
+            kind,
+            position,
+            collider_handle,
+        }
+    }
+
+    pub fn center(&self) -> Vec2 {
+        self.position.as_vec2() + vec2(0.5, 0.5)
+    }
+
+    pub fn destroy_physics(&self, physics: &mut Physics) {
+        physics.remove_collider(self.collider_handle);
+        physics.entities.despawn(self.id);
+    }
+
+    pub fn draw(&self, tile_map: &TiledMap) {
+        let center = self.center();
+        let draw_rect = Rect::new(
+            center.x - ITEM_DRAW_SIZE / 2.,
+            center.y - ITEM_DRAW_SIZE / 2.,
+            ITEM_DRAW_SIZE,
+            ITEM_DRAW_SIZE,
+        );
+        tile_map.spr(TILESET_MAP_ID, self.kind.sprite_id(), draw_rect);
+    }
+}
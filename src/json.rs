@@ -0,0 +1,9 @@
+/// Fallback for `Option` fields of types that derive nanoserde's `DeJson`, as
+/// `#[nserde(default_with = "crate::json::none")]`.
+///
+/// Left to itself, the derived deserializer falls back to a literal `None` for a missing optional
+/// field, which trips `clippy::question_mark` on code nobody wrote. Falling back through here
+/// reads the same and keeps the lint on everywhere else.
+pub fn none<T>() -> Option<T> {
+    None
+}
@@ -0,0 +1,78 @@
+use anyhow::Result;
+use nanoserde::{DeJson, SerJson};
+
+use crate::{
+    constants::{LEADERBOARD_PATH, LEADERBOARD_SIZE},
+    storage,
+};
+
+/// The fastest escapes made on this machine, quickest first
+#[derive(Clone, Debug, Default, DeJson, SerJson)]
+pub struct Leaderboard {
+    pub entries: Vec<LeaderboardEntry>,
+}
+
+#[derive(Clone, Debug, PartialEq, DeJson, SerJson)]
+pub struct LeaderboardEntry {
+    pub initials: String,
+    /// seconds taken to escape
+    pub time: f64,
+}
+
+impl Leaderboard {
+    /// Read the leaderboard from storage. Nothing stored yet is an empty leaderboard.
+    pub fn read() -> Result<Self> {
+        match storage::read(LEADERBOARD_PATH)? {
+            Some(json) => Ok(DeJson::deserialize_json(&json)?),
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub fn write(&self) -> Result<()> {
+        storage::write(LEADERBOARD_PATH, &self.serialize_json())
+    }
+
+    /// Whether an escape this fast would make it onto the board
+    pub fn qualifies(&self, time: f64) -> bool {
+        self.entries.len() < LEADERBOARD_SIZE
+            || self.entries.last().is_some_and(|entry| time < entry.time)
+    }
+
+    /// Add an escape, bumping the slowest one off a full board. Returns its place, if it made it.
+    pub fn insert(&mut self, initials: &str, time: f64) -> Option<usize> {
+        if !self.qualifies(time) {
+            return None;
+        }
+        // ties go to whoever got there first
+        let place = self.entries.partition_point(|entry| entry.time <= time);
+        self.entries.insert(
+            place,
+            LeaderboardEntry {
+                initials: initials.into(),
+                time,
+            },
+        );
+        self.entries.truncate(LEADERBOARD_SIZE);
+        Some(place)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_leaderboard_keeps_fastest_times_in_order() {
+        let mut leaderboard = Leaderboard::default();
+        for i in 0..LEADERBOARD_SIZE {
+            assert_eq!(leaderboard.insert("AAA", 100. + i as f64), Some(i));
+        }
+
+        assert!(!leaderboard.qualifies(200.));
+        assert_eq!(leaderboard.insert("BBB", 200.), None);
+        assert_eq!(leaderboard.insert("CCC", 100.), Some(1));
+        assert_eq!(leaderboard.entries.len(), LEADERBOARD_SIZE);
+        assert_eq!(leaderboard.entries[1].initials, "CCC");
+        assert_eq!(leaderboard.entries.last().unwrap().time, 108.);
+    }
+}
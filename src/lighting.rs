@@ -0,0 +1,165 @@
+use macroquad::{
+    camera::{set_camera, Camera2D},
+    color::{Color, WHITE},
+    material::{gl_use_default_material, gl_use_material, load_material, Material, MaterialParams},
+    math::{vec2, Vec2},
+    miniquad::{BlendFactor, BlendState, BlendValue, Equation, PipelineParams, ShaderSource},
+    texture::{draw_texture_ex, DrawTextureParams, FilterMode, Image, Texture2D},
+    time::get_time,
+    window::clear_background,
+};
+use macroquad_tiled::Layer;
+
+use crate::{
+    camera::create_world_camera,
+    constants::{
+        AMBIENT_LIGHT, LIGHT_TEXTURE_SIZE, PLAYER_LIGHT_COLOR, PLAYER_LIGHT_RADIUS, TORCH_FLICKER,
+        TORCH_LIGHT_COLOR, TORCH_LIGHT_RADIUS,
+    },
+};
+
+/// Darkens the world, except around the player and the torches on the walls.
+///
+/// Lights are added together in their own render target, which starts out at the ambient light
+/// level. That target is then multiplied over the world when it is drawn to the screen.
+pub struct Lighting {
+    /// follows the world camera, but draws into the light target
+    light_camera: Camera2D,
+    /// round light with a soft edge, stretched to the size of each light
+    light_texture: Texture2D,
+    additive_material: Material,
+    multiply_material: Material,
+    /// centers of the torch tiles
+    torches: Vec<Vec2>,
+}
+
+impl Lighting {
+    /// Set up the lighting pass. Returns None if its shaders fail to load, in which case the
+    /// world is drawn fully lit.
+    pub fn new() -> Option<Self> {
+        let additive = BlendState::new(Equation::Add, BlendFactor::One, BlendFactor::One);
+        let multiply = BlendState::new(
+            Equation::Add,
+            BlendFactor::Value(BlendValue::DestinationColor),
+            BlendFactor::Zero,
+        );
+        let materials = load_light_material(additive)
+            .and_then(|additive| Ok((additive, load_light_material(multiply)?)));
+        let (additive_material, multiply_material) = match materials {
+            Ok(materials) => materials,
+            Err(err) => {
+                warn!("could not load the lighting shaders: {}", err);
+                return None;
+            }
+        };
+
+        Some(Self {
+            light_camera: create_world_camera(),
+            light_texture: create_light_texture(),
+            additive_material,
+            multiply_material,
+            torches: Vec::new(),
+        })
+    }
+
+    /// Find the torches on a freshly generated or loaded map
+    pub fn set_torches(&mut self, layer: &Layer, torch_tile_id: u32) {
+        self.torches = layer
+            .data
+            .iter()
+            .enumerate()
+            .filter(|(_, tile)| tile.as_ref().is_some_and(|tile| tile.id == torch_tile_id))
+            .map(|(i, _)| {
+                let (x, y) = (i as u32 % layer.width, i as u32 / layer.width);
+                vec2(x as f32 + 0.5, y as f32 + 0.5)
+            })
+            .collect();
+    }
+
+    /// Render the light levels for this frame into the light target
+    pub fn draw(&mut self, world_camera: &Camera2D, player_center: Vec2) {
+        self.light_camera.target = world_camera.target;
+        set_camera(&self.light_camera);
+        clear_background(AMBIENT_LIGHT);
+
+        gl_use_material(&self.additive_material);
+        self.draw_light(player_center, PLAYER_LIGHT_RADIUS, PLAYER_LIGHT_COLOR);
+        let now = get_time() as f32;
+        for (i, torch) in self.torches.iter().enumerate() {
+            // each torch flickers out of step with the others
+            let phase = i as f32 * 1.7;
+            let flicker = (now * 9. + phase).sin() * 0.6 + (now * 23. + phase * 2.).sin() * 0.4;
+            let radius = TORCH_LIGHT_RADIUS * (1. + TORCH_FLICKER * flicker);
+            self.draw_light(*torch, radius, TORCH_LIGHT_COLOR);
+        }
+        gl_use_default_material();
+    }
+
+    /// Darken the world on screen by the light target. Expects the screen camera to be set.
+    pub fn draw_to_screen(&self) {
+        gl_use_material(&self.multiply_material);
+        draw_texture_ex(
+            &self
+                .light_camera
+                .render_target
+                .as_ref()
+                .expect("light camera missing render target")
+                .texture,
+            0.,
+            0.,
+            WHITE,
+            DrawTextureParams {
+                dest_size: Some(vec2(1., 1.)),
+                ..Default::default()
+            },
+        );
+        gl_use_default_material();
+    }
+
+    fn draw_light(&self, center: Vec2, radius: f32, color: Color) {
+        draw_texture_ex(
+            &self.light_texture,
+            center.x - radius,
+            center.y - radius,
+            color,
+            DrawTextureParams {
+                dest_size: Some(vec2(radius * 2., radius * 2.)),
+                ..Default::default()
+            },
+        );
+    }
+}
+
+fn load_light_material(color_blend: BlendState) -> Result<Material, macroquad::Error> {
+    load_material(
+        ShaderSource::Glsl {
+            vertex: include_str!("../assets/shaders/light.vert"),
+            fragment: include_str!("../assets/shaders/light.frag"),
+        },
+        MaterialParams {
+            pipeline_params: PipelineParams {
+                color_blend: Some(color_blend),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    )
+}
+
+/// White in the middle, fading smoothly to black at the edge of the circle
+fn create_light_texture() -> Texture2D {
+    let size = LIGHT_TEXTURE_SIZE;
+    let mut image = Image::gen_image_color(size, size, Color::new(0., 0., 0., 1.));
+    let center = size as f32 / 2.;
+    for y in 0..size as u32 {
+        for x in 0..size as u32 {
+            let offset = vec2(x as f32 + 0.5, y as f32 + 0.5) - vec2(center, center);
+            let t = (1. - offset.length() / center).clamp(0., 1.);
+            let brightness = t * t * (3. - 2. * t);
+            image.set_pixel(x, y, Color::new(brightness, brightness, brightness, 1.));
+        }
+    }
+    let texture = Texture2D::from_image(&image);
+    texture.set_filter(FilterMode::Linear);
+    texture
+}
@@ -0,0 +1,112 @@
+use std::{cell::Cell, future::Future};
+
+use anyhow::Result;
+use macroquad::{
+    camera::set_default_camera,
+    color::{DARKGRAY, WHITE},
+    shapes::{draw_rectangle, draw_rectangle_lines},
+    text::{draw_text, measure_text},
+    window::{clear_background, next_frame, screen_height, screen_width},
+};
+
+use crate::constants::{LOADING_BAR_HEIGHT, LOADING_BAR_WIDTH};
+
+/// Counts assets as they finish loading, so the loading screen can show how far along it is
+#[derive(Debug)]
+pub struct LoadProgress {
+    total: usize,
+    loaded: Cell<usize>,
+    failed: Cell<bool>,
+}
+
+impl LoadProgress {
+    pub fn new(total: usize) -> Self {
+        Self {
+            total,
+            loaded: Cell::new(0),
+            failed: Cell::new(false),
+        }
+    }
+
+    /// Wrap an asset future so that it counts toward the progress once it completes
+    pub async fn track<T, E>(&self, future: impl Future<Output = Result<T, E>>) -> Result<T>
+    where
+        E: Into<anyhow::Error>,
+    {
+        let result = future.await.map_err(Into::into);
+        self.loaded.set(self.loaded.get() + 1);
+        if result.is_err() {
+            self.failed.set(true);
+        }
+        result
+    }
+
+    pub fn fraction(&self) -> f32 {
+        if self.total == 0 {
+            return 1.;
+        }
+        (self.loaded.get() as f32 / self.total as f32).min(1.)
+    }
+
+    /// Everything has loaded, or something failed and the rest won't be waited on
+    pub fn is_done(&self) -> bool {
+        self.failed.get() || self.loaded.get() >= self.total
+    }
+
+    /// Draw the loading screen every frame until loading is done.
+    ///
+    /// Meant to be joined with the futures being tracked, which get polled in between frames.
+    pub async fn draw_until_done(&self) {
+        while !self.is_done() {
+            self.draw();
+            next_frame().await;
+        }
+    }
+
+    fn draw(&self) {
+        set_default_camera();
+        clear_background(DARKGRAY);
+
+        let x = (screen_width() - LOADING_BAR_WIDTH) / 2.;
+        let y = (screen_height() - LOADING_BAR_HEIGHT) / 2.;
+        draw_rectangle(
+            x,
+            y,
+            LOADING_BAR_WIDTH * self.fraction(),
+            LOADING_BAR_HEIGHT,
+            WHITE,
+        );
+        draw_rectangle_lines(x, y, LOADING_BAR_WIDTH, LOADING_BAR_HEIGHT, 4., WHITE);
+
+        let label = "Loading...";
+        let size = measure_text(label, None, 48, 1.);
+        draw_text(
+            label,
+            (screen_width() - size.width) / 2.,
+            y - 24.,
+            48.,
+            WHITE,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+    use futures::executor::block_on;
+
+    #[test]
+    fn test_load_progress_counts_finished_assets() {
+        let progress = LoadProgress::new(4);
+        assert_eq!(progress.fraction(), 0.);
+
+        block_on(progress.track(async { Ok::<_, anyhow::Error>(()) })).unwrap();
+        assert_eq!(progress.fraction(), 0.25);
+        assert!(!progress.is_done());
+
+        let result = block_on(progress.track(async { Err::<(), _>(anyhow!("missing")) }));
+        assert!(result.is_err());
+        assert!(progress.is_done());
+    }
+}
@@ -0,0 +1,187 @@
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    sync::{LazyLock, RwLock},
+};
+
+use anyhow::{anyhow, Result};
+use macroquad::file::load_string;
+
+/// Text shown to the player in one language, looked up by key.
+///
+/// Locale files have a `key = text` pair on each line, and `#` comments. Text can have
+/// placeholders like `{time}` that are filled in when it is shown.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Locale {
+    /// file name the locale was loaded from, like "en", which settings refer to it by
+    pub code: String,
+    pub strings: HashMap<String, String>,
+}
+
+impl Locale {
+    pub async fn load(path: &str) -> Result<Self> {
+        let text = load_string(path).await?;
+        let code = path
+            .rsplit('/')
+            .next()
+            .and_then(|name| name.split('.').next())
+            .unwrap_or(path);
+        Self::parse(code, &text)
+    }
+
+    pub fn parse(code: &str, text: &str) -> Result<Self> {
+        let mut strings = HashMap::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| anyhow!("{}:{}: expected `key = text`", code, i + 1))?;
+            strings.insert(key.trim().to_string(), value.trim().to_string());
+        }
+        Ok(Self {
+            code: code.into(),
+            strings,
+        })
+    }
+
+    /// Name of the language, in that language
+    pub fn name(&self) -> &str {
+        self.strings
+            .get("language-name")
+            .map(String::as_str)
+            .unwrap_or(&self.code)
+    }
+}
+
+/// Every loaded locale, and which one the game is shown in.
+///
+/// English is built in, so there is always something to fall back on for missing keys, and so
+/// that errors can still be shown when loading fails.
+#[derive(Debug)]
+pub struct Localizer {
+    locales: Vec<Locale>,
+    current: usize,
+}
+
+impl Default for Localizer {
+    fn default() -> Self {
+        let english = Locale::parse("en", include_str!("../assets/locales/en.txt"))
+            .expect("built in locale should parse");
+        Self {
+            locales: vec![english],
+            current: 0,
+        }
+    }
+}
+
+impl Localizer {
+    /// Add a locale, replacing any with the same code
+    pub fn add(&mut self, locale: Locale) {
+        match self.locales.iter_mut().find(|l| l.code == locale.code) {
+            Some(existing) => *existing = locale,
+            None => self.locales.push(locale),
+        }
+    }
+
+    /// Switch languages. Unknown codes switch back to English.
+    pub fn set_language(&mut self, code: &str) {
+        self.current = self
+            .locales
+            .iter()
+            .position(|locale| locale.code == code)
+            .unwrap_or(0);
+    }
+
+    pub fn language(&self) -> &Locale {
+        &self.locales[self.current]
+    }
+
+    /// Code of the language after the current one, wrapping around
+    pub fn next_language(&self) -> &str {
+        &self.locales[(self.current + 1) % self.locales.len()].code
+    }
+
+    /// Text for a key in the current language, falling back to English and then the key itself
+    pub fn get<'a>(&'a self, key: &'a str) -> &'a str {
+        self.language()
+            .strings
+            .get(key)
+            .or_else(|| self.locales[0].strings.get(key))
+            .map(String::as_str)
+            .unwrap_or(key)
+    }
+}
+
+static LOCALIZER: LazyLock<RwLock<Localizer>> = LazyLock::new(Default::default);
+
+/// Add loaded locales alongside the built in English
+pub fn add_locales(locales: Vec<Locale>) {
+    let mut localizer = LOCALIZER.write().unwrap();
+    for locale in locales {
+        localizer.add(locale);
+    }
+}
+
+pub fn set_language(code: &str) {
+    LOCALIZER.write().unwrap().set_language(code);
+}
+
+/// Code of the language after the current one, for the settings menu to cycle through
+pub fn next_language() -> String {
+    LOCALIZER.read().unwrap().next_language().to_string()
+}
+
+pub fn language_name() -> String {
+    LOCALIZER.read().unwrap().language().name().to_string()
+}
+
+/// Text for a key in the current language
+pub fn tr(key: &str) -> String {
+    LOCALIZER.read().unwrap().get(key).to_string()
+}
+
+/// Text for a key in the current language, with each `{name}` replaced by its value
+pub fn tr_with(key: &str, args: &[(&str, &dyn Display)]) -> String {
+    fill(&tr(key), args)
+}
+
+fn fill(text: &str, args: &[(&str, &dyn Display)]) -> String {
+    let mut text = text.to_string();
+    for (name, value) in args {
+        text = text.replace(&format!("{{{}}}", name), &value.to_string());
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_localizer_falls_back_and_fills_placeholders() {
+        let mut localizer = Localizer::default();
+        localizer.add(Locale::parse("xx", "# test\nmain-play = Go\n").unwrap());
+        localizer.set_language("xx");
+        assert_eq!(localizer.get("main-play"), "Go");
+        assert_eq!(localizer.get("main-settings"), "Settings");
+        assert_eq!(localizer.get("no-such-key"), "no-such-key");
+        assert_eq!(localizer.next_language(), "en");
+
+        let text = fill(localizer.get("hud-floor"), &[("floor", &3)]);
+        assert_eq!(text, "Floor 3");
+
+        assert!(Locale::parse("xx", "missing equals").is_err());
+    }
+
+    #[test]
+    fn test_spanish_translates_every_english_key() {
+        let english = Localizer::default();
+        let spanish = Locale::parse("es", include_str!("../assets/locales/es.txt")).unwrap();
+        for key in english.language().strings.keys() {
+            assert!(spanish.strings.contains_key(key), "es is missing {}", key);
+        }
+    }
+}
@@ -0,0 +1,270 @@
+use std::{fmt, sync::RwLock};
+
+use macroquad::miniquad::date;
+
+use crate::constants::LOG_FILTER_VAR;
+
+/// How much a message matters, from most to least
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "error" => Some(Self::Error),
+            "warn" => Some(Self::Warn),
+            "info" => Some(Self::Info),
+            "debug" => Some(Self::Debug),
+            "trace" => Some(Self::Trace),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Error => "ERROR",
+            Self::Warn => "WARN",
+            Self::Info => "INFO",
+            Self::Debug => "DEBUG",
+            Self::Trace => "TRACE",
+        }
+    }
+}
+
+/// Which messages are logged: everything up to a level, with other levels for some modules
+#[derive(Clone, Debug, PartialEq)]
+pub struct Filter {
+    level: Level,
+    /// module path prefixes and their levels. the longest matching prefix wins.
+    modules: Vec<(String, Level)>,
+}
+
+static FILTER: RwLock<Filter> = RwLock::new(Filter {
+    level: Level::Info,
+    modules: Vec::new(),
+});
+
+impl Filter {
+    /// Read a filter like `info,stonehold::map=debug,stonehold::net=warn`. A bare level sets the
+    /// level for everything else. Parts that don't make sense are skipped.
+    pub fn parse(spec: &str) -> Self {
+        let mut filter = Self {
+            level: Level::Info,
+            modules: Vec::new(),
+        };
+        for part in spec.split(',').filter(|part| !part.trim().is_empty()) {
+            match part.split_once('=') {
+                Some((module, level)) => {
+                    if let Some(level) = Level::parse(level) {
+                        filter.modules.push((module.trim().to_string(), level));
+                    }
+                }
+                None => {
+                    if let Some(level) = Level::parse(part) {
+                        filter.level = level;
+                    }
+                }
+            }
+        }
+        filter
+    }
+
+    pub fn enabled(&self, module: &str, level: Level) -> bool {
+        let max = self
+            .modules
+            .iter()
+            .filter(|(prefix, _)| module.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map_or(self.level, |(_, level)| *level);
+        level <= max
+    }
+}
+
+/// Start logging: pick up the filter from the environment, start a fresh log file, and make sure
+/// panics end up in it. Call once, before anything is logged.
+pub fn init() {
+    if let Ok(spec) = std::env::var(LOG_FILTER_VAR) {
+        *FILTER.write().unwrap() = Filter::parse(&spec);
+    }
+    file::open();
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        // the default hook already prints it, so it only needs to go in the file
+        file::write_on_panic(&line(Level::Error, "panic", format_args!("{}", info)));
+        default_hook(info);
+    }));
+}
+
+/// Log a message from a module, if the filter lets it through. Use the macros instead.
+pub fn log(level: Level, module: &str, args: fmt::Arguments) {
+    if !FILTER.read().unwrap().enabled(module, level) {
+        return;
+    }
+    let line = line(level, module, args);
+    match level {
+        Level::Error => macroquad::logging::error!("{}", line),
+        Level::Warn => macroquad::logging::warn!("{}", line),
+        Level::Info => macroquad::logging::info!("{}", line),
+        Level::Debug | Level::Trace => macroquad::logging::debug!("{}", line),
+    }
+    file::write(&line);
+}
+
+/// A line for the log, stamped with the wall clock so it works before the window is up
+fn line(level: Level, module: &str, args: fmt::Arguments) -> String {
+    format!(
+        "{:.3} {:<5} {}: {}",
+        date::now(),
+        level.name(),
+        module,
+        args
+    )
+}
+
+// declared before every other module with #[macro_use], so these are in scope everywhere
+
+macro_rules! log {
+    ($level:expr, $($arg:tt)+) => {
+        $crate::logging::log($level, module_path!(), format_args!($($arg)+))
+    };
+}
+
+macro_rules! error {
+    ($($arg:tt)+) => { log!($crate::logging::Level::Error, $($arg)+) };
+}
+
+macro_rules! warn {
+    ($($arg:tt)+) => { log!($crate::logging::Level::Warn, $($arg)+) };
+}
+
+macro_rules! info {
+    ($($arg:tt)+) => { log!($crate::logging::Level::Info, $($arg)+) };
+}
+
+macro_rules! debug {
+    ($($arg:tt)+) => { log!($crate::logging::Level::Debug, $($arg)+) };
+}
+
+/// The log file, started fresh each run. Older runs' logs are kept alongside it, numbered from
+/// the most recent, and a log that grows too big is moved along the same way.
+#[cfg(not(target_arch = "wasm32"))]
+mod file {
+    use std::{
+        fs::{self, File},
+        io::{self, Write},
+        path::Path,
+        sync::Mutex,
+    };
+
+    use crate::constants::{LOG_BACKUPS, LOG_MAX_BYTES, LOG_PATH};
+
+    struct LogFile {
+        file: File,
+        /// bytes written since it was started
+        written: u64,
+    }
+
+    static FILE: Mutex<Option<LogFile>> = Mutex::new(None);
+
+    /// Path of an older log, counting back from the current one at 0
+    fn path(age: usize) -> String {
+        match age {
+            0 => LOG_PATH.to_string(),
+            _ => format!("{}.{}", LOG_PATH, age),
+        }
+    }
+
+    /// Move every log back a place, dropping the oldest, and start a new one
+    fn rotate() -> io::Result<File> {
+        for age in (1..=LOG_BACKUPS).rev() {
+            let from = path(age - 1);
+            if Path::new(&from).exists() {
+                fs::rename(from, path(age))?;
+            }
+        }
+        File::create(LOG_PATH)
+    }
+
+    pub fn open() {
+        match rotate() {
+            Ok(file) => *FILE.lock().unwrap() = Some(LogFile { file, written: 0 }),
+            Err(err) => macroquad::logging::warn!("could not open {}: {}", LOG_PATH, err),
+        }
+    }
+
+    pub fn write(line: &str) {
+        // poisoned by a panic partway through writing, so the file may end mid-line. stop there.
+        let Ok(mut log) = FILE.lock() else {
+            return;
+        };
+        append(&mut log, line);
+    }
+
+    /// Like `write`, but gives up on the line rather than wait for the file
+    pub fn write_on_panic(line: &str) {
+        // the panic may have come from inside `write` on this same thread, which still holds the
+        // lock while the hook runs. waiting for it would hang instead of panicking.
+        let Ok(mut log) = FILE.try_lock() else {
+            return;
+        };
+        append(&mut log, line);
+    }
+
+    fn append(log: &mut Option<LogFile>, line: &str) {
+        let Some(file) = log.as_mut() else {
+            return;
+        };
+        if file.written >= LOG_MAX_BYTES {
+            match rotate() {
+                Ok(new_file) => {
+                    *file = LogFile {
+                        file: new_file,
+                        written: 0,
+                    }
+                }
+                Err(_) => {
+                    *log = None;
+                    return;
+                }
+            }
+        }
+        if writeln!(file.file, "{}", line).is_ok() {
+            file.written += line.len() as u64 + 1;
+        }
+    }
+}
+
+/// There are no files on the web, so the browser console is all there is
+#[cfg(target_arch = "wasm32")]
+mod file {
+    pub fn open() {}
+
+    pub fn write(_line: &str) {}
+
+    pub fn write_on_panic(_line: &str) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_picks_the_most_specific_module() {
+        let filter = Filter::parse("warn, stonehold::map=debug,stonehold::map::mapgen=error,bad=x");
+        assert!(filter.enabled("stonehold::game", Level::Warn));
+        assert!(!filter.enabled("stonehold::game", Level::Info));
+        assert!(filter.enabled("stonehold::map::rules", Level::Debug));
+        assert!(!filter.enabled("stonehold::map::mapgen", Level::Warn));
+        assert!(!filter.enabled("bad", Level::Info));
+
+        let filter = Filter::parse("");
+        assert!(filter.enabled("stonehold", Level::Info));
+        assert!(!filter.enabled("stonehold", Level::Debug));
+    }
+}
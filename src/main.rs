@@ -1,21 +1,78 @@
 use game::Game;
+use menus::ErrorMenu;
 
+#[macro_use]
+mod logging;
+
+mod achievements;
 mod audio;
 mod camera;
 mod character;
+mod chests;
+mod clock;
+mod collision;
 mod constants;
+mod corpse;
+mod crates;
+mod damage;
+mod debug;
+mod demo;
+mod difficulty;
+mod diorama;
 mod door;
+mod entity;
+mod event;
 mod game;
+mod ghost;
+mod hiding;
+mod input;
+mod interact;
+mod items;
+mod json;
+mod leaderboard;
+mod lighting;
+mod loading;
+mod locale;
 mod map;
 mod menus;
+mod net;
+mod palette;
+mod passage;
+mod perf;
 mod physics;
+mod pipes;
+mod projectiles;
+mod save;
+mod scene;
+mod screenshot;
+mod settings;
+mod spatial;
+mod splits;
+mod stats;
+mod status;
+mod steering;
+mod storage;
+mod ticker;
+mod toast;
+mod traps;
+mod tuning;
+mod upgrades;
+mod watcher;
+mod widgets;
+mod world;
 
 #[macroquad::main("Stonehold")]
 async fn main() {
+    logging::init();
     loop {
-        let mut game = Game::load().await.expect("Could not load game.");
-        game.run_state()
-            .await
-            .expect("Error during game execution.");
+        let result = match Game::load().await {
+            Ok(mut game) => game.run_state().await,
+            Err(err) => Err(err.context("Could not load game.")),
+        };
+        // show what went wrong, then start over from loading when asked to
+        if let Err(err) = result {
+            error!("{:?}", err);
+            scene::run(&mut ErrorMenu::new(&err), &mut ()).await;
+        }
     }
 }
@@ -1,9 +1,10 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use futures::try_join;
 use macroquad::{
+    color::WHITE,
     file::load_string,
-    math::{Rect, UVec2},
-    texture::{load_texture, FilterMode},
+    math::{vec2, Rect, UVec2, Vec2},
+    texture::{draw_texture_ex, load_texture, DrawTextureParams, FilterMode},
 };
 use macroquad_tiled::Map as TileMap;
 use macroquad_tiled::{load_map, TileSet};
@@ -11,36 +12,57 @@ use rapier2d::{
     geometry::{ColliderBuilder, ColliderHandle, ColliderSet},
     na::vector,
 };
-use std::{collections::HashMap, iter, ops::Range};
+use std::collections::{HashMap, HashSet};
 
-use crate::constants::{
-    SOLID_TILES, TERRAIN_MAP_ID, TILESET_MAP_ID, TILESET_MAP_PATH, TILESET_TEXTURE_PATH,
-    TILE_MAP_JSON_PATH,
+use crate::{
+    constants::{
+        CHUNKED_MAP_SIZE, CHUNK_SIZE, OVERHEAD_MAP_ID, PROPS_MAP_ID, TERRAIN_MAP_ID,
+        TILESET_MAP_ID, TILESET_MAP_PATH, TILESET_TEXTURE_PATH, TILE_MAP_JSON_PATH,
+    },
+    entity::{EntityId, EntityKind, EntityRegistry},
+    physics::{CollisionLayer, Physics},
 };
 
+use self::{animation::TileAnimator, chunks::Chunks, theme::TileTheme};
+
+pub mod animation;
+pub mod chunks;
 pub mod mapgen;
+pub mod prefab;
+pub mod rooms;
+pub mod rules;
+pub mod theme;
 
 pub struct Map {
     /// tile map loaded from TilEd
     pub tile_map: TileMap,
 
-    /// physics collider handles
+    /// physics collider handles, keyed by tile. merged tiles share a handle.
     pub colliders: HashMap<UVec2, ColliderHandle>,
 
-    /// bitmask of which tiles are solid
-    pub solid_tile_mask: Vec<bool>,
+    /// animated terrain tiles, swapped in as the terrain is drawn
+    pub animator: TileAnimator,
+
+    /// wall colliders by chunk, on maps large enough to need them
+    pub chunks: Option<Chunks>,
+
+    /// the entity every wall collider belongs to, once there are any
+    wall_id: Option<EntityId>,
 }
 
 impl Map {
-    pub fn new(tile_map: TileMap) -> Self {
-        let solid_tile_mask =
-            Self::create_solid_tile_mask(&tile_map.tilesets[TILESET_MAP_ID], SOLID_TILES);
+    pub fn new(tile_map: TileMap) -> Result<Self> {
+        if !tile_map.tilesets.contains_key(TILESET_MAP_ID) {
+            return Err(anyhow!("tile map has no {} tileset", TILESET_MAP_ID));
+        }
 
-        Self {
+        Ok(Self {
             tile_map,
             colliders: HashMap::new(),
-            solid_tile_mask,
-        }
+            animator: TileAnimator::default(),
+            chunks: None,
+            wall_id: None,
+        })
     }
 
     /// Load the map from a constant path
@@ -57,52 +79,401 @@ impl Map {
         // construct tile map from loaded assets
         let tile_map = load_map(&tile_map_json, &[(TILESET_MAP_PATH, tile_texture)], &[])?;
 
-        Ok(Self::new(tile_map))
+        Self::new(tile_map)
+    }
+
+    /// Load the tilesets of the themes that bring their own, so their maps can be drawn
+    pub async fn load_theme_tilesets(&mut self, themes: &[TileTheme]) -> Result<()> {
+        for theme in themes {
+            let Some(path) = &theme.texture else {
+                continue;
+            };
+            let texture = load_texture(path).await?;
+            texture.set_filter(FilterMode::Nearest);
+            // cut up the same way as the map's own tileset
+            let tileset = &self.tile_map.tilesets[TILESET_MAP_ID];
+            let tileset = TileSet {
+                columns: texture.width() as u32 / tileset.tilewidth as u32,
+                texture,
+                ..copy_tileset(tileset)
+            };
+            self.tile_map
+                .tilesets
+                .insert(theme.tileset.clone(), tileset);
+        }
+        Ok(())
+    }
+
+    /// draw the map in worldspace, skipping tiles outside of `view`
+    pub fn draw(&self, view: Rect) {
+        self.draw_terrain(view);
+        if self.tile_map.contains_layer(PROPS_MAP_ID) {
+            self.draw_layer(PROPS_MAP_ID, view);
+        }
+    }
+
+    /// draw the terrain a tile at a time, so animated tiles can show their current frame
+    fn draw_terrain(&self, view: Rect) {
+        let layer = &self.tile_map.layers[TERRAIN_MAP_ID];
+        let Some(tiles) = clip_to_tiles(view, layer.width, layer.height) else {
+            return;
+        };
+        for (x, y, tile) in self.tile_map.tiles(TERRAIN_MAP_ID, tiles) {
+            let Some(tile) = tile else {
+                continue;
+            };
+            let dest = Rect::new(x as f32, y as f32, 1., 1.);
+            let Some(frame) = self.animator.frame(tile.id, x, y) else {
+                self.tile_map.spr(&tile.tileset, tile.id, dest);
+                continue;
+            };
+            let tileset = &self.tile_map.tilesets[&tile.tileset];
+            draw_texture_ex(
+                &tileset.texture,
+                dest.x,
+                dest.y,
+                WHITE,
+                DrawTextureParams {
+                    dest_size: Some(vec2(dest.w, dest.h)),
+                    source: Some(sprite_source(tileset, frame.id)),
+                    flip_x: frame.flip_x,
+                    ..Default::default()
+                },
+            );
+        }
+    }
+
+    /// draw the wall tops that go over the characters, in worldspace
+    pub fn draw_overhead(&self, view: Rect) {
+        if self.tile_map.contains_layer(OVERHEAD_MAP_ID) {
+            self.draw_layer(OVERHEAD_MAP_ID, view);
+        }
+    }
+
+    /// draw the tiles of a layer that fall inside `view`
+    fn draw_layer(&self, layer_id: &str, view: Rect) {
+        let layer = &self.tile_map.layers[layer_id];
+        if let Some(tiles) = clip_to_tiles(view, layer.width, layer.height) {
+            self.tile_map.draw_tiles(layer_id, tiles, tiles);
+        }
     }
 
-    /// draw the map in worldspace
-    pub fn draw(&self) {
-        let width = self.tile_map.layers[TERRAIN_MAP_ID].width as f32;
-        let height = self.tile_map.layers[TERRAIN_MAP_ID].height as f32;
-        self.tile_map.draw_tiles(
-            TERRAIN_MAP_ID,
-            // TODO(axelmagn): get from function
-            Rect::new(0., 0., width, height),
-            None,
+    /// Add the wall colliders for the terrain, or get its chunks ready on large maps, in place of
+    /// any the map had before
+    pub fn init_colliders(&mut self, theme: &TileTheme, physics: &mut Physics) {
+        self.remove_colliders(physics);
+        let layer = &self.tile_map.layers[TERRAIN_MAP_ID];
+        let size = UVec2::new(layer.width, layer.height);
+        let wall_id = physics.entities.spawn(EntityKind::Wall);
+        self.wall_id = Some(wall_id);
+        if size.max_element() >= CHUNKED_MAP_SIZE {
+            // large maps get their walls a chunk at a time, as characters come near
+            self.chunks = Some(Chunks::new(CHUNK_SIZE, size, wall_id));
+            return;
+        }
+        self.insert_wall_colliders(
+            UVec2::ZERO,
+            size,
+            wall_id,
+            theme,
+            &mut physics.colliders,
+            &mut physics.entities,
         );
     }
 
-    pub fn init_colliders(&mut self, collider_set: &mut ColliderSet) {
-        for (x, y, tile) in self.tile_map.tiles(TERRAIN_MAP_ID, None) {
-            if let Some(tile) = tile {
-                if self.is_tile_solid(tile.id) {
-                    let coord = UVec2::new(x, y);
-                    let collider = ColliderBuilder::cuboid(0.5, 0.5)
-                        .translation(vector![x as f32 + 0.5, y as f32 + 0.5])
-                        .build();
-                    self.colliders.insert(coord, collider_set.insert(collider));
+    /// Take every wall collider out of `physics`, along with the entity they belong to. Call it
+    /// before swapping in a new physics world, or the old handles end up pointing into the new one.
+    pub fn remove_colliders(&mut self, physics: &mut Physics) {
+        // merged tiles share a handle, and loaded chunks keep theirs in here too
+        let handles: HashSet<ColliderHandle> =
+            self.colliders.drain().map(|(_, handle)| handle).collect();
+        for handle in handles {
+            physics.remove_collider(handle);
+        }
+        self.chunks = None;
+        if let Some(wall_id) = self.wall_id.take() {
+            physics.entities.despawn(wall_id);
+        }
+    }
+
+    /// Load wall colliders for the chunks around `areas`, given as (center, margin) in tiles, and
+    /// unload the ones that nothing is near any more. Maps too small to be chunked keep all theirs.
+    pub fn update_chunks(
+        &mut self,
+        areas: &[(Vec2, f32)],
+        theme: &TileTheme,
+        physics: &mut Physics,
+    ) {
+        let Some(mut chunks) = self.chunks.take() else {
+            return;
+        };
+        let (load, unload) = chunks.plan(areas);
+        for chunk in unload {
+            for handle in chunks.loaded.remove(&chunk).unwrap_or_default() {
+                physics.remove_collider(handle);
+            }
+            let (origin, size) = chunks.bounds(chunk);
+            self.colliders
+                .retain(|tile, _| !(tile.cmpge(origin).all() && tile.cmplt(origin + size).all()));
+        }
+        for chunk in load {
+            let (origin, size) = chunks.bounds(chunk);
+            let handles = self.insert_wall_colliders(
+                origin,
+                size,
+                chunks.wall_id,
+                theme,
+                &mut physics.colliders,
+                &mut physics.entities,
+            );
+            chunks.loaded.insert(chunk, handles);
+        }
+        self.chunks = Some(chunks);
+    }
+
+    /// Add merged wall colliders for the solid tiles in a rect of the terrain. Returns their handles.
+    fn insert_wall_colliders(
+        &mut self,
+        origin: UVec2,
+        size: UVec2,
+        wall_id: EntityId,
+        theme: &TileTheme,
+        collider_set: &mut ColliderSet,
+        entities: &mut EntityRegistry,
+    ) -> Vec<ColliderHandle> {
+        // tile by tile, since `TileMap::tiles` stops one short of the last tile in the rect
+        let mut solid = vec![false; (size.x * size.y) as usize];
+        for y in 0..size.y {
+            for x in 0..size.x {
+                let tile = self
+                    .tile_map
+                    .get_tile(TERRAIN_MAP_ID, origin.x + x, origin.y + y);
+                if let Some(tile) = tile {
+                    solid[(y * size.x + x) as usize] = theme.is_solid(tile.id);
+                }
+            }
+        }
+
+        // one cuboid per merged rect instead of one per tile keeps the broad phase small and
+        // avoids characters catching on the seams between neighboring tiles
+        let mut handles = Vec::new();
+        for (rect_origin, rect_size) in merge_solid_tiles(&solid, size.x, size.y) {
+            let rect_origin = origin + rect_origin;
+            let half_extents = rect_size.as_vec2() / 2.;
+            let collider = ColliderBuilder::cuboid(half_extents.x, half_extents.y)
+                .translation(vector![
+                    rect_origin.x as f32 + half_extents.x,
+                    rect_origin.y as f32 + half_extents.y
+                ])
+                .collision_groups(CollisionLayer::Wall.groups())
+                .build();
+            let handle = collider_set.insert(collider);
+            entities.register_collider(handle, wall_id);
+            for x in rect_origin.x..(rect_origin.x + rect_size.x) {
+                for y in rect_origin.y..(rect_origin.y + rect_size.y) {
+                    self.colliders.insert(UVec2::new(x, y), handle);
+                }
+            }
+            handles.push(handle);
+        }
+        handles
+    }
+}
+
+/// Greedily merge a row-major grid of solid tiles into rectangles, returned as (origin, size).
+///
+/// Each rect grows right as far as it can, then down for as long as the whole row below is
+/// solid and unclaimed. This isn't optimal, but it's fast and good enough for dungeon walls.
+pub fn merge_solid_tiles(solid: &[bool], width: u32, height: u32) -> Vec<(UVec2, UVec2)> {
+    let idx = |x: u32, y: u32| (y * width + x) as usize;
+    let mut claimed = vec![false; solid.len()];
+    let mut rects = Vec::new();
+
+    for y in 0..height {
+        for x in 0..width {
+            if !solid[idx(x, y)] || claimed[idx(x, y)] {
+                continue;
+            }
+
+            // grow right
+            let mut w = 1;
+            while x + w < width && solid[idx(x + w, y)] && !claimed[idx(x + w, y)] {
+                w += 1;
+            }
+
+            // grow down
+            let mut h = 1;
+            while y + h < height
+                && (x..(x + w)).all(|x| solid[idx(x, y + h)] && !claimed[idx(x, y + h)])
+            {
+                h += 1;
+            }
+
+            for cy in y..(y + h) {
+                for cx in x..(x + w) {
+                    claimed[idx(cx, cy)] = true;
                 }
             }
+            rects.push((UVec2::new(x, y), UVec2::new(w, h)));
+        }
+    }
+
+    rects
+}
+
+/// The whole tiles that `view` touches, clipped to a `width` by `height` map. `None` if the view
+/// is off the map entirely.
+pub fn clip_to_tiles(view: Rect, width: u32, height: u32) -> Option<Rect> {
+    let min = view.point().floor().max(Vec2::ZERO);
+    let max = (view.point() + view.size())
+        .ceil()
+        .min(UVec2::new(width, height).as_vec2());
+    let size = max - min;
+    (size.x > 0. && size.y > 0.).then(|| Rect::new(min.x, min.y, size.x, size.y))
+}
+
+/// A tileset sharing the same texture, for drawing sprites away from the map
+pub fn copy_tileset(tileset: &TileSet) -> TileSet {
+    TileSet {
+        texture: tileset.texture.clone(),
+        tilewidth: tileset.tilewidth,
+        tileheight: tileset.tileheight,
+        columns: tileset.columns,
+        spacing: tileset.spacing,
+        margin: tileset.margin,
+    }
+}
+
+/// Where a sprite sits in its tileset's texture, in pixels
+pub fn sprite_source(tileset: &TileSet, sprite: u32) -> Rect {
+    let (width, height) = (tileset.tilewidth as f32, tileset.tileheight as f32);
+    Rect::new(
+        (sprite % tileset.columns) as f32 * (width + tileset.spacing as f32)
+            + tileset.margin as f32,
+        (sprite / tileset.columns) as f32 * (height + tileset.spacing as f32)
+            + tileset.margin as f32,
+        width,
+        height,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use macroquad_tiled::{Layer, Tile};
+
+    fn grid(rows: &[&str]) -> (Vec<bool>, u32, u32) {
+        let solid = rows
+            .iter()
+            .flat_map(|row| row.chars().map(|c| c == '#'))
+            .collect();
+        (solid, rows[0].len() as u32, rows.len() as u32)
+    }
+
+    /// A map with only a terrain layer, `#` for wall and anything else for ground
+    fn map_from(rows: &[&str], theme: &TileTheme) -> Map {
+        let mut terrain = Layer {
+            width: rows[0].len() as u32,
+            height: rows.len() as u32,
+            ..Default::default()
+        };
+        for c in rows.iter().flat_map(|row| row.chars()) {
+            let id = if c == '#' {
+                theme.walls.base
+            } else {
+                theme.ground.base
+            };
+            terrain.data.push(Some(Tile {
+                id,
+                tileset: theme.tileset.clone(),
+                attrs: String::new(),
+            }));
+        }
+        Map {
+            tile_map: TileMap {
+                layers: HashMap::from([(TERRAIN_MAP_ID.to_string(), terrain)]),
+                tilesets: HashMap::new(),
+                raw_tiled_map: Default::default(),
+            },
+            colliders: HashMap::new(),
+            animator: TileAnimator::default(),
+            chunks: None,
+            wall_id: None,
         }
     }
 
-    /// Calculate which tiles are solid
-    fn create_solid_tile_mask(tileset: &TileSet, solid_tile_ranges: &[Range<u32>]) -> Vec<bool> {
-        // ugly calculation because the library authors couldn't bother to  store the tilecount field
-        let tile_count: i32 = (tileset.texture.height() as i32 + tileset.spacing
-            - 2 * tileset.margin)
-            / (tileset.tileheight + tileset.spacing)
-            * tileset.columns as i32;
-        let mut out: Vec<bool> = iter::repeat(false).take(tile_count as usize).collect();
-        for range in solid_tile_ranges {
-            for i in range.clone() {
-                out[i as usize] = true;
+    #[test]
+    fn test_merge_solid_tiles_covers_every_solid_tile_once() {
+        let (solid, width, height) = grid(&[
+            "######", //
+            "#....#", //
+            "#.##.#", //
+            "#....#", //
+            "######", //
+        ]);
+        let rects = merge_solid_tiles(&solid, width, height);
+
+        let mut covered = vec![0; solid.len()];
+        for (origin, size) in &rects {
+            for x in origin.x..(origin.x + size.x) {
+                for y in origin.y..(origin.y + size.y) {
+                    covered[(y * width + x) as usize] += 1;
+                }
             }
         }
-        out
+        for (i, is_solid) in solid.iter().enumerate() {
+            assert_eq!(covered[i], *is_solid as i32);
+        }
+
+        // top row, two side columns, bottom row, and the inner block
+        assert_eq!(rects.len(), 5);
+    }
+
+    #[test]
+    fn test_clip_to_tiles_rounds_out_and_stays_on_the_map() {
+        let view = Rect::new(-1.5, 2.25, 4., 3.5);
+        assert_eq!(clip_to_tiles(view, 10, 5), Some(Rect::new(0., 2., 3., 3.)));
+        assert_eq!(clip_to_tiles(Rect::new(12., 0., 4., 4.), 10, 5), None);
+    }
+
+    #[test]
+    fn test_merge_solid_tiles_solid_block_is_one_rect() {
+        let (solid, width, height) = grid(&["###", "###", "###"]);
+        let rects = merge_solid_tiles(&solid, width, height);
+        assert_eq!(rects, vec![(UVec2::new(0, 0), UVec2::new(3, 3))]);
     }
 
-    fn is_tile_solid(&self, tile_id: u32) -> bool {
-        self.solid_tile_mask[tile_id as usize]
+    #[test]
+    fn test_init_colliders_replaces_the_walls_from_before() {
+        let theme = TileTheme::default();
+        let mut map = map_from(&["#####", "#...#", "#.#.#", "#####"], &theme);
+        let mut physics = Physics::default();
+        map.init_colliders(&theme, &mut physics);
+        let count = physics.colliders.len();
+        let first_wall_id = map.wall_id.unwrap();
+        assert!(count > 0);
+
+        // the round sets the floor up again once it opens, which mustn't stack up more walls
+        map.init_colliders(&theme, &mut physics);
+        assert_eq!(physics.colliders.len(), count);
+        assert_eq!(map.colliders.len(), 15);
+        assert_eq!(physics.entities.kind(first_wall_id), None);
+    }
+
+    #[test]
+    fn test_init_colliders_drops_the_chunks_loaded_before() {
+        let theme = TileTheme::default();
+        let row = "#".repeat(CHUNKED_MAP_SIZE as usize);
+        let mut map = map_from(&[&row, &row], &theme);
+        let mut physics = Physics::default();
+        map.init_colliders(&theme, &mut physics);
+        map.update_chunks(&[(vec2(1., 1.), 1.)], &theme, &mut physics);
+        let count = physics.colliders.len();
+        assert!(count > 0);
+
+        map.init_colliders(&theme, &mut physics);
+        assert_eq!(physics.colliders.len(), 0);
+        map.update_chunks(&[(vec2(1., 1.), 1.)], &theme, &mut physics);
+        assert_eq!(physics.colliders.len(), count);
     }
 }
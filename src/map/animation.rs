@@ -0,0 +1,102 @@
+use std::collections::HashMap;
+
+use nanoserde::DeJson;
+
+/// A tile that is drawn as a looping sequence of frames. The layer keeps the tile's own ID, and
+/// only drawing swaps the frames in.
+#[derive(Clone, Debug, DeJson)]
+pub struct TileAnimation {
+    /// ID of the tile in the layer
+    pub tile: u32,
+    pub frames: Vec<AnimationFrame>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, DeJson)]
+pub struct AnimationFrame {
+    /// tile to draw in its place
+    pub id: u32,
+    /// mirror the tile, for tilesets with no spare frames of their own
+    #[nserde(default)]
+    pub flip_x: bool,
+    /// seconds the frame is shown for
+    pub duration: f32,
+}
+
+impl TileAnimation {
+    fn cycle(&self) -> f32 {
+        self.frames.iter().map(|frame| frame.duration).sum()
+    }
+
+    /// The frame showing `time` seconds into the loop
+    pub fn frame_at(&self, time: f32) -> Option<&AnimationFrame> {
+        let cycle = self.cycle();
+        if cycle <= 0. {
+            return self.frames.first();
+        }
+        let mut time = time.rem_euclid(cycle);
+        for frame in &self.frames {
+            if time < frame.duration {
+                return Some(frame);
+            }
+            time -= frame.duration;
+        }
+        self.frames.last()
+    }
+}
+
+/// Plays a map's tile animations, on a clock that starts over whenever the map does
+#[derive(Clone, Debug, Default)]
+pub struct TileAnimator {
+    animations: HashMap<u32, TileAnimation>,
+    elapsed: f32,
+}
+
+impl TileAnimator {
+    pub fn new(animations: &[TileAnimation]) -> Self {
+        Self {
+            animations: animations
+                .iter()
+                .map(|animation| (animation.tile, animation.clone()))
+                .collect(),
+            elapsed: 0.,
+        }
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        self.elapsed += dt;
+    }
+
+    /// The frame to draw for a tile at a spot in the map, if the tile is animated. Every spot runs
+    /// a little ahead or behind, so a wall of torches doesn't flicker in step.
+    pub fn frame(&self, tile_id: u32, x: u32, y: u32) -> Option<&AnimationFrame> {
+        let animation = self.animations.get(&tile_id)?;
+        let offset = (x.wrapping_mul(7) ^ y.wrapping_mul(13)) % 16;
+        animation.frame_at(self.elapsed + offset as f32 / 16. * animation.cycle())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_at_loops_through_frames_by_duration() {
+        let frame = |id, duration| AnimationFrame {
+            id,
+            flip_x: false,
+            duration,
+        };
+        let animation = TileAnimation {
+            tile: 1,
+            frames: vec![frame(1, 0.5), frame(2, 0.25)],
+        };
+        assert_eq!(animation.frame_at(0.).unwrap().id, 1);
+        assert_eq!(animation.frame_at(0.6).unwrap().id, 2);
+        assert_eq!(animation.frame_at(0.8).unwrap().id, 1);
+        assert_eq!(animation.frame_at(-0.1).unwrap().id, 2);
+
+        let animator = TileAnimator::new(&[animation]);
+        assert!(animator.frame(1, 3, 4).is_some());
+        assert!(animator.frame(2, 3, 4).is_none());
+    }
+}
@@ -0,0 +1,99 @@
+use std::collections::{HashMap, HashSet};
+
+use macroquad::math::{IVec2, UVec2, Vec2};
+use rapier2d::geometry::ColliderHandle;
+
+use crate::entity::EntityId;
+
+/// Splits a large map into square chunks of tiles, so that only the chunks around the characters
+/// have wall colliders. Chunks are loaded and unloaded by [`super::Map::update_chunks`].
+#[derive(Debug)]
+pub struct Chunks {
+    /// width and height of a chunk, in tiles
+    pub size: u32,
+    /// size of the whole map, in tiles
+    pub map_size: UVec2,
+    /// entity that owns every wall collider, whichever chunk it is in
+    pub wall_id: EntityId,
+    /// colliders of each loaded chunk, keyed by chunk coordinate
+    pub loaded: HashMap<UVec2, Vec<ColliderHandle>>,
+}
+
+impl Chunks {
+    pub fn new(size: u32, map_size: UVec2, wall_id: EntityId) -> Self {
+        Self {
+            size,
+            map_size,
+            wall_id,
+            loaded: HashMap::new(),
+        }
+    }
+
+    /// Chunks that overlap the square reaching `margin` tiles out from `center`
+    pub fn near(&self, center: Vec2, margin: f32) -> impl Iterator<Item = UVec2> {
+        let last = ((self.map_size.max(UVec2::ONE) - UVec2::ONE) / self.size).as_ivec2();
+        let to_chunk = |point: Vec2| {
+            (point / self.size as f32)
+                .floor()
+                .as_ivec2()
+                .clamp(IVec2::ZERO, last)
+        };
+        let (min, max) = (to_chunk(center - margin), to_chunk(center + margin));
+        (min.y..=max.y)
+            .flat_map(move |y| (min.x..=max.x).map(move |x| UVec2::new(x as u32, y as u32)))
+    }
+
+    /// Tiles covered by a chunk, as (origin, size). Chunks on the far edges can be cut short.
+    pub fn bounds(&self, chunk: UVec2) -> (UVec2, UVec2) {
+        let origin = chunk * self.size;
+        let size = (self.map_size - origin).min(UVec2::splat(self.size));
+        (origin, size)
+    }
+
+    /// Split the chunks that should be loaded for `areas`, given as (center, margin), into the ones
+    /// to load and the ones to unload
+    pub fn plan(&self, areas: &[(Vec2, f32)]) -> (Vec<UVec2>, Vec<UVec2>) {
+        let wanted: HashSet<UVec2> = areas
+            .iter()
+            .flat_map(|&(center, margin)| self.near(center, margin))
+            .collect();
+        let load = wanted
+            .iter()
+            .filter(|chunk| !self.loaded.contains_key(chunk))
+            .copied()
+            .collect();
+        let unload = self
+            .loaded
+            .keys()
+            .filter(|chunk| !wanted.contains(chunk))
+            .copied()
+            .collect();
+        (load, unload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::{EntityKind, EntityRegistry};
+    use macroquad::math::{uvec2, vec2};
+
+    #[test]
+    fn test_chunks_near_an_area_stay_on_the_map() {
+        let wall_id = EntityRegistry::default().spawn(EntityKind::Wall);
+        let mut chunks = Chunks::new(32, uvec2(250, 200), wall_id);
+
+        let near: Vec<UVec2> = chunks.near(vec2(40., 10.), 8.).collect();
+        assert_eq!(near, vec![uvec2(1, 0)]);
+        let corner: HashSet<UVec2> = chunks.near(vec2(230., 190.), 10.).collect();
+        assert_eq!(corner.len(), 4);
+        assert!(corner.contains(&uvec2(7, 6)));
+        assert_eq!(chunks.bounds(uvec2(7, 6)), (uvec2(224, 192), uvec2(26, 8)));
+
+        chunks.loaded.insert(uvec2(0, 0), Vec::new());
+        chunks.loaded.insert(uvec2(5, 5), Vec::new());
+        let (load, unload) = chunks.plan(&[(vec2(16., 16.), 20.)]);
+        assert_eq!(load.len(), 3);
+        assert_eq!(unload, vec![uvec2(5, 5)]);
+    }
+}
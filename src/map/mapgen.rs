@@ -1,58 +1,181 @@
+use std::{iter, ops::Range};
+
+use anyhow::{anyhow, Result};
 use macroquad::{
-    math::{uvec2, Rect, UVec2},
-    rand::gen_range,
+    math::{uvec2, vec2, Rect, UVec2, Vec2},
+    rand::{gen_range, ChooseRandom},
 };
 use macroquad_tiled::Layer;
 use macroquad_tiled::Tile;
 
-use crate::constants::{
-    CORRIDOR_PADDING, DOOR_CLEARANCE, DOOR_LEFT_CLOSED_TILE_ID, DOOR_LEFT_OPEN_TILE_ID,
-    DOOR_RIGHT_CLOSED_TILE_ID, DOOR_RIGHT_OPEN_TILE_ID, FACADE_CENTER_02_TILE_ID,
-    FACADE_CENTER_TILE_ID, FACADE_LEFT_TILE_ID, FACADE_RIGHT_TILE_ID, GROUND_01_TILE_ID,
-    GROUND_02_TILE_ID, GROUND_03_TILE_ID, MAX_ROOM_COUNT, MAX_ROOM_SIZE, MIN_ROOM_SIZE,
-    MONSTER_PIPE_CLOSED_TILE_ID, POOL_EMPTY_TILE_ID, STAIRS_LEFT_TILE_ID, STAIRS_RIGHT_TILE_ID,
-    TILESET_MAP_ID, TILE_FILLER_PROB, WALL_01_TILE_ID, WALL_02_TILE_ID, WALL_03_TILE_ID,
-    WALL_DOWN_TILE_ID, WALL_INNER_DL_ID, WALL_INNER_DR_ID, WALL_INNER_UL_ID, WALL_INNER_UR_ID,
-    WALL_LEFT_TILE_ID, WALL_OUTER_DL_ID, WALL_OUTER_DR_ID, WALL_OUTER_UL_ID, WALL_OUTER_UR_ID,
-    WALL_RIGHT_TILE_ID, WALL_TILE_IDS, WALL_UP_TILE_ID,
+use crate::{
+    constants::{
+        BARREL_COUNT, BSP_MIN_LEAF_SIZE, BSP_ROOM_MARGIN, CAVE_FILL_PROB, CAVE_ROOM_SIZE,
+        CAVE_ROOM_SPACING, CAVE_SMOOTHING_STEPS, CHEST_COINS_MAX, CHEST_COINS_MIN, CHEST_COUNT,
+        CORRIDOR_LOOP_COUNT, CORRIDOR_PADDING, CRATE_COUNT, CYCLING_DOOR_RATIO, DOOR_CLEARANCE,
+        GRID_ROOM_SPACING, ICE_ROOM_PROB, ITEM_SPAWN_PROB, LEVER_COUNT, LOCKED_DOOR_COUNT,
+        MAPGEN_MAX_ATTEMPTS, MIN_ROOM_COUNT, ONE_WAY_PASSAGE_COUNT, PREFAB_ROOM_PROB,
+        PRESSURE_PLATE_COUNT, SPIKE_TRAP_COUNT, TILE_FILLER_PROB, TORCH_COUNT, TORCH_SPACING,
+        TRIM_DEAD_ENDS,
+    },
+    items::ItemKind,
+    map::{
+        prefab::Prefab,
+        rooms::{guard_spawns, tag_rooms, RoomTag},
+        rules::{apply_rule_passes, WALL_RULES},
+        theme::TileTheme,
+    },
+    passage::PassageDirection,
+    tuning::tuning,
 };
 
+/// How rooms get laid out before the shared detail passes run
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MapGenAlgorithm {
+    /// rooms dropped at random, each chained to the last by a corridor
+    #[default]
+    Scatter,
+    /// binary space partitioning. denser, with more corridors
+    Bsp,
+    /// cellular automata caverns. open and organic, with no corridors to lock
+    Cave,
+    /// rooms in rows, snaking from one to the next. never picked at random, but always lays out,
+    /// so it's the fallback when the others keep failing.
+    Grid,
+}
+
+impl MapGenAlgorithm {
+    pub fn random() -> Self {
+        match gen_range(0, 3) {
+            0 => MapGenAlgorithm::Scatter,
+            1 => MapGenAlgorithm::Bsp,
+            _ => MapGenAlgorithm::Cave,
+        }
+    }
+}
+
 pub struct MapGenerator {
-    pub ground_tile_id: u32,
-    pub wall_tile_id: u32,
-    pub tileset_id: String,
+    pub algorithm: MapGenAlgorithm,
+    /// tiles to paint the map with
+    pub theme: TileTheme,
 
     pub size: UVec2,
     pub min_room_size: UVec2,
     pub max_room_size: UVec2,
     pub max_room_count: u32,
+    /// layouts with fewer rooms than this are thrown out and generated again
+    pub min_room_count: u32,
     pub corridor_padding: Option<u32>,
     pub door_clearance: u32,
+    /// how many extra corridors to run between rooms that aren't already joined. 0 leaves the
+    /// rooms chained together like a tree.
+    pub corridor_loop_count: u32,
+    /// fill in floor that leads nowhere once the rooms are joined up
+    pub trim_dead_ends: bool,
+    /// how many locked gates to try to place across corridors. 0 disables them.
+    pub locked_door_count: u32,
+    /// how many one-way passages to try to place across corridors. 0 disables them.
+    pub one_way_passage_count: u32,
+    /// hand-made rooms to stamp into some of the generated ones
+    pub prefabs: Vec<Prefab>,
+    pub prefab_room_prob: f32,
+    /// share of guard rooms paved with ice
+    pub ice_room_prob: f32,
+    /// turn one of the guard doors into a reinforced door, the only cell that can hold a boss
+    pub reinforced_door: bool,
+    /// share of guard doors that open and shut on a timer
+    pub cycling_door_ratio: f32,
+    pub spike_trap_count: u32,
+    pub pressure_plate_count: u32,
+    pub lever_count: u32,
+    pub torch_count: u32,
+    pub crate_count: u32,
+    pub chest_count: u32,
+    pub barrel_count: u32,
 }
 
 pub struct MapGenResult {
     pub layer: Layer,
+    /// decorations drawn over the terrain
+    pub props: Layer,
+    /// wall tops drawn over the characters
+    pub overhead: Layer,
     pub rooms: Vec<Rect>,
+    /// where each guard starts out, with the boss-eligible guards by the exit last
+    pub guard_spawns: Vec<Vec2>,
     pub guard_doors: Vec<UVec2>,
     pub exit_door: UVec2,
+    pub reinforced_door: Option<UVec2>,
+    /// guard doors that open and shut on a timer
+    pub cycling_doors: Vec<UVec2>,
+    pub spike_traps: Vec<UVec2>,
+    /// each pressure plate, and the guard door it works
+    pub pressure_plates: Vec<(UVec2, UVec2)>,
+    /// each lever, and the guard door it works from across the map
+    pub levers: Vec<(UVec2, UVec2)>,
+    pub items: Vec<(UVec2, ItemKind)>,
+    /// leftmost tile and width of each locked gate
+    pub locked_doors: Vec<(UVec2, u32)>,
+    /// leftmost tile, width and direction of each one-way passage
+    pub one_way_passages: Vec<(UVec2, u32, PassageDirection)>,
+    /// pushable crates, on bare floor in the rooms
+    pub crates: Vec<UVec2>,
+    /// each treasure chest, and the coins in it
+    pub chests: Vec<(UVec2, u32)>,
+    /// barrels to hide in, against the back walls of the rooms
+    pub barrels: Vec<UVec2>,
 }
 
 impl MapGenerator {
     pub fn new(size: UVec2) -> Self {
         MapGenerator {
-            ground_tile_id: GROUND_01_TILE_ID,
-            wall_tile_id: WALL_01_TILE_ID,
-            tileset_id: TILESET_MAP_ID.into(),
+            algorithm: MapGenAlgorithm::default(),
+            theme: TileTheme::default(),
             size,
-            min_room_size: MIN_ROOM_SIZE,
-            max_room_size: MAX_ROOM_SIZE,
-            max_room_count: MAX_ROOM_COUNT,
+            min_room_size: tuning().min_room_size(),
+            max_room_size: tuning().max_room_size(),
+            max_room_count: tuning().max_room_count,
+            min_room_count: MIN_ROOM_COUNT,
             corridor_padding: CORRIDOR_PADDING,
             door_clearance: DOOR_CLEARANCE,
+            corridor_loop_count: CORRIDOR_LOOP_COUNT,
+            trim_dead_ends: TRIM_DEAD_ENDS,
+            locked_door_count: LOCKED_DOOR_COUNT,
+            one_way_passage_count: ONE_WAY_PASSAGE_COUNT,
+            prefabs: Vec::new(),
+            prefab_room_prob: PREFAB_ROOM_PROB,
+            ice_room_prob: ICE_ROOM_PROB,
+            reinforced_door: false,
+            cycling_door_ratio: CYCLING_DOOR_RATIO,
+            spike_trap_count: SPIKE_TRAP_COUNT,
+            pressure_plate_count: PRESSURE_PLATE_COUNT,
+            lever_count: LEVER_COUNT,
+            torch_count: TORCH_COUNT,
+            crate_count: CRATE_COUNT,
+            chest_count: CHEST_COUNT,
+            barrel_count: BARREL_COUNT,
         }
     }
 
-    pub fn generate_layer(&self) -> MapGenResult {
+    /// Generate a map, retrying until every room and door can be reached from the player's
+    /// starting room. If the chosen layout keeps failing, a grid layout is tried last.
+    pub fn generate_layer(&self) -> Result<MapGenResult> {
+        for _ in 0..MAPGEN_MAX_ATTEMPTS {
+            match self.try_generate_layer(self.algorithm) {
+                Ok(result) if self.is_connected(&result) => return Ok(result),
+                Ok(_) => warn!("generated map is not fully connected. regenerating."),
+                Err(err) => warn!("could not generate map: {}. regenerating.", err),
+            }
+        }
+        warn!("falling back to a grid layout");
+        let result = self.try_generate_layer(MapGenAlgorithm::Grid)?;
+        if !self.is_connected(&result) {
+            return Err(anyhow!("fallback map is not fully connected"));
+        }
+        Ok(result)
+    }
+
+    fn try_generate_layer(&self, algorithm: MapGenAlgorithm) -> Result<MapGenResult> {
         let mut layer = Layer {
             width: self.size.x,
             height: self.size.y,
@@ -62,15 +185,171 @@ impl MapGenerator {
         // fill layer with wall
         for _ in 0..(self.size.x * self.size.y) {
             let wall_tile = Tile {
-                id: self.wall_tile_id,
-                tileset: self.tileset_id.clone(),
+                id: self.theme.walls.base,
+                tileset: self.theme.tileset.clone(),
                 attrs: String::new(),
             };
             layer.data.push(Some(wall_tile));
         }
 
-        // generate rooms
+        // lay out rooms and corridors
+        let (rooms, mut vertical_corridors) = match algorithm {
+            MapGenAlgorithm::Scatter => self.layout_scattered_rooms(&mut layer),
+            MapGenAlgorithm::Bsp => self.layout_bsp_rooms(&mut layer),
+            MapGenAlgorithm::Cave => self.layout_cave(&mut layer),
+            MapGenAlgorithm::Grid => self.layout_grid_rooms(&mut layer),
+        };
+        if (rooms.len() as u32) < self.min_room_count {
+            return Err(anyhow!(
+                "only laid out {} of at least {} rooms",
+                rooms.len(),
+                self.min_room_count
+            ));
+        }
+
+        // caves are already full of loops, and have no corridors to add to
+        if algorithm != MapGenAlgorithm::Cave {
+            self.generate_corridor_loops(&rooms, &mut layer, &mut vertical_corridors);
+        }
+        self.connect_rooms(&rooms, &mut layer);
+        if self.trim_dead_ends {
+            self.trim_dead_ends(&rooms, &mut layer);
+        }
+        let tags = tag_rooms(&rooms, &layer, &self.theme, self.chest_count);
+        let guard_spawns = guard_spawns(&rooms, &tags);
+        self.rewrite_wall_details(&mut layer);
+
+        // a cell for every guard, and the exit
+        let num_doors = guard_spawns.len() + 1;
+
+        // generate guard doors
+        let mut guard_doors = Vec::new();
+        for _ in 0..10 {
+            let placed = self.generate_guard_doors(num_doors - guard_doors.len(), &mut layer);
+            guard_doors.extend(placed);
+            if num_doors == guard_doors.len() {
+                break;
+            }
+        }
+        // denser layouts can run out of wall to put doors on. that's fine as long as there's an exit.
+        if guard_doors.is_empty() {
+            return Err(anyhow!("no wall left to put the exit door in"));
+        }
+
+        // the exit goes in the wall closest to the room furthest from the start
+        let exit_room = rooms
+            .iter()
+            .zip(&tags)
+            .find(|(_, tag)| **tag == RoomTag::ExitAdjacent)
+            .map(|(room, _)| room.center())
+            .unwrap_or_default();
+        let exit_index = (0..guard_doors.len())
+            .min_by(|&a, &b| {
+                let a = guard_doors[a].as_vec2().distance(exit_room);
+                let b = guard_doors[b].as_vec2().distance(exit_room);
+                a.total_cmp(&b)
+            })
+            .unwrap();
+        let exit_door = guard_doors.remove(exit_index);
+        self.rewrite_exit_door(exit_door, &mut layer);
+
+        let reinforced_door = if self.reinforced_door && !guard_doors.is_empty() {
+            let door = guard_doors.remove(gen_range(0, guard_doors.len()));
+            self.rewrite_reinforced_door(door, &mut layer);
+            Some(door)
+        } else {
+            None
+        };
+
+        let cycling_doors: Vec<UVec2> = guard_doors
+            .iter()
+            .copied()
+            .filter(|_| gen_range(0., 1.) < self.cycling_door_ratio)
+            .collect();
+
+        let mut props = Layer {
+            width: self.size.x,
+            height: self.size.y,
+            data: (0..self.size.x * self.size.y).map(|_| None).collect(),
+            ..Default::default()
+        };
+        self.stamp_prefabs(&rooms, &tags, &mut layer, &mut props);
+
+        let mut items = self.generate_items(&rooms, &tags, &layer);
+        let locked_doors =
+            self.generate_locked_doors(&vertical_corridors, &rooms, &mut items, &mut layer);
+        let one_way_passages = self.generate_one_way_passages(&vertical_corridors, &rooms, &layer);
+        let spike_traps = self.generate_spike_traps(&rooms, &tags, &items, &mut layer);
+        let plate_doors: Vec<UVec2> = guard_doors
+            .iter()
+            .copied()
+            .filter(|door| !cycling_doors.contains(door))
+            .collect();
+        let pressure_plates = self.generate_pressure_plates(&plate_doors, &items, &mut layer);
+        let lever_doors: Vec<UVec2> = plate_doors
+            .iter()
+            .copied()
+            .filter(|door| {
+                !pressure_plates
+                    .iter()
+                    .any(|(_, plate_door)| plate_door == door)
+            })
+            .collect();
+        let levers = self.generate_levers(&lever_doors, &rooms, &items, &layer);
+        let chests = self.generate_chests(&rooms, &tags, &items, &levers, &layer);
+        let crates = self.generate_crates(&rooms, &items, &levers, &chests, &layer);
+        let barrels = self.generate_barrels(&rooms, &items, &levers, &chests, &crates, &layer);
+        self.generate_torches(&mut layer);
+        self.pave_ice_rooms(&rooms, &mut layer);
+
+        // add fillers
+        for &filler in &self.theme.walls.fillers {
+            self.rewrite_random_filler(self.theme.walls.base, filler, TILE_FILLER_PROB, &mut layer);
+        }
+        for &filler in &self.theme.ground.fillers {
+            self.rewrite_random_filler(
+                self.theme.ground.base,
+                filler,
+                TILE_FILLER_PROB,
+                &mut layer,
+            );
+        }
+        self.rewrite_random_filler(
+            self.theme.facades.center,
+            self.theme.facades.center_filler,
+            TILE_FILLER_PROB * 10.,
+            &mut layer,
+        );
+
+        let overhead = overhead_layer(&layer, &self.theme);
+
+        Ok(MapGenResult {
+            layer,
+            props,
+            overhead,
+            rooms,
+            guard_spawns,
+            guard_doors,
+            exit_door,
+            reinforced_door,
+            cycling_doors,
+            spike_traps,
+            pressure_plates,
+            levers,
+            items,
+            locked_doors,
+            one_way_passages,
+            crates,
+            chests,
+            barrels,
+        })
+    }
+
+    /// Drop rooms at random spots, skipping any that overlap, and chain each to the last with a
+    /// corridor. Returns the rooms and the (x, src_y, dest_y) of every vertical corridor.
+    fn layout_scattered_rooms(&self, layer: &mut Layer) -> (Vec<Rect>, Vec<(u32, u32, u32)>) {
         let mut rooms: Vec<Rect> = Vec::new();
+        let mut vertical_corridors: Vec<(u32, u32, u32)> = Vec::new();
         for _ in 0..self.max_room_count {
             let width =
                 gen_range(self.min_room_size.x, self.max_room_size.x + 1).min(layer.width - 1);
@@ -90,111 +369,241 @@ impl MapGenerator {
                 continue;
             }
 
-            self.generate_room(&mut layer, uvec2(x, y), uvec2(width, height));
+            self.generate_room(layer, uvec2(x, y), uvec2(width, height));
 
             // draw corridor from last room
             if let Some(last_room) = rooms.last() {
-                // let horizontal_first = gen_range(0, 2) > 0;
-                let horizontal_first = true;
-
-                let last_x = last_room.center().x as u32;
-                let last_y = last_room.center().y as u32;
-                let room_x = room.center().x as u32;
-                let room_y = room.center().y as u32;
-
-                if horizontal_first {
-                    self.generate_corridor_horizontal(
-                        &mut layer,
-                        last_x,
-                        room_x,
-                        last_y,
-                        self.corridor_padding,
-                    );
-                    self.generate_corridor_vertical(
-                        &mut layer,
-                        room_x,
-                        last_y,
-                        room_y,
-                        self.corridor_padding,
-                    );
-                } else {
-                    self.generate_corridor_vertical(
-                        &mut layer,
-                        last_x,
-                        last_y,
-                        room_y,
-                        self.corridor_padding,
-                    );
-                    self.generate_corridor_horizontal(
-                        &mut layer,
-                        last_x,
-                        room_x,
-                        room_y,
-                        self.corridor_padding,
-                    );
-                }
+                self.connect_room_pair(layer, last_room, &room, &mut vertical_corridors);
             }
 
             rooms.push(room);
         }
+        (rooms, vertical_corridors)
+    }
 
-        self.rewrite_wall_details(&mut layer);
+    /// Put rooms of the smallest size in rows across the map, joined up in a snake that runs back
+    /// and forth along the rows
+    fn layout_grid_rooms(&self, layer: &mut Layer) -> (Vec<Rect>, Vec<(u32, u32, u32)>) {
+        let mut rooms: Vec<Rect> = Vec::new();
+        let mut vertical_corridors: Vec<(u32, u32, u32)> = Vec::new();
+        let size = self.min_room_size;
+        let cell = size + uvec2(GRID_ROOM_SPACING, GRID_ROOM_SPACING);
+        let cols = layer.width.saturating_sub(2) / cell.x;
+        let rows = layer.height.saturating_sub(2) / cell.y;
+        for row in 0..rows {
+            for col in 0..cols {
+                if rooms.len() as u32 >= self.max_room_count {
+                    break;
+                }
+                let col = if row % 2 == 0 { col } else { cols - 1 - col };
+                let x = 1 + col * cell.x + GRID_ROOM_SPACING / 2;
+                let y = 1 + row * cell.y + GRID_ROOM_SPACING / 2;
+                self.generate_room(layer, uvec2(x, y), size);
+                let room = Rect::new(x as f32, y as f32, size.x as f32, size.y as f32);
+                if let Some(last_room) = rooms.last() {
+                    self.connect_room_pair(layer, last_room, &room, &mut vertical_corridors);
+                }
+                rooms.push(room);
+            }
+        }
+        (rooms, vertical_corridors)
+    }
 
-        // TODO: generate guard counts & locations
-        let num_doors = rooms.len();
+    /// Recursively cut the map in two until the pieces are too small to split, put a room in each
+    /// piece, and join the closest rooms of every pair of siblings. Gives tighter, more regular
+    /// layouts than scattering.
+    fn layout_bsp_rooms(&self, layer: &mut Layer) -> (Vec<Rect>, Vec<(u32, u32, u32)>) {
+        let mut rooms: Vec<Rect> = Vec::new();
+        let mut vertical_corridors: Vec<(u32, u32, u32)> = Vec::new();
+        let origin = uvec2(1, 1);
+        let size = uvec2(layer.width - 2, layer.height - 2);
+        self.split_bsp(origin, size, layer, &mut rooms, &mut vertical_corridors);
+        (rooms, vertical_corridors)
+    }
 
-        // generate guard doors
-        let mut guard_doors = Vec::new();
-        for _ in 0..10 {
-            guard_doors = self.generate_guard_doors(num_doors, &mut layer);
-            if num_doors == guard_doors.len() {
-                break;
+    /// Lay out the BSP subtree covering an area. Returns the range of indices in `rooms` that it
+    /// produced.
+    fn split_bsp(
+        &self,
+        origin: UVec2,
+        size: UVec2,
+        layer: &mut Layer,
+        rooms: &mut Vec<Rect>,
+        vertical_corridors: &mut Vec<(u32, u32, u32)>,
+    ) -> Range<usize> {
+        let first = rooms.len();
+        let can_split_x = size.x >= BSP_MIN_LEAF_SIZE.x * 2;
+        let can_split_y = size.y >= BSP_MIN_LEAF_SIZE.y * 2;
+
+        if (!can_split_x && !can_split_y) || rooms.len() as u32 >= self.max_room_count {
+            // leaf. leave a margin so neighboring rooms keep a wall between them
+            let margin = uvec2(BSP_ROOM_MARGIN, BSP_ROOM_MARGIN);
+            let max_size = self.max_room_size.min(size - margin * 2);
+            let min_size = self.min_room_size.min(max_size);
+            let room_size = uvec2(
+                gen_range(min_size.x, max_size.x + 1),
+                gen_range(min_size.y, max_size.y + 1),
+            );
+            let slack = size - margin * 2 - room_size;
+            let room_origin =
+                origin + margin + uvec2(gen_range(0, slack.x + 1), gen_range(0, slack.y + 1));
+            self.generate_room(layer, room_origin, room_size);
+            rooms.push(Rect::new(
+                room_origin.x as f32,
+                room_origin.y as f32,
+                room_size.x as f32,
+                room_size.y as f32,
+            ));
+            return first..rooms.len();
+        }
+
+        // cut across the longer side
+        let split_x = if can_split_x && can_split_y {
+            size.x >= size.y
+        } else {
+            can_split_x
+        };
+        let (a, b) = if split_x {
+            let cut = gen_range(BSP_MIN_LEAF_SIZE.x, size.x - BSP_MIN_LEAF_SIZE.x + 1);
+            (
+                (origin, uvec2(cut, size.y)),
+                (origin + uvec2(cut, 0), uvec2(size.x - cut, size.y)),
+            )
+        } else {
+            let cut = gen_range(BSP_MIN_LEAF_SIZE.y, size.y - BSP_MIN_LEAF_SIZE.y + 1);
+            (
+                (origin, uvec2(size.x, cut)),
+                (origin + uvec2(0, cut), uvec2(size.x, size.y - cut)),
+            )
+        };
+        let left = self.split_bsp(a.0, a.1, layer, rooms, vertical_corridors);
+        let right = self.split_bsp(b.0, b.1, layer, rooms, vertical_corridors);
+
+        // join the two halves through their closest pair of rooms
+        let (i, j) = left
+            .clone()
+            .flat_map(|i| right.clone().map(move |j| (i, j)))
+            .min_by(|&(a_i, a_j), &(b_i, b_j)| {
+                let a = rooms[a_i].center().distance(rooms[a_j].center());
+                let b = rooms[b_i].center().distance(rooms[b_j].center());
+                a.total_cmp(&b)
+            })
+            .unwrap();
+        let (room_a, room_b) = (rooms[i], rooms[j]);
+        self.connect_room_pair(layer, &room_a, &room_b, vertical_corridors);
+
+        first..rooms.len()
+    }
+
+    /// Grow a cave with cellular automata, keep its largest open area, and pick open patches of it
+    /// to act as rooms for spawning guards and items. Caves have no corridors.
+    fn layout_cave(&self, layer: &mut Layer) -> (Vec<Rect>, Vec<(u32, u32, u32)>) {
+        let (width, height) = (layer.width, layer.height);
+        let on_border = |x: u32, y: u32| x == 0 || y == 0 || x == width - 1 || y == height - 1;
+
+        // random noise, then smooth it out. a tile turns to wall when most of its neighbors are
+        let mut solid: Vec<bool> = (0..width * height)
+            .map(|i| on_border(i % width, i / width) || gen_range(0., 1.) < CAVE_FILL_PROB)
+            .collect();
+        for _ in 0..CAVE_SMOOTHING_STEPS {
+            let prev = solid.clone();
+            for y in 0..height {
+                for x in 0..width {
+                    if on_border(x, y) {
+                        continue;
+                    }
+                    let walls = (y - 1..=y + 1)
+                        .flat_map(|ny| (x - 1..=x + 1).map(move |nx| (nx, ny)))
+                        .filter(|&(nx, ny)| (nx, ny) != (x, y) && prev[(ny * width + nx) as usize])
+                        .count();
+                    let i = (y * width + x) as usize;
+                    if walls > 4 {
+                        solid[i] = true;
+                    } else if walls < 4 {
+                        solid[i] = false;
+                    }
+                }
             }
         }
-        assert_eq!(num_doors, guard_doors.len());
 
-        // generate exit door
-        let exit_door = guard_doors.remove(gen_range(0, num_doors));
-        self.rewrite_exit_door(exit_door, &mut layer);
+        // fill in every pocket except the largest one, so the whole cave is connected
+        let mut largest: Vec<bool> = Vec::new();
+        let mut largest_size = 0;
+        let mut seen = solid.clone();
+        for i in 0..seen.len() {
+            if seen[i] {
+                continue;
+            }
+            let start = uvec2(i as u32 % width, i as u32 / width);
+            let region = reachable_tiles(layer, start, &solid);
+            let size = region.iter().filter(|r| **r).count();
+            for (seen, reached) in seen.iter_mut().zip(&region) {
+                *seen |= reached;
+            }
+            if size > largest_size {
+                largest_size = size;
+                largest = region;
+            }
+        }
+        for (i, open) in largest.iter().enumerate() {
+            if *open {
+                layer.data[i] = Some(Tile {
+                    id: self.theme.ground.base,
+                    tileset: self.theme.tileset.clone(),
+                    attrs: String::new(),
+                });
+            }
+        }
 
-        // add fillers
-        self.rewrite_random_filler(
-            WALL_01_TILE_ID,
-            WALL_02_TILE_ID,
-            TILE_FILLER_PROB,
-            &mut layer,
-        );
-        self.rewrite_random_filler(
-            WALL_01_TILE_ID,
-            WALL_03_TILE_ID,
-            TILE_FILLER_PROB,
-            &mut layer,
-        );
-        self.rewrite_random_filler(
-            GROUND_01_TILE_ID,
-            GROUND_02_TILE_ID,
-            TILE_FILLER_PROB,
-            &mut layer,
-        );
-        self.rewrite_random_filler(
-            GROUND_01_TILE_ID,
-            GROUND_03_TILE_ID,
-            TILE_FILLER_PROB,
-            &mut layer,
-        );
-        self.rewrite_random_filler(
-            FACADE_CENTER_TILE_ID,
-            FACADE_CENTER_02_TILE_ID,
-            TILE_FILLER_PROB * 10.,
-            &mut layer,
-        );
+        // rooms are open squares spread out across the cave
+        let room_size = CAVE_ROOM_SIZE;
+        let is_open_square = |x: u32, y: u32| {
+            (y..y + room_size).all(|y| (x..x + room_size).all(|x| largest[xytoi(x, y, layer)]))
+        };
+        let mut candidates: Vec<UVec2> = (0..height.saturating_sub(room_size))
+            .flat_map(|y| (0..width.saturating_sub(room_size)).map(move |x| uvec2(x, y)))
+            .filter(|pos| !largest.is_empty() && is_open_square(pos.x, pos.y))
+            .collect();
+        candidates.shuffle();
 
-        MapGenResult {
-            layer,
-            rooms,
-            guard_doors,
-            exit_door,
+        let mut rooms: Vec<Rect> = Vec::new();
+        let spacing = CAVE_ROOM_SPACING as f32;
+        for pos in candidates {
+            if rooms.len() as u32 >= self.max_room_count {
+                break;
+            }
+            let room = Rect::new(
+                pos.x as f32,
+                pos.y as f32,
+                room_size as f32,
+                room_size as f32,
+            );
+            let padded = Rect::new(
+                room.x - spacing,
+                room.y - spacing,
+                room.w + spacing * 2.,
+                room.h + spacing * 2.,
+            );
+            if !rooms.iter().any(|prior| padded.overlaps(prior)) {
+                rooms.push(room);
+            }
         }
+        (rooms, Vec::new())
+    }
+
+    /// Carve an L-shaped corridor between two room centers, horizontal leg first
+    fn connect_room_pair(
+        &self,
+        layer: &mut Layer,
+        from: &Rect,
+        to: &Rect,
+        vertical_corridors: &mut Vec<(u32, u32, u32)>,
+    ) {
+        let from = from.center().as_uvec2();
+        let to = to.center().as_uvec2();
+        self.generate_corridor_horizontal(layer, from.x, to.x, from.y, self.corridor_padding);
+        self.generate_corridor_vertical(layer, to.x, from.y, to.y, self.corridor_padding);
+        vertical_corridors.push((to.x, from.y, to.y));
     }
 
     pub fn generate_room(&self, layer: &mut Layer, dest: UVec2, size: UVec2) {
@@ -202,8 +611,8 @@ impl MapGenerator {
             for y in dest.y..(dest.y + size.y) {
                 let i = y * layer.width + x;
                 let tile = Tile {
-                    id: self.ground_tile_id,
-                    tileset: self.tileset_id.clone(),
+                    id: self.theme.ground.base,
+                    tileset: self.theme.tileset.clone(),
                     attrs: String::new(),
                 };
                 layer.data[i as usize] = Some(tile);
@@ -226,8 +635,8 @@ impl MapGenerator {
             for x in (src_x - padding)..=(dest_x + padding) {
                 let i = y * layer.width + x;
                 let tile = Tile {
-                    id: self.ground_tile_id,
-                    tileset: self.tileset_id.clone(),
+                    id: self.theme.ground.base,
+                    tileset: self.theme.tileset.clone(),
                     attrs: String::new(),
                 };
                 layer.data[i as usize] = Some(tile);
@@ -250,8 +659,8 @@ impl MapGenerator {
             for y in (src_y - padding)..=(dest_y + padding) {
                 let i = y * layer.width + x;
                 let tile = Tile {
-                    id: self.ground_tile_id,
-                    tileset: self.tileset_id.clone(),
+                    id: self.theme.ground.base,
+                    tileset: self.theme.tileset.clone(),
                     attrs: String::new(),
                 };
                 layer.data[i as usize] = Some(tile);
@@ -259,797 +668,849 @@ impl MapGenerator {
         }
     }
 
+    /// Give the plain walls their edges, corners and facades. Must run while the layer is still
+    /// plain ground and wall.
     pub fn rewrite_wall_details(&self, layer: &mut Layer) {
-        // rewrite wall patterns that we don't have detail tiles for
-        let mut needs_scan = true;
-        while needs_scan {
-            for x in 0..layer.width {
-                for y in 0..layer.height {
-                    needs_scan = false;
-                    needs_scan = self.try_rewrite_thin_horizontal_wall(x, y, layer) || needs_scan;
-                    needs_scan = self.try_rewrite_thin_vertical_wall(x, y, layer) || needs_scan;
-                    // TODO: one of these isn't working correctly. looks like maybe vertical one
-                    needs_scan =
-                        self.try_rewrite_double_corner_horizontal(x, y, layer) || needs_scan;
-                    needs_scan = self.try_rewrite_double_corner_vertical(x, y, layer) || needs_scan;
-                }
-            }
-        }
-
-        // rewrite walls with detail
-        for x in 0..layer.width {
-            for y in 0..layer.height {
-                self.try_rewrite_inner_ul_wall(x, y, layer);
-                self.try_rewrite_inner_ur_wall(x, y, layer);
-                self.try_rewrite_inner_dl_wall(x, y, layer);
-                self.try_rewrite_inner_dr_wall(x, y, layer);
-                self.try_rewrite_outer_ul_wall(x, y, layer);
-                self.try_rewrite_outer_ur_wall(x, y, layer);
-                self.try_rewrite_outer_dl_wall(x, y, layer);
-                self.try_rewrite_outer_dr_wall(x, y, layer);
-            }
-        }
-        for x in 0..layer.width {
-            for y in 0..layer.height {
-                self.try_rewrite_left_wall(x, y, layer);
-                self.try_rewrite_right_wall(x, y, layer);
-            }
-        }
-        for x in 0..layer.width {
-            for y in 0..layer.height {
-                self.try_rewrite_bottom_wall(x, y, layer);
-                self.try_rewrite_top_wall(x, y, layer);
-            }
-        }
-
-        for x in 0..layer.width {
-            for y in 0..layer.height {
-                self.try_rewrite_center_facades(x, y, layer);
-                self.try_rewrite_left_facades(x, y, layer);
-                self.try_rewrite_right_facades(x, y, layer);
-            }
-        }
+        apply_rule_passes(WALL_RULES, layer, &self.theme);
     }
 
-    fn try_rewrite_thin_horizontal_wall(&self, x: u32, y: u32, layer: &mut Layer) -> bool {
-        if x >= layer.width || y >= layer.height - 2 {
-            return false;
-        }
-
-        let i0 = xytoi(x, y, layer);
-        let i1 = xytoi(x, y + 1, layer);
-        let i2 = xytoi(x, y + 2, layer);
-
-        if let (&Some(tile0), &Some(tile1), &Some(tile2)) = (
-            &layer.data[i0].as_ref(),
-            &layer.data[i1].as_ref(),
-            &layer.data[i2].as_ref(),
-        ) {
-            if tile0.id != GROUND_01_TILE_ID
-                || tile1.id != WALL_01_TILE_ID
-                || tile2.id != GROUND_01_TILE_ID
-            {
-                return false;
-            }
-        }
-
-        layer.data[i0] = Some(Tile {
-            id: WALL_01_TILE_ID,
-            tileset: self.tileset_id.clone(),
+    fn rewrite_reinforced_door(&self, pos: UVec2, layer: &mut Layer) {
+        let i = xytoi(pos.x + 1, pos.y, layer);
+        layer.data[i] = Some(Tile {
+            id: self.theme.doors.reinforced_left,
+            tileset: self.theme.tileset.clone(),
             attrs: String::new(),
         });
-
-        true
-    }
-
-    fn try_rewrite_thin_vertical_wall(&self, x: u32, y: u32, layer: &mut Layer) -> bool {
-        if x >= layer.width - 2 || y >= layer.height {
-            return false;
-        }
-
-        let i0 = xytoi(x, y, layer);
-        let i1 = xytoi(x + 1, y, layer);
-        let i2 = xytoi(x + 2, y, layer);
-
-        if let (&Some(tile0), &Some(tile1), &Some(tile2)) = (
-            &layer.data[i0].as_ref(),
-            &layer.data[i1].as_ref(),
-            &layer.data[i2].as_ref(),
-        ) {
-            if tile0.id != GROUND_01_TILE_ID
-                || tile1.id != WALL_01_TILE_ID
-                || tile2.id != GROUND_01_TILE_ID
-            {
-                return false;
-            }
-        }
-
-        layer.data[i0] = Some(Tile {
-            id: WALL_01_TILE_ID,
-            tileset: self.tileset_id.clone(),
+        layer.data[i + 1] = Some(Tile {
+            id: self.theme.doors.reinforced_right,
+            tileset: self.theme.tileset.clone(),
             attrs: String::new(),
         });
-
-        true
     }
 
-    fn try_rewrite_double_corner_horizontal(&self, x: u32, y: u32, layer: &mut Layer) -> bool {
-        if x >= layer.width - 2 || y >= layer.height - 1 {
-            return false;
-        }
-
-        let i00 = xytoi(x, y, layer);
-        let i10 = xytoi(x + 1, y, layer);
-        let i20 = xytoi(x + 2, y, layer);
-        let i01 = xytoi(x, y + 1, layer);
-        let i11 = xytoi(x + 1, y + 1, layer);
-        let i21 = xytoi(x + 2, y + 1, layer);
-
-        if let (
-            &Some(tile00),
-            &Some(tile10),
-            &Some(tile20),
-            &Some(tile01),
-            &Some(tile11),
-            &Some(tile21),
-        ) = (
-            &layer.data[i00].as_ref(),
-            &layer.data[i10].as_ref(),
-            &layer.data[i20].as_ref(),
-            &layer.data[i01].as_ref(),
-            &layer.data[i11].as_ref(),
-            &layer.data[i21].as_ref(),
-        ) {
-            if !(tile00.id == WALL_01_TILE_ID
-                && tile10.id == WALL_01_TILE_ID
-                && tile20.id == GROUND_01_TILE_ID
-                && tile01.id == GROUND_01_TILE_ID
-                && tile11.id == WALL_01_TILE_ID
-                && tile21.id == WALL_01_TILE_ID)
-                && !(tile00.id == GROUND_01_TILE_ID
-                    && tile10.id == WALL_01_TILE_ID
-                    && tile20.id == WALL_01_TILE_ID
-                    && tile01.id == WALL_01_TILE_ID
-                    && tile11.id == WALL_01_TILE_ID
-                    && tile21.id == GROUND_01_TILE_ID)
-            {
-                return false;
-            }
-        }
-
-        layer.data[i00] = Some(Tile {
-            id: WALL_01_TILE_ID,
-            tileset: self.tileset_id.clone(),
-            attrs: String::new(),
-        });
-        layer.data[i10] = Some(Tile {
-            id: WALL_01_TILE_ID,
-            tileset: self.tileset_id.clone(),
-            attrs: String::new(),
-        });
-        layer.data[i20] = Some(Tile {
-            id: WALL_01_TILE_ID,
-            tileset: self.tileset_id.clone(),
+    fn rewrite_exit_door(&self, pos: UVec2, layer: &mut Layer) {
+        // rewrite doors to closed
+        let i = xytoi(pos.x, pos.y, layer);
+        layer.data[i] = Some(Tile {
+            id: self.theme.doors.pipe_closed,
+            tileset: self.theme.tileset.clone(),
             attrs: String::new(),
         });
-        layer.data[i01] = Some(Tile {
-            id: WALL_01_TILE_ID,
-            tileset: self.tileset_id.clone(),
+        let i = xytoi(pos.x + 1, pos.y, layer);
+        layer.data[i] = Some(Tile {
+            id: self.theme.doors.left_closed,
+            tileset: self.theme.tileset.clone(),
             attrs: String::new(),
         });
-        layer.data[i11] = Some(Tile {
-            id: WALL_01_TILE_ID,
-            tileset: self.tileset_id.clone(),
+        let i = xytoi(pos.x + 2, pos.y, layer);
+        layer.data[i] = Some(Tile {
+            id: self.theme.doors.right_closed,
+            tileset: self.theme.tileset.clone(),
             attrs: String::new(),
         });
-        layer.data[i21] = Some(Tile {
-            id: WALL_01_TILE_ID,
-            tileset: self.tileset_id.clone(),
+        let i = xytoi(pos.x + 3, pos.y, layer);
+        layer.data[i] = Some(Tile {
+            id: self.theme.doors.pipe_closed,
+            tileset: self.theme.tileset.clone(),
             attrs: String::new(),
         });
 
-        true
-    }
-    fn try_rewrite_double_corner_vertical(&self, x: u32, y: u32, layer: &mut Layer) -> bool {
-        if x >= layer.width - 1 || y >= layer.height - 2 {
-            return false;
-        }
-
-        let i00 = xytoi(x, y, layer);
-        let i10 = xytoi(x, y + 1, layer);
-        let i20 = xytoi(x, y + 2, layer);
-        let i01 = xytoi(x + 1, y, layer);
-        let i11 = xytoi(x + 1, y + 1, layer);
-        let i21 = xytoi(x + 1, y + 2, layer);
-
-        if let (
-            &Some(tile00),
-            &Some(tile10),
-            &Some(tile20),
-            &Some(tile01),
-            &Some(tile11),
-            &Some(tile21),
-        ) = (
-            &layer.data[i00].as_ref(),
-            &layer.data[i10].as_ref(),
-            &layer.data[i20].as_ref(),
-            &layer.data[i01].as_ref(),
-            &layer.data[i11].as_ref(),
-            &layer.data[i21].as_ref(),
-        ) {
-            if !(tile00.id == WALL_01_TILE_ID
-                && tile10.id == WALL_01_TILE_ID
-                && tile20.id == GROUND_01_TILE_ID
-                && tile01.id == GROUND_01_TILE_ID
-                && tile11.id == WALL_01_TILE_ID
-                && tile21.id == WALL_01_TILE_ID)
-                && !(tile00.id == GROUND_01_TILE_ID
-                    && tile10.id == WALL_01_TILE_ID
-                    && tile20.id == WALL_01_TILE_ID
-                    && tile01.id == WALL_01_TILE_ID
-                    && tile11.id == WALL_01_TILE_ID
-                    && tile21.id == GROUND_01_TILE_ID)
-            {
-                return false;
-            }
-        }
-
-        layer.data[i00] = Some(Tile {
-            id: WALL_01_TILE_ID,
-            tileset: self.tileset_id.clone(),
-            attrs: String::new(),
-        });
-        layer.data[i10] = Some(Tile {
-            id: WALL_01_TILE_ID,
-            tileset: self.tileset_id.clone(),
-            attrs: String::new(),
-        });
-        layer.data[i20] = Some(Tile {
-            id: WALL_01_TILE_ID,
-            tileset: self.tileset_id.clone(),
+        // put some stairs under them
+        let i = xytoi(pos.x, pos.y + 1, layer);
+        layer.data[i] = Some(Tile {
+            id: self.theme.doors.pool_empty,
+            tileset: self.theme.tileset.clone(),
             attrs: String::new(),
         });
-        layer.data[i01] = Some(Tile {
-            id: WALL_01_TILE_ID,
-            tileset: self.tileset_id.clone(),
+        let i = xytoi(pos.x + 1, pos.y + 1, layer);
+        layer.data[i] = Some(Tile {
+            id: self.theme.doors.stairs_left,
+            tileset: self.theme.tileset.clone(),
             attrs: String::new(),
         });
-        layer.data[i11] = Some(Tile {
-            id: WALL_01_TILE_ID,
-            tileset: self.tileset_id.clone(),
+        let i = xytoi(pos.x + 2, pos.y + 1, layer);
+        layer.data[i] = Some(Tile {
+            id: self.theme.doors.stairs_right,
+            tileset: self.theme.tileset.clone(),
             attrs: String::new(),
         });
-        layer.data[i21] = Some(Tile {
-            id: WALL_01_TILE_ID,
-            tileset: self.tileset_id.clone(),
+        let i = xytoi(pos.x + 3, pos.y + 1, layer);
+        layer.data[i] = Some(Tile {
+            id: self.theme.doors.pool_empty,
+            tileset: self.theme.tileset.clone(),
             attrs: String::new(),
         });
-
-        true
     }
 
-    fn try_rewrite_top_wall(&self, x: u32, y: u32, layer: &mut Layer) -> bool {
-        if x >= layer.width || y >= layer.height - 1 {
-            return false;
+    fn generate_guard_doors(&self, max_doors: usize, layer: &mut Layer) -> Vec<UVec2> {
+        let mut candidates: Vec<UVec2> = Vec::new();
+        for x in 0..layer.width {
+            for y in 0..layer.height {
+                if self.check_door_candidate(x, y, layer) {
+                    candidates.push(uvec2(x, y));
+                }
+            }
         }
 
-        let i0 = xytoi(x, y, layer);
-        let i1 = xytoi(x, y + 1, layer);
+        let mut doors: Vec<UVec2> = Vec::new();
+        while doors.len() < max_doors && !candidates.is_empty() {
+            let pos = candidates.remove(gen_range(0, candidates.len()));
 
-        if let (&Some(tile0), &Some(tile1)) = (&layer.data[i0].as_ref(), &layer.data[i1].as_ref()) {
-            if tile0.id != WALL_01_TILE_ID || tile1.id != GROUND_01_TILE_ID {
-                return false;
+            // we have to check again, since doors are 2-wide their placements can interfere
+            if !self.check_door_candidate(pos.x, pos.y, layer) {
+                continue;
             }
-        }
 
-        layer.data[i0] = Some(Tile {
-            id: WALL_UP_TILE_ID,
-            tileset: self.tileset_id.clone(),
-            attrs: String::new(),
-        });
-
-        true
-    }
+            let i = xytoi(pos.x + 1, pos.y, layer);
+            layer.data[i] = Some(Tile {
+                id: self.theme.doors.left_open,
+                tileset: self.theme.tileset.clone(),
+                attrs: String::new(),
+            });
+            let i = xytoi(pos.x + 2, pos.y, layer);
+            layer.data[i] = Some(Tile {
+                id: self.theme.doors.right_open,
+                tileset: self.theme.tileset.clone(),
+                attrs: String::new(),
+            });
 
-    fn try_rewrite_bottom_wall(&self, x: u32, y: u32, layer: &mut Layer) -> bool {
-        if x >= layer.width || y >= layer.height - 1 {
-            return false;
+            doors.push(pos);
         }
 
-        let i0 = xytoi(x, y, layer);
-        let i1 = xytoi(x, y + 1, layer);
-
-        if let (&Some(tile0), &Some(tile1)) = (&layer.data[i0].as_ref(), &layer.data[i1].as_ref()) {
-            if tile0.id != GROUND_01_TILE_ID || tile1.id != WALL_01_TILE_ID {
-                return false;
-            }
-        }
+        doors
+    }
 
-        layer.data[i1] = Some(Tile {
-            id: WALL_DOWN_TILE_ID,
-            tileset: self.tileset_id.clone(),
-            attrs: String::new(),
-        });
+    /// Carve extra corridors until every room can be reached from the first one. Rooms are only
+    /// chained to the previous room, so this should rarely have work to do. Must run before the
+    /// wall detail pass, while the layer is still plain ground and wall.
+    fn connect_rooms(&self, rooms: &[Rect], layer: &mut Layer) {
+        let Some(start) = rooms.first() else {
+            return;
+        };
+        let start = start.center().as_uvec2();
+        loop {
+            let blocked: Vec<bool> = layer
+                .data
+                .iter()
+                .map(|tile| {
+                    tile.as_ref()
+                        .is_none_or(|tile| tile.id != self.theme.ground.base)
+                })
+                .collect();
+            let reached = reachable_tiles(layer, start, &blocked);
+            let is_reached = |room: &Rect| {
+                let center = room.center().as_uvec2();
+                reached[xytoi(center.x, center.y, layer)]
+            };
+            let Some(lost) = rooms.iter().find(|room| !is_reached(room)) else {
+                return;
+            };
 
-        true
+            // patch a corridor in from the closest room we can already get to
+            let lost = lost.center();
+            let nearest = rooms
+                .iter()
+                .filter(|room| is_reached(room))
+                .map(|room| room.center())
+                .min_by(|a, b| a.distance(lost).total_cmp(&b.distance(lost)))
+                .unwrap();
+            let (from, to) = (nearest.as_uvec2(), lost.as_uvec2());
+            self.generate_corridor_horizontal(layer, from.x, to.x, from.y, self.corridor_padding);
+            self.generate_corridor_vertical(layer, to.x, from.y, to.y, self.corridor_padding);
+        }
     }
 
-    fn try_rewrite_left_wall(&self, x: u32, y: u32, layer: &mut Layer) -> bool {
-        if x >= layer.width - 1 || y >= layer.height {
-            return false;
+    /// Join each room to the nearest room it isn't chained to, so there's more than one way around
+    /// the map. Layouts build rooms in order, and only ever join neighbors in that order.
+    fn generate_corridor_loops(
+        &self,
+        rooms: &[Rect],
+        layer: &mut Layer,
+        vertical_corridors: &mut Vec<(u32, u32, u32)>,
+    ) {
+        let mut pairs: Vec<(usize, usize)> = (0..rooms.len())
+            .filter_map(|i| {
+                let center = rooms[i].center();
+                (0..rooms.len())
+                    .filter(|&j| i.abs_diff(j) > 1)
+                    .min_by(|&a, &b| {
+                        let a = rooms[a].center().distance(center);
+                        let b = rooms[b].center().distance(center);
+                        a.total_cmp(&b)
+                    })
+                    .map(|j| (i.min(j), i.max(j)))
+            })
+            .collect();
+        pairs.sort_unstable();
+        pairs.dedup();
+        pairs.shuffle();
+
+        for (i, j) in pairs.into_iter().take(self.corridor_loop_count as usize) {
+            self.connect_room_pair(layer, &rooms[i], &rooms[j], vertical_corridors);
         }
+    }
 
-        let i0 = xytoi(x, y, layer);
-        let i1 = xytoi(x + 1, y, layer);
-
-        if let (&Some(tile0), &Some(tile1)) = (&layer.data[i0].as_ref(), &layer.data[i1].as_ref()) {
-            if tile0.id != WALL_01_TILE_ID || tile1.id != GROUND_01_TILE_ID {
-                return false;
+    /// Fill in floor outside the rooms that is walled in on three sides, over and over until
+    /// there's none left, so spurs and nooks that lead nowhere close up. Must run before the wall
+    /// detail pass, while the layer is still plain ground and wall.
+    fn trim_dead_ends(&self, rooms: &[Rect], layer: &mut Layer) {
+        let in_room = |x: u32, y: u32| {
+            rooms
+                .iter()
+                .any(|room| room.contains(vec2(x as f32 + 0.5, y as f32 + 0.5)))
+        };
+        let mut stack: Vec<UVec2> = (1..layer.height - 1)
+            .flat_map(|y| (1..layer.width - 1).map(move |x| uvec2(x, y)))
+            .collect();
+        while let Some(pos) = stack.pop() {
+            let is_ground = |x: u32, y: u32| {
+                layer.data[xytoi(x, y, layer)]
+                    .as_ref()
+                    .is_some_and(|tile| tile.id == self.theme.ground.base)
+            };
+            if !is_ground(pos.x, pos.y) || in_room(pos.x, pos.y) {
+                continue;
             }
+            let neighbors = [
+                uvec2(pos.x - 1, pos.y),
+                uvec2(pos.x + 1, pos.y),
+                uvec2(pos.x, pos.y - 1),
+                uvec2(pos.x, pos.y + 1),
+            ];
+            let open: Vec<UVec2> = neighbors
+                .into_iter()
+                .filter(|next| is_ground(next.x, next.y))
+                .collect();
+            if open.len() > 1 {
+                continue;
+            }
+            let i = xytoi(pos.x, pos.y, layer);
+            layer.data[i] = Some(Tile {
+                id: self.theme.walls.base,
+                tileset: self.theme.tileset.clone(),
+                attrs: String::new(),
+            });
+            // the tile it opened onto may be the end of the spur now
+            stack.extend(
+                open.into_iter()
+                    .filter(|next| next.x > 0 && next.y > 0)
+                    .filter(|next| next.x < layer.width - 1 && next.y < layer.height - 1),
+            );
         }
-
-        layer.data[i0] = Some(Tile {
-            id: WALL_LEFT_TILE_ID,
-            tileset: self.tileset_id.clone(),
-            attrs: String::new(),
-        });
-
-        true
     }
 
-    fn try_rewrite_right_wall(&self, x: u32, y: u32, layer: &mut Layer) -> bool {
-        if x >= layer.width - 1 || y >= layer.height {
+    /// Check that every room and the floor in front of every door is reachable from the first
+    /// room, with any locked doors open
+    pub fn is_connected(&self, result: &MapGenResult) -> bool {
+        let Some(start) = result.rooms.first() else {
             return false;
+        };
+        let layer = &result.layer;
+        let blocked: Vec<bool> = walkable_mask(layer, &self.theme)
+            .iter()
+            .map(|w| !w)
+            .collect();
+        let reached = reachable_tiles(layer, start.center().as_uvec2(), &blocked);
+        let rooms = result.rooms.iter().map(|room| room.center().as_uvec2());
+        // doors sit in a wall facade, and are entered from the tile below
+        let doors = result
+            .guard_doors
+            .iter()
+            .chain(std::iter::once(&result.exit_door))
+            .chain(result.reinforced_door.iter())
+            .map(|door| uvec2(door.x + 1, door.y + 1));
+        rooms
+            .chain(doors)
+            .all(|pos| reached[xytoi(pos.x, pos.y, layer)])
+    }
+
+    /// Stamp prefabs into some of the guard rooms, lining each prefab's anchor up with the room
+    /// center. Prefabs only go where they fit inside the room with a tile of floor to spare.
+    fn stamp_prefabs(
+        &self,
+        rooms: &[Rect],
+        tags: &[RoomTag],
+        layer: &mut Layer,
+        props: &mut Layer,
+    ) {
+        if self.prefabs.is_empty() {
+            return;
         }
+        for (room, tag) in rooms.iter().zip(tags) {
+            // a prefab has room for the one guard at its anchor, and no more
+            if tag.budget(room).guards != 1 || gen_range(0., 1.) > self.prefab_room_prob {
+                continue;
+            }
+            let prefab = self.prefabs.choose().unwrap();
+            let prefab = if prefab.rotatable {
+                prefab.rotated(gen_range(0, 4))
+            } else {
+                prefab.clone()
+            };
 
-        let i0 = xytoi(x, y, layer);
-        let i1 = xytoi(x + 1, y, layer);
+            let center = room.center().as_uvec2();
+            let (min, max) = (
+                uvec2(room.x as u32 + 1, room.y as u32 + 1),
+                uvec2((room.x + room.w) as u32 - 1, (room.y + room.h) as u32 - 1),
+            );
+            if center.x < min.x + prefab.anchor.x || center.y < min.y + prefab.anchor.y {
+                continue;
+            }
+            let origin = center - prefab.anchor;
+            if origin.x + prefab.size.x > max.x || origin.y + prefab.size.y > max.y {
+                continue;
+            }
 
-        if let (&Some(tile0), &Some(tile1)) = (&layer.data[i0].as_ref(), &layer.data[i1].as_ref()) {
-            if tile0.id != GROUND_01_TILE_ID || tile1.id != WALL_01_TILE_ID {
-                return false;
+            for y in 0..prefab.size.y {
+                for x in 0..prefab.size.x {
+                    let src = (y * prefab.size.x + x) as usize;
+                    let dst = xytoi(origin.x + x, origin.y + y, layer);
+                    if let Some(id) = prefab.terrain[src] {
+                        layer.data[dst] = Some(Tile {
+                            id,
+                            tileset: self.theme.tileset.clone(),
+                            attrs: String::new(),
+                        });
+                    }
+                    if let Some(id) = prefab.props[src] {
+                        props.data[dst] = Some(Tile {
+                            id,
+                            tileset: self.theme.tileset.clone(),
+                            attrs: String::new(),
+                        });
+                    }
+                }
             }
         }
-
-        layer.data[i1] = Some(Tile {
-            id: WALL_RIGHT_TILE_ID,
-            tileset: self.tileset_id.clone(),
-            attrs: String::new(),
-        });
-
-        true
     }
 
-    fn try_rewrite_inner_ul_wall(&self, x: u32, y: u32, layer: &mut Layer) -> bool {
-        if x >= layer.width - 1 || y >= layer.height - 1 {
-            return false;
-        }
-
-        let i00 = xytoi(x, y, layer);
-        let i01 = xytoi(x + 1, y, layer);
-        let i10 = xytoi(x, y + 1, layer);
-        let i11 = xytoi(x + 1, y + 1, layer);
-
-        if let (&Some(tile00), &Some(tile01), &Some(tile10), &Some(tile11)) = (
-            &layer.data[i00].as_ref(),
-            &layer.data[i01].as_ref(),
-            &layer.data[i10].as_ref(),
-            &layer.data[i11].as_ref(),
-        ) {
-            if !WALL_TILE_IDS.contains(&tile00.id)
-                || !WALL_TILE_IDS.contains(&tile01.id)
-                || !WALL_TILE_IDS.contains(&tile10.id)
-                || tile11.id != GROUND_01_TILE_ID
-            {
-                return false;
+    /// Scatter pickups on the floors of the guard rooms. The player's starting room stays empty.
+    fn generate_items(
+        &self,
+        rooms: &[Rect],
+        tags: &[RoomTag],
+        layer: &Layer,
+    ) -> Vec<(UVec2, ItemKind)> {
+        let mut items = Vec::new();
+        for (room, tag) in rooms.iter().zip(tags) {
+            for _ in 0..tag.budget(room).items {
+                if gen_range(0., 1.) > ITEM_SPAWN_PROB {
+                    continue;
+                }
+                // stay off the room edges, and out of the way of the guards standing in the middle
+                let x = gen_range(room.x as u32 + 1, (room.x + room.w) as u32 - 1);
+                let y = gen_range(room.y as u32 + 1, (room.y + room.h) as u32 - 1);
+                let position = uvec2(x, y);
+                if position.as_vec2().distance(room.center()) < 2.
+                    || items.iter().any(|(item, _)| *item == position)
+                {
+                    continue;
+                }
+                let is_ground = layer.data[xytoi(x, y, layer)]
+                    .as_ref()
+                    .is_some_and(|tile| tile.id == self.theme.ground.base);
+                if is_ground {
+                    items.push((position, ItemKind::random_consumable()));
+                }
             }
         }
-
-        layer.data[i00] = Some(Tile {
-            id: WALL_INNER_UL_ID,
-            tileset: self.tileset_id.clone(),
-            attrs: String::new(),
-        });
-
-        true
+        items
     }
 
-    fn try_rewrite_inner_ur_wall(&self, x: u32, y: u32, layer: &mut Layer) -> bool {
-        if x >= layer.width - 1 || y >= layer.height - 1 {
-            return false;
-        }
-
-        let i00 = xytoi(x, y, layer);
-        let i01 = xytoi(x + 1, y, layer);
-        let i10 = xytoi(x, y + 1, layer);
-        let i11 = xytoi(x + 1, y + 1, layer);
-
-        if let (&Some(tile00), &Some(tile01), &Some(tile10), &Some(tile11)) = (
-            &layer.data[i00].as_ref(),
-            &layer.data[i01].as_ref(),
-            &layer.data[i10].as_ref(),
-            &layer.data[i11].as_ref(),
-        ) {
-            if !WALL_TILE_IDS.contains(&tile00.id)
-                || !WALL_TILE_IDS.contains(&tile01.id)
-                || tile10.id != GROUND_01_TILE_ID
-                || !WALL_TILE_IDS.contains(&tile11.id)
+    /// Scatter spikes over the floors of the guard rooms, clear of items and the guards' spawns
+    fn generate_spike_traps(
+        &self,
+        rooms: &[Rect],
+        tags: &[RoomTag],
+        items: &[(UVec2, ItemKind)],
+        layer: &mut Layer,
+    ) -> Vec<UVec2> {
+        let mut traps = Vec::new();
+        // each room is drawn from as many times as its budget allows for
+        let weighted: Vec<&Rect> = rooms
+            .iter()
+            .zip(tags)
+            .flat_map(|(room, tag)| iter::repeat_n(room, tag.budget(room).traps as usize))
+            .collect();
+        if weighted.is_empty() {
+            return traps;
+        }
+        for _ in 0..self.spike_trap_count * 4 {
+            if traps.len() as u32 >= self.spike_trap_count {
+                break;
+            }
+            let room = weighted[gen_range(0, weighted.len())];
+            let x = gen_range(room.x as u32 + 1, (room.x + room.w) as u32 - 1);
+            let y = gen_range(room.y as u32 + 1, (room.y + room.h) as u32 - 1);
+            let position = uvec2(x, y);
+            if position.as_vec2().distance(room.center()) < 2.
+                || items.iter().any(|(item, _)| *item == position)
             {
-                return false;
+                continue;
             }
-        }
-
-        layer.data[i01] = Some(Tile {
-            id: WALL_INNER_UR_ID,
-            tileset: self.tileset_id.clone(),
-            attrs: String::new(),
-        });
-
-        true
-    }
-
-    fn try_rewrite_inner_dl_wall(&self, x: u32, y: u32, layer: &mut Layer) -> bool {
-        if x >= layer.width - 1 || y >= layer.height - 1 {
-            return false;
-        }
-
-        let i00 = xytoi(x, y, layer);
-        let i01 = xytoi(x + 1, y, layer);
-        let i10 = xytoi(x, y + 1, layer);
-        let i11 = xytoi(x + 1, y + 1, layer);
-
-        if let (&Some(tile00), &Some(tile01), &Some(tile10), &Some(tile11)) = (
-            &layer.data[i00].as_ref(),
-            &layer.data[i01].as_ref(),
-            &layer.data[i10].as_ref(),
-            &layer.data[i11].as_ref(),
-        ) {
-            if !WALL_TILE_IDS.contains(&tile00.id)
-                || tile01.id != GROUND_01_TILE_ID
-                || !WALL_TILE_IDS.contains(&tile10.id)
-                || !WALL_TILE_IDS.contains(&tile11.id)
+            let i = xytoi(x, y, layer);
+            if layer.data[i]
+                .as_ref()
+                .is_none_or(|tile| tile.id != self.theme.ground.base)
             {
-                return false;
+                continue;
             }
+            layer.data[i] = Some(Tile {
+                id: self.theme.traps.spikes_down,
+                tileset: self.theme.tileset.clone(),
+                attrs: String::new(),
+            });
+            traps.push(position);
         }
-
-        layer.data[i10] = Some(Tile {
-            id: WALL_INNER_DL_ID,
-            tileset: self.tileset_id.clone(),
-            attrs: String::new(),
-        });
-
-        true
+        traps
     }
 
-    fn try_rewrite_inner_dr_wall(&self, x: u32, y: u32, layer: &mut Layer) -> bool {
-        if x >= layer.width - 1 || y >= layer.height - 1 {
-            return false;
-        }
-
-        let i00 = xytoi(x, y, layer);
-        let i01 = xytoi(x + 1, y, layer);
-        let i10 = xytoi(x, y + 1, layer);
-        let i11 = xytoi(x + 1, y + 1, layer);
-
-        if let (&Some(tile00), &Some(tile01), &Some(tile10), &Some(tile11)) = (
-            &layer.data[i00].as_ref(),
-            &layer.data[i01].as_ref(),
-            &layer.data[i10].as_ref(),
-            &layer.data[i11].as_ref(),
-        ) {
-            if tile00.id != GROUND_01_TILE_ID
-                || !WALL_TILE_IDS.contains(&tile01.id)
-                || !WALL_TILE_IDS.contains(&tile10.id)
-                || !WALL_TILE_IDS.contains(&tile11.id)
+    /// Put pressure plates on the floor a few steps in front of some of the given guard doors
+    fn generate_pressure_plates(
+        &self,
+        doors: &[UVec2],
+        items: &[(UVec2, ItemKind)],
+        layer: &mut Layer,
+    ) -> Vec<(UVec2, UVec2)> {
+        let mut doors = doors.to_vec();
+        doors.shuffle();
+        let mut plates = Vec::new();
+        for door in doors {
+            if plates.len() as u32 >= self.pressure_plate_count {
+                break;
+            }
+            let position = uvec2(door.x + gen_range(1, 3), door.y + 3);
+            if position.y >= layer.height || items.iter().any(|(item, _)| *item == position) {
+                continue;
+            }
+            let i = xytoi(position.x, position.y, layer);
+            if layer.data[i]
+                .as_ref()
+                .is_none_or(|tile| tile.id != self.theme.ground.base)
             {
-                return false;
+                continue;
             }
+            layer.data[i] = Some(Tile {
+                id: self.theme.traps.plate_up,
+                tileset: self.theme.tileset.clone(),
+                attrs: String::new(),
+            });
+            plates.push((position, door));
         }
-
-        layer.data[i11] = Some(Tile {
-            id: WALL_INNER_DR_ID,
-            tileset: self.tileset_id.clone(),
-            attrs: String::new(),
-        });
-
-        true
+        plates
     }
 
-    fn try_rewrite_outer_ul_wall(&self, x: u32, y: u32, layer: &mut Layer) -> bool {
-        if x >= layer.width - 1 || y >= layer.height - 1 {
-            return false;
-        }
-
-        let i00 = xytoi(x, y, layer);
-        let i01 = xytoi(x + 1, y, layer);
-        let i10 = xytoi(x, y + 1, layer);
-        let i11 = xytoi(x + 1, y + 1, layer);
-
-        if let (&Some(tile00), &Some(tile01), &Some(tile10), &Some(tile11)) = (
-            &layer.data[i00].as_ref(),
-            &layer.data[i01].as_ref(),
-            &layer.data[i10].as_ref(),
-            &layer.data[i11].as_ref(),
-        ) {
-            if tile00.id != GROUND_01_TILE_ID
-                || tile01.id != GROUND_01_TILE_ID
-                || tile10.id != GROUND_01_TILE_ID
-                || !WALL_TILE_IDS.contains(&tile11.id)
-            {
-                return false;
+    /// Put levers on bare floor in rooms other than those of the guard doors they work, so
+    /// pulling one shuts a cell somewhere out of sight
+    fn generate_levers(
+        &self,
+        doors: &[UVec2],
+        rooms: &[Rect],
+        items: &[(UVec2, ItemKind)],
+        layer: &Layer,
+    ) -> Vec<(UVec2, UVec2)> {
+        let mut doors = doors.to_vec();
+        doors.shuffle();
+        let mut levers = Vec::new();
+        if rooms.len() < 2 {
+            return levers;
+        }
+        for door in doors {
+            if levers.len() as u32 >= self.lever_count {
+                break;
+            }
+            for _ in 0..MAPGEN_MAX_ATTEMPTS {
+                let room = rooms[gen_range(0, rooms.len())];
+                // the door sits in the wall along the top of its room
+                if room.contains(door.as_vec2() + vec2(2., 1.)) {
+                    continue;
+                }
+                let x = gen_range(room.x as u32 + 1, (room.x + room.w) as u32 - 1);
+                let y = gen_range(room.y as u32 + 1, (room.y + room.h) as u32 - 1);
+                let position = uvec2(x, y);
+                if position.as_vec2().distance(room.center()) < 2.
+                    || items.iter().any(|(item, _)| *item == position)
+                    || levers.iter().any(|(lever, _)| *lever == position)
+                {
+                    continue;
+                }
+                if layer.data[xytoi(x, y, layer)]
+                    .as_ref()
+                    .is_some_and(|tile| tile.id == self.theme.ground.base)
+                {
+                    levers.push((position, door));
+                    break;
+                }
             }
         }
-
-        layer.data[i11] = Some(Tile {
-            id: WALL_OUTER_UL_ID,
-            tileset: self.tileset_id.clone(),
-            attrs: String::new(),
-        });
-
-        true
+        levers
     }
 
-    fn try_rewrite_outer_ur_wall(&self, x: u32, y: u32, layer: &mut Layer) -> bool {
-        if x >= layer.width - 1 || y >= layer.height - 1 {
-            return false;
+    /// Hide chests in the dead-end rooms, the ones with a single way in. Maps without enough
+    /// dead ends fall back to the rooms with the fewest ways in. The starting room never gets one.
+    fn generate_chests(
+        &self,
+        rooms: &[Rect],
+        tags: &[RoomTag],
+        items: &[(UVec2, ItemKind)],
+        levers: &[(UVec2, UVec2)],
+        layer: &Layer,
+    ) -> Vec<(UVec2, u32)> {
+        let walkable = walkable_mask(layer, &self.theme);
+        let mut candidates: Vec<(bool, usize, &Rect)> = rooms
+            .iter()
+            .zip(tags)
+            .skip(1)
+            .map(|(room, tag)| {
+                let openings = room_openings(room, &walkable, layer);
+                (*tag != RoomTag::Treasure, openings, room)
+            })
+            // walled in all round, so nobody could reach the chest
+            .filter(|(_, openings, _)| *openings > 0)
+            .collect();
+        candidates.shuffle();
+        // treasure rooms first, then whatever's closest to a dead end
+        candidates.sort_by_key(|(not_treasure, openings, _)| (*not_treasure, *openings));
+
+        let mut chests = Vec::new();
+        for (_, _, room) in candidates {
+            if chests.len() as u32 >= self.chest_count {
+                break;
+            }
+            for _ in 0..MAPGEN_MAX_ATTEMPTS {
+                let x = gen_range(room.x as u32 + 1, (room.x + room.w) as u32 - 1);
+                let y = gen_range(room.y as u32 + 1, (room.y + room.h) as u32 - 1);
+                let position = uvec2(x, y);
+                if position.as_vec2().distance(room.center()) < 2.
+                    || items.iter().any(|(item, _)| *item == position)
+                    || levers.iter().any(|(lever, _)| *lever == position)
+                {
+                    continue;
+                }
+                if layer.data[xytoi(x, y, layer)]
+                    .as_ref()
+                    .is_some_and(|tile| tile.id == self.theme.ground.base)
+                {
+                    chests.push((position, gen_range(CHEST_COINS_MIN, CHEST_COINS_MAX + 1)));
+                    break;
+                }
+            }
         }
+        chests
+    }
 
-        let i00 = xytoi(x, y, layer);
-        let i01 = xytoi(x + 1, y, layer);
-        let i10 = xytoi(x, y + 1, layer);
-        let i11 = xytoi(x + 1, y + 1, layer);
-
-        if let (&Some(tile00), &Some(tile01), &Some(tile10), &Some(tile11)) = (
-            &layer.data[i00].as_ref(),
-            &layer.data[i01].as_ref(),
-            &layer.data[i10].as_ref(),
-            &layer.data[i11].as_ref(),
-        ) {
-            if tile00.id != GROUND_01_TILE_ID
-                || tile01.id != GROUND_01_TILE_ID
-                || !WALL_TILE_IDS.contains(&tile10.id)
-                || tile11.id != GROUND_01_TILE_ID
+    /// Set crates down on bare floor in the rooms, out of the way of items, levers, chests and
+    /// where characters spawn
+    fn generate_crates(
+        &self,
+        rooms: &[Rect],
+        items: &[(UVec2, ItemKind)],
+        levers: &[(UVec2, UVec2)],
+        chests: &[(UVec2, u32)],
+        layer: &Layer,
+    ) -> Vec<UVec2> {
+        let mut crates = Vec::new();
+        if rooms.is_empty() {
+            return crates;
+        }
+        for _ in 0..self.crate_count * 4 {
+            if crates.len() as u32 >= self.crate_count {
+                break;
+            }
+            let room = rooms[gen_range(0, rooms.len())];
+            let x = gen_range(room.x as u32 + 1, (room.x + room.w) as u32 - 1);
+            let y = gen_range(room.y as u32 + 1, (room.y + room.h) as u32 - 1);
+            let position = uvec2(x, y);
+            if position.as_vec2().distance(room.center()) < 2.
+                || items.iter().any(|(item, _)| *item == position)
+                || crates.contains(&position)
+                || levers.iter().any(|(lever, _)| *lever == position)
+                || chests.iter().any(|(chest, _)| *chest == position)
+            {
+                continue;
+            }
+            // spikes and plates have already replaced the bare floor they sit on
+            if layer.data[xytoi(x, y, layer)]
+                .as_ref()
+                .is_some_and(|tile| tile.id == self.theme.ground.base)
             {
-                return false;
+                crates.push(position);
             }
         }
-
-        layer.data[i10] = Some(Tile {
-            id: WALL_OUTER_UR_ID,
-            tileset: self.tileset_id.clone(),
-            attrs: String::new(),
-        });
-
-        true
+        crates
     }
-    fn try_rewrite_outer_dl_wall(&self, x: u32, y: u32, layer: &mut Layer) -> bool {
-        if x >= layer.width - 1 || y >= layer.height - 1 {
-            return false;
-        }
 
-        let i00 = xytoi(x, y, layer);
-        let i01 = xytoi(x + 1, y, layer);
-        let i10 = xytoi(x, y + 1, layer);
-        let i11 = xytoi(x + 1, y + 1, layer);
-
-        if let (&Some(tile00), &Some(tile01), &Some(tile10), &Some(tile11)) = (
-            &layer.data[i00].as_ref(),
-            &layer.data[i01].as_ref(),
-            &layer.data[i10].as_ref(),
-            &layer.data[i11].as_ref(),
-        ) {
-            if tile00.id != GROUND_01_TILE_ID
-                || !WALL_TILE_IDS.contains(&tile01.id)
-                || tile10.id != GROUND_01_TILE_ID
-                || tile11.id != GROUND_01_TILE_ID
+    /// Stand barrels against the back walls of the rooms, on bare floor that nothing else has
+    /// taken
+    fn generate_barrels(
+        &self,
+        rooms: &[Rect],
+        items: &[(UVec2, ItemKind)],
+        levers: &[(UVec2, UVec2)],
+        chests: &[(UVec2, u32)],
+        crates: &[UVec2],
+        layer: &Layer,
+    ) -> Vec<UVec2> {
+        let mut barrels = Vec::new();
+        if rooms.is_empty() {
+            return barrels;
+        }
+        let is_tile = |x, y, id| {
+            layer.data[xytoi(x, y, layer)]
+                .as_ref()
+                .is_some_and(|tile| tile.id == id)
+        };
+        for _ in 0..self.barrel_count * 4 {
+            if barrels.len() as u32 >= self.barrel_count {
+                break;
+            }
+            let room = rooms[gen_range(0, rooms.len())];
+            let x = gen_range(room.x as u32 + 1, (room.x + room.w) as u32 - 1);
+            // the first floor tile down from the top of the room, right under the wall's face
+            let Some(y) = (room.y.max(1.) as u32..(room.y + room.h) as u32)
+                .find(|&y| is_tile(x, y, self.theme.ground.base))
+            else {
+                continue;
+            };
+            let position = uvec2(x, y);
+            if !is_tile(x, y - 1, self.theme.facades.center)
+                || items.iter().any(|(item, _)| *item == position)
+                || levers.iter().any(|(lever, _)| *lever == position)
+                || chests.iter().any(|(chest, _)| *chest == position)
+                || crates.contains(&position)
+                || barrels.contains(&position)
             {
-                return false;
+                continue;
             }
+            barrels.push(position);
         }
-
-        layer.data[i01] = Some(Tile {
-            id: WALL_OUTER_DL_ID,
-            tileset: self.tileset_id.clone(),
-            attrs: String::new(),
-        });
-
-        true
+        barrels
     }
 
-    fn try_rewrite_outer_dr_wall(&self, x: u32, y: u32, layer: &mut Layer) -> bool {
-        if x >= layer.width - 1 || y >= layer.height - 1 {
-            return false;
+    /// Ice over the bare floor of some guard rooms, leaving traps and prefab tiles as they are.
+    /// Runs after everything is placed, so items and crates can end up out on the ice.
+    fn pave_ice_rooms(&self, rooms: &[Rect], layer: &mut Layer) {
+        let Some(ice) = self.theme.ground.ice else {
+            return;
+        };
+        for room in rooms.iter().skip(1) {
+            if gen_range(0., 1.) > self.ice_room_prob {
+                continue;
+            }
+            for y in room.y as u32..(room.y + room.h) as u32 {
+                for x in room.x as u32..(room.x + room.w) as u32 {
+                    let i = xytoi(x, y, layer);
+                    if let Some(tile) = &mut layer.data[i] {
+                        if tile.id == self.theme.ground.base {
+                            tile.id = ice;
+                        }
+                    }
+                }
+            }
         }
+    }
 
-        let i00 = xytoi(x, y, layer);
-        let i01 = xytoi(x + 1, y, layer);
-        let i10 = xytoi(x, y + 1, layer);
-        let i11 = xytoi(x + 1, y + 1, layer);
-
-        if let (&Some(tile00), &Some(tile01), &Some(tile10), &Some(tile11)) = (
-            &layer.data[i00].as_ref(),
-            &layer.data[i01].as_ref(),
-            &layer.data[i10].as_ref(),
-            &layer.data[i11].as_ref(),
-        ) {
-            if !WALL_TILE_IDS.contains(&tile00.id)
-                || tile01.id != GROUND_01_TILE_ID
-                || tile10.id != GROUND_01_TILE_ID
-                || tile11.id != GROUND_01_TILE_ID
+    /// Hang torches on some of the wall faces, spaced out so the light is spread around
+    fn generate_torches(&self, layer: &mut Layer) {
+        let mut candidates: Vec<UVec2> = (0..layer.height)
+            .flat_map(|y| (0..layer.width).map(move |x| uvec2(x, y)))
+            .filter(|position| {
+                layer.data[xytoi(position.x, position.y, layer)]
+                    .as_ref()
+                    .is_some_and(|tile| tile.id == self.theme.facades.center)
+            })
+            .collect();
+        candidates.shuffle();
+
+        let mut torches: Vec<UVec2> = Vec::new();
+        for position in candidates {
+            if torches.len() as u32 >= self.torch_count {
+                break;
+            }
+            if torches
+                .iter()
+                .any(|torch| torch.as_vec2().distance(position.as_vec2()) < TORCH_SPACING)
             {
-                return false;
+                continue;
             }
+            let i = xytoi(position.x, position.y, layer);
+            layer.data[i] = Some(Tile {
+                id: self.theme.facades.torch,
+                tileset: self.theme.tileset.clone(),
+                attrs: String::new(),
+            });
+            torches.push(position);
         }
-
-        layer.data[i00] = Some(Tile {
-            id: WALL_OUTER_DR_ID,
-            tileset: self.tileset_id.clone(),
-            attrs: String::new(),
-        });
-
-        true
     }
 
-    fn try_rewrite_center_facades(&self, x: u32, y: u32, layer: &mut Layer) -> bool {
-        if x >= layer.width || y >= layer.height - 1 {
-            return false;
+    /// Block some corridors with locked gates, and hide one key per gate somewhere the player can
+    /// reach without opening any of them. Since keys fit any gate, that's enough to guarantee the
+    /// map stays solvable. Gates that can't be given a reachable key are not placed.
+    fn generate_locked_doors(
+        &self,
+        vertical_corridors: &[(u32, u32, u32)],
+        rooms: &[Rect],
+        items: &mut Vec<(UVec2, ItemKind)>,
+        layer: &mut Layer,
+    ) -> Vec<(UVec2, u32)> {
+        if rooms.is_empty() {
+            return Vec::new();
+        }
+        let padding = self.corridor_padding.unwrap_or(1);
+        let width = padding * 2 + 1;
+
+        // one candidate per corridor, so gates don't stack up in the same hallway
+        let mut candidates: Vec<UVec2> = vertical_corridors
+            .iter()
+            .filter_map(|&(x, src_y, dest_y)| {
+                let (src_y, dest_y) = (src_y.min(dest_y), src_y.max(dest_y));
+                let rows: Vec<u32> = (src_y..=dest_y)
+                    .filter(|&y| self.check_locked_door_candidate(x - padding, y, width, layer))
+                    .collect();
+                (!rows.is_empty()).then(|| uvec2(x - padding, rows[rows.len() / 2]))
+            })
+            .collect();
+
+        let mut doors: Vec<(UVec2, u32)> = Vec::new();
+        while doors.len() < self.locked_door_count as usize && !candidates.is_empty() {
+            let pos = candidates.remove(gen_range(0, candidates.len()));
+            doors.push((pos, width));
         }
 
-        let i0 = xytoi(x, y, layer);
-        let i1 = xytoi(x, y + 1, layer);
-
-        if let (&Some(tile0), &Some(tile1)) = (&layer.data[i0].as_ref(), &layer.data[i1].as_ref()) {
-            if tile0.id != WALL_UP_TILE_ID || tile1.id != GROUND_01_TILE_ID {
-                return false;
+        // find where the player can get to with every gate shut
+        let mut blocked = walkable_mask(layer, &self.theme)
+            .iter()
+            .map(|w| !w)
+            .collect::<Vec<bool>>();
+        for (pos, width) in &doors {
+            for x in pos.x..(pos.x + width) {
+                blocked[xytoi(x, pos.y, layer)] = true;
             }
         }
-
-        layer.data[i1] = Some(Tile {
-            id: FACADE_CENTER_TILE_ID,
-            tileset: self.tileset_id.clone(),
-            attrs: String::new(),
-        });
-
-        true
-    }
-
-    fn try_rewrite_left_facades(&self, x: u32, y: u32, layer: &mut Layer) -> bool {
-        if x >= layer.width || y >= layer.height - 1 {
-            return false;
+        let reachable = reachable_tiles(layer, rooms[0].center().as_uvec2(), &blocked);
+
+        // keys go on open floor in reachable rooms, away from other items and guards
+        let mut key_spots: Vec<UVec2> = Vec::new();
+        for room in rooms {
+            for x in (room.x as u32 + 1)..((room.x + room.w) as u32 - 1) {
+                for y in (room.y as u32 + 1)..((room.y + room.h) as u32 - 1) {
+                    let pos = uvec2(x, y);
+                    let is_ground = layer.data[xytoi(x, y, layer)]
+                        .as_ref()
+                        .is_some_and(|tile| tile.id == self.theme.ground.base);
+                    if is_ground
+                        && reachable[xytoi(x, y, layer)]
+                        && pos != room.center().as_uvec2()
+                        && !items.iter().any(|(item_pos, _)| *item_pos == pos)
+                    {
+                        key_spots.push(pos);
+                    }
+                }
+            }
         }
+        doors.truncate(key_spots.len());
 
-        let i0 = xytoi(x, y, layer);
-        let i1 = xytoi(x, y + 1, layer);
-
-        if let (&Some(tile0), &Some(tile1)) = (&layer.data[i0].as_ref(), &layer.data[i1].as_ref()) {
-            if tile0.id != WALL_OUTER_DL_ID || tile1.id != GROUND_01_TILE_ID {
-                return false;
+        for (pos, width) in &doors {
+            let key = key_spots.remove(gen_range(0, key_spots.len()));
+            items.push((key, ItemKind::Key));
+            for x in pos.x..(pos.x + width) {
+                let id = if x == pos.x {
+                    self.theme.doors.locked_left
+                } else if x == pos.x + width - 1 {
+                    self.theme.doors.locked_right
+                } else {
+                    self.theme.doors.locked_center
+                };
+                let i = xytoi(x, pos.y, layer);
+                layer.data[i] = Some(Tile {
+                    id,
+                    tileset: self.theme.tileset.clone(),
+                    attrs: String::new(),
+                });
             }
         }
 
-        layer.data[i1] = Some(Tile {
-            id: FACADE_LEFT_TILE_ID,
-            tileset: self.tileset_id.clone(),
-            attrs: String::new(),
-        });
-
-        true
+        doors
     }
 
-    fn try_rewrite_right_facades(&self, x: u32, y: u32, layer: &mut Layer) -> bool {
-        if x >= layer.width || y >= layer.height - 1 {
-            return false;
-        }
-
-        let i0 = xytoi(x, y, layer);
-        let i1 = xytoi(x, y + 1, layer);
-
-        if let (&Some(tile0), &Some(tile1)) = (&layer.data[i0].as_ref(), &layer.data[i1].as_ref()) {
-            if tile0.id != WALL_OUTER_DR_ID || tile1.id != GROUND_01_TILE_ID {
-                return false;
+    /// Put one-way passages across corridors, but only where every room can still be reached with
+    /// the passage treated as a wall. A player who goes through the wrong way can always get back
+    /// around, so the passages are shortcuts to choose between rather than traps.
+    fn generate_one_way_passages(
+        &self,
+        vertical_corridors: &[(u32, u32, u32)],
+        rooms: &[Rect],
+        layer: &Layer,
+    ) -> Vec<(UVec2, u32, PassageDirection)> {
+        let mut passages = Vec::new();
+        let Some(start) = rooms.first() else {
+            return passages;
+        };
+        let padding = self.corridor_padding.unwrap_or(1);
+        let width = padding * 2 + 1;
+
+        let mut candidates: Vec<UVec2> = vertical_corridors
+            .iter()
+            .filter_map(|&(x, src_y, dest_y)| {
+                let (src_y, dest_y) = (src_y.min(dest_y), src_y.max(dest_y));
+                let rows: Vec<u32> = (src_y..=dest_y)
+                    .filter(|&y| self.check_locked_door_candidate(x - padding, y, width, layer))
+                    .collect();
+                (!rows.is_empty()).then(|| uvec2(x - padding, rows[rows.len() / 2]))
+            })
+            .collect();
+        candidates.shuffle();
+
+        let mut blocked: Vec<bool> = walkable_mask(layer, &self.theme)
+            .iter()
+            .map(|w| !w)
+            .collect();
+        for pos in candidates {
+            if passages.len() as u32 >= self.one_way_passage_count {
+                break;
+            }
+            let mut trial = blocked.clone();
+            for x in pos.x..(pos.x + width) {
+                trial[xytoi(x, pos.y, layer)] = true;
+            }
+            let reached = reachable_tiles(layer, start.center().as_uvec2(), &trial);
+            let all_reached = rooms.iter().all(|room| {
+                let center = room.center().as_uvec2();
+                reached[xytoi(center.x, center.y, layer)]
+            });
+            if all_reached {
+                blocked = trial;
+                passages.push((pos, width, PassageDirection::random()));
             }
         }
-
-        layer.data[i1] = Some(Tile {
-            id: FACADE_RIGHT_TILE_ID,
-            tileset: self.tileset_id.clone(),
-            attrs: String::new(),
-        });
-
-        true
-    }
-
-    fn rewrite_exit_door(&self, pos: UVec2, layer: &mut Layer) {
-        // rewrite doors to closed
-        let i = xytoi(pos.x, pos.y, layer);
-        layer.data[i] = Some(Tile {
-            id: MONSTER_PIPE_CLOSED_TILE_ID,
-            tileset: self.tileset_id.clone(),
-            attrs: String::new(),
-        });
-        let i = xytoi(pos.x + 1, pos.y, layer);
-        layer.data[i] = Some(Tile {
-            id: DOOR_LEFT_CLOSED_TILE_ID,
-            tileset: self.tileset_id.clone(),
-            attrs: String::new(),
-        });
-        let i = xytoi(pos.x + 2, pos.y, layer);
-        layer.data[i] = Some(Tile {
-            id: DOOR_RIGHT_CLOSED_TILE_ID,
-            tileset: self.tileset_id.clone(),
-            attrs: String::new(),
-        });
-        let i = xytoi(pos.x + 3, pos.y, layer);
-        layer.data[i] = Some(Tile {
-            id: MONSTER_PIPE_CLOSED_TILE_ID,
-            tileset: self.tileset_id.clone(),
-            attrs: String::new(),
-        });
-
-        // put some stairs under them
-        let i = xytoi(pos.x, pos.y + 1, layer);
-        layer.data[i] = Some(Tile {
-            id: POOL_EMPTY_TILE_ID,
-            tileset: self.tileset_id.clone(),
-            attrs: String::new(),
-        });
-        let i = xytoi(pos.x + 1, pos.y + 1, layer);
-        layer.data[i] = Some(Tile {
-            id: STAIRS_LEFT_TILE_ID,
-            tileset: self.tileset_id.clone(),
-            attrs: String::new(),
-        });
-        let i = xytoi(pos.x + 2, pos.y + 1, layer);
-        layer.data[i] = Some(Tile {
-            id: STAIRS_RIGHT_TILE_ID,
-            tileset: self.tileset_id.clone(),
-            attrs: String::new(),
-        });
-        let i = xytoi(pos.x + 3, pos.y + 1, layer);
-        layer.data[i] = Some(Tile {
-            id: POOL_EMPTY_TILE_ID,
-            tileset: self.tileset_id.clone(),
-            attrs: String::new(),
-        });
+        passages
     }
 
-    fn generate_guard_doors(&self, max_doors: usize, layer: &mut Layer) -> Vec<UVec2> {
-        let mut candidates: Vec<UVec2> = Vec::new();
-        for x in 0..layer.width {
-            for y in 0..layer.height {
-                if self.check_door_candidate(x, y, layer) {
-                    candidates.push(uvec2(x, y));
-                }
-            }
+    /// Check if a row of floor spans a corridor exactly, with straight walls on either side for a
+    /// tile above and below, so a gate placed there would block it
+    fn check_locked_door_candidate(&self, x: u32, y: u32, width: u32, layer: &Layer) -> bool {
+        if x == 0 || y == 0 || x + width >= layer.width || y + 1 >= layer.height {
+            return false;
         }
-
-        let mut doors: Vec<UVec2> = Vec::new();
-        while doors.len() < max_doors && !candidates.is_empty() {
-            let pos = candidates.remove(gen_range(0, candidates.len()));
-
-            // we have to check again, since doors are 2-wide their placements can interfere
-            if !self.check_door_candidate(pos.x, pos.y, layer) {
-                continue;
+        let is_ground = |x: u32, y: u32| {
+            layer.data[xytoi(x, y, layer)]
+                .as_ref()
+                .is_some_and(|tile| tile.id == self.theme.ground.base)
+        };
+        let is_solid = |x: u32, y: u32| {
+            layer.data[xytoi(x, y, layer)]
+                .as_ref()
+                .is_some_and(|tile| self.theme.is_solid(tile.id))
+        };
+        for y in (y - 1)..=(y + 1) {
+            if !is_solid(x - 1, y) || !is_solid(x + width, y) {
+                return false;
+            }
+            if !(x..(x + width)).all(|x| is_ground(x, y)) {
+                return false;
             }
-
-            let i = xytoi(pos.x + 1, pos.y, layer);
-            layer.data[i] = Some(Tile {
-                id: DOOR_LEFT_OPEN_TILE_ID,
-                tileset: self.tileset_id.clone(),
-                attrs: String::new(),
-            });
-            let i = xytoi(pos.x + 2, pos.y, layer);
-            layer.data[i] = Some(Tile {
-                id: DOOR_RIGHT_OPEN_TILE_ID,
-                tileset: self.tileset_id.clone(),
-                attrs: String::new(),
-            });
-
-            doors.push(pos);
         }
-
-        doors
+        true
     }
 
     /// Check if a location is a candidate for door placement
@@ -1061,7 +1522,7 @@ impl MapGenerator {
             // check if we can place door on a facade
             let i = xytoi(x, y, layer);
             if let &Some(tile) = &layer.data[i].as_ref() {
-                if tile.id != FACADE_CENTER_TILE_ID {
+                if tile.id != self.theme.facades.center {
                     return false;
                 }
             }
@@ -1070,7 +1531,7 @@ impl MapGenerator {
             for y in (y + 1)..(y + self.door_clearance) {
                 let i = xytoi(x, y, layer);
                 if let &Some(tile) = &layer.data[i].as_ref() {
-                    if tile.id != GROUND_01_TILE_ID {
+                    if tile.id != self.theme.ground.base {
                         return false;
                     }
                 }
@@ -1103,7 +1564,7 @@ impl MapGenerator {
                 // rewrite to dst
                 layer.data[i] = Some(Tile {
                     id: dst,
-                    tileset: self.tileset_id.clone(),
+                    tileset: self.theme.tileset.clone(),
                     attrs: String::new(),
                 });
 
@@ -1115,6 +1576,97 @@ impl MapGenerator {
     }
 }
 
+/// Copy the wall tops along the south sides of floors out of a terrain layer, for drawing over the
+/// characters. The terrain keeps them too, since it is what the walls' colliders are built from.
+pub fn overhead_layer(terrain: &Layer, theme: &TileTheme) -> Layer {
+    Layer {
+        width: terrain.width,
+        height: terrain.height,
+        data: terrain
+            .data
+            .iter()
+            .map(|tile| {
+                tile.as_ref()
+                    .filter(|tile| theme.is_overhead(tile.id))
+                    .map(|tile| Tile {
+                        id: tile.id,
+                        tileset: tile.tileset.clone(),
+                        attrs: tile.attrs.clone(),
+                    })
+            })
+            .collect(),
+        ..Default::default()
+    }
+}
+
+/// Which tiles of a layer can be walked on, in row-major order
+pub fn walkable_mask(layer: &Layer, theme: &TileTheme) -> Vec<bool> {
+    layer
+        .data
+        .iter()
+        .map(|tile| tile.as_ref().is_some_and(|tile| !theme.is_solid(tile.id)))
+        .collect()
+}
+
+/// Flood fill out from `start` through 4-connected tiles that aren't blocked. Returns a row-major
+/// mask of the tiles that were reached.
+pub fn reachable_tiles(layer: &Layer, start: UVec2, blocked: &[bool]) -> Vec<bool> {
+    let mut reached = vec![false; layer.data.len()];
+    if start.x >= layer.width || start.y >= layer.height || blocked[xytoi(start.x, start.y, layer)]
+    {
+        return reached;
+    }
+    let mut stack = vec![start];
+    reached[xytoi(start.x, start.y, layer)] = true;
+    while let Some(pos) = stack.pop() {
+        let neighbors = [
+            (pos.x > 0).then(|| uvec2(pos.x - 1, pos.y)),
+            (pos.x + 1 < layer.width).then(|| uvec2(pos.x + 1, pos.y)),
+            (pos.y > 0).then(|| uvec2(pos.x, pos.y - 1)),
+            (pos.y + 1 < layer.height).then(|| uvec2(pos.x, pos.y + 1)),
+        ];
+        for next in neighbors.into_iter().flatten() {
+            let i = xytoi(next.x, next.y, layer);
+            if !reached[i] && !blocked[i] {
+                reached[i] = true;
+                stack.push(next);
+            }
+        }
+    }
+    reached
+}
+
+/// Count the ways into a room: separate stretches of walkable tile in the ring just outside it.
+/// A room with one way in is a dead end.
+pub fn room_openings(room: &Rect, walkable: &[bool], layer: &Layer) -> usize {
+    let (left, top) = (room.x as i32 - 1, room.y as i32 - 1);
+    let (right, bottom) = ((room.x + room.w) as i32, (room.y + room.h) as i32);
+    // walk the ring clockwise from the top left corner
+    let ring = (left..right)
+        .map(|x| (x, top))
+        .chain((top..bottom).map(|y| (right, y)))
+        .chain((left + 1..=right).rev().map(|x| (x, bottom)))
+        .chain((top + 1..=bottom).rev().map(|y| (left, y)));
+    let is_open: Vec<bool> = ring
+        .map(|(x, y)| {
+            x >= 0
+                && y >= 0
+                && (x as u32) < layer.width
+                && (y as u32) < layer.height
+                && walkable[xytoi(x as u32, y as u32, layer)]
+        })
+        .collect();
+    // count where closed turns to open, going round the ring
+    let openings = (0..is_open.len())
+        .filter(|&i| is_open[i] && !is_open[(i + is_open.len() - 1) % is_open.len()])
+        .count();
+    if openings == 0 && is_open.iter().all(|open| *open) {
+        // nothing but floor around it, so it's open on every side
+        return 4;
+    }
+    openings
+}
+
 pub fn xytoi(x: u32, y: u32, layer: &Layer) -> usize {
     (y * layer.width + x) as usize
 }
@@ -1129,6 +1681,17 @@ fn _itoxy(i: usize, layer: &Layer) -> UVec2 {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{
+        constants::{GROUND_01_TILE_ID, WALL_01_TILE_ID},
+        map::rules::RewriteRule,
+    };
+
+    fn wall_rule(name: &str) -> Option<&'static RewriteRule> {
+        WALL_RULES
+            .iter()
+            .flat_map(|pass| pass.rules)
+            .find(|rule| rule.name == name)
+    }
 
     #[test]
     fn test_mapgen_corner_tile_horizontal_performs_rewrite() {
@@ -1151,10 +1714,13 @@ mod tests {
 
         // create mapgen
         let mapgen = MapGenerator::new(uvec2(width, height));
+        let rule = wall_rule("double_corner_horizontal")
+            .unwrap()
+            .resolve(&mapgen.theme);
 
         // check that the rewrite does not trigger on the base case
-        let did_rewrite = mapgen.try_rewrite_double_corner_horizontal(0, 0, &mut layer);
-        assert_eq!(did_rewrite, false);
+        let did_rewrite = rule.try_apply(0, 0, &mut layer, &mapgen.theme);
+        assert!(!did_rewrite);
         for i in 0..(width * height) {
             if let &Some(tile) = &layer.data[i as usize].as_ref() {
                 assert_eq!(tile.id, WALL_01_TILE_ID);
@@ -1178,8 +1744,8 @@ mod tests {
         });
 
         // check that double corner variant 1 rewrites correctly
-        let did_rewrite = mapgen.try_rewrite_double_corner_horizontal(0, 0, &mut layer);
-        assert_eq!(did_rewrite, true);
+        let did_rewrite = rule.try_apply(0, 0, &mut layer, &mapgen.theme);
+        assert!(did_rewrite);
         for i in 0..(width * height) {
             if let &Some(tile) = &layer.data[i as usize].as_ref() {
                 assert_eq!(tile.id, WALL_01_TILE_ID);
@@ -1203,8 +1769,8 @@ mod tests {
         });
 
         // check that double corner variant 2 rewrites correctly
-        let did_rewrite = mapgen.try_rewrite_double_corner_horizontal(0, 0, &mut layer);
-        assert_eq!(did_rewrite, true);
+        let did_rewrite = rule.try_apply(0, 0, &mut layer, &mapgen.theme);
+        assert!(did_rewrite);
         for i in 0..(width * height) {
             if let &Some(tile) = &layer.data[i as usize].as_ref() {
                 assert_eq!(tile.id, WALL_01_TILE_ID);
@@ -1235,10 +1801,13 @@ mod tests {
 
         // create mapgen
         let mapgen = MapGenerator::new(uvec2(width, height));
+        let rule = wall_rule("double_corner_vertical")
+            .unwrap()
+            .resolve(&mapgen.theme);
 
         // check that the rewrite does not trigger on the base case
-        let did_rewrite = mapgen.try_rewrite_double_corner_vertical(0, 0, &mut layer);
-        assert_eq!(did_rewrite, false);
+        let did_rewrite = rule.try_apply(0, 0, &mut layer, &mapgen.theme);
+        assert!(!did_rewrite);
         for i in 0..(width * height) {
             if let &Some(tile) = &layer.data[i as usize].as_ref() {
                 assert_eq!(tile.id, WALL_01_TILE_ID);
@@ -1262,8 +1831,8 @@ mod tests {
         });
 
         // check that double corner variant 1 rewrites correctly
-        let did_rewrite = mapgen.try_rewrite_double_corner_vertical(0, 0, &mut layer);
-        assert_eq!(did_rewrite, true);
+        let did_rewrite = rule.try_apply(0, 0, &mut layer, &mapgen.theme);
+        assert!(did_rewrite);
         for i in 0..(width * height) {
             if let &Some(tile) = &layer.data[i as usize].as_ref() {
                 assert_eq!(tile.id, WALL_01_TILE_ID);
@@ -1287,8 +1856,8 @@ mod tests {
         });
 
         // check that double corner variant 2 rewrites correctly
-        let did_rewrite = mapgen.try_rewrite_double_corner_vertical(0, 0, &mut layer);
-        assert_eq!(did_rewrite, true);
+        let did_rewrite = rule.try_apply(0, 0, &mut layer, &mapgen.theme);
+        assert!(did_rewrite);
         for i in 0..(width * height) {
             if let &Some(tile) = &layer.data[i as usize].as_ref() {
                 assert_eq!(tile.id, WALL_01_TILE_ID);
@@ -1297,4 +1866,295 @@ mod tests {
             panic!("None tile found");
         }
     }
+
+    #[test]
+    fn test_reachable_tiles_stops_at_blocked_tiles() {
+        let layer = Layer {
+            width: 3,
+            height: 3,
+            data: (0..9).map(|_| None).collect(),
+            ..Default::default()
+        };
+        // a wall down the middle column cuts the left side off from the right
+        #[rustfmt::skip]
+        let blocked = [
+            false, true, false,
+            false, true, false,
+            false, true, false,
+        ];
+        let reached = reachable_tiles(&layer, uvec2(0, 0), &blocked);
+        assert_eq!(
+            reached,
+            [true, false, false, true, false, false, true, false, false]
+        );
+    }
+
+    #[test]
+    fn test_locked_door_keys_are_reachable_with_doors_shut() {
+        let mapgen = MapGenerator::new(uvec2(128, 96));
+        for _ in 0..10 {
+            let result = mapgen.generate_layer().unwrap();
+            let layer = &result.layer;
+            // the gates are already written into the layer as walkable tiles, so block them
+            let mut blocked: Vec<bool> = walkable_mask(layer, &mapgen.theme)
+                .iter()
+                .map(|w| !w)
+                .collect();
+            for (pos, width) in &result.locked_doors {
+                for x in pos.x..(pos.x + width) {
+                    blocked[xytoi(x, pos.y, layer)] = true;
+                }
+            }
+            let reached = reachable_tiles(layer, result.rooms[0].center().as_uvec2(), &blocked);
+            let keys: Vec<UVec2> = result
+                .items
+                .iter()
+                .filter(|(_, kind)| *kind == ItemKind::Key)
+                .map(|(pos, _)| *pos)
+                .collect();
+            assert_eq!(keys.len(), result.locked_doors.len());
+            for key in keys {
+                assert!(reached[xytoi(key.x, key.y, layer)]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_one_way_passages_never_cut_rooms_off() {
+        let mapgen = MapGenerator::new(uvec2(128, 96));
+        for _ in 0..10 {
+            let result = mapgen.generate_layer().unwrap();
+            let layer = &result.layer;
+            // walked the wrong way, a passage is as good as a wall
+            let mut blocked: Vec<bool> = walkable_mask(layer, &mapgen.theme)
+                .iter()
+                .map(|w| !w)
+                .collect();
+            for (pos, width, _) in &result.one_way_passages {
+                for x in pos.x..(pos.x + width) {
+                    blocked[xytoi(x, pos.y, layer)] = true;
+                }
+            }
+            let reached = reachable_tiles(layer, result.rooms[0].center().as_uvec2(), &blocked);
+            for room in &result.rooms {
+                let center = room.center().as_uvec2();
+                assert!(reached[xytoi(center.x, center.y, layer)]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_connect_rooms_links_isolated_rooms() {
+        let mapgen = MapGenerator::new(uvec2(40, 40));
+        let mut layer = Layer {
+            width: 40,
+            height: 40,
+            data: (0..1600)
+                .map(|_| {
+                    Some(Tile {
+                        id: WALL_01_TILE_ID,
+                        tileset: "".into(),
+                        attrs: "".into(),
+                    })
+                })
+                .collect(),
+            ..Default::default()
+        };
+        let rooms = [Rect::new(2., 2., 10., 10.), Rect::new(25., 25., 10., 10.)];
+        for room in &rooms {
+            mapgen.generate_room(
+                &mut layer,
+                uvec2(room.x as u32, room.y as u32),
+                uvec2(room.w as u32, room.h as u32),
+            );
+        }
+
+        mapgen.connect_rooms(&rooms, &mut layer);
+
+        let blocked: Vec<bool> = layer
+            .data
+            .iter()
+            .map(|tile| tile.as_ref().unwrap().id != GROUND_01_TILE_ID)
+            .collect();
+        let reached = reachable_tiles(&layer, rooms[0].center().as_uvec2(), &blocked);
+        let target = rooms[1].center().as_uvec2();
+        assert!(reached[xytoi(target.x, target.y, &layer)]);
+    }
+
+    #[test]
+    fn test_trim_dead_ends_closes_spurs_but_keeps_corridors() {
+        let mapgen = MapGenerator::new(uvec2(30, 20));
+        let mut layer = Layer {
+            width: 30,
+            height: 20,
+            data: (0..600)
+                .map(|_| {
+                    Some(Tile {
+                        id: WALL_01_TILE_ID,
+                        tileset: "".into(),
+                        attrs: "".into(),
+                    })
+                })
+                .collect(),
+            ..Default::default()
+        };
+        let rooms = [Rect::new(2., 2., 5., 5.), Rect::new(20., 2., 5., 5.)];
+        for room in &rooms {
+            mapgen.generate_room(
+                &mut layer,
+                uvec2(room.x as u32, room.y as u32),
+                uvec2(room.w as u32, room.h as u32),
+            );
+        }
+        // a corridor between the rooms, with a spur hanging off it
+        mapgen.generate_corridor_horizontal(&mut layer, 4, 22, 4, None);
+        mapgen.generate_room(&mut layer, uvec2(12, 5), uvec2(1, 10));
+
+        mapgen.trim_dead_ends(&rooms, &mut layer);
+
+        let is_ground = |x: u32, y: u32| {
+            layer.data[xytoi(x, y, &layer)].as_ref().unwrap().id == GROUND_01_TILE_ID
+        };
+        assert!((7..20).all(|x| is_ground(x, 4)));
+        assert!((5..15).all(|y| !is_ground(12, y)));
+    }
+
+    #[test]
+    fn test_room_openings_finds_dead_ends() {
+        let mapgen = MapGenerator::new(uvec2(30, 20));
+        let mut layer = Layer {
+            width: 30,
+            height: 20,
+            data: (0..600)
+                .map(|_| {
+                    Some(Tile {
+                        id: WALL_01_TILE_ID,
+                        tileset: "".into(),
+                        attrs: "".into(),
+                    })
+                })
+                .collect(),
+            ..Default::default()
+        };
+        let rooms = [Rect::new(2., 2., 6., 6.), Rect::new(20., 2., 6., 6.)];
+        for room in &rooms {
+            mapgen.generate_room(
+                &mut layer,
+                uvec2(room.x as u32, room.y as u32),
+                uvec2(room.w as u32, room.h as u32),
+            );
+        }
+        mapgen.generate_corridor_horizontal(&mut layer, 5, 23, 5, Some(1));
+        // a second way out of the first room, down to nowhere in particular
+        mapgen.generate_corridor_vertical(&mut layer, 4, 5, 15, Some(1));
+
+        let walkable = walkable_mask(&layer, &mapgen.theme);
+        assert_eq!(room_openings(&rooms[0], &walkable, &layer), 2);
+        assert_eq!(room_openings(&rooms[1], &walkable, &layer), 1);
+    }
+
+    #[test]
+    fn test_generated_maps_are_connected() {
+        let mapgen = MapGenerator::new(uvec2(128, 96));
+        for _ in 0..10 {
+            let result = mapgen.generate_layer().unwrap();
+            assert!(mapgen.is_connected(&result));
+        }
+    }
+
+    #[test]
+    fn test_reinforced_door_is_kept_apart_from_guard_doors() {
+        let mut mapgen = MapGenerator::new(uvec2(128, 96));
+        mapgen.reinforced_door = true;
+        let result = mapgen.generate_layer().unwrap();
+        let door = result.reinforced_door.unwrap();
+        assert!(!result.guard_doors.contains(&door));
+        assert_ne!(result.exit_door, door);
+        let tile = result.layer.data[xytoi(door.x + 1, door.y, &result.layer)].as_ref();
+        assert_eq!(tile.unwrap().id, mapgen.theme.doors.reinforced_left);
+    }
+
+    #[test]
+    fn test_traps_stay_clear_of_items_crates_and_cycling_doors() {
+        let mapgen = MapGenerator::new(uvec2(128, 96));
+        for _ in 0..5 {
+            let result = mapgen.generate_layer().unwrap();
+            for trap in &result.spike_traps {
+                assert!(!result.items.iter().any(|(item, _)| item == trap));
+                assert!(!result.crates.contains(trap));
+            }
+            for (_, door) in result.pressure_plates.iter().chain(&result.levers) {
+                assert!(result.guard_doors.contains(door));
+                assert!(!result.cycling_doors.contains(door));
+            }
+        }
+    }
+
+    #[test]
+    fn test_barrels_stand_against_walls_clear_of_other_props() {
+        let mapgen = MapGenerator::new(uvec2(128, 96));
+        for _ in 0..5 {
+            let result = mapgen.generate_layer().unwrap();
+            let facades = &mapgen.theme.facades;
+            for barrel in &result.barrels {
+                let wall = result.layer.data[xytoi(barrel.x, barrel.y - 1, &result.layer)].as_ref();
+                assert!([facades.center, facades.center_filler, facades.torch]
+                    .contains(&wall.unwrap().id));
+                assert!(!result.crates.contains(barrel));
+                assert!(!result.chests.iter().any(|(chest, _)| chest == barrel));
+            }
+        }
+    }
+
+    #[test]
+    fn test_bsp_maps_are_connected() {
+        let mut mapgen = MapGenerator::new(uvec2(128, 96));
+        mapgen.algorithm = MapGenAlgorithm::Bsp;
+        for _ in 0..10 {
+            let result = mapgen.generate_layer().unwrap();
+            assert!(result.rooms.len() > 1);
+            assert!(mapgen.is_connected(&result));
+            for (i, a) in result.rooms.iter().enumerate() {
+                assert!(result.rooms[i + 1..].iter().all(|b| !a.overlaps(b)));
+            }
+        }
+    }
+
+    #[test]
+    fn test_overhead_layer_holds_south_wall_tops_from_terrain() {
+        let mapgen = MapGenerator::new(uvec2(128, 96));
+        let result = mapgen.generate_layer().unwrap();
+        let mut count = 0;
+        for (overhead, terrain) in result.overhead.data.iter().zip(&result.layer.data) {
+            if let Some(tile) = overhead {
+                assert!(mapgen.theme.is_overhead(tile.id));
+                assert_eq!(terrain.as_ref().map(|t| t.id), Some(tile.id));
+                count += 1;
+            }
+        }
+        assert!(count > 0);
+    }
+
+    #[test]
+    fn test_grid_fallback_lays_out_and_impossible_maps_fail() {
+        let mapgen = MapGenerator::new(uvec2(128, 96));
+        let result = mapgen.try_generate_layer(MapGenAlgorithm::Grid).unwrap();
+        assert!(result.rooms.len() as u32 >= mapgen.min_room_count);
+        assert!(mapgen.is_connected(&result));
+
+        let mut mapgen = MapGenerator::new(uvec2(128, 96));
+        mapgen.min_room_count = u32::MAX;
+        assert!(mapgen.generate_layer().is_err());
+    }
+
+    #[test]
+    fn test_cave_maps_are_connected() {
+        let mut mapgen = MapGenerator::new(uvec2(128, 96));
+        mapgen.algorithm = MapGenAlgorithm::Cave;
+        for _ in 0..10 {
+            let result = mapgen.generate_layer().unwrap();
+            assert!(result.rooms.len() > 1);
+            assert!(mapgen.is_connected(&result));
+        }
+    }
 }
@@ -0,0 +1,165 @@
+use anyhow::{anyhow, Result};
+use macroquad::{
+    file::load_string,
+    math::{uvec2, UVec2},
+    texture::Texture2D,
+};
+use macroquad_tiled::{load_map, Layer, Map as TileMap};
+
+use crate::constants::{
+    PREFAB_ANCHOR_PROPERTY, PREFAB_PROPS_LAYER_ID, PREFAB_ROTATABLE_PROPERTY,
+    PREFAB_TERRAIN_LAYER_ID, TILESET_MAP_PATH,
+};
+
+/// A hand-made room fragment that map generation can stamp into a generated room.
+///
+/// Prefabs are authored in Tiled as small maps with a `terrain` tile layer that overwrites the
+/// generated terrain, and an optional `props` tile layer drawn on top of it. Empty cells are left
+/// alone. Metadata lives in map properties, which must be string typed because macroquad-tiled
+/// reads every property value as a string:
+/// - `anchor`: "x,y" of the tile lined up with the room's center, where its guard spawns.
+///   Defaults to the middle of the prefab.
+/// - `rotatable`: "true" lets generation turn the prefab in quarter turns.
+#[derive(Clone, Debug)]
+pub struct Prefab {
+    pub name: String,
+    pub size: UVec2,
+    /// row-major tile IDs. empty cells leave the generated tile alone.
+    pub terrain: Vec<Option<u32>>,
+    pub props: Vec<Option<u32>>,
+    pub anchor: UVec2,
+    pub rotatable: bool,
+}
+
+impl Prefab {
+    pub async fn load(path: &str, tileset_texture: &Texture2D) -> Result<Self> {
+        let json = load_string(path).await?;
+        let tile_map = load_map(&json, &[(TILESET_MAP_PATH, tileset_texture.clone())], &[])?;
+        Self::from_tile_map(path, &tile_map)
+    }
+
+    pub fn from_tile_map(name: &str, tile_map: &TileMap) -> Result<Self> {
+        let terrain = tile_map
+            .layers
+            .get(PREFAB_TERRAIN_LAYER_ID)
+            .ok_or_else(|| anyhow!("prefab {} has no {} layer", name, PREFAB_TERRAIN_LAYER_ID))?;
+        let size = uvec2(terrain.width, terrain.height);
+        let props = match tile_map.layers.get(PREFAB_PROPS_LAYER_ID) {
+            Some(props) => layer_tile_ids(props),
+            None => vec![None; terrain.data.len()],
+        };
+        let property = |name: &str| {
+            tile_map
+                .raw_tiled_map
+                .properties
+                .iter()
+                .find(|property| property.name == name)
+                .map(|property| property.value.as_str())
+        };
+        let anchor = match property(PREFAB_ANCHOR_PROPERTY) {
+            Some(anchor) => parse_anchor(anchor)
+                .ok_or_else(|| anyhow!("prefab {} has a bad anchor: {:?}", name, anchor))?,
+            None => size / 2,
+        };
+        if anchor.x >= size.x || anchor.y >= size.y {
+            return Err(anyhow!("prefab {} has its anchor outside of it", name));
+        }
+        let rotatable = property(PREFAB_ROTATABLE_PROPERTY) == Some("true");
+
+        Ok(Self {
+            name: name.into(),
+            size,
+            terrain: layer_tile_ids(terrain),
+            props,
+            anchor,
+            rotatable,
+        })
+    }
+
+    /// Copy of the prefab turned clockwise by some number of quarter turns
+    pub fn rotated(&self, quarter_turns: u32) -> Self {
+        let mut prefab = self.clone();
+        for _ in 0..quarter_turns % 4 {
+            prefab = prefab.rotated_once();
+        }
+        prefab
+    }
+
+    fn rotated_once(&self) -> Self {
+        let (width, height) = (self.size.x, self.size.y);
+        // (x, y) lands at (height - 1 - y, x) in a grid that is `height` tiles wide
+        let rotate = |tiles: &[Option<u32>]| {
+            let mut out = vec![None; tiles.len()];
+            for y in 0..height {
+                for x in 0..width {
+                    let (nx, ny) = (height - 1 - y, x);
+                    out[(ny * height + nx) as usize] = tiles[(y * width + x) as usize];
+                }
+            }
+            out
+        };
+        Self {
+            name: self.name.clone(),
+            size: uvec2(height, width),
+            terrain: rotate(&self.terrain),
+            props: rotate(&self.props),
+            anchor: uvec2(height - 1 - self.anchor.y, self.anchor.x),
+            rotatable: self.rotatable,
+        }
+    }
+}
+
+/// Parse an "x,y" tile coordinate
+fn parse_anchor(anchor: &str) -> Option<UVec2> {
+    let (x, y) = anchor.split_once(',')?;
+    Some(uvec2(x.trim().parse().ok()?, y.trim().parse().ok()?))
+}
+
+fn layer_tile_ids(layer: &Layer) -> Vec<Option<u32>> {
+    layer
+        .data
+        .iter()
+        .map(|tile| tile.as_ref().map(|tile| tile.id))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prefab_rotation_moves_tiles_and_anchor() {
+        // 3x2 prefab:
+        // 1 2 3
+        // 4 5 6
+        let prefab = Prefab {
+            name: "test".into(),
+            size: uvec2(3, 2),
+            terrain: (1..=6).map(Some).collect(),
+            props: vec![None; 6],
+            anchor: uvec2(2, 0),
+            rotatable: true,
+        };
+
+        // turned clockwise:
+        // 4 1
+        // 5 2
+        // 6 3
+        let rotated = prefab.rotated(1);
+        assert_eq!(rotated.size, uvec2(2, 3));
+        assert_eq!(rotated.terrain, [4, 1, 5, 2, 6, 3].map(Some).to_vec());
+        assert_eq!(rotated.anchor, uvec2(1, 2));
+
+        let round_trip = prefab.rotated(4);
+        assert_eq!(round_trip.terrain, prefab.terrain);
+        assert_eq!(round_trip.anchor, prefab.anchor);
+    }
+
+    #[test]
+    fn test_parse_anchor() {
+        assert_eq!(parse_anchor("3,4"), Some(uvec2(3, 4)));
+        assert_eq!(parse_anchor(" 3, 4 "), Some(uvec2(3, 4)));
+        assert_eq!(parse_anchor("3"), None);
+        assert_eq!(parse_anchor("-1,2"), None);
+    }
+}
@@ -0,0 +1,192 @@
+use std::collections::VecDeque;
+
+use macroquad::{
+    math::{uvec2, vec2, Rect, UVec2, Vec2},
+    rand::ChooseRandom,
+};
+use macroquad_tiled::Layer;
+
+use crate::constants::{EMPTY_ROOM_SHARE, GUARD_POST_LARGE_AREA, GUARD_SPAWN_SPACING};
+
+use super::{
+    mapgen::{room_openings, walkable_mask, xytoi},
+    theme::TileTheme,
+};
+
+/// What a room is for, which decides how much gets put in it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RoomTag {
+    /// where the player starts. left bare, so the first few steps are safe.
+    Start,
+    /// the room furthest from the start, which the exit opens onto
+    ExitAdjacent,
+    GuardPost,
+    /// a far-off dead end, worth the detour
+    Treasure,
+    Empty,
+}
+
+/// How much of each kind of content a room can hold
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ContentBudget {
+    pub guards: u32,
+    /// chances at an item, each rolled against the item spawn probability
+    pub items: u32,
+    /// weight of the room when scattering spike traps
+    pub traps: u32,
+}
+
+impl RoomTag {
+    pub fn budget(&self, room: &Rect) -> ContentBudget {
+        match self {
+            RoomTag::Start | RoomTag::Empty => ContentBudget::default(),
+            RoomTag::ExitAdjacent => ContentBudget {
+                guards: 2,
+                items: 0,
+                traps: 1,
+            },
+            RoomTag::GuardPost => ContentBudget {
+                guards: if room.w * room.h >= GUARD_POST_LARGE_AREA {
+                    2
+                } else {
+                    1
+                },
+                items: 1,
+                traps: 1,
+            },
+            RoomTag::Treasure => ContentBudget {
+                guards: 0,
+                items: 2,
+                traps: 2,
+            },
+        }
+    }
+}
+
+/// Tag the rooms of a laid out map. The first room is the start, the room the longest walk from it
+/// leads to the exit, up to `treasure_count` dead ends in the far half of the map hold treasure,
+/// and the smallest of the rest are left empty. Everything else is a guard post.
+pub fn tag_rooms(
+    rooms: &[Rect],
+    layer: &Layer,
+    theme: &TileTheme,
+    treasure_count: u32,
+) -> Vec<RoomTag> {
+    let mut tags = vec![RoomTag::GuardPost; rooms.len()];
+    let Some(start) = rooms.first() else {
+        return tags;
+    };
+    tags[0] = RoomTag::Start;
+
+    let walkable = walkable_mask(layer, theme);
+    let blocked: Vec<bool> = walkable.iter().map(|w| !w).collect();
+    let distances = tile_distances(layer, start.center().as_uvec2(), &blocked);
+    let distance = |room: &Rect| {
+        let center = room.center().as_uvec2();
+        distances[xytoi(center.x, center.y, layer)].unwrap_or(0)
+    };
+
+    let Some(exit) = (1..rooms.len()).max_by_key(|&i| distance(&rooms[i])) else {
+        return tags;
+    };
+    tags[exit] = RoomTag::ExitAdjacent;
+
+    let mut far: Vec<u32> = (1..rooms.len()).map(|i| distance(&rooms[i])).collect();
+    far.sort_unstable();
+    let median = far[far.len() / 2];
+    let mut dead_ends: Vec<usize> = (1..rooms.len())
+        .filter(|&i| tags[i] == RoomTag::GuardPost)
+        .filter(|&i| distance(&rooms[i]) >= median)
+        .filter(|&i| room_openings(&rooms[i], &walkable, layer) == 1)
+        .collect();
+    dead_ends.sort_by_key(|&i| std::cmp::Reverse(distance(&rooms[i])));
+    for i in dead_ends.into_iter().take(treasure_count as usize) {
+        tags[i] = RoomTag::Treasure;
+    }
+
+    // shuffled first, so rooms of the same size take turns being left empty
+    let mut rest: Vec<usize> = (1..rooms.len())
+        .filter(|&i| tags[i] == RoomTag::GuardPost)
+        .collect();
+    rest.shuffle();
+    rest.sort_by(|&a, &b| {
+        let (a, b) = (&rooms[a], &rooms[b]);
+        (a.w * a.h).total_cmp(&(b.w * b.h))
+    });
+    let empty = (rest.len() as f32 * EMPTY_ROOM_SHARE) as usize;
+    for i in rest.into_iter().take(empty) {
+        tags[i] = RoomTag::Empty;
+    }
+
+    tags
+}
+
+/// Where the guards of each room spawn, spread out in a row across its center. Guards in the room
+/// by the exit come last, so the boss, on floors with one, guards the way out.
+pub fn guard_spawns(rooms: &[Rect], tags: &[RoomTag]) -> Vec<Vec2> {
+    let mut order: Vec<usize> = (0..rooms.len()).collect();
+    order.sort_by_key(|&i| tags[i] == RoomTag::ExitAdjacent);
+    order
+        .into_iter()
+        .flat_map(|i| {
+            let center = rooms[i].center();
+            let count = tags[i].budget(&rooms[i]).guards;
+            (0..count).map(move |k| {
+                let offset = k as f32 - (count - 1) as f32 / 2.;
+                center + vec2(offset * GUARD_SPAWN_SPACING, 0.)
+            })
+        })
+        .collect()
+}
+
+/// Breadth first walk out from `start` through 4-connected tiles that aren't blocked. Returns the
+/// number of steps to each tile, in row-major order, or `None` for tiles it couldn't reach.
+pub fn tile_distances(layer: &Layer, start: UVec2, blocked: &[bool]) -> Vec<Option<u32>> {
+    let mut distances = vec![None; layer.data.len()];
+    if start.x >= layer.width || start.y >= layer.height || blocked[xytoi(start.x, start.y, layer)]
+    {
+        return distances;
+    }
+    let mut queue = VecDeque::from([start]);
+    distances[xytoi(start.x, start.y, layer)] = Some(0);
+    while let Some(pos) = queue.pop_front() {
+        let steps = distances[xytoi(pos.x, pos.y, layer)].unwrap_or(0) + 1;
+        let neighbors = [
+            (pos.x > 0).then(|| uvec2(pos.x - 1, pos.y)),
+            (pos.x + 1 < layer.width).then(|| uvec2(pos.x + 1, pos.y)),
+            (pos.y > 0).then(|| uvec2(pos.x, pos.y - 1)),
+            (pos.y + 1 < layer.height).then(|| uvec2(pos.x, pos.y + 1)),
+        ];
+        for next in neighbors.into_iter().flatten() {
+            let i = xytoi(next.x, next.y, layer);
+            if distances[i].is_none() && !blocked[i] {
+                distances[i] = Some(steps);
+                queue.push_back(next);
+            }
+        }
+    }
+    distances
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::mapgen::MapGenerator;
+
+    #[test]
+    fn test_tag_rooms_picks_one_start_and_one_exit() {
+        let mapgen = MapGenerator::new(uvec2(128, 96));
+        for _ in 0..10 {
+            let result = mapgen.generate_layer().unwrap();
+            let tags = tag_rooms(&result.rooms, &result.layer, &mapgen.theme, 2);
+            let count = |tag: RoomTag| tags.iter().filter(|t| **t == tag).count();
+            assert_eq!(tags[0], RoomTag::Start);
+            assert_eq!(count(RoomTag::Start), 1);
+            assert_eq!(count(RoomTag::ExitAdjacent), 1);
+            assert!(count(RoomTag::Treasure) <= 2);
+
+            let spawns = guard_spawns(&result.rooms, &tags);
+            assert!(!spawns.iter().any(|spawn| result.rooms[0].contains(*spawn)));
+        }
+    }
+}
@@ -0,0 +1,390 @@
+use macroquad_tiled::{Layer, Tile};
+
+use super::{mapgen::xytoi, theme::TileTheme};
+
+/// A tile rewrite, tried at every spot on the map with the top left of its pattern there.
+///
+/// Patterns and replacements are rows of space separated tile names, looked up in the theme:
+/// - `.` matches anything, and as a replacement leaves the tile alone
+/// - `g` is bare ground and `#` is bare wall. `W` matches any wall tile, detailed or not.
+/// - `up`, `down`, `left` and `right` are wall edges
+/// - `iul`, `iur`, `idl` and `idr` are inner wall corners, and `oul`, `our`, `odl` and `odr` outer
+/// - `fc`, `fl` and `fr` are the center, left and right wall facades
+pub struct RewriteRule {
+    pub name: &'static str,
+    /// the rule fires where any one of these matches
+    pub patterns: &'static [&'static [&'static str]],
+    pub replace: &'static [&'static str],
+}
+
+/// Rules run together in one sweep over the map
+pub struct RulePass {
+    /// sweep again after any rule fires, until none do
+    pub repeat: bool,
+    pub rules: &'static [RewriteRule],
+}
+
+/// How generated walls get their detail tiles, in the order the passes run
+pub const WALL_RULES: &[RulePass] = &[
+    // thicken wall patterns that we don't have detail tiles for
+    RulePass {
+        repeat: true,
+        rules: &[
+            RewriteRule {
+                name: "thin_horizontal_wall",
+                patterns: &[&["g", "#", "g"]],
+                replace: &["#", ".", "."],
+            },
+            RewriteRule {
+                name: "thin_vertical_wall",
+                patterns: &[&["g # g"]],
+                replace: &["# . ."],
+            },
+            RewriteRule {
+                name: "double_corner_horizontal",
+                patterns: &[&["# # g", "g # #"], &["g # #", "# # g"]],
+                replace: &["# # #", "# # #"],
+            },
+            RewriteRule {
+                name: "double_corner_vertical",
+                patterns: &[&["# g", "# #", "g #"], &["g #", "# #", "# g"]],
+                replace: &["# #", "# #", "# #"],
+            },
+        ],
+    },
+    RulePass {
+        repeat: false,
+        rules: &[
+            RewriteRule {
+                name: "inner_ul_wall",
+                patterns: &[&["W W", "W g"]],
+                replace: &["iul .", ". ."],
+            },
+            RewriteRule {
+                name: "inner_ur_wall",
+                patterns: &[&["W W", "g W"]],
+                replace: &[". iur", ". ."],
+            },
+            RewriteRule {
+                name: "inner_dl_wall",
+                patterns: &[&["W g", "W W"]],
+                replace: &[". .", "idl ."],
+            },
+            RewriteRule {
+                name: "inner_dr_wall",
+                patterns: &[&["g W", "W W"]],
+                replace: &[". .", ". idr"],
+            },
+            RewriteRule {
+                name: "outer_ul_wall",
+                patterns: &[&["g g", "g W"]],
+                replace: &[". .", ". oul"],
+            },
+            RewriteRule {
+                name: "outer_ur_wall",
+                patterns: &[&["g g", "W g"]],
+                replace: &[". .", "our ."],
+            },
+            RewriteRule {
+                name: "outer_dl_wall",
+                patterns: &[&["g W", "g g"]],
+                replace: &[". odl", ". ."],
+            },
+            RewriteRule {
+                name: "outer_dr_wall",
+                patterns: &[&["W g", "g g"]],
+                replace: &["odr .", ". ."],
+            },
+        ],
+    },
+    RulePass {
+        repeat: false,
+        rules: &[
+            RewriteRule {
+                name: "left_wall",
+                patterns: &[&["# g"]],
+                replace: &["left ."],
+            },
+            RewriteRule {
+                name: "right_wall",
+                patterns: &[&["g #"]],
+                replace: &[". right"],
+            },
+        ],
+    },
+    RulePass {
+        repeat: false,
+        rules: &[
+            RewriteRule {
+                name: "bottom_wall",
+                patterns: &[&["g", "#"]],
+                replace: &[".", "down"],
+            },
+            RewriteRule {
+                name: "top_wall",
+                patterns: &[&["#", "g"]],
+                replace: &["up", "."],
+            },
+        ],
+    },
+    RulePass {
+        repeat: false,
+        rules: &[
+            RewriteRule {
+                name: "center_facade",
+                patterns: &[&["up", "g"]],
+                replace: &[".", "fc"],
+            },
+            RewriteRule {
+                name: "left_facade",
+                patterns: &[&["odl", "g"]],
+                replace: &[".", "fl"],
+            },
+            RewriteRule {
+                name: "right_facade",
+                patterns: &[&["odr", "g"]],
+                replace: &[".", "fr"],
+            },
+        ],
+    },
+];
+
+/// Run rule passes over a whole layer, sweeping each column top to bottom, left to right
+pub fn apply_rule_passes(passes: &[RulePass], layer: &mut Layer, theme: &TileTheme) {
+    for pass in passes {
+        let rules: Vec<ResolvedRule> = pass.rules.iter().map(|rule| rule.resolve(theme)).collect();
+        loop {
+            let mut changed = false;
+            for x in 0..layer.width {
+                for y in 0..layer.height {
+                    for rule in &rules {
+                        changed = rule.try_apply(x, y, layer, theme) || changed;
+                    }
+                }
+            }
+            if !pass.repeat || !changed {
+                break;
+            }
+        }
+    }
+}
+
+/// One tile of a pattern, with its name looked up
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum TileMatch {
+    Any,
+    AnyWall,
+    Id(u32),
+    /// a name the theme doesn't have, which never matches
+    Unknown,
+}
+
+/// A rule with its tile names looked up in a theme, ready to run
+pub struct ResolvedRule {
+    patterns: Vec<Vec<Vec<TileMatch>>>,
+    replace: Vec<Vec<Option<u32>>>,
+}
+
+impl RewriteRule {
+    pub fn resolve(&self, theme: &TileTheme) -> ResolvedRule {
+        let lookup = |name: &str| {
+            let tile = tile_named(name, theme);
+            if tile.is_none() && !matches!(name, "." | "W") {
+                warn!("wall rule {} names an unknown tile: {}", self.name, name);
+            }
+            tile
+        };
+        let patterns = self
+            .patterns
+            .iter()
+            .map(|pattern| {
+                pattern
+                    .iter()
+                    .map(|row| {
+                        row.split_whitespace()
+                            .map(|name| match name {
+                                "." => TileMatch::Any,
+                                "W" => TileMatch::AnyWall,
+                                name => lookup(name).map_or(TileMatch::Unknown, TileMatch::Id),
+                            })
+                            .collect()
+                    })
+                    .collect()
+            })
+            .collect();
+        let replace = self
+            .replace
+            .iter()
+            .map(|row| row.split_whitespace().map(lookup).collect())
+            .collect();
+        ResolvedRule { patterns, replace }
+    }
+}
+
+impl ResolvedRule {
+    /// Rewrite the tiles at `x`, `y` if one of the patterns matches there. Returns whether it did.
+    pub fn try_apply(&self, x: u32, y: u32, layer: &mut Layer, theme: &TileTheme) -> bool {
+        let matches = |pattern: &Vec<Vec<TileMatch>>| {
+            let width = pattern.first().map_or(0, |row| row.len()) as u32;
+            if x + width > layer.width || y + pattern.len() as u32 > layer.height {
+                return false;
+            }
+            pattern.iter().enumerate().all(|(dy, row)| {
+                row.iter().enumerate().all(|(dx, tile_match)| {
+                    let i = xytoi(x + dx as u32, y + dy as u32, layer);
+                    match (tile_match, &layer.data[i]) {
+                        (TileMatch::Any, _) => true,
+                        (_, None) => false,
+                        (TileMatch::AnyWall, Some(tile)) => theme.is_wall(tile.id),
+                        (TileMatch::Id(id), Some(tile)) => tile.id == *id,
+                        (TileMatch::Unknown, Some(_)) => false,
+                    }
+                })
+            })
+        };
+        if !self.patterns.iter().any(matches) {
+            return false;
+        }
+
+        for (dy, row) in self.replace.iter().enumerate() {
+            for (dx, id) in row.iter().enumerate() {
+                let Some(id) = *id else {
+                    continue;
+                };
+                let i = xytoi(x + dx as u32, y + dy as u32, layer);
+                layer.data[i] = Some(Tile {
+                    id,
+                    tileset: theme.tileset.clone(),
+                    attrs: String::new(),
+                });
+            }
+        }
+        true
+    }
+}
+
+/// The theme's tile for a name used in a rule, or `None` for `.` and names it doesn't know
+fn tile_named(name: &str, theme: &TileTheme) -> Option<u32> {
+    let walls = &theme.walls;
+    let facades = &theme.facades;
+    Some(match name {
+        "g" => theme.ground.base,
+        "#" => walls.base,
+        "up" => walls.up,
+        "down" => walls.down,
+        "left" => walls.left,
+        "right" => walls.right,
+        "iul" => walls.inner_ul,
+        "iur" => walls.inner_ur,
+        "idl" => walls.inner_dl,
+        "idr" => walls.inner_dr,
+        "oul" => walls.outer_ul,
+        "our" => walls.outer_ur,
+        "odl" => walls.outer_dl,
+        "odr" => walls.outer_dr,
+        "fc" => facades.center,
+        "fl" => facades.left,
+        "fr" => facades.right,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wall_rules_are_well_formed() {
+        let theme = TileTheme::default();
+        for rule in WALL_RULES.iter().flat_map(|pass| pass.rules) {
+            let shape = |grid: &[&str]| -> Vec<usize> {
+                grid.iter()
+                    .map(|row| row.split_whitespace().count())
+                    .collect()
+            };
+            for pattern in rule.patterns {
+                assert_eq!(shape(pattern), shape(rule.replace), "{}", rule.name);
+                for name in pattern.iter().flat_map(|row| row.split_whitespace()) {
+                    assert!(
+                        matches!(name, "." | "W") || tile_named(name, &theme).is_some(),
+                        "{}: {}",
+                        rule.name,
+                        name
+                    );
+                }
+            }
+            for name in rule.replace.iter().flat_map(|row| row.split_whitespace()) {
+                assert!(
+                    name == "." || tile_named(name, &theme).is_some(),
+                    "{}: {}",
+                    rule.name,
+                    name
+                );
+            }
+        }
+    }
+
+    /// Lay out a layer from rows of `#` for wall and anything else for ground
+    fn layer_from(rows: &[&str], theme: &TileTheme) -> Layer {
+        let mut layer = Layer {
+            width: rows[0].len() as u32,
+            height: rows.len() as u32,
+            ..Default::default()
+        };
+        for row in rows {
+            for c in row.chars() {
+                let id = if c == '#' {
+                    theme.walls.base
+                } else {
+                    theme.ground.base
+                };
+                layer.data.push(Some(Tile {
+                    id,
+                    tileset: theme.tileset.clone(),
+                    attrs: String::new(),
+                }));
+            }
+        }
+        layer
+    }
+
+    #[test]
+    fn test_wall_rules_match_the_old_rewrites() {
+        let theme = TileTheme::default();
+        let mut layer = layer_from(
+            &[
+                "##########",
+                "#gggggggg#",
+                "#gg##gggg#",
+                "#gg##gg#g#",
+                "#ggggggg##",
+                "#g#gggggg#",
+                "#g#ggg##g#",
+                "#gggg##gg#",
+                "##########",
+            ],
+            &theme,
+        );
+        apply_rule_passes(WALL_RULES, &mut layer, &theme);
+
+        // what the hand written try_rewrite_* methods made of the same layer, except that the
+        // thin walls on the right keep getting thickened until none are left
+        let expected: [[u32; 10]; 9] = [
+            [1, 2, 2, 2, 2, 2, 2, 2, 2, 3],
+            [13, 40, 40, 40, 40, 40, 40, 40, 40, 15],
+            [13, 48, 48, 4, 5, 48, 4, 26, 26, 27],
+            [13, 48, 48, 16, 17, 48, 16, 3, 0, 0],
+            [13, 48, 48, 57, 59, 48, 57, 16, 2, 3],
+            [25, 26, 5, 48, 48, 48, 48, 57, 40, 15],
+            [1, 2, 17, 48, 48, 4, 26, 5, 48, 15],
+            [13, 40, 59, 48, 48, 15, 0, 13, 48, 15],
+            [25, 26, 26, 26, 26, 27, 0, 25, 26, 27],
+        ];
+        for (y, row) in expected.iter().enumerate() {
+            for (x, id) in row.iter().enumerate() {
+                let tile = layer.data[xytoi(x as u32, y as u32, &layer)].as_ref();
+                assert_eq!(tile.map(|tile| tile.id), Some(*id), "{}, {}", x, y);
+            }
+        }
+    }
+}
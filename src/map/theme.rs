@@ -0,0 +1,319 @@
+use anyhow::Result;
+use macroquad::file::load_string;
+use nanoserde::DeJson;
+
+use super::animation::{AnimationFrame, TileAnimation};
+use crate::constants::{
+    _MONSTER_PIPE_OPEN_TILE_ID, _POOL_FULL_TILE_ID, DOOR_LEFT_CLOSED_TILE_ID,
+    DOOR_LEFT_OPEN_TILE_ID, DOOR_RIGHT_CLOSED_TILE_ID, DOOR_RIGHT_OPEN_TILE_ID,
+    FACADE_CENTER_02_TILE_ID, FACADE_CENTER_TILE_ID, FACADE_LEFT_TILE_ID, FACADE_RIGHT_TILE_ID,
+    GROUND_01_TILE_ID, GROUND_02_TILE_ID, GROUND_03_TILE_ID, ICE_TILE_ID,
+    LOCKED_DOOR_CENTER_TILE_ID, LOCKED_DOOR_LEFT_TILE_ID, LOCKED_DOOR_RIGHT_TILE_ID,
+    MONSTER_PIPE_CLOSED_TILE_ID, PIPE_FRAME_TIME, POOL_EMPTY_TILE_ID, POOL_FRAME_TIME,
+    PRESSURE_PLATE_DOWN_TILE_ID, PRESSURE_PLATE_UP_TILE_ID, REINFORCED_DOOR_LEFT_TILE_ID,
+    REINFORCED_DOOR_RIGHT_TILE_ID, SPIKES_DOWN_TILE_ID, SPIKES_UP_TILE_ID, STAIRS_LEFT_TILE_ID,
+    STAIRS_RIGHT_TILE_ID, TILESET_MAP_ID, TORCH_FRAME_TIME, TORCH_TILE_ID, WALL_01_TILE_ID,
+    WALL_02_TILE_ID, WALL_03_TILE_ID, WALL_DOWN_TILE_ID, WALL_INNER_DL_ID, WALL_INNER_DR_ID,
+    WALL_INNER_UL_ID, WALL_INNER_UR_ID, WALL_LEFT_TILE_ID, WALL_OUTER_DL_ID, WALL_OUTER_DR_ID,
+    WALL_OUTER_UL_ID, WALL_OUTER_UR_ID, WALL_RIGHT_TILE_ID, WALL_UP_TILE_ID,
+};
+
+/// The tile IDs that map generation and doors paint with.
+///
+/// Themes are loaded from JSON files so that maps can be generated for another tileset, or just
+/// with a different floor and wall palette. The default theme is the tiny dungeon tileset.
+#[derive(Clone, Debug, DeJson)]
+pub struct TileTheme {
+    pub name: String,
+    /// ID that the map uses to refer to the theme's tileset
+    pub tileset: String,
+    /// image for a tileset the tile map doesn't have, cut into tiles the same size as the map's.
+    /// tiles are numbered the same way, so prefabs can be stamped in with either.
+    #[nserde(default_with = "crate::json::none")]
+    pub texture: Option<String>,
+    /// tiles nothing can walk through, which get wall colliders
+    pub solid: Vec<u32>,
+    pub ground: GroundTiles,
+    pub walls: WallTiles,
+    pub facades: FacadeTiles,
+    pub doors: DoorTiles,
+    pub traps: TrapTiles,
+    /// tiles drawn as a loop of frames
+    #[nserde(default)]
+    pub animations: Vec<TileAnimation>,
+}
+
+#[derive(Clone, Debug, DeJson)]
+pub struct GroundTiles {
+    pub base: u32,
+    /// variations sprinkled over the base tile
+    pub fillers: Vec<u32>,
+    /// slippery floor that some rooms are paved with. themes without it have no ice rooms.
+    #[nserde(default_with = "crate::json::none")]
+    pub ice: Option<u32>,
+}
+
+#[derive(Clone, Debug, DeJson)]
+pub struct WallTiles {
+    pub base: u32,
+    /// variations sprinkled over the base tile
+    pub fillers: Vec<u32>,
+    /// edges, named after the side of the wall that faces the floor
+    pub up: u32,
+    pub down: u32,
+    pub left: u32,
+    pub right: u32,
+    pub inner_ul: u32,
+    pub inner_ur: u32,
+    pub inner_dl: u32,
+    pub inner_dr: u32,
+    pub outer_ul: u32,
+    pub outer_ur: u32,
+    pub outer_dl: u32,
+    pub outer_dr: u32,
+}
+
+/// Wall faces seen from the floor below them
+#[derive(Clone, Debug, DeJson)]
+pub struct FacadeTiles {
+    pub center: u32,
+    pub left: u32,
+    pub right: u32,
+    /// variation sprinkled over the center tile
+    pub center_filler: u32,
+    /// center tile with a light on it
+    pub torch: u32,
+}
+
+#[derive(Clone, Debug, DeJson)]
+pub struct DoorTiles {
+    pub left_open: u32,
+    pub right_open: u32,
+    pub left_closed: u32,
+    pub right_closed: u32,
+    /// flanks the exit door
+    pub pipe_closed: u32,
+    pub pipe_open: u32,
+    pub pool_empty: u32,
+    pub pool_full: u32,
+    /// steps up to the exit door
+    pub stairs_left: u32,
+    pub stairs_right: u32,
+    /// gates across corridors
+    pub locked_left: u32,
+    pub locked_center: u32,
+    pub locked_right: u32,
+    /// open cell door that only a boss can be trapped in
+    pub reinforced_left: u32,
+    pub reinforced_right: u32,
+}
+
+#[derive(Clone, Debug, DeJson)]
+pub struct TrapTiles {
+    pub spikes_up: u32,
+    pub spikes_down: u32,
+    pub plate_up: u32,
+    pub plate_down: u32,
+}
+
+impl TileTheme {
+    pub async fn load(path: &str) -> Result<Self> {
+        let json = load_string(path).await?;
+        Self::from_json(&json)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        Ok(DeJson::deserialize_json(json)?)
+    }
+
+    pub fn is_solid(&self, tile_id: u32) -> bool {
+        self.solid.contains(&tile_id)
+    }
+
+    pub fn is_wall(&self, tile_id: u32) -> bool {
+        let walls = &self.walls;
+        tile_id == walls.base
+            || walls.fillers.contains(&tile_id)
+            || [
+                walls.up,
+                walls.down,
+                walls.left,
+                walls.right,
+                walls.inner_ul,
+                walls.inner_ur,
+                walls.inner_dl,
+                walls.inner_dr,
+                walls.outer_ul,
+                walls.outer_ur,
+                walls.outer_dl,
+                walls.outer_dr,
+            ]
+            .contains(&tile_id)
+    }
+
+    /// Whether a tile is the top of a wall along the south side of a floor, which characters
+    /// standing on the floor should be drawn behind
+    pub fn is_overhead(&self, tile_id: u32) -> bool {
+        let walls = &self.walls;
+        [
+            walls.down,
+            walls.inner_dl,
+            walls.inner_dr,
+            walls.outer_ul,
+            walls.outer_ur,
+        ]
+        .contains(&tile_id)
+    }
+}
+
+impl Default for TileTheme {
+    fn default() -> Self {
+        Self {
+            name: "dungeon".into(),
+            tileset: TILESET_MAP_ID.into(),
+            texture: None,
+            solid: vec![
+                WALL_01_TILE_ID,
+                WALL_02_TILE_ID,
+                WALL_03_TILE_ID,
+                WALL_UP_TILE_ID,
+                WALL_DOWN_TILE_ID,
+                WALL_LEFT_TILE_ID,
+                WALL_RIGHT_TILE_ID,
+                WALL_INNER_UL_ID,
+                WALL_INNER_UR_ID,
+                WALL_INNER_DL_ID,
+                WALL_INNER_DR_ID,
+                WALL_OUTER_UL_ID,
+                WALL_OUTER_UR_ID,
+                WALL_OUTER_DL_ID,
+                WALL_OUTER_DR_ID,
+                MONSTER_PIPE_CLOSED_TILE_ID,
+                _MONSTER_PIPE_OPEN_TILE_ID,
+            ],
+            ground: GroundTiles {
+                base: GROUND_01_TILE_ID,
+                fillers: vec![GROUND_02_TILE_ID, GROUND_03_TILE_ID],
+                ice: Some(ICE_TILE_ID),
+            },
+            walls: WallTiles {
+                base: WALL_01_TILE_ID,
+                fillers: vec![WALL_02_TILE_ID, WALL_03_TILE_ID],
+                up: WALL_UP_TILE_ID,
+                down: WALL_DOWN_TILE_ID,
+                left: WALL_LEFT_TILE_ID,
+                right: WALL_RIGHT_TILE_ID,
+                inner_ul: WALL_INNER_UL_ID,
+                inner_ur: WALL_INNER_UR_ID,
+                inner_dl: WALL_INNER_DL_ID,
+                inner_dr: WALL_INNER_DR_ID,
+                outer_ul: WALL_OUTER_UL_ID,
+                outer_ur: WALL_OUTER_UR_ID,
+                outer_dl: WALL_OUTER_DL_ID,
+                outer_dr: WALL_OUTER_DR_ID,
+            },
+            facades: FacadeTiles {
+                center: FACADE_CENTER_TILE_ID,
+                left: FACADE_LEFT_TILE_ID,
+                right: FACADE_RIGHT_TILE_ID,
+                center_filler: FACADE_CENTER_02_TILE_ID,
+                torch: TORCH_TILE_ID,
+            },
+            doors: DoorTiles {
+                left_open: DOOR_LEFT_OPEN_TILE_ID,
+                right_open: DOOR_RIGHT_OPEN_TILE_ID,
+                left_closed: DOOR_LEFT_CLOSED_TILE_ID,
+                right_closed: DOOR_RIGHT_CLOSED_TILE_ID,
+                pipe_closed: MONSTER_PIPE_CLOSED_TILE_ID,
+                pipe_open: _MONSTER_PIPE_OPEN_TILE_ID,
+                pool_empty: POOL_EMPTY_TILE_ID,
+                pool_full: _POOL_FULL_TILE_ID,
+                stairs_left: STAIRS_LEFT_TILE_ID,
+                stairs_right: STAIRS_RIGHT_TILE_ID,
+                locked_left: LOCKED_DOOR_LEFT_TILE_ID,
+                locked_center: LOCKED_DOOR_CENTER_TILE_ID,
+                locked_right: LOCKED_DOOR_RIGHT_TILE_ID,
+                reinforced_left: REINFORCED_DOOR_LEFT_TILE_ID,
+                reinforced_right: REINFORCED_DOOR_RIGHT_TILE_ID,
+            },
+            traps: TrapTiles {
+                spikes_up: SPIKES_UP_TILE_ID,
+                spikes_down: SPIKES_DOWN_TILE_ID,
+                plate_up: PRESSURE_PLATE_UP_TILE_ID,
+                plate_down: PRESSURE_PLATE_DOWN_TILE_ID,
+            },
+            animations: vec![
+                mirrored_animation(TORCH_TILE_ID, TORCH_FRAME_TIME),
+                mirrored_animation(_POOL_FULL_TILE_ID, POOL_FRAME_TIME),
+                mirrored_animation(_MONSTER_PIPE_OPEN_TILE_ID, PIPE_FRAME_TIME),
+            ],
+        }
+    }
+}
+
+/// The tiny dungeon tileset has no spare frames, so its animated tiles flip back and forth
+fn mirrored_animation(tile: u32, duration: f32) -> TileAnimation {
+    TileAnimation {
+        tile,
+        frames: vec![
+            AnimationFrame {
+                id: tile,
+                flip_x: false,
+                duration,
+            },
+            AnimationFrame {
+                id: tile,
+                flip_x: true,
+                duration,
+            },
+        ],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dungeon_theme_matches_default() {
+        let json = include_str!("../../assets/themes/dungeon.json");
+        let theme = TileTheme::from_json(json).unwrap();
+        let default = TileTheme::default();
+        assert_eq!(theme.tileset, default.tileset);
+        let mut solid = theme.solid.clone();
+        solid.sort();
+        let mut default_solid = default.solid.clone();
+        default_solid.sort();
+        assert_eq!(solid, default_solid);
+        assert_eq!(theme.ground.base, default.ground.base);
+        assert_eq!(theme.ground.ice, default.ground.ice);
+        assert_eq!(theme.walls.fillers, default.walls.fillers);
+        assert_eq!(theme.doors.locked_center, default.doors.locked_center);
+        assert_eq!(theme.facades.torch, default.facades.torch);
+        let animated = |theme: &TileTheme| -> Vec<(u32, usize)> {
+            theme
+                .animations
+                .iter()
+                .map(|animation| (animation.tile, animation.frames.len()))
+                .collect()
+        };
+        assert_eq!(animated(&theme), animated(&default));
+        assert!(theme.is_wall(WALL_OUTER_DR_ID));
+        assert!(!theme.is_wall(GROUND_01_TILE_ID));
+        assert!(theme.is_solid(MONSTER_PIPE_CLOSED_TILE_ID));
+        assert!(!theme.is_solid(FACADE_CENTER_TILE_ID));
+    }
+
+    #[test]
+    fn test_crypt_theme_paints_with_its_own_tileset() {
+        let json = include_str!("../../assets/themes/crypt.json");
+        let theme = TileTheme::from_json(json).unwrap();
+        let default = TileTheme::default();
+        assert_ne!(theme.tileset, default.tileset);
+        assert!(theme.texture.is_some());
+        // its reinforced door has tiles of its own, past the end of the dungeon tileset
+        assert_ne!(theme.doors.reinforced_left, theme.doors.locked_left);
+        assert!(!theme.is_solid(theme.doors.reinforced_left));
+        assert!(theme.is_solid(theme.walls.base));
+    }
+}
@@ -1,241 +1,973 @@
-use anyhow::Result;
+use anyhow::{Error, Result};
 use macroquad::{
-    audio::{play_sound_once},
-    color::{DARKGRAY, WHITE},
-    math::{vec2, RectOffset},
+    color::{Color, DARKGRAY, WHITE},
+    input::{get_char_pressed, is_key_pressed, KeyCode},
+    math::{vec2, Rect, RectOffset},
+    miniquad::window,
+    shapes::draw_rectangle,
     texture::Image,
+    time::{get_frame_time, get_time},
     ui::{root_ui, Skin},
-    window::{clear_background, next_frame, screen_height, screen_width},
+    window::{clear_background, screen_width},
 };
+use macroquad_tiled::TileSet;
 
-use crate::{audio::Sounds, game::GameState};
+use crate::{
+    audio::{SoundBank, SoundId},
+    constants::{
+        INITIALS_LENGTH, LEADERBOARD_SIZE, MENU_MARGIN, MENU_ROW_HEIGHT, RESULTS_WIDTH,
+        UPGRADE_MAX_LEVEL,
+    },
+    demo::Demo,
+    diorama::Diorama,
+    game::{Game, Round},
+    input::{Binding, KeyBindings},
+    leaderboard::Leaderboard,
+    locale::{self, tr, tr_with},
+    palette::Palette,
+    scene::{Scene, Transition},
+    settings::Settings,
+    stats::RunStats,
+    toast::Toasts,
+    upgrades::{PlayerUpgrades, Upgrade},
+    widgets::{label_left, label_right, Anchor, MenuFocus, VStack},
+};
 
 pub struct MainMenu {
     skin: Skin,
-    next_state: Option<GameState>,
-    sounds: Sounds,
+    transition: Option<Transition<Game>>,
+    focus: MenuFocus,
+    sounds: SoundBank,
+    /// offer to continue a saved run
+    can_continue: bool,
+    text_scale: f32,
+    /// floor panned across in the background. plain gray if it couldn't be generated.
+    diorama: Option<Diorama>,
 }
 
 impl MainMenu {
-    pub fn new(sounds: &Sounds) -> Self {
+    pub fn new(
+        sounds: &SoundBank,
+        can_continue: bool,
+        text_scale: f32,
+        diorama: Option<Diorama>,
+    ) -> Self {
         Self {
-            skin: base_skin(),
-            next_state: None,
+            skin: base_skin(text_scale),
+            transition: None,
+            focus: MenuFocus::default(),
             sounds: sounds.clone(),
+            can_continue,
+            text_scale,
+            diorama,
         }
     }
+}
+
+impl Scene<Game> for MainMenu {
+    fn handle_input(&mut self, _game: &mut Game) {
+        self.focus.handle_input();
+    }
 
-    pub async fn run(&mut self) -> Result<GameState> {
-        loop {
-            if let Some(next_state) = self.next_state {
-                return Ok(next_state);
-            }
-            self.draw();
-            next_frame().await
+    fn update(&mut self, _game: &mut Game) -> Option<Transition<Game>> {
+        if let Some(diorama) = &mut self.diorama {
+            diorama.update(get_frame_time());
         }
+        self.transition.take()
     }
 
-    pub fn draw(&mut self) {
+    fn draw(&mut self, game: &mut Game) {
         clear_background(DARKGRAY);
+        if let Some(diorama) = &self.diorama {
+            diorama.draw();
+        }
         root_ui().push_skin(&self.skin);
+        // the title, a gap, and a row for each button
+        let rows = if self.can_continue { 7 } else { 6 };
+        let mut stack = VStack::panel(720., rows, self.text_scale, Anchor::Center);
         root_ui().window(0, vec2(0., 0.), vec2(300., 300.), |ui| {
-            ui.label(
-                Some(vec2(screen_width() / 2. - 350., screen_height() * 2. / 5.)),
-                "Escape from Stonehold",
-            );
+            stack.label(ui, &tr("main-title"));
+            stack.space();
 
-            if ui.button(
-                vec2(screen_width() / 2. - 64., screen_height() * 3. / 5.),
-                "Play",
-            ) {
-                // TODO(axelmagn): play sound
-                // TODO(axelmagn): transition to instructions
-                self.next_state = Some(GameState::Instructions);
-                play_sound_once(&self.sounds.click);
+            if stack.button(ui, &mut self.focus, &tr("main-play")) {
+                self.transition = Some(Transition::Switch(game.instructions_menu()));
+                self.sounds.play(SoundId::CLICK);
+            };
+
+            if stack.button(ui, &mut self.focus, &tr("main-two-players")) {
+                // a second player shares the keyboard
+                game.local_coop = true;
+                self.transition = Some(Transition::Switch(game.instructions_menu()));
+                self.sounds.play(SoundId::CLICK);
+            };
+
+            if self.can_continue && stack.button(ui, &mut self.focus, &tr("main-continue")) {
+                self.transition = Some(game.continue_run());
+                self.sounds.play(SoundId::CLICK);
+            };
+
+            if stack.button(ui, &mut self.focus, &tr("main-leaderboard")) {
+                self.transition = Some(Transition::Switch(game.leaderboard_menu()));
+                self.sounds.play(SoundId::CLICK);
+            };
+
+            if stack.button(ui, &mut self.focus, &tr("main-settings")) {
+                self.transition = Some(Transition::Push(game.settings_menu()));
+                self.sounds.play(SoundId::CLICK);
             };
         });
     }
 }
 
+/// How to play, with each control named from the key bindings and acted out beside it
 pub struct InstructionsMenu {
     skin: Skin,
-    next_state: Option<GameState>,
-    sounds: Sounds,
+    transition: Option<Transition<Game>>,
+    focus: MenuFocus,
+    sounds: SoundBank,
+    /// also explain the second player's keys
+    two_players: bool,
+    text_scale: f32,
+    /// sprites for the demos. they are left out if the map has no tileset.
+    tileset: Option<TileSet>,
+    /// when the menu opened, so the demos start from the top
+    start_time: f64,
 }
 
 impl InstructionsMenu {
-    pub fn new(sounds: &Sounds) -> Self {
+    pub fn new(
+        sounds: &SoundBank,
+        text_scale: f32,
+        two_players: bool,
+        tileset: Option<TileSet>,
+    ) -> Self {
         Self {
-            skin: Self::make_skin(),
-            next_state: None,
+            skin: label_skin(48, text_scale),
+            transition: None,
+            focus: MenuFocus::default(),
             sounds: sounds.clone(),
+            two_players,
+            text_scale,
+            tileset,
+            start_time: get_time(),
         }
     }
 
-    fn make_skin() -> Skin {
-        let label_style = root_ui()
-            .style_builder()
-            .font(include_bytes!(
-                "../assets/kenney_kenney-fonts/Fonts/Kenney Pixel.ttf"
-            ))
-            .unwrap()
-            .text_color(WHITE)
-            .font_size(48)
-            .build();
-
-        Skin {
-            label_style,
-            ..base_skin()
+    /// Each line of the instructions, and the demo that goes with it
+    fn lines(&self) -> Vec<(String, Option<Demo>)> {
+        let keys = KeyBindings::PLAYER_ONE;
+        let optional_name =
+            |binding: Option<Binding>| binding.map(Binding::name).unwrap_or_default();
+        let mut lines = vec![
+            (tr("instructions-goal"), None),
+            (
+                tr_with(
+                    "instructions-move",
+                    &[
+                        ("move", &keys.movement_name()),
+                        ("sprint", &keys.sprint.name()),
+                        ("sneak", &optional_name(keys.sneak)),
+                    ],
+                ),
+                Some(Demo::Walk),
+            ),
+            (
+                tr_with(
+                    "instructions-attack",
+                    &[
+                        ("attack", &keys.attack.name()),
+                        ("throw", &optional_name(keys.throw)),
+                    ],
+                ),
+                Some(Demo::Shove),
+            ),
+            (
+                tr_with(
+                    "instructions-interact",
+                    &[("interact", &keys.interact.name())],
+                ),
+                Some(Demo::Interact),
+            ),
+            (tr("instructions-guards"), None),
+            (tr("instructions-trap"), Some(Demo::Trap)),
+            (tr("instructions-exit"), None),
+        ];
+        if self.two_players {
+            let keys = KeyBindings::PLAYER_TWO;
+            let line = tr_with(
+                "instructions-partner",
+                &[
+                    ("move", &keys.movement_name()),
+                    ("attack", &keys.attack.name()),
+                    ("interact", &keys.interact.name()),
+                ],
+            );
+            lines.insert(4, (line, None));
         }
+        lines
     }
+}
 
-    pub async fn run(&mut self) -> Result<GameState> {
-        loop {
-            if let Some(next_state) = self.next_state {
-                return Ok(next_state);
-            }
-            self.draw();
-            next_frame().await
+impl Scene<Game> for InstructionsMenu {
+    fn handle_input(&mut self, game: &mut Game) {
+        if self.focus.handle_input() {
+            self.transition = Some(Transition::Switch(game.main_menu()));
+            self.sounds.play(SoundId::CLICK);
         }
     }
 
-    pub fn draw(&mut self) {
+    fn update(&mut self, _game: &mut Game) -> Option<Transition<Game>> {
+        self.transition.take()
+    }
+
+    fn draw(&mut self, _game: &mut Game) {
         clear_background(DARKGRAY);
         root_ui().push_skin(&self.skin);
+        let lines = self.lines();
+        let time = get_time() - self.start_time;
+        let mut text = VStack::panel(1000., lines.len(), self.text_scale, Anchor::Top);
+        let mut buttons = VStack::panel(1000., 1, self.text_scale, Anchor::Bottom);
         root_ui().window(0, vec2(0., 0.), vec2(300., 300.), |ui| {
-            ui.label(
-                Some(vec2(screen_width() / 2. - 350., screen_height() * 1. / 8.)),
-                "Escape your captors!",
-            );
-            ui.label(
-                Some(vec2(screen_width() / 2. - 350., screen_height() * 2. / 8.)),
-                "Move with WASD keys.",
-            );
-            ui.label(
-                Some(vec2(screen_width() / 2. - 350., screen_height() * 3. / 8.)),
-                "Attack with left mouse button.",
-            );
-            ui.label(
-                Some(vec2(screen_width() / 2. - 350., screen_height() * 4. / 8.)),
-                "Their armor is too strong for you to kill them.",
-            );
-            ui.label(
-                Some(vec2(screen_width() / 2. - 350., screen_height() * 5. / 8.)),
-                "Try to trap them in open jail cells.",
-            );
-            ui.label(
-                Some(vec2(screen_width() / 2. - 350., screen_height() * 6. / 8.)),
-                "When you trap enough guards, the exit will open.",
-            );
+            for (line, demo) in &lines {
+                // a column of demos down the left, with the lines lined up beside them
+                let row = text.row();
+                let tile = row.h * 0.8;
+                let demo_area =
+                    Rect::new(row.x, row.y + (row.h - tile) / 2., Demo::WIDTH * tile, tile);
+                if let (Some(demo), Some(tileset)) = (demo, &self.tileset) {
+                    demo.draw(tileset, demo_area, time);
+                }
+                let caption_x = demo_area.right() + tile / 2.;
+                let caption = Rect::new(caption_x, row.y, row.right() - caption_x, row.h);
+                label_left(ui, caption, line);
+            }
 
-            if ui.button(
-                vec2(screen_width() / 2. - 64., screen_height() * 7. / 8.),
-                "Begin",
-            ) {
-                // TODO(axelmagn): play sound
-                // TODO(axelmagn): transition to instructions
-                self.next_state = Some(GameState::InGame);
-                play_sound_once(&self.sounds.click);
+            if buttons.button(ui, &mut self.focus, &tr("instructions-begin")) {
+                self.transition = Some(Transition::Switch(Box::new(Round::new())));
+                self.sounds.play(SoundId::CLICK);
             };
         });
     }
 }
 
+/// How a round went, for the game over screen. Taken when the round ends, since the next floor is
+/// generated before the screen is shown.
+#[derive(Clone, Debug, Default)]
+pub struct GameOverContext {
+    pub message: String,
+    pub won: bool,
+    /// seconds the round took
+    pub run_time: f64,
+    pub best_time: Option<f64>,
+    pub stats: RunStats,
+    /// seed the floor was generated from. None for a continued run, whose map was loaded.
+    pub seed: Option<u64>,
+}
+
+impl GameOverContext {
+    /// What to show in the results panel, as a name and a value for each row
+    fn results(&self) -> Vec<(String, String)> {
+        let stats = &self.stats;
+        let mut results = vec![
+            (tr("game-over-time"), time_str(self.run_time)),
+            (tr("game-over-trapped"), stats.guards_trapped.to_string()),
+            (tr("game-over-damage"), stats.damage_taken.to_string()),
+            (
+                tr("game-over-distance"),
+                tr_with(
+                    "game-over-tiles",
+                    &[("distance", &format!("{:.0}", stats.distance_traveled))],
+                ),
+            ),
+            (tr("game-over-spotted"), stats.times_spotted.to_string()),
+        ];
+        if stats.guards_defeated > 0 {
+            results.push((tr("game-over-defeated"), stats.guards_defeated.to_string()));
+        }
+        if self.won {
+            if let Some(best_time) = self.best_time {
+                results.push((tr("game-over-best-time"), time_str(best_time)));
+            }
+        } else if let Some(cause) = stats.death_cause {
+            results.push((tr("game-over-cause"), tr(cause.name_key())));
+        }
+        if let Some(seed) = self.seed {
+            results.push((tr("game-over-seed"), seed.to_string()));
+        }
+        results
+    }
+}
+
 pub struct GameOverMenu {
-    message: String,
+    context: GameOverContext,
     skin: Skin,
-    next_state: Option<GameState>,
-    sounds: Sounds,
-    show_times: bool,
-    run_time: Option<f64>,
-    best_time: Option<f64>,
+    transition: Option<Transition<Game>>,
+    focus: MenuFocus,
+    sounds: SoundBank,
+    /// popups left over from the round, like achievements unlocked by escaping
+    toasts: Toasts,
+    text_scale: f32,
 }
 
 impl GameOverMenu {
-    pub fn new(
-        message: &str,
-        sounds: &Sounds,
-        show_times: bool,
-        run_time: Option<f64>,
-        best_time: Option<f64>,
-    ) -> Self {
+    pub fn new(context: GameOverContext, sounds: &SoundBank, text_scale: f32) -> Self {
         Self {
-            message: message.into(),
-            skin: Self::make_skin(),
-            next_state: None,
+            context,
+            skin: label_skin(48, text_scale),
+            transition: None,
+            focus: MenuFocus::default(),
             sounds: sounds.clone(),
-            show_times,
-            run_time,
-            best_time,
+            toasts: Toasts::default(),
+            text_scale,
+        }
+    }
+
+    /// Put the seed on the clipboard, to share the floor or report a bug on it
+    fn copy_seed(&mut self, seed: u64) {
+        window::clipboard_set(&seed.to_string());
+        self.toasts.push(tr("game-over-seed-copied"));
+    }
+}
+
+impl Scene<Game> for GameOverMenu {
+    fn open(&mut self, game: &mut Game) {
+        self.toasts = std::mem::take(&mut game.toasts);
+    }
+
+    fn handle_input(&mut self, game: &mut Game) {
+        if self.focus.handle_input() {
+            self.transition = Some(Transition::Switch(game.main_menu()));
+            self.sounds.play(SoundId::CLICK);
         }
     }
 
-    pub async fn run(&mut self) -> Result<GameState> {
-        loop {
-            if let Some(next_state) = self.next_state {
-                return Ok(next_state);
+    fn update(&mut self, _game: &mut Game) -> Option<Transition<Game>> {
+        self.transition.take()
+    }
+
+    fn draw(&mut self, game: &mut Game) {
+        clear_background(DARKGRAY);
+        root_ui().push_skin(&self.skin);
+        let results = self.context.results();
+        let seed = self.context.seed;
+
+        // the message over the results, with the buttons kept along the bottom
+        let mut title = VStack::panel(640., 1, self.text_scale, Anchor::Top);
+        let button_count = if seed.is_some() { 3 } else { 2 };
+        let mut buttons = VStack::panel(640., button_count, self.text_scale, Anchor::Bottom);
+        let room = Rect::new(
+            0.,
+            title.area().bottom(),
+            screen_width(),
+            buttons.area().top() - title.area().bottom(),
+        );
+        let size = vec2(
+            RESULTS_WIDTH * self.text_scale,
+            MENU_ROW_HEIGHT * self.text_scale * results.len() as f32,
+        );
+        let area = Rect::new(0., 0., size.x, size.y.min(room.h));
+        let area = Rect {
+            x: room.x + (room.w - area.w) / 2.,
+            y: room.y + (room.h - area.h) / 2.,
+            ..area
+        };
+        draw_rectangle(
+            area.x - MENU_MARGIN,
+            area.y - MENU_MARGIN,
+            area.w + MENU_MARGIN * 2.,
+            area.h + MENU_MARGIN * 2.,
+            Color::new(0., 0., 0., 0.3),
+        );
+        let mut rows = VStack::new(area, results.len(), self.text_scale);
+
+        root_ui().window(0, vec2(0., 0.), vec2(300., 300.), |ui| {
+            title.label(ui, &self.context.message);
+            for (name, value) in &results {
+                let row = rows.row();
+                label_left(ui, row, name);
+                label_right(ui, row, value);
+            }
+
+            if buttons.button(ui, &mut self.focus, &tr("game-over-play-again")) {
+                self.transition = Some(Transition::Switch(game.next_round()));
+                self.sounds.play(SoundId::CLICK);
+            };
+            if let Some(seed) = seed {
+                if buttons.button(ui, &mut self.focus, &tr("game-over-copy-seed")) {
+                    self.copy_seed(seed);
+                    self.sounds.play(SoundId::CLICK);
+                }
             }
-            self.draw();
-            next_frame().await
+            if buttons.button(ui, &mut self.focus, &tr("game-over-main-menu")) {
+                self.transition = Some(Transition::Switch(game.main_menu()));
+                self.sounds.play(SoundId::CLICK);
+            };
+        });
+
+        self.toasts.update();
+        self.toasts.draw(screen_width(), 48. * self.text_scale);
+    }
+}
+
+/// Spends coins on upgrades between floors
+pub struct ShopMenu {
+    skin: Skin,
+    transition: Option<Transition<Game>>,
+    focus: MenuFocus,
+    sounds: SoundBank,
+    /// coins and upgrades as spent so far, handed back to the game when the shop closes
+    coins: u32,
+    upgrades: PlayerUpgrades,
+    text_scale: f32,
+}
+
+impl ShopMenu {
+    pub fn new(sounds: &SoundBank, coins: u32, upgrades: PlayerUpgrades, text_scale: f32) -> Self {
+        Self {
+            skin: base_skin(text_scale),
+            transition: None,
+            focus: MenuFocus::default(),
+            sounds: sounds.clone(),
+            coins,
+            upgrades,
+            text_scale,
         }
     }
+}
+
+impl Scene<Game> for ShopMenu {
+    fn close(&mut self, game: &mut Game) -> Result<()> {
+        game.coins = self.coins;
+        game.upgrades = self.upgrades;
+        Ok(())
+    }
 
-    fn make_skin() -> Skin {
-        let label_style = root_ui()
-            .style_builder()
-            .font(include_bytes!(
-                "../assets/kenney_kenney-fonts/Fonts/Kenney Pixel.ttf"
-            ))
-            .unwrap()
-            .text_color(WHITE)
-            .font_size(48)
-            .build();
-
-        Skin {
-            label_style,
-            ..base_skin()
+    fn handle_input(&mut self, _game: &mut Game) {
+        if self.focus.handle_input() {
+            self.transition = Some(Transition::Switch(Box::new(Round::new())));
+            self.sounds.play(SoundId::CLICK);
         }
     }
 
-    pub fn draw(&mut self) {
+    fn update(&mut self, _game: &mut Game) -> Option<Transition<Game>> {
+        self.transition.take()
+    }
+
+    fn draw(&mut self, _game: &mut Game) {
         clear_background(DARKGRAY);
         root_ui().push_skin(&self.skin);
+        // the title and coins, then the upgrades and the way out each after a gap
+        let rows = Upgrade::ALL.len() + 5;
+        let mut stack = VStack::panel(640., rows, self.text_scale, Anchor::Center)
+            .with_button_width(560. * self.text_scale);
         root_ui().window(0, vec2(0., 0.), vec2(300., 300.), |ui| {
-            ui.label(
-                Some(vec2(screen_width() / 2. - 96., screen_height() * 1. / 6.)),
-                &self.message,
-            );
+            stack.label(ui, &tr("shop-title"));
+            stack.label(ui, &tr_with("shop-coins", &[("coins", &self.coins)]));
+            stack.space();
 
-            if self.show_times {
-                if let Some(run_time) = self.run_time {
-                    ui.label(
-                        Some(vec2(screen_width() / 2. - 96., screen_height() * 2. / 6.)),
-                        &format!("Run time: {}", time_str(run_time)),
-                    );
-                }
-                if let Some(best_time) = self.best_time {
-                    ui.label(
-                        Some(vec2(screen_width() / 2. - 96., screen_height() * 3. / 6.)),
-                        &format!("Best time: {}", time_str(best_time)),
-                    );
+            for upgrade in Upgrade::ALL {
+                let name = tr(upgrade.name());
+                let level = self.upgrades.level(upgrade);
+                let label = match self.upgrades.cost(upgrade) {
+                    Some(cost) => tr_with(
+                        "shop-item",
+                        &[
+                            ("name", &name),
+                            ("level", &level),
+                            ("max", &UPGRADE_MAX_LEVEL),
+                            ("cost", &cost),
+                        ],
+                    ),
+                    None => tr_with(
+                        "shop-item-maxed",
+                        &[
+                            ("name", &name),
+                            ("level", &level),
+                            ("max", &UPGRADE_MAX_LEVEL),
+                        ],
+                    ),
+                };
+                if stack.button(ui, &mut self.focus, &label) {
+                    if self.upgrades.buy(upgrade, &mut self.coins) {
+                        self.sounds.play(SoundId::COINS);
+                    } else {
+                        self.sounds.play(SoundId::CLICK);
+                    }
                 }
             }
+            stack.space();
 
-            if ui.button(
-                vec2(screen_width() / 2. - 64., screen_height() * 4. / 6.),
-                "Play Again",
-            ) {
-                // TODO(axelmagn): play sound
-                self.next_state = Some(GameState::InGame);
-                play_sound_once(&self.sounds.click);
+            if stack.button(ui, &mut self.focus, &tr("shop-continue")) {
+                self.transition = Some(Transition::Switch(Box::new(Round::new())));
+                self.sounds.play(SoundId::CLICK);
+            };
+        });
+    }
+}
+
+/// Shown over a round when the player presses Escape, which is held until they resume
+pub struct PauseMenu {
+    skin: Skin,
+    transition: Option<Transition<Game>>,
+    focus: MenuFocus,
+    sounds: SoundBank,
+    text_scale: f32,
+}
+
+impl PauseMenu {
+    pub fn new(sounds: &SoundBank, text_scale: f32) -> Self {
+        Self {
+            skin: base_skin(text_scale),
+            transition: None,
+            focus: MenuFocus::default(),
+            sounds: sounds.clone(),
+            text_scale,
+        }
+    }
+}
+
+impl Scene<Game> for PauseMenu {
+    fn handle_input(&mut self, _game: &mut Game) {
+        if self.focus.handle_input() {
+            self.transition = Some(Transition::Pop);
+            self.sounds.play(SoundId::CLICK);
+        }
+    }
+
+    fn update(&mut self, _game: &mut Game) -> Option<Transition<Game>> {
+        self.transition.take()
+    }
+
+    fn draw(&mut self, game: &mut Game) {
+        clear_background(DARKGRAY);
+        root_ui().push_skin(&self.skin);
+        // the title, a gap and a row for each button
+        let mut stack = VStack::panel(480., 5, self.text_scale, Anchor::Center);
+        root_ui().window(0, vec2(0., 0.), vec2(300., 300.), |ui| {
+            stack.label(ui, &tr("pause-title"));
+            stack.space();
+
+            if stack.button(ui, &mut self.focus, &tr("pause-resume")) {
+                self.transition = Some(Transition::Pop);
+                self.sounds.play(SoundId::CLICK);
+            };
+            if stack.button(ui, &mut self.focus, &tr("pause-settings")) {
+                self.transition = Some(Transition::Push(game.settings_menu()));
+                self.sounds.play(SoundId::CLICK);
+            };
+            if stack.button(ui, &mut self.focus, &tr("pause-quit")) {
+                // the run is saved on the way out
+                self.transition = Some(game.quit_paused_run());
+                self.sounds.play(SoundId::CLICK);
             };
-            if ui.button(
-                vec2(screen_width() / 2. - 64., screen_height() * 5. / 6.),
-                "Main Menu",
+        });
+    }
+}
+
+pub struct SettingsMenu {
+    skin: Skin,
+    transition: Option<Transition<Game>>,
+    focus: MenuFocus,
+    sounds: SoundBank,
+    /// settings as edited so far. changes are applied and saved as they are made.
+    settings: Settings,
+}
+
+impl SettingsMenu {
+    pub fn new(sounds: &SoundBank, settings: &Settings) -> Self {
+        Self {
+            skin: base_skin(settings.text_scale()),
+            transition: None,
+            focus: MenuFocus::default(),
+            sounds: sounds.clone(),
+            settings: settings.clone(),
+        }
+    }
+}
+
+impl Scene<Game> for SettingsMenu {
+    fn close(&mut self, game: &mut Game) -> Result<()> {
+        game.settings = self.settings.clone();
+        game.cameras.apply_settings(&game.settings);
+        Ok(())
+    }
+
+    fn handle_input(&mut self, _game: &mut Game) {
+        if self.focus.handle_input() {
+            self.transition = Some(Transition::Pop);
+            self.sounds.play(SoundId::CLICK);
+        }
+    }
+
+    fn update(&mut self, _game: &mut Game) -> Option<Transition<Game>> {
+        self.transition.take()
+    }
+
+    fn draw(&mut self, _game: &mut Game) {
+        clear_background(DARKGRAY);
+        root_ui().push_skin(&self.skin);
+        let mut changed = false;
+        // the title, a button for each setting, and the way back
+        let text_scale = self.settings.text_scale();
+        let mut stack = VStack::panel(640., 16, text_scale, Anchor::Center)
+            .with_button_width(560. * text_scale);
+        root_ui().window(0, vec2(0., 0.), vec2(300., 300.), |ui| {
+            stack.label(ui, &tr("settings-title"));
+
+            let (width, height) = self.settings.window_size();
+            if stack.button(
+                ui,
+                &mut self.focus,
+                &tr_with("settings-window", &[("width", &width), ("height", &height)]),
+            ) {
+                self.settings.next_window_size();
+                changed = true;
+            }
+            if stack.button(
+                ui,
+                &mut self.focus,
+                &tr_with(
+                    "settings-fullscreen",
+                    &[("value", &on_off(self.settings.fullscreen))],
+                ),
+            ) {
+                self.settings.fullscreen = !self.settings.fullscreen;
+                changed = true;
+            }
+            if stack.button(
+                ui,
+                &mut self.focus,
+                &tr_with(
+                    "settings-pixel-perfect",
+                    &[("value", &on_off(self.settings.integer_scaling))],
+                ),
+            ) {
+                self.settings.integer_scaling = !self.settings.integer_scaling;
+                changed = true;
+            }
+            if stack.button(
+                ui,
+                &mut self.focus,
+                &tr_with("settings-crt", &[("value", &on_off(self.settings.crt))]),
+            ) {
+                self.settings.crt = !self.settings.crt;
+                changed = true;
+            }
+            if stack.button(
+                ui,
+                &mut self.focus,
+                &tr_with(
+                    "settings-language",
+                    &[("language", &locale::language_name())],
+                ),
+            ) {
+                self.settings.language = locale::next_language();
+                locale::set_language(&self.settings.language);
+                changed = true;
+            }
+            if stack.button(
+                ui,
+                &mut self.focus,
+                &tr_with(
+                    "settings-palette",
+                    &[("palette", &tr(Palette::get(self.settings.palette).name))],
+                ),
+            ) {
+                self.settings.next_palette();
+                changed = true;
+            }
+            if stack.button(
+                ui,
+                &mut self.focus,
+                &tr_with(
+                    "settings-high-contrast",
+                    &[("value", &on_off(self.settings.high_contrast))],
+                ),
+            ) {
+                self.settings.high_contrast = !self.settings.high_contrast;
+                changed = true;
+            }
+            if stack.button(
+                ui,
+                &mut self.focus,
+                &tr_with(
+                    "settings-shape-cues",
+                    &[("value", &on_off(self.settings.shape_cues))],
+                ),
+            ) {
+                self.settings.shape_cues = !self.settings.shape_cues;
+                changed = true;
+            }
+            if stack.button(
+                ui,
+                &mut self.focus,
+                &tr_with(
+                    "settings-reduced-motion",
+                    &[("value", &on_off(self.settings.reduced_motion))],
+                ),
+            ) {
+                self.settings.reduced_motion = !self.settings.reduced_motion;
+                changed = true;
+            }
+            if stack.button(
+                ui,
+                &mut self.focus,
+                &tr_with(
+                    "settings-text-size",
+                    &[("percent", &(self.settings.text_scale() * 100.).round())],
+                ),
+            ) {
+                self.settings.next_text_size();
+                changed = true;
+            }
+            if stack.button(
+                ui,
+                &mut self.focus,
+                &tr_with(
+                    "settings-escape-alarm",
+                    &[("value", &on_off(self.settings.escape_alarm))],
+                ),
+            ) {
+                self.settings.escape_alarm = !self.settings.escape_alarm;
+                changed = true;
+            }
+            if stack.button(
+                ui,
+                &mut self.focus,
+                &tr_with(
+                    "settings-difficulty",
+                    &[("difficulty", &tr(self.settings.difficulty().name))],
+                ),
             ) {
-                // TODO(axelmagn): play sound
-                self.next_state = Some(GameState::MainMenu);
-                play_sound_once(&self.sounds.click);
+                self.settings.next_difficulty();
+                changed = true;
+            }
+            if stack.button(
+                ui,
+                &mut self.focus,
+                &tr_with(
+                    "settings-splits",
+                    &[("value", &on_off(self.settings.speedrun_splits))],
+                ),
+            ) {
+                self.settings.speedrun_splits = !self.settings.speedrun_splits;
+                changed = true;
+            }
+            if stack.button(
+                ui,
+                &mut self.focus,
+                &tr_with(
+                    "settings-door-markers",
+                    &[("value", &on_off(self.settings.door_markers))],
+                ),
+            ) {
+                self.settings.door_markers = !self.settings.door_markers;
+                changed = true;
+            }
+
+            if stack.button(ui, &mut self.focus, &tr("settings-back")) {
+                self.transition = Some(Transition::Pop);
+                self.sounds.play(SoundId::CLICK);
+            };
+        });
+
+        if changed {
+            self.sounds.play(SoundId::CLICK);
+            self.skin = base_skin(self.settings.text_scale());
+            self.settings.apply_window();
+            if let Err(err) = self.settings.write() {
+                warn!("could not save settings: {}", err);
+            }
+        }
+    }
+}
+
+/// Shown when loading or running the game fails, with the whole chain of causes. There may be no
+/// game to show it over, so it runs on its own.
+pub struct ErrorMenu {
+    skin: Skin,
+    lines: Vec<String>,
+    retry: bool,
+    focus: MenuFocus,
+}
+
+impl ErrorMenu {
+    pub fn new(err: &Error) -> Self {
+        Self {
+            // settings may be what failed to load, so text stays at its usual size
+            skin: label_skin(32, 1.),
+            lines: err.chain().map(|cause| cause.to_string()).collect(),
+            retry: false,
+            focus: MenuFocus::default(),
+        }
+    }
+}
+
+impl Scene<()> for ErrorMenu {
+    fn handle_input(&mut self, _: &mut ()) {
+        self.focus.handle_input();
+    }
+
+    fn update(&mut self, _: &mut ()) -> Option<Transition<()>> {
+        // loading starts over once the error is out of the way
+        self.retry.then_some(Transition::Pop)
+    }
+
+    fn draw(&mut self, _: &mut ()) {
+        clear_background(DARKGRAY);
+        root_ui().push_skin(&self.skin);
+        // the title, a gap and each cause, with the retry button along the bottom
+        let rows = self.lines.len() + 2;
+        let mut text = VStack::panel(960., rows, 1., Anchor::Top);
+        let mut buttons = VStack::panel(960., 1, 1., Anchor::Bottom);
+        root_ui().window(0, vec2(0., 0.), vec2(300., 300.), |ui| {
+            text.label(ui, &tr("error-title"));
+            text.space();
+            for (i, line) in self.lines.iter().enumerate() {
+                let line = if i == 0 {
+                    line.clone()
+                } else {
+                    tr_with("error-caused-by", &[("cause", line)])
+                };
+                text.label(ui, &line);
+            }
+
+            if buttons.button(ui, &mut self.focus, &tr("error-retry")) {
+                self.retry = true;
+            };
+        });
+    }
+}
+
+fn on_off(value: bool) -> String {
+    if value {
+        tr("on")
+    } else {
+        tr("off")
+    }
+}
+
+pub struct LeaderboardMenu {
+    skin: Skin,
+    transition: Option<Transition<Game>>,
+    focus: MenuFocus,
+    sounds: SoundBank,
+    leaderboard: Leaderboard,
+    /// escape time waiting for the player to type their initials
+    new_time: Option<f64>,
+    initials: String,
+    /// place of the entry added this time around, to highlight it
+    new_place: Option<usize>,
+    /// reached by escaping, so the done button goes on to the round's stats
+    after_round: bool,
+    text_scale: f32,
+}
+
+impl LeaderboardMenu {
+    pub fn new(sounds: &SoundBank, new_time: Option<f64>, text_scale: f32) -> Self {
+        let after_round = new_time.is_some();
+        let leaderboard = Leaderboard::read().unwrap_or_else(|err| {
+            warn!("could not read the leaderboard: {}", err);
+            Leaderboard::default()
+        });
+        let new_time = new_time.filter(|time| leaderboard.qualifies(*time));
+        Self {
+            skin: label_skin(32, text_scale),
+            transition: None,
+            focus: MenuFocus::default(),
+            sounds: sounds.clone(),
+            leaderboard,
+            new_time,
+            initials: String::new(),
+            new_place: None,
+            after_round,
+            text_scale,
+        }
+    }
+
+    /// Where the done button goes
+    fn back(&self, game: &mut Game) -> Box<dyn Scene<Game>> {
+        if self.after_round {
+            game.game_over_menu()
+        } else {
+            game.main_menu()
+        }
+    }
+
+    /// Type initials for a new entry, and add it to the board on Enter
+    fn enter_initials(&mut self) {
+        while let Some(c) = get_char_pressed() {
+            if c.is_ascii_alphanumeric() && self.initials.len() < INITIALS_LENGTH {
+                self.initials.push(c.to_ascii_uppercase());
+            }
+        }
+        if is_key_pressed(KeyCode::Backspace) {
+            self.initials.pop();
+        }
+        if is_key_pressed(KeyCode::Enter) && !self.initials.is_empty() {
+            let Some(time) = self.new_time.take() else {
+                return;
+            };
+            self.new_place = self.leaderboard.insert(&self.initials, time);
+            if let Err(err) = self.leaderboard.write() {
+                warn!("could not save the leaderboard: {}", err);
+            }
+            self.sounds.play(SoundId::CLICK);
+        }
+    }
+}
+
+impl Scene<Game> for LeaderboardMenu {
+    fn handle_input(&mut self, game: &mut Game) {
+        // Enter and Backspace are for the initials until they are in
+        if self.new_time.is_some() {
+            self.enter_initials();
+        } else if self.focus.handle_input() {
+            self.transition = Some(Transition::Switch(self.back(game)));
+            self.sounds.play(SoundId::CLICK);
+        }
+    }
+
+    fn update(&mut self, _game: &mut Game) -> Option<Transition<Game>> {
+        self.transition.take()
+    }
+
+    fn draw(&mut self, game: &mut Game) {
+        clear_background(DARKGRAY);
+        root_ui().push_skin(&self.skin);
+        // the title and the board, with the new entry or the way out along the bottom
+        let rows = LEADERBOARD_SIZE + 2;
+        let mut board = VStack::panel(640., rows, self.text_scale, Anchor::Top);
+        let mut footer = VStack::panel(640., 1, self.text_scale, Anchor::Bottom);
+        root_ui().window(0, vec2(0., 0.), vec2(300., 300.), |ui| {
+            board.label(ui, &tr("leaderboard-title"));
+            board.space();
+
+            for (i, entry) in self.leaderboard.entries.iter().enumerate() {
+                let marker = if self.new_place == Some(i) { ">" } else { " " };
+                board.label(
+                    ui,
+                    &format!(
+                        "{}{:>2}. {:<3}  {}",
+                        marker,
+                        i + 1,
+                        entry.initials,
+                        time_str(entry.time)
+                    ),
+                );
+            }
+
+            if let Some(time) = self.new_time {
+                footer.label(
+                    ui,
+                    &tr_with(
+                        "leaderboard-new-best",
+                        &[("time", &time_str(time)), ("initials", &self.initials)],
+                    ),
+                );
+            } else if footer.button(ui, &mut self.focus, &tr("leaderboard-done")) {
+                self.transition = Some(Transition::Switch(self.back(game)));
+                self.sounds.play(SoundId::CLICK);
             };
         });
     }
@@ -245,7 +977,30 @@ pub fn time_str(time: f64) -> String {
     format!("{:02}:{:02.4}", time as u64 / 60, time % 60.)
 }
 
-pub fn base_skin() -> Skin {
+/// Font size for the player's text size setting
+fn scaled_font_size(size: u16, text_scale: f32) -> u16 {
+    (size as f32 * text_scale).round() as u16
+}
+
+/// The base skin with plainer labels, for menus with more to read than a title
+fn label_skin(size: u16, text_scale: f32) -> Skin {
+    let label_style = root_ui()
+        .style_builder()
+        .font(include_bytes!(
+            "../assets/kenney_kenney-fonts/Fonts/Kenney Pixel.ttf"
+        ))
+        .unwrap()
+        .text_color(WHITE)
+        .font_size(scaled_font_size(size, text_scale))
+        .build();
+
+    Skin {
+        label_style,
+        ..base_skin(text_scale)
+    }
+}
+
+pub fn base_skin(text_scale: f32) -> Skin {
     // TODO(axelmagn): customize for different screens
     let label_style = root_ui()
         .style_builder()
@@ -254,7 +1009,7 @@ pub fn base_skin() -> Skin {
         ))
         .unwrap()
         .text_color(WHITE)
-        .font_size(48)
+        .font_size(scaled_font_size(48, text_scale))
         .build();
 
     let window_style = root_ui()
@@ -302,7 +1057,10 @@ pub fn base_skin() -> Skin {
         .text_color(WHITE)
         .text_color_hovered(WHITE)
         .text_color_clicked(WHITE)
-        .font_size(32)
+        // tints the button the keyboard is on
+        .color_selected(Color::from_rgba(255, 210, 120, 255))
+        .color_selected_hovered(Color::from_rgba(255, 210, 120, 255))
+        .font_size(scaled_font_size(32, text_scale))
         .build();
 
     Skin {
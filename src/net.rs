@@ -0,0 +1,286 @@
+use std::{
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+    time::Instant,
+};
+
+use anyhow::{anyhow, Context, Result};
+use macroquad::math::Vec2;
+use nanoserde::{DeBin, SerBin};
+
+use crate::{constants::NET_TIMEOUT, input::PlayerInput, upgrades::PlayerUpgrades};
+
+/// Whether this game runs the dungeon, or follows one run by someone else
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NetRole {
+    /// simulates everything, and sends the partner what happened
+    Host,
+    /// sends its input to the host, and draws the snapshots that come back
+    Client,
+}
+
+/// Everything sent between co-op games. Both kinds are sent every frame and stand alone, so a
+/// lost packet is made up for by the next one.
+#[derive(Clone, Debug, PartialEq, SerBin, DeBin)]
+pub enum NetMessage {
+    Input(NetInput),
+    /// boxed, since it is much bigger than an input
+    Snapshot(Box<Snapshot>),
+}
+
+/// [`PlayerInput`] in a form that can be sent
+#[derive(Clone, Copy, Debug, Default, PartialEq, SerBin, DeBin)]
+pub struct NetInput {
+    pub move_direction: [f32; 2],
+    pub sneak: bool,
+    pub sprint: bool,
+    pub attack: bool,
+    pub aim: [f32; 2],
+    pub throw: bool,
+    pub interact: bool,
+}
+
+impl From<PlayerInput> for NetInput {
+    fn from(input: PlayerInput) -> Self {
+        Self {
+            move_direction: input.move_direction.to_array(),
+            sneak: input.sneak,
+            sprint: input.sprint,
+            attack: input.attack,
+            aim: input.aim.to_array(),
+            throw: input.throw,
+            interact: input.interact,
+        }
+    }
+}
+
+impl From<NetInput> for PlayerInput {
+    fn from(input: NetInput) -> Self {
+        Self {
+            move_direction: Vec2::from_array(input.move_direction),
+            sneak: input.sneak,
+            sprint: input.sprint,
+            attack: input.attack,
+            aim: Vec2::from_array(input.aim),
+            throw: input.throw,
+            interact: input.interact,
+        }
+    }
+}
+
+/// The host's dungeon as of one frame.
+///
+/// The client builds the same map from the seed, so only what changes during a round is sent.
+/// Entities are referred to by their ID, which comes out the same on both sides.
+#[derive(Clone, Debug, Default, PartialEq, SerBin, DeBin)]
+pub struct Snapshot {
+    pub seed: u64,
+    pub floor: u32,
+    /// the host's player, then the client's
+    pub players: Vec<CharacterState>,
+    pub guards: Vec<CharacterState>,
+    /// guards pacing behind the cell doors they were trapped in
+    pub jailed: Vec<CharacterState>,
+    /// every guard door, in the order they were built
+    pub guard_doors: Vec<DoorState>,
+    /// whether each spike trap is up, in the order they were built
+    pub spikes: Vec<bool>,
+    /// whether each lever has been pulled, in the order they were built
+    pub levers: Vec<bool>,
+    /// whether each chest has been opened, in the order they were built
+    pub chests: Vec<bool>,
+    pub exit_open: bool,
+    /// seconds left on the escape alarm, if it is going off
+    pub alarm_time_left: Option<f64>,
+    /// IDs of locked doors and items that are still there
+    pub locked_doors: Vec<u32>,
+    pub items: Vec<u32>,
+    /// top left of each crate, in the order they were built
+    pub crates: Vec<[f32; 2]>,
+    pub score: u32,
+    pub score_target: u32,
+    pub keys: u32,
+    pub coins: u32,
+    pub upgrades: PlayerUpgrades,
+    pub outcome: RoundOutcome,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, SerBin, DeBin)]
+pub struct DoorState {
+    pub is_open: bool,
+    pub is_trapped: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, SerBin, DeBin)]
+pub enum RoundOutcome {
+    #[default]
+    Playing,
+    Escaped,
+    Died,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, SerBin, DeBin)]
+pub struct CharacterState {
+    pub id: u32,
+    pub position: [f32; 2],
+    pub health: u32,
+    pub facing_left: bool,
+    pub suspicion: f32,
+    pub is_attacking: bool,
+    /// ID of the hiding spot a player is tucked away in
+    pub hiding_spot: Option<u32>,
+}
+
+/// A UDP connection to the other player
+#[derive(Debug)]
+pub struct NetSession {
+    pub role: NetRole,
+    socket: UdpSocket,
+    /// where to send to. the host learns it from the first message the client sends.
+    peer: Option<SocketAddr>,
+    last_heard: Option<Instant>,
+}
+
+impl NetSession {
+    /// Wait for a partner to join on a port
+    pub fn host(port: u16) -> Result<Self> {
+        let socket = UdpSocket::bind(("0.0.0.0", port))
+            .with_context(|| format!("could not host on port {}", port))?;
+        Self::new(NetRole::Host, socket, None)
+    }
+
+    /// Join a host at an address like "192.168.1.20:7777"
+    pub fn join(address: &str) -> Result<Self> {
+        let peer = address
+            .to_socket_addrs()
+            .with_context(|| format!("could not find host {}", address))?
+            .next()
+            .ok_or_else(|| anyhow!("could not find host {}", address))?;
+        let socket = UdpSocket::bind(("0.0.0.0", 0))?;
+        Self::new(NetRole::Client, socket, Some(peer))
+    }
+
+    fn new(role: NetRole, socket: UdpSocket, peer: Option<SocketAddr>) -> Result<Self> {
+        socket.set_nonblocking(true)?;
+        Ok(Self {
+            role,
+            socket,
+            peer,
+            last_heard: None,
+        })
+    }
+
+    /// Start a session from `--host <port>` or `--join <address>` on the command line
+    pub fn from_args(mut args: impl Iterator<Item = String>) -> Result<Option<Self>> {
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| anyhow!("{} needs a value after it", arg))
+            };
+            match arg.as_str() {
+                "--host" => {
+                    let port = value()?;
+                    let port = port.parse().with_context(|| format!("bad port {}", port))?;
+                    return Self::host(port).map(Some);
+                }
+                "--join" => return Self::join(&value()?).map(Some),
+                _ => {}
+            }
+        }
+        Ok(None)
+    }
+
+    /// Heard from the other player recently
+    pub fn is_connected(&self) -> bool {
+        self.last_heard
+            .is_some_and(|time| time.elapsed().as_secs_f64() < NET_TIMEOUT)
+    }
+
+    pub fn send(&self, message: &NetMessage) {
+        let Some(peer) = self.peer else {
+            return;
+        };
+        if let Err(err) = self.socket.send_to(&message.serialize_bin(), peer) {
+            warn!("could not send to {}: {}", peer, err);
+        }
+    }
+
+    /// Everything that arrived since the last call
+    pub fn receive(&mut self) -> Vec<NetMessage> {
+        let mut messages = Vec::new();
+        let mut buf = [0; 65536];
+        loop {
+            let (len, from) = match self.socket.recv_from(&mut buf) {
+                Ok(received) => received,
+                Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => break,
+                Err(err) => {
+                    warn!("could not receive: {}", err);
+                    break;
+                }
+            };
+            if self.role == NetRole::Host && self.peer != Some(from) {
+                if self.is_connected() {
+                    // someone else is already playing
+                    continue;
+                }
+                self.peer = Some(from);
+            } else if self.peer != Some(from) {
+                continue;
+            }
+            match NetMessage::deserialize_bin(&buf[..len]) {
+                Ok(message) => {
+                    self.last_heard = Some(Instant::now());
+                    messages.push(message);
+                }
+                Err(err) => warn!("bad message from {}: {}", from, err),
+            }
+        }
+        messages
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_messages_round_trip_over_loopback() {
+        let mut host = NetSession::host(0).unwrap();
+        let port = host.socket.local_addr().unwrap().port();
+        let mut client = NetSession::join(&format!("127.0.0.1:{}", port)).unwrap();
+
+        let input = NetMessage::Input(NetInput {
+            move_direction: [1., 0.],
+            attack: true,
+            ..Default::default()
+        });
+        client.send(&input);
+        let snapshot = NetMessage::Snapshot(Box::new(Snapshot {
+            seed: 42,
+            players: vec![CharacterState::default(); 2],
+            outcome: RoundOutcome::Escaped,
+            ..Default::default()
+        }));
+
+        // loopback is quick, but not instant
+        let mut received = Vec::new();
+        for _ in 0..100 {
+            received.extend(host.receive());
+            if !received.is_empty() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+        assert_eq!(received, vec![input]);
+
+        host.send(&snapshot);
+        let mut received = Vec::new();
+        for _ in 0..100 {
+            received.extend(client.receive());
+            if !received.is_empty() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+        assert_eq!(received, vec![snapshot]);
+    }
+}
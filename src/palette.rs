@@ -0,0 +1,86 @@
+use macroquad::color::Color;
+
+use crate::settings::Settings;
+
+/// Colors for the cues that tell the player something is wrong or where to go
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Palette {
+    /// locale key for the palette's name in the settings menu
+    pub name: &'static str,
+    /// a boss about to charge, and guards that have spotted the player
+    pub alert: Color,
+    /// guidance arrow toward the open exit. None keeps the arrow texture's own colors.
+    pub arrow: Option<Color>,
+    /// outlines around characters in high contrast mode
+    pub player_outline: Color,
+    pub guard_outline: Color,
+}
+
+impl Palette {
+    pub const DEFAULT: Self = Self {
+        name: "palette-default",
+        alert: Color::new(1., 0., 0., 1.),
+        arrow: None,
+        player_outline: Color::new(1., 1., 1., 1.),
+        guard_outline: Color::new(1., 0., 0., 1.),
+    };
+
+    /// orange and blue, which red-green color blindness keeps apart
+    pub const RED_GREEN: Self = Self {
+        name: "palette-red-green",
+        alert: Color::new(0.9, 0.6, 0., 1.),
+        arrow: Some(Color::new(0.35, 0.7, 0.9, 1.)),
+        player_outline: Color::new(0.35, 0.7, 0.9, 1.),
+        guard_outline: Color::new(0.9, 0.6, 0., 1.),
+    };
+
+    /// red and teal, which blue-yellow color blindness keeps apart
+    pub const BLUE_YELLOW: Self = Self {
+        name: "palette-blue-yellow",
+        alert: Color::new(0.85, 0.15, 0.3, 1.),
+        arrow: Some(Color::new(0., 0.6, 0.55, 1.)),
+        player_outline: Color::new(0., 0.6, 0.55, 1.),
+        guard_outline: Color::new(0.85, 0.15, 0.3, 1.),
+    };
+
+    pub const ALL: &'static [Self] = &[Self::DEFAULT, Self::RED_GREEN, Self::BLUE_YELLOW];
+
+    pub fn get(index: usize) -> Self {
+        Self::ALL[index % Self::ALL.len()]
+    }
+}
+
+/// How cues are drawn, from the player's accessibility settings
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CueStyle {
+    pub palette: Palette,
+    /// outline characters so they stand out from the floor
+    pub high_contrast: bool,
+    /// draw shapes alongside cues that are otherwise told apart by color
+    pub shape_cues: bool,
+}
+
+impl CueStyle {
+    pub fn new(settings: &Settings) -> Self {
+        Self {
+            palette: Palette::get(settings.palette),
+            high_contrast: settings.high_contrast,
+            shape_cues: settings.shape_cues,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::locale::Localizer;
+
+    #[test]
+    fn test_every_palette_has_a_name() {
+        let localizer = Localizer::default();
+        for palette in Palette::ALL {
+            assert_ne!(localizer.get(palette.name), palette.name);
+        }
+        assert_eq!(Palette::get(Palette::ALL.len()), Palette::DEFAULT);
+    }
+}
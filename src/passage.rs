@@ -0,0 +1,136 @@
+use macroquad::{
+    color::Color,
+    math::{vec2, UVec2, Vec2},
+    rand::gen_range,
+    shapes::draw_triangle,
+};
+use nalgebra::vector;
+use nanoserde::{DeJson, SerJson};
+use rapier2d::{
+    geometry::{ColliderBuilder, ColliderSet},
+    pipeline::{ActiveHooks, ContactModificationContext, PhysicsHooks},
+};
+
+use crate::{
+    constants::{ONE_WAY_ALLOWED_ANGLE, ONE_WAY_HALF_THICKNESS},
+    entity::{EntityKind, EntityRegistry},
+    physics::CollisionLayer,
+};
+
+/// Which way a one-way passage lets things through
+#[derive(Clone, Copy, Debug, PartialEq, Eq, DeJson, SerJson)]
+pub enum PassageDirection {
+    Up,
+    Down,
+}
+
+impl PassageDirection {
+    pub fn random() -> Self {
+        match gen_range(0, 2) {
+            0 => PassageDirection::Up,
+            _ => PassageDirection::Down,
+        }
+    }
+
+    /// unit vector in the direction of travel
+    pub fn vector(&self) -> Vec2 {
+        match self {
+            PassageDirection::Up => vec2(0., -1.),
+            PassageDirection::Down => vec2(0., 1.),
+        }
+    }
+
+    /// Tag for the passage's collider, so the physics hooks can tell which way it goes
+    fn user_data(&self) -> u128 {
+        match self {
+            PassageDirection::Up => 1,
+            PassageDirection::Down => 2,
+        }
+    }
+
+    pub fn from_user_data(user_data: u128) -> Option<Self> {
+        match user_data {
+            1 => Some(PassageDirection::Up),
+            2 => Some(PassageDirection::Down),
+            _ => None,
+        }
+    }
+}
+
+/// A row of floor across a corridor that can only be crossed one way. Its collider is a thin
+/// slab that only pushes back on things coming at it from the far side.
+pub struct OneWayPassage {
+    /// leftmost tile of the row
+    pub position: UVec2,
+    pub width: u32,
+    pub direction: PassageDirection,
+}
+
+impl OneWayPassage {
+    pub fn create(
+        position: UVec2,
+        width: u32,
+        direction: PassageDirection,
+        collider_set: &mut ColliderSet,
+        entities: &mut EntityRegistry,
+    ) -> Self {
+        let half_width = width as f32 / 2.;
+        let collider = ColliderBuilder::cuboid(half_width, ONE_WAY_HALF_THICKNESS)
+            .translation(vector![
+                position.x as f32 + half_width,
+                position.y as f32 + 0.5
+            ])
+            .collision_groups(CollisionLayer::Wall.groups())
+            .active_hooks(ActiveHooks::MODIFY_SOLVER_CONTACTS)
+            .user_data(direction.user_data())
+            .build();
+        let collider_handle = collider_set.insert(collider);
+        let id = entities.spawn(EntityKind::OneWayPassage);
+        entities.register_collider(collider_handle, id);
+
+        Self {
+            position,
+            width,
+            direction,
+        }
+    }
+
+    /// Draw a faint chevron on each tile, pointing the way through
+    pub fn draw(&self) {
+        let color = Color::new(1., 1., 1., 0.4);
+        let forward = self.direction.vector() * 0.3;
+        let side = forward.perp();
+        for x in self.position.x..(self.position.x + self.width) {
+            let center = vec2(x as f32 + 0.5, self.position.y as f32 + 0.5);
+            draw_triangle(
+                center + forward,
+                center - forward + side,
+                center - forward - side,
+                color,
+            );
+        }
+    }
+}
+
+/// Lets things through one-way passages in their direction of travel only
+pub struct OneWayHooks;
+
+impl PhysicsHooks for OneWayHooks {
+    fn modify_solver_contacts(&self, context: &mut ContactModificationContext) {
+        let colliders = context.colliders;
+        // the contact normal points from the first collider to the second. a passage only
+        // pushes back on things on the side it lets them out on.
+        let allowed = if let Some(direction) =
+            PassageDirection::from_user_data(colliders[context.collider1].user_data)
+        {
+            direction.vector()
+        } else if let Some(direction) =
+            PassageDirection::from_user_data(colliders[context.collider2].user_data)
+        {
+            -direction.vector()
+        } else {
+            return;
+        };
+        context.update_as_oneway_platform(&vector![allowed.x, allowed.y], ONE_WAY_ALLOWED_ANGLE);
+    }
+}
@@ -0,0 +1,65 @@
+use std::collections::VecDeque;
+
+use crate::constants::PERF_WINDOW_FRAMES;
+
+/// Frame and physics step times over the last few frames, for the performance overlay
+#[derive(Debug, Default)]
+pub struct PerfStats {
+    frame_times: VecDeque<f64>,
+    physics_times: VecDeque<f64>,
+}
+
+impl PerfStats {
+    /// Record one frame, with how long it and its physics step took in seconds
+    pub fn record(&mut self, frame_time: f64, physics_time: f64) {
+        for (samples, sample) in [
+            (&mut self.frame_times, frame_time),
+            (&mut self.physics_times, physics_time),
+        ] {
+            samples.push_back(sample);
+            if samples.len() > PERF_WINDOW_FRAMES {
+                samples.pop_front();
+            }
+        }
+    }
+
+    pub fn fps(&self) -> f64 {
+        let frame_time = average(&self.frame_times);
+        if frame_time > 0. {
+            1. / frame_time
+        } else {
+            0.
+        }
+    }
+
+    /// average physics step time in milliseconds
+    pub fn physics_ms(&self) -> f64 {
+        average(&self.physics_times) * 1000.
+    }
+}
+
+fn average(samples: &VecDeque<f64>) -> f64 {
+    if samples.is_empty() {
+        return 0.;
+    }
+    samples.iter().sum::<f64>() / samples.len() as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_perf_stats_average_over_rolling_window() {
+        let mut perf = PerfStats::default();
+        assert_eq!(perf.fps(), 0.);
+
+        // a slow frame that should fall out of the window
+        perf.record(1., 1.);
+        for _ in 0..PERF_WINDOW_FRAMES {
+            perf.record(0.02, 0.004);
+        }
+        assert!((perf.fps() - 50.).abs() < 1e-6);
+        assert!((perf.physics_ms() - 4.).abs() < 1e-6);
+    }
+}
@@ -1,21 +1,110 @@
-use macroquad::time::get_frame_time;
+use macroquad::{math::Vec2, time::get_frame_time};
 use rapier2d::{
     crossbeam::{self, channel::Receiver},
     dynamics::{
         CCDSolver, ImpulseJointSet, IntegrationParameters, IslandManager, MultibodyJointSet,
         RigidBody, RigidBodyHandle, RigidBodySet,
     },
-    geometry::{BroadPhase, ColliderSet, CollisionEvent, ContactForceEvent, NarrowPhase},
-    math::{Real, Vector},
-    pipeline::{ChannelEventCollector, PhysicsPipeline, QueryPipeline},
+    geometry::{
+        BroadPhase, Collider, ColliderHandle, ColliderSet, CollisionEvent, ContactForceEvent,
+        Group, InteractionGroups, NarrowPhase, Ray, Shape,
+    },
+    math::{Isometry, Point, Real, Vector},
+    pipeline::{ChannelEventCollector, PhysicsPipeline, QueryFilter, QueryPipeline},
+};
+
+use crate::{
+    entity::{EntityId, EntityKind, EntityRegistry},
+    passage::{OneWayHooks, PassageDirection},
 };
 
+/// What a collider is, for deciding what it can touch. Colliders are never tested against kinds
+/// of things they don't care about, like the attack sensor against walls.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CollisionLayer {
+    Player,
+    Guard,
+    /// anything solid that isn't a character: walls, locked doors, one-way passages and crates
+    Wall,
+    /// guard doors and the exit
+    DoorSensor,
+    AttackSensor,
+    Projectile,
+    /// items, spikes and pressure plates
+    TileSensor,
+}
+
+impl CollisionLayer {
+    fn group(self) -> Group {
+        match self {
+            CollisionLayer::Player => Group::GROUP_1,
+            CollisionLayer::Guard => Group::GROUP_2,
+            CollisionLayer::Wall => Group::GROUP_3,
+            CollisionLayer::DoorSensor => Group::GROUP_4,
+            CollisionLayer::AttackSensor => Group::GROUP_5,
+            CollisionLayer::Projectile => Group::GROUP_6,
+            CollisionLayer::TileSensor => Group::GROUP_7,
+        }
+    }
+
+    /// Layers this one can touch. Has to agree with every other layer's, or the pair won't touch.
+    fn touches(self) -> &'static [CollisionLayer] {
+        use CollisionLayer::*;
+        match self {
+            Player => &[Guard, Wall, DoorSensor, TileSensor],
+            Guard => &[
+                Player,
+                Guard,
+                Wall,
+                DoorSensor,
+                AttackSensor,
+                Projectile,
+                TileSensor,
+            ],
+            Wall => &[Player, Guard, Wall, Projectile],
+            DoorSensor | TileSensor => &[Player, Guard],
+            AttackSensor => &[Guard],
+            Projectile => &[Guard, Wall],
+        }
+    }
+
+    /// Groups to give colliders on this layer
+    pub fn groups(self) -> InteractionGroups {
+        let filter = self
+            .touches()
+            .iter()
+            .fold(Group::NONE, |filter, layer| filter | layer.group());
+        InteractionGroups::new(self.group(), filter)
+    }
+
+    /// A query that finds what colliders on this layer would touch
+    pub fn query(self) -> QueryFilter<'static> {
+        QueryFilter::default().groups(self.groups())
+    }
+
+    /// A query that only finds colliders on this layer
+    pub fn only(self) -> QueryFilter<'static> {
+        QueryFilter::default().groups(InteractionGroups::new(Group::ALL, self.group()))
+    }
+}
+
+/// Whatever a ray or shape cast ran into first
+#[derive(Clone, Copy, Debug)]
+pub struct QueryHit {
+    /// the entity owning the collider that was hit, if it has one
+    pub entity: Option<(EntityId, EntityKind)>,
+    /// how far along the cast the hit was, in tiles
+    pub distance: f32,
+}
+
 /// Game physics manager
-#[derive(Default)]
 pub struct Physics {
     pub bodies: RigidBodySet,
     pub colliders: ColliderSet,
 
+    /// which entity owns each collider
+    pub entities: EntityRegistry,
+
     // simulation structures - generally not fussed with much
     pub gravity: Vector<Real>,
     pub integration_params: IntegrationParameters,
@@ -26,16 +115,50 @@ pub struct Physics {
     pub impulse_joints: ImpulseJointSet,
     pub multibody_joints: MultibodyJointSet,
     pub ccd_solver: CCDSolver,
+    /// kept up to date by each step, for character controllers to cast against
     pub query_pipeline: QueryPipeline,
+
+    /// sends the events of each step to the receivers below. the channels last as long as the
+    /// physics world, rather than being made anew every step.
+    event_collector: ChannelEventCollector,
+    collision_events: Receiver<CollisionEvent>,
+    contact_force_events: Receiver<ContactForceEvent>,
+}
+
+impl Default for Physics {
+    fn default() -> Self {
+        let (collision_send, collision_events) = crossbeam::channel::unbounded();
+        let (contact_force_send, contact_force_events) = crossbeam::channel::unbounded();
+        Self {
+            bodies: RigidBodySet::default(),
+            colliders: ColliderSet::default(),
+            entities: EntityRegistry::default(),
+            gravity: Vector::default(),
+            integration_params: IntegrationParameters::default(),
+            physics_pipeline: PhysicsPipeline::default(),
+            islands: IslandManager::default(),
+            broad_phase: BroadPhase::default(),
+            narrow_phase: NarrowPhase::default(),
+            impulse_joints: ImpulseJointSet::default(),
+            multibody_joints: MultibodyJointSet::default(),
+            ccd_solver: CCDSolver::default(),
+            query_pipeline: QueryPipeline::default(),
+            event_collector: ChannelEventCollector::new(collision_send, contact_force_send),
+            collision_events,
+            contact_force_events,
+        }
+    }
 }
 
 impl Physics {
-    pub fn step(&mut self) -> (Receiver<CollisionEvent>, Receiver<ContactForceEvent>) {
+    /// Advance the simulation by a frame. Events from the step can be taken with
+    /// [`Physics::next_collision_event`] until the next one, which drops any left over.
+    pub fn step(&mut self) {
         self.integration_params.dt = get_frame_time();
 
-        let (collision_send, collision_recv) = crossbeam::channel::unbounded();
-        let (contact_force_send, contact_force_recv) = crossbeam::channel::unbounded();
-        let event_handler = ChannelEventCollector::new(collision_send, contact_force_send);
+        self.collision_events.try_iter().for_each(drop);
+        // nothing asks for contact forces, but they're cleared in case something starts to
+        self.contact_force_events.try_iter().for_each(drop);
 
         self.physics_pipeline.step(
             &self.gravity,
@@ -48,13 +171,115 @@ impl Physics {
             &mut self.impulse_joints,
             &mut self.multibody_joints,
             &mut self.ccd_solver,
-            // Some(&mut self.query_pipeline),
-            None,
-            &(),
-            &event_handler,
+            Some(&mut self.query_pipeline),
+            &OneWayHooks,
+            &self.event_collector,
+        );
+    }
+
+    /// Cast a ray from `origin`, up to `max_distance` tiles along `direction`
+    pub fn raycast(
+        &self,
+        origin: Vec2,
+        direction: Vec2,
+        max_distance: f32,
+        filter: QueryFilter,
+    ) -> Option<QueryHit> {
+        let direction = direction.normalize_or_zero();
+        let ray = Ray::new(
+            Point::new(origin.x, origin.y),
+            Vector::new(direction.x, direction.y),
         );
+        let (handle, distance) = self.query_pipeline.cast_ray(
+            &self.bodies,
+            &self.colliders,
+            &ray,
+            max_distance,
+            true,
+            filter,
+        )?;
+        Some(QueryHit {
+            entity: self.entities.collider_entity(handle),
+            distance,
+        })
+    }
+
+    /// Sweep a shape from `origin` along `motion`, to see what it would run into on the way
+    pub fn shape_cast(
+        &self,
+        shape: &dyn Shape,
+        origin: Vec2,
+        motion: Vec2,
+        filter: QueryFilter,
+    ) -> Option<QueryHit> {
+        let (handle, toi) = self.query_pipeline.cast_shape(
+            &self.bodies,
+            &self.colliders,
+            &Isometry::translation(origin.x, origin.y),
+            &Vector::new(motion.x, motion.y),
+            shape,
+            1.,
+            true,
+            filter,
+        )?;
+        Some(QueryHit {
+            entity: self.entities.collider_entity(handle),
+            distance: toi.toi * motion.length(),
+        })
+    }
+
+    /// Entities with a collider covering `point`
+    pub fn entities_at(&self, point: Vec2, filter: QueryFilter) -> Vec<(EntityId, EntityKind)> {
+        let mut entities = Vec::new();
+        self.query_pipeline.intersections_with_point(
+            &self.bodies,
+            &self.colliders,
+            &Point::new(point.x, point.y),
+            filter,
+            |handle| {
+                entities.extend(self.entities.collider_entity(handle));
+                true
+            },
+        );
+        entities
+    }
+
+    /// Entities of the given kinds with a collider overlapping a sensor, as of the last step
+    pub fn intersecting<'a>(
+        &'a self,
+        sensor: ColliderHandle,
+        kinds: &'a [EntityKind],
+    ) -> impl Iterator<Item = EntityId> + 'a {
+        self.narrow_phase
+            .intersection_pairs_with(sensor)
+            .filter(|(_, _, intersecting)| *intersecting)
+            .filter_map(move |(a, b, _)| {
+                let other = if a == sensor { b } else { a };
+                self.entities.collider_entity(other)
+            })
+            .filter(|(_, kind)| kinds.contains(kind))
+            .map(|(id, _)| id)
+    }
+
+    /// Whether the way between two points is clear, up to `slack` tiles short of `to`. Walls,
+    /// locked doors and crates block the view. One-way passages are low enough to see over.
+    pub fn line_of_sight(&self, from: Vec2, to: Vec2, slack: f32) -> bool {
+        let see_over =
+            |_, collider: &Collider| PassageDirection::from_user_data(collider.user_data).is_none();
+        let distance = from.distance(to);
+        let filter = CollisionLayer::Wall.only().predicate(&see_over);
+        self.raycast(from, to - from, distance, filter)
+            .is_none_or(|hit| hit.distance >= distance - slack)
+    }
 
-        (collision_recv, contact_force_recv)
+    /// Take the next collision event from the last step, if there are any left
+    pub fn next_collision_event(&self) -> Option<CollisionEvent> {
+        self.collision_events.try_recv().ok()
+    }
+
+    /// Bring the query pipeline up to date with colliders added since the last step
+    pub fn update_queries(&mut self) {
+        self.query_pipeline.update(&self.bodies, &self.colliders);
     }
 
     pub fn remove_body(
@@ -62,6 +287,14 @@ impl Physics {
         body_handle: &RigidBodyHandle,
         remove_attached_colliders: bool,
     ) -> Option<RigidBody> {
+        self.entities.unregister_body(*body_handle);
+        if remove_attached_colliders {
+            if let Some(body) = self.bodies.get(*body_handle) {
+                for collider_handle in body.colliders() {
+                    self.entities.unregister_collider(*collider_handle);
+                }
+            }
+        }
         self.bodies.remove(
             *body_handle,
             &mut self.islands,
@@ -71,4 +304,49 @@ impl Physics {
             remove_attached_colliders,
         )
     }
+
+    pub fn remove_collider(&mut self, collider_handle: ColliderHandle) -> Option<Collider> {
+        self.entities.unregister_collider(collider_handle);
+        self.colliders
+            .remove(collider_handle, &mut self.islands, &mut self.bodies, true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collision_layers_agree_on_what_touches() {
+        let all = [
+            CollisionLayer::Player,
+            CollisionLayer::Guard,
+            CollisionLayer::Wall,
+            CollisionLayer::DoorSensor,
+            CollisionLayer::AttackSensor,
+            CollisionLayer::Projectile,
+            CollisionLayer::TileSensor,
+        ];
+        for a in all {
+            for b in all {
+                assert_eq!(
+                    a.touches().contains(&b),
+                    b.touches().contains(&a),
+                    "{:?} and {:?}",
+                    a,
+                    b
+                );
+                assert_eq!(
+                    a.groups().test(b.groups()),
+                    a.touches().contains(&b),
+                    "{:?} and {:?}",
+                    a,
+                    b
+                );
+            }
+        }
+        assert!(!CollisionLayer::AttackSensor
+            .groups()
+            .test(CollisionLayer::Wall.groups()));
+    }
 }
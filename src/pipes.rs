@@ -0,0 +1,205 @@
+use macroquad::{
+    color::Color,
+    math::{vec2, Rect, UVec2, Vec2},
+    texture::{draw_texture_ex, DrawTextureParams},
+};
+use macroquad_tiled::Map as TiledMap;
+
+use crate::{
+    constants::{
+        GUARD_SPRITE_ID, REINFORCEMENT_ALARM_INTERVAL, REINFORCEMENT_CAP, REINFORCEMENT_INTERVAL,
+        REINFORCEMENT_START_TIME, REINFORCEMENT_WARNING_TIME,
+    },
+    door::ExitDoor,
+    event::{EventQueue, GameEvent},
+    map::{sprite_source, theme::TileTheme},
+};
+
+/// One of the monster pipes either side of the exit, which reinforcements crawl out of
+#[derive(Clone, Debug, PartialEq)]
+pub struct MonsterPipe {
+    pub position: UVec2,
+}
+
+impl MonsterPipe {
+    /// Where a guard coming out of the pipe stands, on the floor below it
+    pub fn spawn_position(&self) -> Vec2 {
+        self.position.as_vec2() + vec2(0., 1.)
+    }
+}
+
+/// Sends more guards out of the monster pipes once a floor has gone on too long, or as soon as
+/// the escape alarm goes off, up to a cap per floor
+#[derive(Clone, Debug, Default)]
+pub struct Reinforcements {
+    pipes: Vec<MonsterPipe>,
+    /// when the next guard comes out. None until reinforcements start.
+    due: Option<f64>,
+    /// the pipe the next guard comes out of
+    next_pipe: usize,
+    /// the next guard's warning has already been given
+    warned: bool,
+    pub spawned: u32,
+}
+
+impl Reinforcements {
+    pub fn new(exit_door: &ExitDoor) -> Self {
+        let pipes = [0, 3]
+            .into_iter()
+            .map(|x| MonsterPipe {
+                position: exit_door.position + UVec2::new(x, 0),
+            })
+            .collect();
+        Self {
+            pipes,
+            ..Default::default()
+        }
+    }
+
+    /// Warn of and send out guards as they come due. `elapsed` is seconds since the floor started.
+    pub fn update(&mut self, now: f64, elapsed: f64, is_alarm: bool, events: &mut EventQueue) {
+        if self.pipes.is_empty() || self.spawned >= REINFORCEMENT_CAP {
+            return;
+        }
+        let interval = if is_alarm {
+            REINFORCEMENT_ALARM_INTERVAL
+        } else {
+            REINFORCEMENT_INTERVAL
+        };
+        let due = match self.due {
+            // the alarm hurries along a guard that was a while off
+            Some(due) => due.min(now + interval),
+            None if is_alarm || elapsed > REINFORCEMENT_START_TIME => {
+                now + REINFORCEMENT_WARNING_TIME
+            }
+            None => return,
+        };
+        self.due = Some(due);
+
+        let pipe = &self.pipes[self.next_pipe];
+        if !self.warned && now >= due - REINFORCEMENT_WARNING_TIME {
+            self.warned = true;
+            events.emit(GameEvent::PipeRumbling {
+                position: pipe.spawn_position(),
+            });
+        }
+        if now >= due {
+            events.emit(GameEvent::ReinforcementArrived {
+                position: pipe.spawn_position(),
+            });
+            self.spawned += 1;
+            self.next_pipe = (self.next_pipe + 1) % self.pipes.len();
+            self.warned = false;
+            self.due = Some(now + interval);
+        }
+    }
+
+    /// How far the next guard is through crawling out, from 0 to 1, while its warning is given
+    fn arrival_progress(&self, now: f64) -> Option<f32> {
+        if !self.warned {
+            return None;
+        }
+        let due = self.due?;
+        let t = 1. - (due - now) / REINFORCEMENT_WARNING_TIME;
+        Some(t.clamp(0., 1.) as f32)
+    }
+
+    /// Draw the pipe opening up and a guard sliding out of it before it arrives
+    pub fn draw(&self, now: f64, tile_map: &TiledMap, theme: &TileTheme) {
+        let Some(progress) = self.arrival_progress(now) else {
+            return;
+        };
+        let Some(tileset) = tile_map.tilesets.get(&theme.tileset) else {
+            return;
+        };
+        let pipe = &self.pipes[self.next_pipe];
+        let position = pipe.position.as_vec2();
+        tile_map.spr(
+            &theme.tileset,
+            theme.doors.pipe_open,
+            Rect::new(position.x, position.y, 1., 1.),
+        );
+        draw_texture_ex(
+            &tileset.texture,
+            position.x,
+            position.y + progress,
+            Color::new(1., 1., 1., progress),
+            DrawTextureParams {
+                dest_size: Some(vec2(1., 1.)),
+                source: Some(sprite_source(tileset, GUARD_SPRITE_ID)),
+                ..Default::default()
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reinforcements() -> Reinforcements {
+        Reinforcements {
+            pipes: vec![
+                MonsterPipe {
+                    position: UVec2::new(4, 2),
+                },
+                MonsterPipe {
+                    position: UVec2::new(7, 2),
+                },
+            ],
+            ..Default::default()
+        }
+    }
+
+    fn drain(events: &mut EventQueue) -> Vec<GameEvent> {
+        std::iter::from_fn(|| events.pop()).collect()
+    }
+
+    #[test]
+    fn test_reinforcements_warn_then_arrive_up_to_the_cap() {
+        let mut reinforcements = reinforcements();
+        let mut events = EventQueue::default();
+        reinforcements.update(0., REINFORCEMENT_START_TIME / 2., false, &mut events);
+        assert!(drain(&mut events).is_empty());
+
+        let start = 100.;
+        reinforcements.update(start, REINFORCEMENT_START_TIME + 1., false, &mut events);
+        assert_eq!(
+            drain(&mut events),
+            vec![GameEvent::PipeRumbling {
+                position: vec2(4., 3.)
+            }]
+        );
+        let arrival = start + REINFORCEMENT_WARNING_TIME;
+        reinforcements.update(arrival, 0., false, &mut events);
+        assert_eq!(
+            drain(&mut events),
+            vec![GameEvent::ReinforcementArrived {
+                position: vec2(4., 3.)
+            }]
+        );
+
+        // the alarm brings the next one in sooner, from the other pipe
+        reinforcements.update(arrival, 0., true, &mut events);
+        let next = arrival + REINFORCEMENT_ALARM_INTERVAL;
+        reinforcements.update(next, 0., true, &mut events);
+        assert_eq!(
+            drain(&mut events),
+            vec![
+                GameEvent::PipeRumbling {
+                    position: vec2(7., 3.)
+                },
+                GameEvent::ReinforcementArrived {
+                    position: vec2(7., 3.)
+                }
+            ]
+        );
+
+        let mut now = next;
+        for _ in 0..REINFORCEMENT_CAP * 2 {
+            now += REINFORCEMENT_ALARM_INTERVAL;
+            reinforcements.update(now, 0., true, &mut events);
+        }
+        assert_eq!(reinforcements.spawned, REINFORCEMENT_CAP);
+    }
+}
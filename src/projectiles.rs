@@ -0,0 +1,116 @@
+use macroquad::math::{vec2, Rect, Vec2};
+use macroquad_tiled::Map as TiledMap;
+use nalgebra::vector;
+use rapier2d::{
+    dynamics::{RigidBodyBuilder, RigidBodyHandle, RigidBodySet},
+    geometry::{ActiveCollisionTypes, Ball, ColliderBuilder, ColliderSet},
+    pipeline::ActiveEvents,
+};
+
+use crate::{
+    camera::snap_to_pixel,
+    clock,
+    constants::{
+        PROJECTILE_DRAW_SIZE, PROJECTILE_LIFETIME, PROJECTILE_RADIUS, PROJECTILE_SPEED,
+        ROCK_TILE_ID, TILESET_MAP_ID,
+    },
+    entity::{EntityId, EntityKind, EntityRegistry},
+    event::GameEvent,
+    physics::{CollisionLayer, Physics},
+};
+
+/// A thrown rock. Flies in a straight line until it hits something or runs out of range.
+pub struct Projectile {
+    pub id: EntityId,
+    /// center of the projectile in world space
+    pub position: Vec2,
+    body_handle: RigidBodyHandle,
+    spawn_time: f64,
+}
+
+impl Projectile {
+    pub fn create(
+        position: Vec2,
+        direction: Vec2,
+        collider_set: &mut ColliderSet,
+        rigid_body_set: &mut RigidBodySet,
+        entities: &mut EntityRegistry,
+    ) -> Self {
+        let velocity = direction.normalize_or_zero() * PROJECTILE_SPEED;
+        let body = RigidBodyBuilder::kinematic_velocity_based()
+            .translation(vector![position.x, position.y])
+            .linvel(vector![velocity.x, velocity.y])
+            .build();
+
+        // kinematic sensors don't report fixed colliders (walls) unless asked to
+        let collider = ColliderBuilder::ball(PROJECTILE_RADIUS)
+            .sensor(true)
+            .collision_groups(CollisionLayer::Projectile.groups())
+            .active_events(ActiveEvents::COLLISION_EVENTS)
+            .active_collision_types(
+                ActiveCollisionTypes::default() | ActiveCollisionTypes::KINEMATIC_FIXED,
+            )
+            .build();
+
+        let body_handle = rigid_body_set.insert(body);
+        let collider_handle =
+            collider_set.insert_with_parent(collider, body_handle, rigid_body_set);
+        let id = entities.spawn(EntityKind::Projectile);
+        entities.register_collider(collider_handle, id);
+        entities.register_body(body_handle, id);
+
+        Self {
+            id,
+            position,
+            body_handle,
+            spawn_time: clock::now(),
+        }
+    }
+
+    pub fn post_physics(&mut self, physics: &Physics) {
+        let body = &physics.bodies[self.body_handle];
+        self.position.x = body.translation().x;
+        self.position.y = body.translation().y;
+    }
+
+    /// What the rock would hit in the next `dt` seconds of flight, as an impact event
+    pub fn sweep(&self, physics: &Physics, dt: f32) -> Option<GameEvent> {
+        let velocity = physics.bodies[self.body_handle].linvel();
+        let hit = physics.shape_cast(
+            &Ball::new(PROJECTILE_RADIUS),
+            self.position,
+            vec2(velocity.x, velocity.y) * dt,
+            CollisionLayer::Projectile.query(),
+        )?;
+        let guard = match hit.entity? {
+            (guard, EntityKind::Guard) => Some(guard),
+            (_, EntityKind::Wall) => None,
+            _ => return None,
+        };
+        Some(GameEvent::ProjectileImpact {
+            projectile: self.id,
+            guard,
+        })
+    }
+
+    /// The projectile has flown its full range and should land
+    pub fn is_expired(&self) -> bool {
+        clock::now() > self.spawn_time + PROJECTILE_LIFETIME
+    }
+
+    pub fn destroy_physics(&self, physics: &mut Physics) {
+        physics.remove_body(&self.body_handle, true);
+        physics.entities.despawn(self.id);
+    }
+
+    pub fn draw(&self, tile_map: &TiledMap) {
+        let position = snap_to_pixel(self.position);
+        let draw_rect = Rect::new(
+            position.x - PROJECTILE_DRAW_SIZE / 2.,
+            position.y - PROJECTILE_DRAW_SIZE / 2.,
+            PROJECTILE_DRAW_SIZE,
+            PROJECTILE_DRAW_SIZE,
+        );
+        tile_map.spr(TILESET_MAP_ID, ROCK_TILE_ID, draw_rect);
+    }
+}
@@ -0,0 +1,181 @@
+use anyhow::{anyhow, Result};
+use nanoserde::{DeJson, SerJson};
+
+use crate::{
+    constants::SAVE_PATH, items::ItemKind, passage::PassageDirection, splits::Splits,
+    stats::RunStats, storage, upgrades::PlayerUpgrades,
+};
+
+/// Snapshot of a run in progress, written when the player quits so they can continue later.
+///
+/// Only what can't be rebuilt is stored: the tiles as they currently look, and where everything
+/// is. Colliders are recreated from this on load. Tile positions are stored as `[x, y]`.
+#[derive(Clone, Debug, DeJson, SerJson)]
+pub struct SaveGame {
+    pub floor: u32,
+    pub score: u32,
+    pub score_target: u32,
+    pub keys: u32,
+    /// coins carried down from earlier floors, and found on this one
+    #[nserde(default)]
+    pub coins: u32,
+    /// bought in the shop between floors
+    #[nserde(default)]
+    pub upgrades: PlayerUpgrades,
+    /// seconds on the run clock
+    pub elapsed: f64,
+    pub stats: RunStats,
+    /// split times through the run so far
+    #[nserde(default)]
+    pub splits: Splits,
+    /// name of the tile theme the map was painted with
+    pub theme: String,
+    /// row-major tile IDs of the terrain and props layers
+    pub terrain: Vec<Option<u32>>,
+    pub props: Vec<Option<u32>>,
+    pub player: SavedCharacter,
+    pub guards: Vec<SavedCharacter>,
+    /// trapped guards, at the cell doors they pace behind
+    #[nserde(default)]
+    pub jailed: Vec<SavedCharacter>,
+    /// seconds until the guards seal the exit, if the alarm was going off
+    #[nserde(default_with = "crate::json::none")]
+    pub alarm_time_left: Option<f64>,
+    pub guard_doors: Vec<SavedGuardDoor>,
+    pub exit_door: [u32; 2],
+    /// leftmost tile and width of each locked gate
+    pub locked_doors: Vec<([u32; 2], u32)>,
+    /// leftmost tile, width and direction of each one-way passage
+    #[nserde(default)]
+    pub one_way_passages: Vec<([u32; 2], u32, PassageDirection)>,
+    pub items: Vec<([u32; 2], ItemKind)>,
+    /// position and timer offset of each spike trap
+    pub spike_traps: Vec<([u32; 2], f64)>,
+    /// each pressure plate, and the guard door it works
+    pub pressure_plates: Vec<([u32; 2], [u32; 2])>,
+    /// each lever, the guard door it works, and whether it has been pulled
+    #[nserde(default)]
+    pub levers: Vec<([u32; 2], [u32; 2], bool)>,
+    /// top left of each crate, wherever it was shoved to
+    #[nserde(default)]
+    pub crates: Vec<[f32; 2]>,
+    /// each chest, the coins in it, and whether it has been opened
+    #[nserde(default)]
+    pub chests: Vec<([u32; 2], u32, bool)>,
+    /// tile of each barrel to hide in. cells to hide in come from the guard doors.
+    #[nserde(default)]
+    pub barrels: Vec<[u32; 2]>,
+}
+
+#[derive(Clone, Debug, DeJson, SerJson)]
+pub struct SavedCharacter {
+    pub position: [f32; 2],
+    pub health: u32,
+    pub is_boss: bool,
+}
+
+#[derive(Clone, Debug, DeJson, SerJson)]
+pub struct SavedGuardDoor {
+    pub position: [u32; 2],
+    pub is_open: bool,
+    pub is_trapped: bool,
+    pub reinforced: bool,
+    #[nserde(default_with = "crate::json::none")]
+    pub cycle_offset: Option<f64>,
+}
+
+impl SaveGame {
+    pub fn exists() -> bool {
+        storage::exists(SAVE_PATH)
+    }
+
+    pub fn read() -> Result<Self> {
+        let json = storage::read(SAVE_PATH)?.ok_or_else(|| anyhow!("there is no saved game"))?;
+        Ok(DeJson::deserialize_json(&json)?)
+    }
+
+    pub fn write(&self) -> Result<()> {
+        storage::write(SAVE_PATH, &self.serialize_json())
+    }
+
+    /// Throw away the save, once the run it belongs to is over
+    pub fn delete() -> Result<()> {
+        storage::remove(SAVE_PATH)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_game_round_trips_through_json() {
+        let save = SaveGame {
+            floor: 2,
+            score: 1,
+            score_target: 3,
+            keys: 1,
+            coins: 30,
+            upgrades: PlayerUpgrades {
+                extra_hearts: 1,
+                quick_attack: 0,
+                long_reach: 2,
+            },
+            elapsed: 42.5,
+            stats: RunStats {
+                guards_trapped: 2,
+                distance_traveled: 80.5,
+                ..Default::default()
+            },
+            splits: Splits {
+                traps: vec![12.5],
+                floors: vec![],
+            },
+            theme: "crypt".into(),
+            terrain: vec![Some(0), None, Some(48)],
+            props: vec![None, Some(66), None],
+            player: SavedCharacter {
+                position: [3.5, 4.],
+                health: 2,
+                is_boss: false,
+            },
+            guards: vec![SavedCharacter {
+                position: [10., 12.25],
+                health: 8,
+                is_boss: true,
+            }],
+            jailed: vec![SavedCharacter {
+                position: [6., 6.],
+                health: 3,
+                is_boss: false,
+            }],
+            alarm_time_left: Some(12.5),
+            guard_doors: vec![SavedGuardDoor {
+                position: [5, 6],
+                is_open: false,
+                is_trapped: true,
+                reinforced: false,
+                cycle_offset: Some(1.25),
+            }],
+            exit_door: [7, 8],
+            locked_doors: vec![([1, 2], 3)],
+            one_way_passages: vec![([3, 5], 3, PassageDirection::Up)],
+            items: vec![([4, 4], ItemKind::Key)],
+            spike_traps: vec![([9, 9], 0.5)],
+            pressure_plates: vec![([6, 9], [5, 6])],
+            levers: vec![([3, 9], [5, 6], true)],
+            crates: vec![[2.5, 3.75]],
+            chests: vec![([8, 2], 12, false)],
+            barrels: vec![[4, 1]],
+        };
+
+        let loaded = SaveGame::deserialize_json(&save.serialize_json()).unwrap();
+        assert_eq!(loaded.serialize_json(), save.serialize_json());
+        assert_eq!(loaded.stats, save.stats);
+        assert_eq!(loaded.guards[0].position, [10., 12.25]);
+        assert_eq!(loaded.jailed[0].position, [6., 6.]);
+        assert_eq!(loaded.alarm_time_left, Some(12.5));
+        assert_eq!(loaded.items[0].1, ItemKind::Key);
+        assert_eq!(loaded.guard_doors[0].cycle_offset, Some(1.25));
+    }
+}
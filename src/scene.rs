@@ -0,0 +1,194 @@
+use anyhow::Result;
+use macroquad::window::next_frame;
+
+/// Where to go once a scene is done
+pub enum Transition<C> {
+    /// open a scene over this one, which picks up where it left off once the new one is popped
+    Push(Box<dyn Scene<C>>),
+    /// close this scene and go back to the one underneath
+    Pop,
+    /// close every open scene and start a new one
+    Switch(Box<dyn Scene<C>>),
+}
+
+/// One screen of the game, like a menu or the round, run a frame at a time over whatever it
+/// needs from `C`, which is the game for everything but the error screen.
+///
+/// Buttons are handled as they are drawn, so whatever a scene chooses during a frame, by key or
+/// button, is only acted on when `update` runs at the start of the next one. That way the key that
+/// closes a scene doesn't also count in the scene it goes back to.
+pub trait Scene<C> {
+    /// The scene has just been put on the stack
+    fn open(&mut self, _ctx: &mut C) {}
+
+    /// The scene is on top again, now that the one over it has been popped
+    fn resume(&mut self, _ctx: &mut C) {}
+
+    /// The scene has been taken off the stack for good
+    fn close(&mut self, _ctx: &mut C) -> Result<()> {
+        Ok(())
+    }
+
+    /// Read keys that aren't tied to a button
+    fn handle_input(&mut self, _ctx: &mut C) {}
+
+    /// Advance the scene by a frame. Returns where to go next once the scene is done.
+    fn update(&mut self, ctx: &mut C) -> Option<Transition<C>>;
+
+    fn draw(&mut self, ctx: &mut C);
+}
+
+/// Run a scene until it asks to go somewhere else
+pub async fn run<C>(scene: &mut dyn Scene<C>, ctx: &mut C) -> Transition<C> {
+    loop {
+        if let Some(transition) = scene.update(ctx) {
+            return transition;
+        }
+        scene.handle_input(ctx);
+        scene.draw(ctx);
+        next_frame().await
+    }
+}
+
+/// The scenes that are open, with the one being played on top. Anything underneath waits until
+/// the scenes over it are popped, like a round under the pause menu.
+pub struct SceneStack<C> {
+    scenes: Vec<Box<dyn Scene<C>>>,
+}
+
+impl<C> SceneStack<C> {
+    pub fn new(mut scene: Box<dyn Scene<C>>, ctx: &mut C) -> Self {
+        scene.open(ctx);
+        Self {
+            scenes: vec![scene],
+        }
+    }
+
+    pub fn current(&mut self) -> &mut dyn Scene<C> {
+        self.scenes
+            .last_mut()
+            .expect("there is always a scene open")
+            .as_mut()
+    }
+
+    pub fn apply(&mut self, transition: Transition<C>, ctx: &mut C) -> Result<()> {
+        match transition {
+            Transition::Push(mut scene) => {
+                scene.open(ctx);
+                self.scenes.push(scene);
+            }
+            // the bottom scene has nothing to go back to, so it stays
+            Transition::Pop if self.scenes.len() > 1 => {
+                if let Some(mut scene) = self.scenes.pop() {
+                    scene.close(ctx)?;
+                }
+                self.current().resume(ctx);
+            }
+            Transition::Pop => {}
+            Transition::Switch(mut scene) => {
+                // top down, so a menu is done with the round before the round is torn down
+                while let Some(mut open) = self.scenes.pop() {
+                    open.close(ctx)?;
+                }
+                scene.open(ctx);
+                self.scenes.push(scene);
+            }
+        }
+        Ok(())
+    }
+
+    /// Run the top scene for a frame, moving on to wherever it asks to go
+    pub async fn run_frame(&mut self, ctx: &mut C) -> Result<()> {
+        if let Some(transition) = self.current().update(ctx) {
+            return self.apply(transition, ctx);
+        }
+        let scene = self.current();
+        scene.handle_input(ctx);
+        scene.draw(ctx);
+        next_frame().await;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A scene that writes down what happens to it
+    struct Logged {
+        name: &'static str,
+    }
+
+    impl Logged {
+        fn new(name: &'static str) -> Box<Self> {
+            Box::new(Self { name })
+        }
+    }
+
+    impl Scene<Vec<String>> for Logged {
+        fn open(&mut self, log: &mut Vec<String>) {
+            log.push(format!("open {}", self.name));
+        }
+
+        fn resume(&mut self, log: &mut Vec<String>) {
+            log.push(format!("resume {}", self.name));
+        }
+
+        fn close(&mut self, log: &mut Vec<String>) -> Result<()> {
+            log.push(format!("close {}", self.name));
+            Ok(())
+        }
+
+        fn update(&mut self, _log: &mut Vec<String>) -> Option<Transition<Vec<String>>> {
+            None
+        }
+
+        fn draw(&mut self, _log: &mut Vec<String>) {}
+    }
+
+    #[test]
+    fn test_pause_pops_back_to_the_round_it_covered() {
+        let mut log = Vec::new();
+        let mut scenes = SceneStack::new(Logged::new("round"), &mut log);
+        scenes
+            .apply(Transition::Push(Logged::new("paused")), &mut log)
+            .unwrap();
+        scenes
+            .apply(Transition::Push(Logged::new("settings")), &mut log)
+            .unwrap();
+
+        scenes.apply(Transition::Pop, &mut log).unwrap();
+        scenes.apply(Transition::Pop, &mut log).unwrap();
+        // the round is at the bottom, so there's nothing left to pop
+        scenes.apply(Transition::Pop, &mut log).unwrap();
+        assert_eq!(
+            log,
+            [
+                "open round",
+                "open paused",
+                "open settings",
+                "close settings",
+                "resume paused",
+                "close paused",
+                "resume round",
+            ]
+        );
+
+        log.clear();
+        scenes
+            .apply(Transition::Push(Logged::new("paused")), &mut log)
+            .unwrap();
+        scenes
+            .apply(Transition::Switch(Logged::new("main menu")), &mut log)
+            .unwrap();
+        assert_eq!(
+            log,
+            [
+                "open paused",
+                "close paused",
+                "close round",
+                "open main menu"
+            ]
+        );
+    }
+}
@@ -0,0 +1,21 @@
+use std::{
+    fs,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Result};
+use macroquad::texture::RenderTarget;
+
+use crate::constants::SCREENSHOT_DIR;
+
+/// Write the contents of a render target to a timestamped PNG. Returns the path written to.
+pub fn save_render_target(render_target: &RenderTarget, name: &str) -> Result<String> {
+    if cfg!(target_arch = "wasm32") {
+        return Err(anyhow!("screenshots can't be saved on the web"));
+    }
+    fs::create_dir_all(SCREENSHOT_DIR)?;
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+    let path = format!("{}/{}_{}.png", SCREENSHOT_DIR, name, timestamp);
+    render_target.texture.get_texture_data().export_png(&path);
+    Ok(path)
+}
@@ -0,0 +1,155 @@
+use anyhow::Result;
+use macroquad::window::{request_new_screen_size, set_fullscreen};
+use nanoserde::{DeJson, SerJson};
+
+use crate::{
+    constants::{DEFAULT_LANGUAGE, SETTINGS_PATH, TEXT_SCALES, WINDOW_SIZE_PRESETS},
+    difficulty::Difficulty,
+    palette::Palette,
+    storage,
+};
+
+/// Player preferences, kept on disk between sessions
+#[derive(Clone, Debug, PartialEq, DeJson, SerJson)]
+pub struct Settings {
+    /// index into the window size presets
+    pub window_size: usize,
+    pub fullscreen: bool,
+    /// only scale the game up by whole multiples, so every pixel stays the same size
+    pub integer_scaling: bool,
+    /// scanlines and screen curvature over the world
+    #[nserde(default)]
+    pub crt: bool,
+    /// index into the color palettes for alerts and guidance
+    #[nserde(default)]
+    pub palette: usize,
+    /// outline the player and guards
+    #[nserde(default)]
+    pub high_contrast: bool,
+    /// draw shapes alongside color cues
+    #[nserde(default)]
+    pub shape_cues: bool,
+    /// no camera shake
+    #[nserde(default)]
+    pub reduced_motion: bool,
+    /// index into the text scales for menus and the HUD
+    #[nserde(default)]
+    pub text_size: usize,
+    /// code of the locale menus and UI are shown in. unknown codes get english.
+    #[nserde(default)]
+    pub language: String,
+    /// opening the exit sounds an alarm, and the guards rush to seal it before time runs out
+    #[nserde(default)]
+    pub escape_alarm: bool,
+    /// index into the difficulties
+    #[nserde(default)]
+    pub difficulty: usize,
+    /// show split times against the best run under the timer
+    #[nserde(default)]
+    pub speedrun_splits: bool,
+    /// point out the nearest open cell doors while a guard gives chase
+    #[nserde(default)]
+    pub door_markers: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            window_size: 1,
+            fullscreen: false,
+            integer_scaling: false,
+            crt: false,
+            palette: 0,
+            high_contrast: false,
+            shape_cues: false,
+            reduced_motion: false,
+            text_size: 0,
+            language: DEFAULT_LANGUAGE.into(),
+            escape_alarm: false,
+            difficulty: 0,
+            speedrun_splits: false,
+            door_markers: false,
+        }
+    }
+}
+
+impl Settings {
+    /// Read settings from storage. Nothing stored yet gives the defaults.
+    pub fn read() -> Result<Self> {
+        match storage::read(SETTINGS_PATH)? {
+            Some(json) => Ok(DeJson::deserialize_json(&json)?),
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub fn write(&self) -> Result<()> {
+        storage::write(SETTINGS_PATH, &self.serialize_json())
+    }
+
+    /// Width and height of the window when it isn't fullscreen
+    pub fn window_size(&self) -> (u32, u32) {
+        WINDOW_SIZE_PRESETS[self.window_size % WINDOW_SIZE_PRESETS.len()]
+    }
+
+    pub fn next_window_size(&mut self) {
+        self.window_size = (self.window_size + 1) % WINDOW_SIZE_PRESETS.len();
+    }
+
+    /// How much bigger than usual to draw menu and HUD text
+    pub fn text_scale(&self) -> f32 {
+        TEXT_SCALES[self.text_size % TEXT_SCALES.len()]
+    }
+
+    pub fn next_text_size(&mut self) {
+        self.text_size = (self.text_size + 1) % TEXT_SCALES.len();
+    }
+
+    pub fn next_palette(&mut self) {
+        self.palette = (self.palette + 1) % Palette::ALL.len();
+    }
+
+    pub fn difficulty(&self) -> Difficulty {
+        Difficulty::get(self.difficulty)
+    }
+
+    pub fn next_difficulty(&mut self) {
+        self.difficulty = (self.difficulty + 1) % Difficulty::ALL.len();
+    }
+
+    /// Resize the window to match the settings
+    pub fn apply_window(&self) {
+        set_fullscreen(self.fullscreen);
+        if !self.fullscreen {
+            let (width, height) = self.window_size();
+            request_new_screen_size(width as f32, height as f32);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_settings_missing_newer_fields_get_defaults() {
+        let json = r#"{"window_size":2,"fullscreen":true,"integer_scaling":false}"#;
+        let settings = Settings::deserialize_json(json).unwrap();
+        assert_eq!(settings.window_size, 2);
+        assert!(!settings.crt);
+        assert_eq!(settings.palette, 0);
+        assert_eq!(settings.text_scale(), 1.);
+    }
+
+    #[test]
+    fn test_window_size_cycles_through_presets() {
+        let mut settings = Settings::default();
+        let first = settings.window_size();
+        for _ in 0..WINDOW_SIZE_PRESETS.len() {
+            settings.next_window_size();
+        }
+        assert_eq!(settings.window_size(), first);
+
+        settings.window_size = WINDOW_SIZE_PRESETS.len();
+        assert_eq!(settings.window_size(), WINDOW_SIZE_PRESETS[0]);
+    }
+}
@@ -0,0 +1,75 @@
+use std::collections::HashMap;
+
+use macroquad::math::{IVec2, Vec2};
+
+/// Buckets items by position into square cells, so that finding what is near a point only looks
+/// at a few cells instead of every item.
+///
+/// Items are stored by a point. Queries return everything in the cells a square around the query
+/// point touches, which can include items a little outside the radius, so callers still do their
+/// own exact check on what comes back.
+#[derive(Debug)]
+pub struct SpatialHash<T> {
+    cell_size: f32,
+    cells: HashMap<IVec2, Vec<T>>,
+}
+
+impl<T: Copy + Ord> SpatialHash<T> {
+    pub fn new(cell_size: f32) -> Self {
+        Self {
+            cell_size,
+            cells: HashMap::new(),
+        }
+    }
+
+    /// Empty the hash, keeping its cells' allocations around to fill again
+    pub fn clear(&mut self) {
+        self.cells.values_mut().for_each(Vec::clear);
+    }
+
+    pub fn insert(&mut self, position: Vec2, item: T) {
+        self.cells
+            .entry(self.cell(position))
+            .or_default()
+            .push(item);
+    }
+
+    /// Items stored within about `radius` of `position`, sorted
+    pub fn query(&self, position: Vec2, radius: f32) -> Vec<T> {
+        let (min, max) = (self.cell(position - radius), self.cell(position + radius));
+        let mut items: Vec<T> = (min.y..=max.y)
+            .flat_map(|y| (min.x..=max.x).map(move |x| IVec2::new(x, y)))
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .copied()
+            .collect();
+        items.sort_unstable();
+        items
+    }
+
+    fn cell(&self, position: Vec2) -> IVec2 {
+        (position / self.cell_size).floor().as_ivec2()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use macroquad::math::vec2;
+
+    #[test]
+    fn test_query_finds_nearby_items_across_cells() {
+        let mut hash = SpatialHash::new(4.);
+        hash.insert(vec2(3.5, 3.5), 0);
+        hash.insert(vec2(4.5, 4.5), 1);
+        hash.insert(vec2(-0.5, 0.), 2);
+        hash.insert(vec2(40., 40.), 3);
+
+        assert_eq!(hash.query(vec2(4., 4.), 1.), vec![0, 1]);
+        assert_eq!(hash.query(vec2(0., 0.), 1.), vec![0, 2]);
+        assert!(hash.query(vec2(20., 20.), 2.).is_empty());
+
+        hash.clear();
+        assert!(hash.query(vec2(4., 4.), 1.).is_empty());
+    }
+}
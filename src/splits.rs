@@ -0,0 +1,170 @@
+use anyhow::Result;
+use nanoserde::{DeJson, SerJson};
+
+use crate::{constants::SPLITS_PATH, storage};
+
+/// Times through a run at each milestone: every guard trapped on a floor, and every floor escaped.
+/// The best times ever reached at each milestone are kept the same way, to race against.
+#[derive(Clone, Debug, Default, PartialEq, DeJson, SerJson)]
+pub struct Splits {
+    /// seconds into the floor each guard was trapped, in order
+    pub traps: Vec<f64>,
+    /// seconds into the run each floor was escaped, in order
+    pub floors: Vec<f64>,
+}
+
+impl Splits {
+    /// Read the best splits from storage. Nothing stored yet means nothing to race against.
+    pub fn read() -> Result<Self> {
+        match storage::read(SPLITS_PATH)? {
+            Some(json) => Ok(DeJson::deserialize_json(&json)?),
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub fn write(&self) -> Result<()> {
+        storage::write(SPLITS_PATH, &self.serialize_json())
+    }
+}
+
+/// A milestone just reached
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Split {
+    pub kind: SplitKind,
+    /// how many of its kind came before it
+    pub index: usize,
+    /// seconds into the floor for a trap, or into the run for a floor
+    pub time: f64,
+    /// seconds behind the best time for the same milestone, or ahead of it if negative. None the
+    /// first time it's reached.
+    pub delta: Option<f64>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SplitKind {
+    Trap,
+    Floor,
+}
+
+/// Splits for the run in progress, checked against the best ones as they are reached
+#[derive(Debug, Default)]
+pub struct SplitTracker {
+    pub current: Splits,
+    pub best: Splits,
+    /// the latest milestone, for the HUD
+    pub last: Option<Split>,
+}
+
+impl SplitTracker {
+    pub fn new(best: Splits) -> Self {
+        Self {
+            best,
+            ..Default::default()
+        }
+    }
+
+    /// Start over from the first floor
+    pub fn start_run(&mut self) {
+        self.current = Splits::default();
+        self.last = None;
+    }
+
+    /// Start counting traps again on a new floor. The floor just escaped stays up until the first
+    /// trap.
+    pub fn start_floor(&mut self) {
+        self.current.traps.clear();
+    }
+
+    /// A guard was trapped this many seconds into the floor
+    pub fn trap(&mut self, time: f64) {
+        let split = record(
+            SplitKind::Trap,
+            &mut self.current.traps,
+            &mut self.best.traps,
+            time,
+        );
+        self.last = Some(split);
+    }
+
+    /// The floor was escaped after taking this many seconds
+    pub fn floor(&mut self, floor_time: f64) {
+        let time = self.current.floors.last().copied().unwrap_or(0.) + floor_time;
+        let split = record(
+            SplitKind::Floor,
+            &mut self.current.floors,
+            &mut self.best.floors,
+            time,
+        );
+        self.last = Some(split);
+    }
+}
+
+/// Add a split, and keep it as the best if it beat the best for the same milestone
+fn record(kind: SplitKind, times: &mut Vec<f64>, best: &mut Vec<f64>, time: f64) -> Split {
+    let index = times.len();
+    times.push(time);
+    let delta = best.get(index).map(|best| time - best);
+    match best.get_mut(index) {
+        Some(best) => *best = best.min(time),
+        None => best.push(time),
+    }
+    Split {
+        kind,
+        index,
+        time,
+        delta,
+    }
+}
+
+/// Minutes, seconds and milliseconds, like 01:05.250
+pub fn clock_str(seconds: f64) -> String {
+    let millis = (seconds.max(0.) * 1000.) as u64;
+    format!(
+        "{:02}:{:02}.{:03}",
+        millis / 60_000,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+/// Seconds ahead of or behind a best time, always signed, like -1.250
+pub fn delta_str(delta: f64) -> String {
+    format!("{:+.3}", delta)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_splits_race_against_and_keep_the_best() {
+        let mut splits = SplitTracker::new(Splits {
+            traps: vec![10.],
+            floors: vec![60.],
+        });
+        splits.trap(12.);
+        assert_eq!(splits.last.unwrap().delta, Some(2.));
+        splits.trap(20.);
+        assert_eq!(splits.last.unwrap().delta, None);
+        splits.floor(50.);
+        assert_eq!(splits.last.unwrap().delta, Some(-10.));
+
+        splits.start_floor();
+        splits.floor(30.);
+        let last = splits.last.unwrap();
+        assert_eq!(
+            (last.kind, last.index, last.time),
+            (SplitKind::Floor, 1, 80.)
+        );
+        assert_eq!(splits.best.traps, vec![10., 20.]);
+        assert_eq!(splits.best.floors, vec![50., 80.]);
+    }
+
+    #[test]
+    fn test_clock_shows_milliseconds() {
+        assert_eq!(clock_str(65.25), "01:05.250");
+        assert_eq!(clock_str(0.), "00:00.000");
+        assert_eq!(delta_str(-1.25), "-1.250");
+        assert_eq!(delta_str(0.5), "+0.500");
+    }
+}
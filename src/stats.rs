@@ -0,0 +1,68 @@
+use nanoserde::{DeJson, SerJson};
+
+use crate::{
+    damage::DamageKind,
+    event::{EventHandler, GameEvent},
+};
+
+/// Tallies kept over a round, shown on the game over screen
+#[derive(Clone, Debug, Default, PartialEq, DeJson, SerJson)]
+pub struct RunStats {
+    /// seconds from the start of the round to the end of it
+    pub time: f64,
+    pub guards_trapped: u32,
+    pub damage_taken: u32,
+    /// tiles walked by the player
+    pub distance_traveled: f32,
+    pub times_spotted: u32,
+    /// guards put down by shoves, on difficulties that allow it
+    #[nserde(default)]
+    pub guards_defeated: u32,
+    /// what finished off the player, if the round ended in death
+    #[nserde(default_with = "crate::json::none")]
+    pub death_cause: Option<DamageKind>,
+}
+
+impl EventHandler for RunStats {
+    fn handle_event(&mut self, event: &GameEvent) {
+        match event {
+            GameEvent::GuardTrapped { .. } => self.guards_trapped += 1,
+            GameEvent::PlayerDamaged { amount } => self.damage_taken += amount,
+            GameEvent::GuardAlerted { .. } => self.times_spotted += 1,
+            GameEvent::GuardDefeated { .. } => self.guards_defeated += 1,
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::{EntityKind, EntityRegistry};
+    use macroquad::math::Vec2;
+
+    #[test]
+    fn test_run_stats_tally_events() {
+        let mut entities = EntityRegistry::default();
+        let guard = entities.spawn(EntityKind::Guard);
+        let door = entities.spawn(EntityKind::GuardDoor);
+        let position = Vec2::ZERO;
+        let mut stats = RunStats::default();
+
+        stats.handle_event(&GameEvent::GuardAlerted { guard, position });
+        stats.handle_event(&GameEvent::GuardAlerted { guard, position });
+        stats.handle_event(&GameEvent::PlayerDamaged { amount: 2 });
+        stats.handle_event(&GameEvent::GuardTrapped {
+            door,
+            guard,
+            position,
+        });
+        stats.handle_event(&GameEvent::GuardDefeated { guard, position });
+        stats.handle_event(&GameEvent::ExitOpened);
+
+        assert_eq!(stats.times_spotted, 2);
+        assert_eq!(stats.damage_taken, 2);
+        assert_eq!(stats.guards_trapped, 1);
+        assert_eq!(stats.guards_defeated, 1);
+    }
+}
@@ -0,0 +1,156 @@
+use crate::constants::{
+    INVULNERABLE_TILE_ID, POISONED_TILE_ID, POISON_DAMAGE, POISON_TICK_INTERVAL,
+    SLOW_ACCELERATION_FACTOR, SLOW_KNOCKBACK_FACTOR, SLOW_TILE_ID,
+    SPEED_POTION_ACCELERATION_FACTOR, SPEED_POTION_TILE_ID,
+};
+
+/// A timed effect on a character
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StatusKind {
+    /// moves slower, and gets knocked around more
+    Slow,
+    /// moves faster, like after a speed potion
+    Haste,
+    /// takes no damage or knockback
+    Invulnerable,
+    /// loses health every few seconds
+    Poisoned,
+}
+
+impl StatusKind {
+    /// HUD icon for the effect
+    pub fn sprite_id(&self) -> u32 {
+        match self {
+            StatusKind::Slow => SLOW_TILE_ID,
+            StatusKind::Haste => SPEED_POTION_TILE_ID,
+            StatusKind::Invulnerable => INVULNERABLE_TILE_ID,
+            StatusKind::Poisoned => POISONED_TILE_ID,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct StatusEffect {
+    kind: StatusKind,
+    until: f64,
+}
+
+/// The timed effects on a character, and what they do to its stats.
+///
+/// Each kind is on or off; getting one again only keeps it going until the later of the two end
+/// times, so effects never stack up in strength. Different kinds all apply at once, so haste and
+/// slow partly cancel out.
+#[derive(Clone, Debug, Default)]
+pub struct StatusEffects {
+    effects: Vec<StatusEffect>,
+    next_poison_tick: f64,
+}
+
+impl StatusEffects {
+    pub fn add(&mut self, kind: StatusKind, now: f64, duration: f64) {
+        let until = now + duration;
+        if let Some(effect) = self
+            .effects
+            .iter_mut()
+            .find(|e| e.kind == kind && e.until > now)
+        {
+            effect.until = effect.until.max(until);
+            return;
+        }
+        self.effects.retain(|e| e.kind != kind);
+        self.effects.push(StatusEffect { kind, until });
+        if kind == StatusKind::Poisoned {
+            self.next_poison_tick = now + POISON_TICK_INTERVAL;
+        }
+    }
+
+    pub fn has(&self, kind: StatusKind, now: f64) -> bool {
+        self.effects.iter().any(|e| e.kind == kind && e.until > now)
+    }
+
+    /// Effects still running, in the order they were first picked up
+    pub fn active(&self, now: f64) -> impl Iterator<Item = StatusKind> + '_ {
+        self.effects
+            .iter()
+            .filter(move |e| e.until > now)
+            .map(|e| e.kind)
+    }
+
+    /// Drop effects that have run out. Returns the poison damage that came due since last time.
+    pub fn tick(&mut self, now: f64) -> u32 {
+        let mut damage = 0;
+        if let Some(poison) = self.effects.iter().find(|e| e.kind == StatusKind::Poisoned) {
+            while self.next_poison_tick <= now.min(poison.until) {
+                damage += POISON_DAMAGE;
+                self.next_poison_tick += POISON_TICK_INTERVAL;
+            }
+        }
+        self.effects.retain(|e| e.until > now);
+        damage
+    }
+
+    pub fn acceleration_factor(&self, now: f64) -> f32 {
+        self.active(now)
+            .map(|kind| match kind {
+                StatusKind::Slow => SLOW_ACCELERATION_FACTOR,
+                StatusKind::Haste => SPEED_POTION_ACCELERATION_FACTOR,
+                StatusKind::Invulnerable | StatusKind::Poisoned => 1.,
+            })
+            .product()
+    }
+
+    /// How much of a hit actually lands
+    pub fn damage_taken(&self, amount: u32, now: f64) -> u32 {
+        if self.has(StatusKind::Invulnerable, now) {
+            0
+        } else {
+            amount
+        }
+    }
+
+    pub fn knockback_factor(&self, now: f64) -> f32 {
+        if self.has(StatusKind::Invulnerable, now) {
+            0.
+        } else if self.has(StatusKind::Slow, now) {
+            SLOW_KNOCKBACK_FACTOR
+        } else {
+            1.
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_effects_refresh_instead_of_stacking() {
+        let mut status = StatusEffects::default();
+        status.add(StatusKind::Haste, 0., 4.);
+        status.add(StatusKind::Haste, 1., 1.);
+        assert_eq!(status.active(0.).count(), 1);
+        assert!(status.has(StatusKind::Haste, 3.5));
+        assert_eq!(
+            status.acceleration_factor(0.),
+            SPEED_POTION_ACCELERATION_FACTOR
+        );
+
+        status.add(StatusKind::Invulnerable, 0., 1.);
+        assert_eq!(status.damage_taken(2, 0.5), 0);
+        assert_eq!(status.knockback_factor(0.5), 0.);
+        status.tick(2.);
+        assert_eq!(status.damage_taken(2, 2.), 2);
+        status.tick(5.);
+        assert_eq!(status.active(5.).count(), 0);
+    }
+
+    #[test]
+    fn test_poison_ticks_until_it_wears_off() {
+        let mut status = StatusEffects::default();
+        status.add(StatusKind::Poisoned, 0., POISON_TICK_INTERVAL * 2.5);
+        assert_eq!(status.tick(POISON_TICK_INTERVAL / 2.), 0);
+        assert_eq!(status.tick(POISON_TICK_INTERVAL), POISON_DAMAGE);
+        assert_eq!(status.tick(POISON_TICK_INTERVAL * 10.), POISON_DAMAGE);
+        assert!(!status.has(StatusKind::Poisoned, POISON_TICK_INTERVAL * 10.));
+    }
+}
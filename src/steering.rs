@@ -0,0 +1,102 @@
+use std::f32::consts::TAU;
+
+use macroquad::math::Vec2;
+
+/// Direction to push off from neighbors closer than `radius`, so a crowd spreads out instead of
+/// piling into one spot. Closer neighbors push harder, up to a length of 1 each.
+pub fn separation(center: Vec2, neighbors: &[Vec2], radius: f32) -> Vec2 {
+    neighbors
+        .iter()
+        .map(|&neighbor| {
+            let away = center - neighbor;
+            let distance = away.length();
+            if distance <= 0. || distance >= radius {
+                return Vec2::ZERO;
+            }
+            away / distance * (1. - distance / radius)
+        })
+        .fold(Vec2::ZERO, |push, away| push + away)
+}
+
+/// Spots spaced evenly on a ring of `radius` around `target`, one for each chaser, so a squad
+/// comes at its target from every side instead of all running at the same point.
+///
+/// Chasers keep their order around the ring and the ring is turned to sit as close as it can to
+/// where they already are, so nobody has to cut across the others to reach their spot.
+pub fn surround_slots(target: Vec2, chasers: &[Vec2], radius: f32) -> Vec<Vec2> {
+    let angles: Vec<f32> = chasers
+        .iter()
+        .map(|&chaser| {
+            let offset = chaser - target;
+            offset.y.atan2(offset.x)
+        })
+        .collect();
+    let mut order: Vec<usize> = (0..chasers.len()).collect();
+    order.sort_by(|&a, &b| angles[a].total_cmp(&angles[b]));
+
+    let spacing = TAU / chasers.len() as f32;
+    let (sin, cos) = order
+        .iter()
+        .enumerate()
+        .map(|(slot, &chaser)| angles[chaser] - slot as f32 * spacing)
+        .fold((0., 0.), |(sin, cos), turn: f32| {
+            (sin + turn.sin(), cos + turn.cos())
+        });
+    let turn = sin.atan2(cos);
+
+    let mut slots = vec![target; chasers.len()];
+    for (slot, &chaser) in order.iter().enumerate() {
+        slots[chaser] = target + Vec2::from_angle(turn + slot as f32 * spacing) * radius;
+    }
+    slots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use macroquad::math::vec2;
+
+    #[test]
+    fn test_separation_pushes_away_from_close_neighbors() {
+        let center = vec2(0., 0.);
+        assert_eq!(separation(center, &[vec2(3., 0.), center], 2.), Vec2::ZERO);
+
+        let push = separation(center, &[vec2(1., 0.)], 2.);
+        assert_eq!(push, vec2(-0.5, 0.));
+        let closer = separation(center, &[vec2(0.5, 0.)], 2.);
+        assert!(closer.length() > push.length());
+
+        // neighbors on either side cancel out
+        let between = separation(center, &[vec2(1., 0.), vec2(-1., 0.)], 2.);
+        assert_eq!(between, Vec2::ZERO);
+    }
+
+    #[test]
+    fn test_surround_slots_spread_chasers_around_the_target() {
+        let target = vec2(10., 10.);
+        assert!(surround_slots(target, &[], 3.).is_empty());
+
+        // a lone chaser heads for the side it is already on
+        let [slot] = surround_slots(target, &[vec2(20., 10.)], 3.)[..] else {
+            panic!("expected one slot");
+        };
+        assert!(slot.distance(vec2(13., 10.)) < 1e-4);
+
+        // chasers bunched up on one side get spread around it
+        let chasers = [
+            vec2(20., 9.),
+            vec2(20., 10.),
+            vec2(20., 11.),
+            vec2(20., 12.),
+        ];
+        let slots = surround_slots(target, &chasers, 3.);
+        for (i, slot) in slots.iter().enumerate() {
+            assert!((slot.distance(target) - 3.).abs() < 1e-4);
+            for other in &slots[i + 1..] {
+                assert!(slot.distance(*other) > 4.);
+            }
+        }
+        // and keep their order, so the top chaser takes the top-most spot
+        assert!(slots[0].y < slots[2].y);
+    }
+}
@@ -0,0 +1,116 @@
+use anyhow::Result;
+
+/// Small text files that persist between sessions: settings, saves and the leaderboard.
+///
+/// Natively each key is a file path. On the web there is no file system, so keys are stored in
+/// the browser's localStorage instead, through the `stonehold_storage` plugin in
+/// `web/storage.js`.
+pub fn read(key: &str) -> Result<Option<String>> {
+    platform::read(key)
+}
+
+pub fn write(key: &str, value: &str) -> Result<()> {
+    platform::write(key, value)
+}
+
+pub fn remove(key: &str) -> Result<()> {
+    platform::remove(key)
+}
+
+pub fn exists(key: &str) -> bool {
+    platform::exists(key)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+mod platform {
+    use std::{fs, io::ErrorKind, path::Path};
+
+    use anyhow::Result;
+
+    pub fn read(key: &str) -> Result<Option<String>> {
+        match fs::read_to_string(key) {
+            Ok(value) => Ok(Some(value)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn write(key: &str, value: &str) -> Result<()> {
+        fs::write(key, value)?;
+        Ok(())
+    }
+
+    pub fn remove(key: &str) -> Result<()> {
+        if exists(key) {
+            fs::remove_file(key)?;
+        }
+        Ok(())
+    }
+
+    pub fn exists(key: &str) -> bool {
+        Path::new(key).exists()
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+mod platform {
+    use anyhow::Result;
+
+    extern "C" {
+        /// length in bytes of the stored value, or -1 if there is none
+        fn stonehold_storage_len(key: *const u8, key_len: usize) -> i32;
+        fn stonehold_storage_read(key: *const u8, key_len: usize, buf: *mut u8, buf_len: usize);
+        fn stonehold_storage_write(key: *const u8, key_len: usize, value: *const u8, len: usize);
+        fn stonehold_storage_remove(key: *const u8, key_len: usize);
+    }
+
+    /// version of the JS plugin this expects, checked by the miniquad loader
+    #[no_mangle]
+    pub extern "C" fn stonehold_storage_crate_version() -> u32 {
+        1 << 16
+    }
+
+    pub fn read(key: &str) -> Result<Option<String>> {
+        let len = unsafe { stonehold_storage_len(key.as_ptr(), key.len()) };
+        if len < 0 {
+            return Ok(None);
+        }
+        let mut buf = vec![0; len as usize];
+        unsafe { stonehold_storage_read(key.as_ptr(), key.len(), buf.as_mut_ptr(), buf.len()) };
+        Ok(Some(String::from_utf8(buf)?))
+    }
+
+    pub fn write(key: &str, value: &str) -> Result<()> {
+        unsafe { stonehold_storage_write(key.as_ptr(), key.len(), value.as_ptr(), value.len()) };
+        Ok(())
+    }
+
+    pub fn remove(key: &str) -> Result<()> {
+        unsafe { stonehold_storage_remove(key.as_ptr(), key.len()) };
+        Ok(())
+    }
+
+    pub fn exists(key: &str) -> bool {
+        unsafe { stonehold_storage_len(key.as_ptr(), key.len()) >= 0 }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_storage_round_trips_and_removes() {
+        let key = std::env::temp_dir().join("stonehold_storage_test.json");
+        let key = key.to_str().unwrap();
+        remove(key).unwrap();
+        assert_eq!(read(key).unwrap(), None);
+
+        write(key, "{\"a\": 1}").unwrap();
+        assert!(exists(key));
+        assert_eq!(read(key).unwrap().as_deref(), Some("{\"a\": 1}"));
+
+        remove(key).unwrap();
+        assert!(!exists(key));
+    }
+}
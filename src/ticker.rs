@@ -0,0 +1,88 @@
+use std::collections::VecDeque;
+
+use macroquad::{
+    color::{Color, WHITE},
+    text::{draw_text, measure_text},
+    time::get_time,
+};
+
+use crate::constants::{TICKER_FADE_TIME, TICKER_MAX_ENTRIES, TICKER_TIME};
+
+/// What has been happening in the round, listed up from the bottom of the screen with the newest
+/// last. Older lines scroll up as new ones come in, and fade away after a while.
+#[derive(Debug, Default)]
+pub struct Ticker {
+    /// each line and when it came in, oldest first
+    entries: VecDeque<(String, f64)>,
+}
+
+impl Ticker {
+    /// Add a line, unless the same one is still up
+    pub fn push(&mut self, text: String) {
+        self.push_at(text, get_time());
+    }
+
+    fn push_at(&mut self, text: String, now: f64) {
+        if self.entries.iter().any(|(entry, _)| *entry == text) {
+            return;
+        }
+        self.entries.push_back((text, now));
+        while self.entries.len() > TICKER_MAX_ENTRIES {
+            self.entries.pop_front();
+        }
+    }
+
+    /// Drop lines that have been up long enough
+    pub fn update(&mut self) {
+        self.update_at(get_time());
+    }
+
+    fn update_at(&mut self, now: f64) {
+        self.entries
+            .retain(|(_, shown_at)| now < shown_at + TICKER_TIME);
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Draw the lines right-aligned to `right`, with the newest sitting on `bottom`
+    pub fn draw(&self, right: f32, bottom: f32, font_size: f32) {
+        let now = get_time();
+        for (i, (text, shown_at)) in self.entries.iter().rev().enumerate() {
+            let age = now - shown_at;
+            let alpha = ((TICKER_TIME - age) / TICKER_FADE_TIME).clamp(0., 1.) as f32;
+            let width = measure_text(text, None, font_size as u16, 1.).width;
+            draw_text(
+                text,
+                right - width,
+                bottom - font_size * i as f32,
+                font_size,
+                Color { a: alpha, ..WHITE },
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ticker_skips_repeats_and_lets_old_lines_go() {
+        let mut ticker = Ticker::default();
+        ticker.push_at("spotted".into(), 0.);
+        ticker.push_at("spotted".into(), 1.);
+        assert_eq!(ticker.entries.len(), 1);
+        for i in 0..TICKER_MAX_ENTRIES {
+            ticker.push_at(format!("trapped {}", i), 1.);
+        }
+        assert_eq!(ticker.entries.len(), TICKER_MAX_ENTRIES);
+        assert_eq!(ticker.entries[0].0, "trapped 0");
+
+        ticker.update_at(1. + TICKER_TIME);
+        assert!(ticker.entries.is_empty());
+        ticker.push_at("spotted".into(), 10.);
+        assert_eq!(ticker.entries.len(), 1);
+    }
+}
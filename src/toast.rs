@@ -0,0 +1,65 @@
+use std::collections::VecDeque;
+
+use macroquad::{
+    color::{Color, WHITE},
+    shapes::draw_rectangle,
+    text::{draw_text, measure_text},
+    time::get_time,
+};
+
+use crate::constants::{TOAST_FADE_TIME, TOAST_TIME};
+
+/// Short messages that pop up at the top of the screen, one after another
+#[derive(Debug, Default)]
+pub struct Toasts {
+    queue: VecDeque<String>,
+    /// when the message at the front of the queue went up
+    shown_at: Option<f64>,
+}
+
+impl Toasts {
+    pub fn push(&mut self, text: String) {
+        self.queue.push_back(text);
+    }
+
+    /// Take down the current message once it has been up long enough
+    pub fn update(&mut self) {
+        let now = get_time();
+        match self.shown_at {
+            Some(shown_at) if now > shown_at + TOAST_TIME => {
+                self.queue.pop_front();
+                self.shown_at = (!self.queue.is_empty()).then_some(now);
+            }
+            None if !self.queue.is_empty() => self.shown_at = Some(now),
+            _ => {}
+        }
+    }
+
+    /// Draw the current message centered across a space `width` wide, fading in and out
+    pub fn draw(&self, width: f32, font_size: f32) {
+        let (Some(text), Some(shown_at)) = (self.queue.front(), self.shown_at) else {
+            return;
+        };
+        let age = get_time() - shown_at;
+        let alpha = (age.min(TOAST_TIME - age) / TOAST_FADE_TIME).clamp(0., 1.) as f32;
+
+        let size = measure_text(text, None, font_size as u16, 1.);
+        let padding = 8.;
+        let x = (width - size.width) / 2.;
+        let y = 16.;
+        draw_rectangle(
+            x - padding,
+            y,
+            size.width + padding * 2.,
+            size.height + padding * 2.,
+            Color::new(0., 0., 0., 0.7 * alpha),
+        );
+        draw_text(
+            text,
+            x,
+            y + padding + size.offset_y,
+            font_size,
+            Color { a: alpha, ..WHITE },
+        );
+    }
+}
@@ -0,0 +1,205 @@
+use macroquad::{
+    color::WHITE,
+    math::{vec2, UVec2, Vec2},
+    texture::{draw_texture_ex, DrawTextureParams},
+};
+use macroquad_tiled::{Layer, Map as TiledMap, Tile};
+use nalgebra::vector;
+use rapier2d::geometry::{ColliderBuilder, ColliderHandle, ColliderSet};
+
+use crate::{
+    clock,
+    constants::{
+        LEVER_LEAN, LEVER_SPRITE_ID, SPIKES_DOWN_TIME, SPIKES_UP_TIME, TILESET_MAP_ID,
+        TRAP_SENSOR_HALF_SIZE,
+    },
+    entity::{EntityId, EntityKind, EntityRegistry},
+    event::{EventQueue, GameEvent},
+    interact::Interactable,
+    map::{mapgen::xytoi, sprite_source, theme::TileTheme},
+    physics::CollisionLayer,
+};
+
+/// Spikes that poke up out of the floor on a cycle, hurting anyone standing on them
+pub struct SpikeTrap {
+    pub id: EntityId,
+    pub position: UVec2,
+    /// seconds added to the clock so that traps don't all fire together
+    pub offset: f64,
+    pub is_up: bool,
+    pub collider_handle: ColliderHandle,
+}
+
+impl SpikeTrap {
+    pub fn create(
+        position: UVec2,
+        offset: f64,
+        collider_set: &mut ColliderSet,
+        entities: &mut EntityRegistry,
+    ) -> Self {
+        let collider_handle = create_tile_sensor(position, collider_set);
+        let id = entities.spawn(EntityKind::SpikeTrap);
+        entities.register_collider(collider_handle, id);
+
+        Self {
+            id,
+            position,
+            offset,
+            is_up: false,
+            collider_handle,
+        }
+    }
+
+    /// Raise or lower the spikes when their timer says so
+    pub fn update(&mut self, layer: &mut Layer, theme: &TileTheme) {
+        let phase = (clock::now() + self.offset) % (SPIKES_UP_TIME + SPIKES_DOWN_TIME);
+        self.set_up(phase < SPIKES_UP_TIME, layer, theme);
+    }
+
+    pub fn set_up(&mut self, is_up: bool, layer: &mut Layer, theme: &TileTheme) {
+        if is_up == self.is_up {
+            return;
+        }
+        self.is_up = is_up;
+        let id = if is_up {
+            theme.traps.spikes_up
+        } else {
+            theme.traps.spikes_down
+        };
+        set_tile(self.position, id, layer, theme);
+    }
+}
+
+/// A plate in the floor that swings a nearby cell door open or shut whenever it is stepped on
+pub struct PressurePlate {
+    pub id: EntityId,
+    pub position: UVec2,
+    /// position of the guard door this plate works
+    pub door_position: UVec2,
+    /// someone was standing on the plate last time it was checked
+    pub is_pressed: bool,
+    pub collider_handle: ColliderHandle,
+}
+
+impl PressurePlate {
+    pub fn create(
+        position: UVec2,
+        door_position: UVec2,
+        collider_set: &mut ColliderSet,
+        entities: &mut EntityRegistry,
+    ) -> Self {
+        let collider_handle = create_tile_sensor(position, collider_set);
+        let id = entities.spawn(EntityKind::PressurePlate);
+        entities.register_collider(collider_handle, id);
+
+        Self {
+            id,
+            position,
+            door_position,
+            is_pressed: false,
+            collider_handle,
+        }
+    }
+
+    /// Update whether the plate is held down. Returns true if it was just stepped on.
+    pub fn set_pressed(&mut self, is_pressed: bool, layer: &mut Layer, theme: &TileTheme) -> bool {
+        if is_pressed == self.is_pressed {
+            return false;
+        }
+        self.is_pressed = is_pressed;
+        let id = if is_pressed {
+            theme.traps.plate_down
+        } else {
+            theme.traps.plate_up
+        };
+        set_tile(self.position, id, layer, theme);
+        is_pressed
+    }
+}
+
+/// A lever on the floor that swings a guard door somewhere else on the map when it is pulled
+pub struct Lever {
+    pub id: EntityId,
+    pub position: UVec2,
+    /// position of the guard door this lever works
+    pub door_position: UVec2,
+    /// leaning the other way from how it started
+    pub is_pulled: bool,
+}
+
+impl Lever {
+    pub fn create(position: UVec2, door_position: UVec2, entities: &mut EntityRegistry) -> Self {
+        // nothing bumps into a lever, so it has no collider
+        let id = entities.spawn(EntityKind::Lever);
+        Self {
+            id,
+            position,
+            door_position,
+            is_pulled: false,
+        }
+    }
+
+    pub fn center(&self) -> Vec2 {
+        self.position.as_vec2() + vec2(0.5, 0.5)
+    }
+
+    /// Draw the handle leaning one way or the other, depending on which way it was last pulled
+    pub fn draw(&self, tile_map: &TiledMap) {
+        let tileset = &tile_map.tilesets[TILESET_MAP_ID];
+        let rotation = if self.is_pulled {
+            LEVER_LEAN
+        } else {
+            -LEVER_LEAN
+        };
+        draw_texture_ex(
+            &tileset.texture,
+            self.position.x as f32,
+            self.position.y as f32,
+            WHITE,
+            DrawTextureParams {
+                dest_size: Some(vec2(1., 1.)),
+                source: Some(sprite_source(tileset, LEVER_SPRITE_ID)),
+                rotation,
+                // lean from the bottom of the handle
+                pivot: Some(self.position.as_vec2() + vec2(0.5, 1.)),
+                ..Default::default()
+            },
+        );
+    }
+}
+
+impl Interactable for Lever {
+    fn interact_position(&self) -> Vec2 {
+        self.center()
+    }
+
+    fn prompt(&self) -> &'static str {
+        "prompt-pull-lever"
+    }
+
+    fn interact(&mut self, _player: EntityId, events: &mut EventQueue) {
+        events.emit(GameEvent::LeverPulled {
+            lever: self.id,
+            position: self.center(),
+        });
+    }
+}
+
+/// Sensor a little smaller than a tile, so brushing past the edge doesn't count as standing on it
+fn create_tile_sensor(position: UVec2, collider_set: &mut ColliderSet) -> ColliderHandle {
+    let collider = ColliderBuilder::cuboid(TRAP_SENSOR_HALF_SIZE, TRAP_SENSOR_HALF_SIZE)
+        .translation(vector![position.x as f32 + 0.5, position.y as f32 + 0.5])
+        .sensor(true)
+        .collision_groups(CollisionLayer::TileSensor.groups())
+        .build();
+    collider_set.insert(collider)
+}
+
+fn set_tile(position: UVec2, id: u32, layer: &mut Layer, theme: &TileTheme) {
+    let i = xytoi(position.x, position.y, layer);
+    layer.data[i] = Some(Tile {
+        id,
+        tileset: theme.tileset.clone(),
+        attrs: String::new(),
+    });
+}
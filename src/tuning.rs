@@ -0,0 +1,136 @@
+use std::sync::RwLock;
+
+use anyhow::Result;
+use macroquad::{file::load_string, math::UVec2};
+use nanoserde::DeRon;
+
+use crate::constants::{
+    ATTACK_COOLDOWN, BOSS_ACCELERATION, BOSS_CHARGE_COOLDOWN, DAMAGE_COOLDOWN, GUARD_ACCELERATION,
+    GUARD_BRAKING, GUARD_KNOCKBACK_COOLDOWN, KINEMATIC_PLAYER, MAX_ROOM_COUNT, MAX_ROOM_SIZE,
+    MIN_ROOM_SIZE, PLAYER_ACCELERATION, PLAYER_ATTACK_KNOCKBACK, PLAYER_BRAKING,
+    PLAYER_GUARD_KNOCKBACK, PLAYER_KNOCKBACK_COOLDOWN, THROW_COOLDOWN,
+};
+
+/// Gameplay numbers that are tuned by feel.
+///
+/// These are read from a RON file at startup, and again whenever it is reloaded, so they can be
+/// changed without a rebuild. Anything left out of the file keeps its value from constants.rs.
+/// Characters and maps pick up new values when they are next created.
+#[derive(Clone, Copy, Debug, PartialEq, DeRon)]
+pub struct Tuning {
+    #[nserde(default = "PLAYER_ACCELERATION")]
+    pub player_acceleration: f32,
+    #[nserde(default = "PLAYER_BRAKING")]
+    pub player_braking: f32,
+    #[nserde(default = "GUARD_ACCELERATION")]
+    pub guard_acceleration: f32,
+    #[nserde(default = "GUARD_BRAKING")]
+    pub guard_braking: f32,
+    #[nserde(default = "BOSS_ACCELERATION")]
+    pub boss_acceleration: f32,
+    /// slide the player along walls with a character controller, rather than simulating its body
+    #[nserde(default = "KINEMATIC_PLAYER")]
+    pub kinematic_player: bool,
+
+    #[nserde(default = "ATTACK_COOLDOWN")]
+    pub attack_cooldown: f64,
+    #[nserde(default = "THROW_COOLDOWN")]
+    pub throw_cooldown: f64,
+    #[nserde(default = "DAMAGE_COOLDOWN")]
+    pub damage_cooldown: f64,
+    #[nserde(default = "PLAYER_KNOCKBACK_COOLDOWN")]
+    pub player_knockback_cooldown: f64,
+    #[nserde(default = "GUARD_KNOCKBACK_COOLDOWN")]
+    pub guard_knockback_cooldown: f64,
+    #[nserde(default = "BOSS_CHARGE_COOLDOWN")]
+    pub boss_charge_cooldown: f64,
+
+    /// knockback impulses, so a guard twice as heavy is knocked half as fast
+    #[nserde(default = "PLAYER_GUARD_KNOCKBACK")]
+    pub player_guard_knockback: f32,
+    #[nserde(default = "PLAYER_ATTACK_KNOCKBACK")]
+    pub player_attack_knockback: f32,
+
+    /// width and height of rooms, in tiles
+    #[nserde(default = "MIN_ROOM_SIZE.to_array()")]
+    pub min_room_size: [u32; 2],
+    #[nserde(default = "MAX_ROOM_SIZE.to_array()")]
+    pub max_room_size: [u32; 2],
+    #[nserde(default = "MAX_ROOM_COUNT")]
+    pub max_room_count: u32,
+}
+
+impl Tuning {
+    pub const DEFAULT: Self = Self {
+        player_acceleration: PLAYER_ACCELERATION,
+        player_braking: PLAYER_BRAKING,
+        guard_acceleration: GUARD_ACCELERATION,
+        guard_braking: GUARD_BRAKING,
+        boss_acceleration: BOSS_ACCELERATION,
+        kinematic_player: KINEMATIC_PLAYER,
+        attack_cooldown: ATTACK_COOLDOWN,
+        throw_cooldown: THROW_COOLDOWN,
+        damage_cooldown: DAMAGE_COOLDOWN,
+        player_knockback_cooldown: PLAYER_KNOCKBACK_COOLDOWN,
+        guard_knockback_cooldown: GUARD_KNOCKBACK_COOLDOWN,
+        boss_charge_cooldown: BOSS_CHARGE_COOLDOWN,
+        player_guard_knockback: PLAYER_GUARD_KNOCKBACK,
+        player_attack_knockback: PLAYER_ATTACK_KNOCKBACK,
+        min_room_size: MIN_ROOM_SIZE.to_array(),
+        max_room_size: MAX_ROOM_SIZE.to_array(),
+        max_room_count: MAX_ROOM_COUNT,
+    };
+
+    pub async fn load(path: &str) -> Result<Self> {
+        let ron = load_string(path).await?;
+        Self::from_ron(&ron)
+    }
+
+    pub fn from_ron(ron: &str) -> Result<Self> {
+        Ok(DeRon::deserialize_ron(ron)?)
+    }
+
+    pub fn min_room_size(&self) -> UVec2 {
+        UVec2::from_array(self.min_room_size)
+    }
+
+    pub fn max_room_size(&self) -> UVec2 {
+        UVec2::from_array(self.max_room_size)
+    }
+}
+
+impl Default for Tuning {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+static TUNING: RwLock<Tuning> = RwLock::new(Tuning::DEFAULT);
+
+/// The tuning values currently in effect
+pub fn tuning() -> Tuning {
+    *TUNING.read().unwrap()
+}
+
+/// Replace the tuning values for everything created from now on
+pub fn set_tuning(tuning: Tuning) {
+    *TUNING.write().unwrap() = tuning;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tuning_fills_missing_values_from_constants() {
+        let tuning =
+            Tuning::from_ron("(player_acceleration: 70.0, min_room_size: (8, 9))").unwrap();
+        assert_eq!(tuning.player_acceleration, 70.);
+        assert_eq!(tuning.min_room_size(), UVec2::new(8, 9));
+        assert_eq!(tuning.guard_acceleration, GUARD_ACCELERATION);
+        assert_eq!(tuning.max_room_size(), MAX_ROOM_SIZE);
+
+        let tuning = Tuning::from_ron(include_str!("../assets/tuning.ron")).unwrap();
+        assert_eq!(tuning, Tuning::DEFAULT);
+    }
+}
@@ -0,0 +1,106 @@
+use nanoserde::{DeBin, DeJson, SerBin, SerJson};
+
+use crate::constants::{
+    LONG_REACH_BONUS, QUICK_ATTACK_FACTOR, UPGRADE_BASE_COST, UPGRADE_MAX_LEVEL,
+};
+
+/// Something the shop between floors sells
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Upgrade {
+    /// one more heart to fill
+    ExtraHeart,
+    /// less time to wait between attacks
+    QuickAttack,
+    /// attacks reach further
+    LongReach,
+}
+
+impl Upgrade {
+    pub const ALL: [Self; 3] = [Self::ExtraHeart, Self::QuickAttack, Self::LongReach];
+
+    /// locale key for the upgrade's name
+    pub fn name(&self) -> &'static str {
+        match self {
+            Upgrade::ExtraHeart => "upgrade-extra-heart",
+            Upgrade::QuickAttack => "upgrade-quick-attack",
+            Upgrade::LongReach => "upgrade-long-reach",
+        }
+    }
+}
+
+/// What the players have bought in the shop so far this run. Characters consult it in place of
+/// the raw constants for the stats it covers.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, DeJson, SerJson, DeBin, SerBin)]
+pub struct PlayerUpgrades {
+    pub extra_hearts: u32,
+    pub quick_attack: u32,
+    pub long_reach: u32,
+}
+
+impl PlayerUpgrades {
+    pub fn level(&self, upgrade: Upgrade) -> u32 {
+        match upgrade {
+            Upgrade::ExtraHeart => self.extra_hearts,
+            Upgrade::QuickAttack => self.quick_attack,
+            Upgrade::LongReach => self.long_reach,
+        }
+    }
+
+    /// Coins the next level of an upgrade costs, or None once it can't go any higher
+    pub fn cost(&self, upgrade: Upgrade) -> Option<u32> {
+        let level = self.level(upgrade);
+        (level < UPGRADE_MAX_LEVEL).then_some(UPGRADE_BASE_COST * (level + 1))
+    }
+
+    /// Buy the next level of an upgrade if there are coins enough, taking them out of `coins`
+    pub fn buy(&mut self, upgrade: Upgrade, coins: &mut u32) -> bool {
+        let Some(cost) = self.cost(upgrade).filter(|cost| cost <= coins) else {
+            return false;
+        };
+        *coins -= cost;
+        match upgrade {
+            Upgrade::ExtraHeart => self.extra_hearts += 1,
+            Upgrade::QuickAttack => self.quick_attack += 1,
+            Upgrade::LongReach => self.long_reach += 1,
+        }
+        true
+    }
+
+    pub fn max_health(&self, base: u32) -> u32 {
+        base + self.extra_hearts
+    }
+
+    pub fn attack_cooldown(&self, base: f64) -> f64 {
+        base * QUICK_ATTACK_FACTOR.powi(self.quick_attack as i32)
+    }
+
+    pub fn attack_radius(&self, base: f32) -> f32 {
+        base * (1. + LONG_REACH_BONUS * self.long_reach as f32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_upgrades_cost_more_each_level_up_to_the_max() {
+        let mut upgrades = PlayerUpgrades::default();
+        let mut coins = UPGRADE_BASE_COST * 2;
+        assert!(upgrades.buy(Upgrade::ExtraHeart, &mut coins));
+        assert_eq!(coins, UPGRADE_BASE_COST);
+        // the second heart costs twice as much, which is more than is left
+        assert!(!upgrades.buy(Upgrade::ExtraHeart, &mut coins));
+        assert_eq!(coins, UPGRADE_BASE_COST);
+        assert_eq!(upgrades.max_health(5), 6);
+
+        let mut coins = u32::MAX;
+        for _ in 0..UPGRADE_MAX_LEVEL * 2 {
+            upgrades.buy(Upgrade::LongReach, &mut coins);
+        }
+        assert_eq!(upgrades.long_reach, UPGRADE_MAX_LEVEL);
+        assert_eq!(upgrades.cost(Upgrade::LongReach), None);
+        assert!(upgrades.attack_radius(1.) > 1.);
+        assert_eq!(upgrades.attack_cooldown(1.), 1.);
+    }
+}
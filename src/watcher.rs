@@ -0,0 +1,47 @@
+use std::{fs, time::SystemTime};
+
+use macroquad::time::get_time;
+
+use crate::constants::ASSET_POLL_INTERVAL;
+
+/// Notices when asset files are changed on disk, so they can be reloaded while the game runs.
+///
+/// This polls modification times rather than subscribing to file system events. It only works
+/// on native builds; on the web nothing ever looks changed.
+pub struct AssetWatcher {
+    files: Vec<(&'static str, Option<SystemTime>)>,
+    last_poll: f64,
+}
+
+impl AssetWatcher {
+    pub fn new(paths: &[&'static str]) -> Self {
+        Self {
+            files: paths.iter().map(|path| (*path, modified(path))).collect(),
+            last_poll: get_time(),
+        }
+    }
+
+    /// Check the files every so often. Returns true if any changed since they were last checked.
+    pub fn poll(&mut self) -> bool {
+        if cfg!(target_arch = "wasm32") || get_time() < self.last_poll + ASSET_POLL_INTERVAL {
+            return false;
+        }
+        self.last_poll = get_time();
+
+        let mut changed = false;
+        for (path, last_modified) in &mut self.files {
+            let modified = modified(path);
+            if modified != *last_modified {
+                *last_modified = modified;
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
@@ -0,0 +1,232 @@
+use macroquad::{
+    input::{is_key_pressed, KeyCode},
+    math::{vec2, Rect, Vec2},
+    ui::{widgets, Ui},
+    window::{screen_height, screen_width},
+};
+
+use crate::constants::{MENU_BUTTON_WIDTH, MENU_MARGIN, MENU_ROW_HEIGHT};
+
+/// Which part of its container a panel sits against
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Anchor {
+    Top,
+    Center,
+    Bottom,
+}
+
+impl Anchor {
+    /// Top left of something `size` big placed in `container`, kept a margin away from its edges
+    pub fn place(self, size: Vec2, container: Rect) -> Vec2 {
+        let x = container.x + (container.w - size.x) / 2.;
+        let y = match self {
+            Anchor::Top => container.y + MENU_MARGIN,
+            Anchor::Center => container.y + (container.h - size.y) / 2.,
+            Anchor::Bottom => container.y + container.h - size.y - MENU_MARGIN,
+        };
+        vec2(x, y)
+    }
+}
+
+pub fn screen_rect() -> Rect {
+    Rect::new(0., 0., screen_width(), screen_height())
+}
+
+/// Area of a panel anchored to the screen, shrunk to fit if the window is too small for it
+pub fn panel(size: Vec2, anchor: Anchor) -> Rect {
+    let screen = screen_rect();
+    let room = vec2(screen.w, screen.h) - Vec2::splat(MENU_MARGIN * 2.);
+    let size = size.min(room).max(Vec2::ZERO);
+    let position = anchor.place(size, screen);
+    Rect::new(position.x, position.y, size.x, size.y)
+}
+
+/// Text against the left of `area`, centered from top to bottom
+pub fn label_left(ui: &mut Ui, area: Rect, text: &str) {
+    let size = ui.calc_size(text);
+    widgets::Label::new(text)
+        .position(vec2(area.x, area.y + (area.h - size.y) / 2.))
+        .size(size)
+        .ui(ui);
+}
+
+/// Text against the right of `area`, centered from top to bottom
+pub fn label_right(ui: &mut Ui, area: Rect, text: &str) {
+    let size = ui.calc_size(text);
+    widgets::Label::new(text)
+        .position(vec2(area.right() - size.x, area.y + (area.h - size.y) / 2.))
+        .size(size)
+        .ui(ui);
+}
+
+/// Rows laid out down the middle of an area, one after another. Rows are squeezed to fit if there
+/// isn't room for all of them at full height.
+#[derive(Clone, Debug)]
+pub struct VStack {
+    area: Rect,
+    row_height: f32,
+    /// top of the next row
+    cursor: f32,
+    button_width: f32,
+}
+
+impl VStack {
+    /// A stack for `rows` rows at the player's text size, centered in `area`
+    pub fn new(area: Rect, rows: usize, text_scale: f32) -> Self {
+        let row_height = (MENU_ROW_HEIGHT * text_scale).min(area.h / rows.max(1) as f32);
+        let height = row_height * rows as f32;
+        Self {
+            area,
+            row_height,
+            cursor: area.y + (area.h - height) / 2.,
+            button_width: (MENU_BUTTON_WIDTH * text_scale).min(area.w),
+        }
+    }
+
+    /// A stack filling a panel anchored to the screen
+    pub fn panel(width: f32, rows: usize, text_scale: f32, anchor: Anchor) -> Self {
+        let size = vec2(
+            width * text_scale,
+            MENU_ROW_HEIGHT * text_scale * rows as f32,
+        );
+        Self::new(panel(size, anchor), rows, text_scale)
+    }
+
+    /// Make buttons wider than usual, for labels that change, like settings
+    pub fn with_button_width(self, button_width: f32) -> Self {
+        Self {
+            button_width: button_width.min(self.area.w),
+            ..self
+        }
+    }
+
+    /// Everything the stack's rows can take up
+    pub fn area(&self) -> Rect {
+        self.area
+    }
+
+    /// Take the next row
+    pub fn row(&mut self) -> Rect {
+        let row = Rect::new(self.area.x, self.cursor, self.area.w, self.row_height);
+        self.cursor += self.row_height;
+        row
+    }
+
+    /// Leave a row empty
+    pub fn space(&mut self) {
+        self.row();
+    }
+
+    /// Text centered in the next row
+    pub fn label(&mut self, ui: &mut Ui, text: &str) {
+        let row = self.row();
+        widgets::Label::new(text)
+            .position(row.point())
+            .size(row.size())
+            .ui(ui);
+    }
+
+    /// Button centered in the next row, highlighted if it has the keyboard focus. Returns whether
+    /// it was clicked, or pressed from the keyboard.
+    pub fn button(&mut self, ui: &mut Ui, focus: &mut MenuFocus, text: &str) -> bool {
+        let row = self.row();
+        // a little gap between buttons one above the other
+        let size = vec2(self.button_width, row.h * 0.85);
+        let (focused, pressed) = focus.next_button();
+        let clicked = widgets::Button::new(text)
+            .position(row.point() + (row.size() - size) / 2.)
+            .size(size)
+            .selected(focused)
+            .ui(ui);
+        clicked || pressed
+    }
+}
+
+/// Which of a menu's buttons the keyboard is on. Buttons are numbered in the order they are drawn,
+/// so menus whose buttons come and go don't need to declare them up front.
+#[derive(Clone, Debug, Default)]
+pub struct MenuFocus {
+    index: usize,
+    /// buttons drawn last frame, to wrap around
+    count: usize,
+    /// buttons drawn so far this frame
+    drawn: usize,
+    /// the focused button was pressed this frame
+    pressed: bool,
+}
+
+impl MenuFocus {
+    /// Move between buttons with the arrow keys and press one with Enter or Space. Call once a
+    /// frame before drawing. Returns whether Escape was pressed to back out of the menu.
+    pub fn handle_input(&mut self) -> bool {
+        self.count = self.drawn;
+        self.drawn = 0;
+        if self.count > 0 {
+            if is_key_pressed(KeyCode::Up) {
+                self.index = (self.index + self.count - 1) % self.count;
+            }
+            if is_key_pressed(KeyCode::Down) {
+                self.index = (self.index + 1) % self.count;
+            }
+            // a button may have gone since last frame
+            self.index = self.index.min(self.count - 1);
+        }
+        self.pressed = [KeyCode::Enter, KeyCode::KpEnter, KeyCode::Space]
+            .into_iter()
+            .any(is_key_pressed);
+        is_key_pressed(KeyCode::Escape)
+    }
+
+    /// Number the next button drawn. Returns whether it has the focus, and whether it was pressed.
+    fn next_button(&mut self) -> (bool, bool) {
+        let focused = self.drawn == self.index;
+        self.drawn += 1;
+        (focused, focused && self.pressed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stack_rows_are_centered_and_squeezed_to_fit() {
+        let area = Rect::new(0., 100., 400., 600.);
+        let mut stack = VStack::new(area, 4, 1.);
+        let first = stack.row();
+        assert_eq!(first.h, MENU_ROW_HEIGHT);
+        assert_eq!(first.y, 100. + (600. - MENU_ROW_HEIGHT * 4.) / 2.);
+        assert_eq!(stack.row().y, first.y + MENU_ROW_HEIGHT);
+
+        let mut squeezed = VStack::new(Rect::new(0., 0., 400., 100.), 4, 1.);
+        assert_eq!(squeezed.row(), Rect::new(0., 0., 400., 25.));
+        squeezed.space();
+        squeezed.space();
+        assert_eq!(squeezed.row().bottom(), 100.);
+    }
+
+    #[test]
+    fn test_focus_numbers_buttons_in_draw_order() {
+        let mut focus = MenuFocus::default();
+        assert_eq!(focus.next_button(), (true, false));
+        assert_eq!(focus.next_button(), (false, false));
+        focus.count = 2;
+        focus.drawn = 0;
+        focus.index = 1;
+        focus.pressed = true;
+        assert_eq!(focus.next_button(), (false, false));
+        assert_eq!(focus.next_button(), (true, true));
+    }
+
+    #[test]
+    fn test_anchors_keep_a_margin_from_the_edges() {
+        let container = Rect::new(0., 0., 200., 100.);
+        let size = vec2(100., 20.);
+        assert_eq!(Anchor::Top.place(size, container), vec2(50., MENU_MARGIN));
+        assert_eq!(Anchor::Center.place(size, container), vec2(50., 40.));
+        assert_eq!(
+            Anchor::Bottom.place(size, container),
+            vec2(50., 80. - MENU_MARGIN)
+        );
+    }
+}
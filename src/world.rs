@@ -0,0 +1,344 @@
+use anyhow::Result;
+use macroquad::{
+    math::{Rect, UVec2, Vec2},
+    rand::{gen_range, srand},
+};
+use macroquad_tiled::Layer;
+
+use crate::{
+    audio::SoundBank,
+    character::Character,
+    chests::Chest,
+    constants::{
+        BOSS_FLOOR_INTERVAL, DOOR_CYCLE_CLOSED_TIME, DOOR_CYCLE_OPEN_TIME, SPIKES_DOWN_TIME,
+        SPIKES_UP_TIME,
+    },
+    crates::Crate,
+    door::{ExitDoor, GuardDoor, LockedDoor},
+    hiding::{HidingSpot, HidingSpotKind},
+    items::Item,
+    map::{
+        mapgen::{MapGenAlgorithm, MapGenResult, MapGenerator},
+        prefab::Prefab,
+        theme::TileTheme,
+    },
+    passage::OneWayPassage,
+    physics::Physics,
+    traps::{Lever, PressurePlate, SpikeTrap},
+};
+
+/// Everything generated fresh for a new map: its layers, and a physics world with the map's
+/// doors, traps and items in it. Characters are added with `create_characters`.
+pub struct World {
+    pub terrain: Layer,
+    pub props: Layer,
+    /// wall tops drawn over the characters
+    pub overhead: Layer,
+    /// theme the map was painted with
+    pub theme: TileTheme,
+    pub rooms: Vec<Rect>,
+    /// where each guard starts out
+    pub guard_spawns: Vec<Vec2>,
+    /// the last guard is a boss, and one of the doors is reinforced to trap it
+    pub has_boss: bool,
+    pub physics: Physics,
+    pub guard_doors: Vec<GuardDoor>,
+    pub exit_door: ExitDoor,
+    pub locked_doors: Vec<LockedDoor>,
+    pub one_way_passages: Vec<OneWayPassage>,
+    pub spike_traps: Vec<SpikeTrap>,
+    pub pressure_plates: Vec<PressurePlate>,
+    pub levers: Vec<Lever>,
+    pub items: Vec<Item>,
+    pub crates: Vec<Crate>,
+    pub chests: Vec<Chest>,
+    /// barrels, and the doorways of the cells
+    pub hiding_spots: Vec<HidingSpot>,
+    /// guards to trap before the exit opens
+    pub score_target: u32,
+}
+
+/// Generates the map for a floor and builds the world around it
+pub struct WorldBuilder<'a> {
+    pub size: UVec2,
+    pub prefabs: &'a [Prefab],
+    pub themes: &'a [TileTheme],
+    /// floor of the run being built, which decides whether it has a boss
+    pub floor: u32,
+}
+
+impl WorldBuilder<'_> {
+    pub fn build(&self, seed: u64) -> Result<World> {
+        srand(seed);
+
+        let mut mapgen = MapGenerator::new(self.size);
+        mapgen.algorithm = MapGenAlgorithm::random();
+        mapgen.prefabs = self.prefabs.to_vec();
+        mapgen.theme = self
+            .themes
+            .get(gen_range(0, self.themes.len()))
+            .cloned()
+            .unwrap_or_default();
+        mapgen.reinforced_door = is_boss_floor(self.floor);
+
+        let MapGenResult {
+            rooms,
+            guard_spawns,
+            mut layer,
+            props,
+            overhead,
+            guard_doors,
+            exit_door,
+            reinforced_door,
+            cycling_doors,
+            spike_traps,
+            pressure_plates,
+            levers,
+            items,
+            locked_doors,
+            one_way_passages,
+            crates,
+            chests,
+            barrels,
+        } = mapgen.generate_layer()?;
+        debug!("rooms: {:?}", rooms);
+        info!("theme: {}", mapgen.theme.name);
+
+        let mut physics = Physics::default();
+
+        let mut guard_doors: Vec<GuardDoor> = guard_doors
+            .iter()
+            .map(|position| {
+                let mut door = GuardDoor::create(
+                    *position,
+                    false,
+                    &mut physics.colliders,
+                    &mut physics.entities,
+                );
+                if cycling_doors.contains(position) {
+                    door.cycle_offset =
+                        Some(gen_range(0., DOOR_CYCLE_OPEN_TIME + DOOR_CYCLE_CLOSED_TIME));
+                }
+                door
+            })
+            .collect();
+        if let Some(position) = reinforced_door {
+            guard_doors.push(GuardDoor::create(
+                position,
+                true,
+                &mut physics.colliders,
+                &mut physics.entities,
+            ));
+        }
+
+        // DEBUG
+        // let score_target = 1;
+        let score_target = guard_doors.iter().filter(|door| !door.reinforced).count() as u32 / 2;
+
+        let spike_traps = spike_traps
+            .iter()
+            .map(|position| {
+                SpikeTrap::create(
+                    *position,
+                    gen_range(0., SPIKES_UP_TIME + SPIKES_DOWN_TIME),
+                    &mut physics.colliders,
+                    &mut physics.entities,
+                )
+            })
+            .collect();
+        let pressure_plates: Vec<PressurePlate> = pressure_plates
+            .iter()
+            .map(|(position, door)| {
+                PressurePlate::create(
+                    *position,
+                    *door,
+                    &mut physics.colliders,
+                    &mut physics.entities,
+                )
+            })
+            .collect();
+        let levers: Vec<Lever> = levers
+            .iter()
+            .map(|(position, door)| Lever::create(*position, *door, &mut physics.entities))
+            .collect();
+        // doors worked by plates and levers start out shut
+        let worked_doors = pressure_plates
+            .iter()
+            .map(|plate| plate.door_position)
+            .chain(levers.iter().map(|lever| lever.door_position));
+        for door_position in worked_doors {
+            if let Some(door) = guard_doors
+                .iter_mut()
+                .find(|door| door.position() == door_position)
+            {
+                door.set_open(false, &mut layer, &mapgen.theme);
+            }
+        }
+
+        let exit_door = ExitDoor::create(exit_door, &mut physics.colliders, &mut physics.entities);
+
+        let locked_doors = locked_doors
+            .iter()
+            .map(|(position, width)| {
+                LockedDoor::create(
+                    *position,
+                    *width,
+                    &mut physics.colliders,
+                    &mut physics.entities,
+                )
+            })
+            .collect();
+
+        let one_way_passages = one_way_passages
+            .iter()
+            .map(|(position, width, direction)| {
+                OneWayPassage::create(
+                    *position,
+                    *width,
+                    *direction,
+                    &mut physics.colliders,
+                    &mut physics.entities,
+                )
+            })
+            .collect();
+
+        let items = items
+            .iter()
+            .map(|(position, kind)| {
+                Item::create(
+                    *kind,
+                    *position,
+                    &mut physics.colliders,
+                    &mut physics.entities,
+                )
+            })
+            .collect();
+
+        let crates = crates
+            .iter()
+            .map(|position| {
+                Crate::create(
+                    position.as_vec2(),
+                    &mut physics.colliders,
+                    &mut physics.bodies,
+                    &mut physics.entities,
+                )
+            })
+            .collect();
+
+        let chests = chests
+            .iter()
+            .map(|(position, coins)| Chest::create(*position, *coins, &mut physics.entities))
+            .collect();
+
+        let mut hiding_spots: Vec<HidingSpot> = barrels
+            .iter()
+            .map(|position| {
+                HidingSpot::create(HidingSpotKind::Barrel, *position, &mut physics.entities)
+            })
+            .collect();
+        hiding_spots.extend(
+            guard_doors
+                .iter()
+                .map(|door| HidingSpot::cell(door.position(), &mut physics.entities)),
+        );
+
+        Ok(World {
+            terrain: layer,
+            props,
+            overhead,
+            theme: mapgen.theme,
+            rooms,
+            guard_spawns,
+            has_boss: reinforced_door.is_some(),
+            physics,
+            guard_doors,
+            exit_door,
+            locked_doors,
+            one_way_passages,
+            spike_traps,
+            pressure_plates,
+            levers,
+            items,
+            crates,
+            chests,
+            hiding_spots,
+            score_target,
+        })
+    }
+}
+
+impl World {
+    /// Put the player in the first room and a guard in each of the others
+    pub fn create_characters(&mut self, sounds: &SoundBank) -> (Character, Vec<Character>) {
+        let physics = &mut self.physics;
+        let player = Character::create_player(
+            self.rooms[0].center(),
+            &mut physics.colliders,
+            &mut physics.bodies,
+            &mut physics.entities,
+            sounds,
+        );
+        let guards = self
+            .guard_spawns
+            .iter()
+            .enumerate()
+            .map(|(i, spawn)| {
+                let create = if self.has_boss && i == self.guard_spawns.len() - 1 {
+                    Character::create_boss
+                } else {
+                    Character::create_guard
+                };
+                create(
+                    *spawn,
+                    &mut physics.colliders,
+                    &mut physics.bodies,
+                    &mut physics.entities,
+                    sounds,
+                )
+            })
+            .collect();
+        (player, guards)
+    }
+}
+
+fn is_boss_floor(floor: u32) -> bool {
+    floor.is_multiple_of(BOSS_FLOOR_INTERVAL)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use macroquad::math::uvec2;
+
+    #[test]
+    fn test_rebuilt_world_has_consistent_collider_counts() {
+        let themes = [TileTheme::default()];
+        let builder = WorldBuilder {
+            size: uvec2(128, 96),
+            prefabs: &[],
+            themes: &themes,
+            floor: BOSS_FLOOR_INTERVAL,
+        };
+
+        let world = builder.build(7).unwrap();
+        // one collider for everything placed, and nothing else until characters and walls go in
+        let placed = world.guard_doors.len()
+            + 1
+            + world.locked_doors.len()
+            + world.one_way_passages.len()
+            + world.spike_traps.len()
+            + world.pressure_plates.len()
+            + world.items.len()
+            + world.crates.len();
+        assert_eq!(world.physics.colliders.len(), placed);
+
+        let rebuilt = builder.build(7).unwrap();
+        assert_eq!(rebuilt.rooms, world.rooms);
+        assert_eq!(rebuilt.score_target, world.score_target);
+        assert_eq!(
+            rebuilt.physics.colliders.len(),
+            world.physics.colliders.len()
+        );
+    }
+}
@@ -0,0 +1,37 @@
+"use strict";
+
+// localStorage access for src/storage.rs, which has no file system to save to on the web.
+// Load after mq_js_bundle.js and before calling load().
+(function () {
+    function key_string(key_ptr, key_len) {
+        return "stonehold:" + UTF8ToString(key_ptr, key_len);
+    }
+
+    function encoded(key_ptr, key_len) {
+        var value = window.localStorage.getItem(key_string(key_ptr, key_len));
+        return value === null ? null : new TextEncoder().encode(value);
+    }
+
+    function register_plugin(importObject) {
+        importObject.env.stonehold_storage_len = function (key_ptr, key_len) {
+            var bytes = encoded(key_ptr, key_len);
+            return bytes === null ? -1 : bytes.length;
+        };
+        importObject.env.stonehold_storage_read = function (key_ptr, key_len, buf_ptr, buf_len) {
+            var bytes = encoded(key_ptr, key_len);
+            if (bytes === null) {
+                return;
+            }
+            new Uint8Array(wasm_memory.buffer, buf_ptr, buf_len).set(bytes.subarray(0, buf_len));
+        };
+        importObject.env.stonehold_storage_write = function (key_ptr, key_len, value_ptr, value_len) {
+            var value = UTF8ToString(value_ptr, value_len);
+            window.localStorage.setItem(key_string(key_ptr, key_len), value);
+        };
+        importObject.env.stonehold_storage_remove = function (key_ptr, key_len) {
+            window.localStorage.removeItem(key_string(key_ptr, key_len));
+        };
+    }
+
+    miniquad_add_plugin({ register_plugin, version: "0.1.0", name: "stonehold_storage" });
+})();